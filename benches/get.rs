@@ -0,0 +1,59 @@
+//! Latency of the commands shell prompts and status lines run on every
+//! redraw, end to end: process start, reading the cache, printing. `get`
+//! should stay under 2ms on an SSD; `prompt`, which reads the config and the
+//! whole cache, is the yardstick.
+//!
+//! `cargo bench --bench get`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The binary with config, cache and runtime dir isolated under `home`,
+/// where there's no daemon to ask
+fn quotabar(home: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_quotabar"));
+    command
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_RUNTIME_DIR", home.join("run"))
+        .env_remove("QUOTABAR_CONFIG")
+        .env_remove("QUOTABAR_CONFIG_DIR")
+        .env_remove("QUOTABAR_CACHE_DIR")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+fn bench_get(c: &mut Criterion) {
+    let home = tempfile::tempdir().unwrap();
+    let cache = home.path().join(".cache/quotabar");
+    std::fs::create_dir_all(&cache).unwrap();
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache/v1-baseline.json"),
+        cache.join("state.json"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(home.path().join(".config/quotabar")).unwrap();
+    std::fs::write(
+        home.path().join(".config/quotabar/config.toml"),
+        "[general]\nselected_provider = \"claude\"\n",
+    )
+    .unwrap();
+
+    let mut run = |name: &str, args: &[&str]| {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let status = quotabar(home.path(), args).status().unwrap();
+                assert!(status.success());
+            })
+        });
+    };
+    run("get", &["get", "claude.primary.used_percent"]);
+    run("prompt", &["prompt"]);
+}
+
+criterion_group!(benches, bench_get);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+//! Latency of `quotabar prompt`, which shell prompts run on every redraw,
+//! both when it prints and when it stays silent under the threshold. It
+//! reads the config and the cache and nothing else, so it should stay in
+//! single-digit milliseconds.
+//!
+//! `cargo bench --bench prompt`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The binary with config, cache and runtime dir isolated under `home`,
+/// where there's no daemon to ask
+fn quotabar(home: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_quotabar"));
+    command
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_RUNTIME_DIR", home.join("run"))
+        .env_remove("QUOTABAR_CONFIG")
+        .env_remove("QUOTABAR_CONFIG_DIR")
+        .env_remove("QUOTABAR_CACHE_DIR")
+        .stderr(Stdio::null());
+    command
+}
+
+fn bench_prompt(c: &mut Criterion) {
+    let home = tempfile::tempdir().unwrap();
+    let cache = home.path().join(".cache/quotabar");
+    std::fs::create_dir_all(&cache).unwrap();
+    // Claude's most constrained window is at 51%
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache/v1-baseline.json"),
+        cache.join("state.json"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(home.path().join(".config/quotabar")).unwrap();
+    std::fs::write(
+        home.path().join(".config/quotabar/config.toml"),
+        "[general]\nselected_provider = \"claude\"\n",
+    )
+    .unwrap();
+
+    let mut run = |name: &str, threshold: &str, shown: bool| {
+        let args = ["prompt", "--threshold", threshold];
+        let output = quotabar(home.path(), &args).output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(!output.stdout.is_empty(), shown, "{}", name);
+
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let status = quotabar(home.path(), &args)
+                    .stdout(Stdio::null())
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+            })
+        });
+    };
+    run("prompt/shown", "50", true);
+    run("prompt/hidden", "75", false);
+}
+
+criterion_group!(benches, bench_prompt);
+criterion_main!(benches);
@@ -0,0 +1,104 @@
+//! Nudging waybar to re-run quotabar modules once the cache has changed
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+/// Check that SIGRTMIN+`n` is a real-time signal; waybar accepts
+/// `"signal"` values from 1 up to SIGRTMAX-SIGRTMIN (30 on glibc)
+pub fn validate(n: u8) -> Result<()> {
+    let max = libc::SIGRTMAX() - libc::SIGRTMIN();
+    if n == 0 || i32::from(n) > max {
+        bail!("waybar signal must be between 1 and {}, got {}", max, n);
+    }
+    Ok(())
+}
+
+/// Send SIGRTMIN+`n` to every running waybar so modules with `"signal": n`
+/// update now. Returns how many processes were signalled; ones owned by
+/// other users or gone by the time we get to them are skipped.
+pub fn send(n: u8) -> Result<usize> {
+    validate(n)?;
+    let signal = libc::SIGRTMIN() + i32::from(n);
+    let mut sent = 0;
+    for pid in processes_named(Path::new("/proc"), "waybar") {
+        // SAFETY: kill(2) takes plain integers and has no memory-safety
+        // preconditions
+        if unsafe { libc::kill(pid, signal) } == 0 {
+            sent += 1;
+        }
+    }
+    tracing::debug!("sent SIGRTMIN+{} to {} waybar process(es)", n, sent);
+    Ok(sent)
+}
+
+/// Pids under `proc` whose name or argv[0] basename is exactly `name`
+/// (wrappers such as NixOS's keep argv[0] but rename the process).
+/// Entries that can't be read are skipped.
+fn processes_named(proc: &Path, name: &str) -> Vec<i32> {
+    let Ok(entries) = fs::read_dir(proc) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let matches = |file: &str| {
+                fs::read(entry.path().join(file)).is_ok_and(|raw| process_name(file, &raw) == name)
+            };
+            (matches("comm") || matches("cmdline")).then_some(pid)
+        })
+        .collect()
+}
+
+/// The name in a `comm` file, or the argv[0] basename in a `cmdline` file
+fn process_name<'a>(file: &str, raw: &'a [u8]) -> &'a str {
+    let text = match file {
+        "comm" => raw.strip_suffix(b"\n").unwrap_or(raw),
+        _ => raw.split(|b| *b == 0).next().unwrap_or_default(),
+    };
+    let text = std::str::from_utf8(text).unwrap_or_default();
+    text.rsplit('/').next().unwrap_or(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_range() {
+        assert!(validate(1).is_ok());
+        assert!(validate(8).is_ok());
+        let max = libc::SIGRTMAX() - libc::SIGRTMIN();
+        assert!(validate(max as u8).is_ok());
+        assert!(validate(0).is_err());
+        let error = validate(max as u8 + 1).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("between 1 and {}", max)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_processes_named() {
+        let proc = tempfile::tempdir().unwrap();
+        let process = |pid: &str, comm: &str, cmdline: &[u8]| {
+            let dir = proc.path().join(pid);
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("comm"), comm).unwrap();
+            fs::write(dir.join("cmdline"), cmdline).unwrap();
+        };
+        process("100", "waybar\n", b"waybar\0-c\0config");
+        process("200", ".waybar-wrapped\n", b"/usr/bin/waybar\0");
+        process("300", "waybar-helper\n", b"waybar-helper\0");
+        process("400", "bash\n", b"bash\0waybar\0");
+        // Unreadable or non-process entries are skipped
+        fs::create_dir(proc.path().join("500")).unwrap();
+        fs::create_dir(proc.path().join("self")).unwrap();
+
+        let mut pids = processes_named(proc.path(), "waybar");
+        pids.sort();
+        assert_eq!(pids, vec![100, 200]);
+    }
+}
@@ -0,0 +1,86 @@
+//! Opening provider pages with `[general] open_url_command`
+
+use anyhow::{bail, Context, Result};
+use std::process::{Command, Stdio};
+
+/// `command` split into words the way a shell would, with `{url}` replaced
+/// by `url` in each; the URL is passed as the last word when none mentions
+/// it. Splitting comes first, so the URL is never reinterpreted.
+pub fn command_line(command: &str, url: &str) -> Result<Vec<String>> {
+    let mut words = shlex::split(command).context("unbalanced quotes")?;
+    if words.is_empty() {
+        bail!("command is empty");
+    }
+    if words.iter().any(|word| word.contains("{url}")) {
+        for word in &mut words {
+            *word = word.replace("{url}", url);
+        }
+    } else {
+        words.push(url.to_string());
+    }
+    Ok(words)
+}
+
+/// Run `command` for `url` in a session of its own, so the browser outlives
+/// the popup
+pub fn open(command: &str, url: &str) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let words = command_line(command, url)?;
+    let mut child = Command::new(&words[0]);
+    child
+        .args(&words[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid(2) is async-signal-safe and touches no memory
+    unsafe {
+        child.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = child
+        .spawn()
+        .with_context(|| format!("Failed to run {}", words[0]))?;
+    tracing::debug!("opened {} with {}", url, words[0]);
+    // Reaped in the background so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/usage?a=1&b=2";
+
+    #[test]
+    fn test_command_line_substitutes_url() {
+        assert_eq!(
+            command_line("firefox --new-window {url}", URL).unwrap(),
+            ["firefox", "--new-window", URL]
+        );
+        assert_eq!(
+            command_line("browser --app={url}", URL).unwrap(),
+            ["browser", &format!("--app={}", URL)]
+        );
+        // Quoted words stay whole and the URL isn't split or expanded
+        assert_eq!(
+            command_line("'/opt/My Browser/run' -P 'work profile' \"{url}\"", URL).unwrap(),
+            ["/opt/My Browser/run", "-P", "work profile", URL]
+        );
+    }
+
+    #[test]
+    fn test_command_line_appends_url() {
+        assert_eq!(command_line("xdg-open", URL).unwrap(), ["xdg-open", URL]);
+    }
+
+    #[test]
+    fn test_command_line_rejects_bad_commands() {
+        assert!(command_line("", URL).is_err());
+        assert!(command_line("   ", URL).is_err());
+        assert!(command_line("firefox 'unterminated", URL).is_err());
+    }
+}
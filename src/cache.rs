@@ -1,51 +1,1536 @@
-use crate::models::{Provider, UsageSnapshot};
-use anyhow::Result;
+use crate::config::{Config, Storage, Thresholds};
+use crate::events::{self, UsageEvent, MAX_EVENTS};
+use crate::files;
+use crate::models::{Provider, UsageSnapshot, WindowKind};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::Db;
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The state.json layout this build writes. Fields added later carry serde
+/// defaults so older files still load; a change they can't absorb bumps this
+/// and adds a step to [`STEPS`].
+pub const CACHE_VERSION: u32 = 2;
+
+/// How long to wait for another process's refresh to release the cache
+/// before going ahead without the lock
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a bounded wait retries the lock
+const LOCK_POLL: Duration = Duration::from_millis(50);
+
+/// One upgrade of a state.json object, from the version before it to the next
+type Step = fn(&mut Map<String, Value>);
+
+/// Steps in order, the first upgrading version 1
+const STEPS: &[Step] = &[windows_from_slots];
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CacheState {
+    /// Layout of this file; missing in files from before it was versioned
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
     pub snapshots: HashMap<Provider, UsageSnapshot>,
     pub updated_at: DateTime<Utc>,
+    /// Providers that answered 429, and when they may be fetched again
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub backoff_until: HashMap<Provider, DateTime<Utc>>,
+    /// Providers whose last fetch failed; cleared by the next success
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub last_errors: HashMap<Provider, FetchFailure>,
+    /// Threshold crossings and resets seen between saves, oldest first and
+    /// at most [`MAX_EVENTS`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<UsageEvent>,
+}
+
+/// Why a provider's last fetch failed, and when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FetchFailure {
+    pub reason: String,
+    pub at: DateTime<Utc>,
 }
 
 impl CacheState {
+    /// state.json in `$QUOTABAR_CACHE_DIR` or `~/.cache/quotabar`; the
+    /// history, popup state and logs sit beside it
     pub fn cache_path() -> PathBuf {
-        dirs::cache_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("quotabar")
-            .join("state.json")
+        crate::paths::cache_dir().path.join("state.json")
+    }
+
+    pub fn lock_path() -> PathBuf {
+        Self::cache_path().with_file_name("state.lock")
+    }
+
+    /// Take the exclusive cache lock, waiting up to [`LOCK_TIMEOUT`]; a
+    /// timeout is logged, and callers usually carry on unlocked
+    pub fn lock() -> Result<CacheLock> {
+        lock_within(&Self::lock_path(), LOCK_TIMEOUT)
+    }
+
+    /// [`lock`](Self::lock) without blocking the async runtime
+    pub async fn lock_async() -> Result<CacheLock> {
+        let path = Self::lock_path();
+        let lock = CacheLock::acquire_within_async(&path, LOCK_TIMEOUT).await?;
+        lock_or_warn(lock, &path, LOCK_TIMEOUT)
+    }
+
+    /// Take the exclusive cache lock if no other process holds it
+    pub fn try_lock() -> Result<Option<CacheLock>> {
+        CacheLock::try_acquire(&Self::lock_path())
     }
 
+    /// The cache from the configured storage, or None when there is none yet
     pub fn load() -> Result<Option<Self>> {
-        let path = Self::cache_path();
-        if path.exists() {
-            let content = std::fs::read_to_string(&path)?;
-            let state: CacheState = serde_json::from_str(&content)?;
-            Ok(Some(state))
-        } else {
-            Ok(None)
+        Backend::current().load()
+    }
+
+    /// Read state.json at `path`, keeping whatever entries of a damaged file
+    /// still parse; the rest go to `state.json.quarantine`. A file that
+    /// can't be read as a cache at all is moved aside to
+    /// `state.json.corrupt-<timestamp>`, so one bad write costs a refetch
+    /// rather than every reader's data.
+    pub fn load_from(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            tracing::debug!(path = %path.display(), "no cache file yet");
+            return Ok(None);
+        }
+        let content = std::fs::read(path)?;
+        match Self::recover(&content) {
+            Ok((state, quarantined)) => {
+                if !quarantined.is_empty() {
+                    if let Err(e) = state.quarantine(path, quarantined) {
+                        tracing::warn!("could not quarantine damaged cache entries: {:#}", e);
+                    }
+                }
+                tracing::trace!(path = %path.display(), providers = state.snapshots.len(), "read cache");
+                Ok(Some(state))
+            }
+            Err(e) => {
+                let aside = corrupt_path(path, Utc::now());
+                std::fs::rename(path, &aside)?;
+                tracing::warn!(
+                    "unreadable cache moved to {}, starting empty: {:#}",
+                    aside.display(),
+                    e
+                );
+                Ok(None)
+            }
         }
     }
 
-    pub fn save(&self) -> Result<()> {
-        let path = Self::cache_path();
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
+    /// Read state.json content, upgrading older layouts. Entries that
+    /// don't parse, such as a provider this build doesn't know, are dropped
+    /// with a warning instead of failing the whole file.
+    pub fn parse(content: &str) -> Result<Self> {
+        Self::from_value(serde_json::from_str(content)?)
+    }
+
+    /// [`parse`](Self::parse) for JSON that's already been read
+    pub fn from_value(value: Value) -> Result<Self> {
+        Ok(Self::salvage(value, Damage::default())?.0)
+    }
+
+    /// Read state.json bytes as far as they allow: invalid UTF-8 is
+    /// replaced, a file cut short is closed after its last complete value,
+    /// and each entry is parsed on its own. Returns the cache and, keyed by
+    /// where they were (e.g. "snapshots.codex"), the entries it had to drop.
+    fn recover(content: &[u8]) -> Result<(Self, Map<String, Value>)> {
+        let lossy = std::str::from_utf8(content).is_err();
+        let text = String::from_utf8_lossy(content);
+        let mut quarantined = Map::new();
+        let value = match serde_json::from_str(&text) {
+            Ok(value) => value,
+            Err(e) if e.is_eof() => {
+                let (closed, cut) = close_truncated(&text).ok_or(e)?;
+                quarantined.insert("(truncated)".to_string(), cut.into());
+                serde_json::from_str(&closed)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let (state, dropped) = Self::salvage(value, Damage { lossy })?;
+        quarantined.extend(dropped);
+        Ok((state, quarantined))
+    }
+
+    /// Build the cache from a state.json object entry by entry, returning
+    /// the entries that didn't parse. Without an `updated_at` the newest
+    /// snapshot's is used; with neither, or when it isn't an object, the
+    /// file isn't a cache.
+    fn salvage(mut value: Value, damage: Damage) -> Result<(Self, Map<String, Value>)> {
+        let Some(object) = value.as_object_mut() else {
+            bail!("expected a JSON object");
+        };
+        upgrade(object, STEPS)?;
+        let mut dropped = Map::new();
+        let snapshots: HashMap<Provider, UsageSnapshot> =
+            take_entries(object, "snapshots", damage, &mut dropped);
+        let backoff_until = take_entries(object, "backoff_until", damage, &mut dropped);
+        let last_errors = take_entries(object, "last_errors", damage, &mut dropped);
+        let events = take_list(object, "events", damage, &mut dropped);
+        let updated_at = take_entry(object, "updated_at", damage, &mut dropped)
+            .or_else(|| snapshots.values().map(|s| s.updated_at).max())
+            .context("missing updated_at")?;
+        let schema_version = object
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .map_or_else(first_version, |v| v as u32);
+        let state = Self {
+            schema_version,
+            snapshots,
+            updated_at,
+            backoff_until,
+            last_errors,
+            events,
+        };
+        Ok((state, dropped))
+    }
+
+    /// Keep `entries` in `state.json.quarantine` beside `path` for
+    /// inspection. The cache itself is left alone: readers don't hold the
+    /// lock, so the damage is only gone once the next locked save rewrites
+    /// it, and until then the same entries aren't quarantined or reported
+    /// again.
+    fn quarantine(&self, path: &Path, entries: Map<String, Value>) -> Result<()> {
+        let aside = quarantine_path(path);
+        let already = std::fs::read(&aside)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Value>(&content).ok())
+            .is_some_and(|record| record.get("entries") == Some(&Value::Object(entries.clone())));
+        if already {
+            tracing::debug!(path = %aside.display(), "damaged entries already quarantined");
+            return Ok(());
         }
+        let count = entries.len();
+        let record = serde_json::json!({
+            "quarantined_at": Utc::now(),
+            "entries": entries,
+        });
+        files::write_atomic(&aside, serde_json::to_string_pretty(&record)?.as_bytes())?;
+        tracing::warn!(
+            "cache was damaged: recovered {} snapshots, quarantined {} entries in {}",
+            self.snapshots.len(),
+            count,
+            aside.display()
+        );
+        Ok(())
+    }
+
+    /// Load, change and save the cache under its lock, so writers in other
+    /// processes can't drop each other's changes
+    pub fn update(change: impl FnOnce(&mut CacheState)) -> Result<()> {
+        Self::update_at(Backend::current(), &Self::lock_path(), change)
+    }
+
+    fn update_at(
+        backend: &Backend,
+        lock_path: &Path,
+        change: impl FnOnce(&mut CacheState),
+    ) -> Result<()> {
+        let _lock = lock_within(lock_path, LOCK_TIMEOUT)?;
+        let mut state = backend.load()?.unwrap_or_else(|| Self::empty(Utc::now()));
+        change(&mut state);
+        backend.save(&state)
+    }
+
+    /// Write the cache to the configured storage; callers changing what's
+    /// there hold the lock from loading until this returns
+    pub fn save(&self) -> Result<()> {
+        Backend::current().save(self)
+    }
 
-        // Atomic write: write to temp file, then rename
-        let temp_path = path.with_extension("tmp");
+    fn save_to(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(&temp_path, content)?;
-        std::fs::rename(&temp_path, &path)?;
+        files::write_atomic(path, content.as_bytes())?;
+        tracing::debug!(path = %path.display(), providers = self.snapshots.len(), "wrote cache");
 
         Ok(())
     }
 
+    /// Strip who each snapshot belongs to, for `redact_identity`
+    pub fn redact_identities(&mut self) {
+        for identity in self
+            .snapshots
+            .values_mut()
+            .filter_map(|s| s.identity.as_mut())
+        {
+            identity.redact();
+        }
+    }
+
+    /// Nothing cached yet
+    pub fn empty(now: DateTime<Utc>) -> Self {
+        Self {
+            schema_version: CACHE_VERSION,
+            snapshots: HashMap::new(),
+            updated_at: now,
+            backoff_until: HashMap::new(),
+            last_errors: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
     pub fn get(&self, provider: Provider) -> Option<&UsageSnapshot> {
         self.snapshots.get(&provider)
     }
+
+    /// Time since the cache was last written, see [`age`]
+    pub fn age(&self, now: DateTime<Utc>) -> Duration {
+        age(self.updated_at, now)
+    }
+
+    /// Whether the cache was last written more than `max_age` ago
+    pub fn is_stale(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        is_stale(self.updated_at, max_age, now)
+    }
+
+    /// Time since a provider's snapshot was taken; None without one
+    pub fn provider_age(&self, provider: Provider, now: DateTime<Utc>) -> Option<Duration> {
+        self.get(provider).map(|s| age(s.updated_at, now))
+    }
+
+    /// Whether a provider's snapshot is older than `max_age`; a provider
+    /// with no snapshot is stale
+    pub fn is_provider_stale(
+        &self,
+        provider: Provider,
+        max_age: Duration,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.get(provider).is_none_or(|s| s.is_stale(max_age, now))
+    }
+
+    /// Events that happened after `since`, oldest first
+    pub fn recent_events(&self, since: DateTime<Utc>) -> Vec<&UsageEvent> {
+        self.events.iter().filter(|e| e.at > since).collect()
+    }
+
+    /// Add the events from `before`'s snapshots of `fetched` providers to
+    /// this state's, dropping the oldest past [`MAX_EVENTS`]. A provider
+    /// new to the cache has nothing to compare with, so no events.
+    pub fn record_events(
+        &mut self,
+        before: Option<&CacheState>,
+        fetched: &[Provider],
+        thresholds: impl Fn(Provider) -> Thresholds,
+    ) {
+        for &provider in fetched {
+            let old = before.and_then(|b| b.get(provider));
+            if let (Some(old), Some(new)) = (old, self.snapshots.get(&provider)) {
+                self.events
+                    .extend(events::diff(old, new, thresholds(provider)));
+            }
+        }
+        let excess = self.events.len().saturating_sub(MAX_EVENTS);
+        self.events.drain(..excess);
+    }
+
+    /// The retry time for a provider if its backoff has not yet passed
+    pub fn backoff_for(&self, provider: Provider, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.backoff_until
+            .get(&provider)
+            .copied()
+            .filter(|until| *until > now)
+    }
+}
+
+/// Just enough of state.json to pick out one provider's snapshot, which is
+/// left unparsed until then
+#[derive(Deserialize)]
+struct Minimal<'a> {
+    #[serde(default = "first_version")]
+    schema_version: u32,
+    #[serde(borrow)]
+    snapshots: HashMap<&'a str, &'a RawValue>,
+    /// Required, as by the full read, though not used
+    updated_at: DateTime<Utc>,
+}
+
+/// One provider's snapshot from state.json at `path`, parsing only that
+/// snapshot and never the config: the fast path for `get`. A file this can't
+/// read as the current layout, such as an older or damaged one, goes through
+/// [`CacheState::load_from`] instead, so the answer is always the full
+/// read's; only the fields around the snapshots aren't checked.
+pub fn read_minimal(path: &Path, provider: Provider) -> Result<Option<UsageSnapshot>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if let Ok(minimal) = serde_json::from_str::<Minimal>(&content) {
+        if minimal.schema_version > STEPS.len() as u32 {
+            // As in `parse`, a snapshot that doesn't parse counts as missing
+            return Ok(minimal
+                .snapshots
+                .get(provider.id())
+                .and_then(|raw| serde_json::from_str(raw.get()).ok()));
+        }
+    }
+    tracing::debug!(path = %path.display(), "reading the whole cache");
+    Ok(CacheState::load_from(path)?.and_then(|mut state| state.snapshots.remove(&provider)))
+}
+
+/// Where the cache and history are kept, from `[general] storage`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// state.json at this path, with history.jsonl beside it
+    Json(PathBuf),
+    /// A database at this path holding the cache, events and history
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+}
+
+impl Backend {
+    /// The configured backend, settled once per process, so a change of
+    /// storage applies from the next start. Only `storage` is read from the
+    /// config, and without SQLite support not even that: `doctor` reports a
+    /// config asking for it.
+    pub fn current() -> &'static Backend {
+        static CURRENT: OnceLock<Backend> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let storage = match cfg!(feature = "sqlite") {
+                true => Config::storage(),
+                false => Storage::Json,
+            };
+            Self::for_storage(storage, &crate::paths::cache_dir().path)
+        })
+    }
+
+    /// The backend for `storage` with its files in `dir`. Asking for SQLite
+    /// in a build without it falls back to the JSON files, with a warning.
+    pub fn for_storage(storage: Storage, dir: &Path) -> Self {
+        match storage {
+            Storage::Json => Backend::Json(dir.join("state.json")),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite => Backend::Sqlite(dir.join("quotabar.db")),
+            #[cfg(not(feature = "sqlite"))]
+            Storage::Sqlite => {
+                tracing::warn!(
+                    "storage = \"sqlite\" needs a build with the sqlite feature, using JSON files"
+                );
+                Backend::Json(dir.join("state.json"))
+            }
+        }
+    }
+
+    /// state.json or quotabar.db
+    pub fn path(&self) -> &Path {
+        match self {
+            Backend::Json(path) => path,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => path,
+        }
+    }
+
+    /// The files a save changes, main file first, for noticing writes from
+    /// other processes
+    pub fn files(&self) -> Vec<PathBuf> {
+        match self {
+            Backend::Json(path) => vec![path.clone()],
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => {
+                let mut wal = path.as_os_str().to_os_string();
+                wal.push("-wal");
+                vec![path.clone(), PathBuf::from(wal)]
+            }
+        }
+    }
+
+    /// Whether a cache has been saved here
+    pub fn exists(&self) -> Result<bool> {
+        match self {
+            Backend::Json(path) => Ok(path.exists()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => Ok(path.exists() && Db::open(path)?.load_state()?.is_some()),
+        }
+    }
+
+    pub fn load(&self) -> Result<Option<CacheState>> {
+        match self {
+            Backend::Json(path) => CacheState::load_from(path),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => {
+                if !path.exists() && !path.with_file_name("state.json").exists() {
+                    tracing::debug!(path = %path.display(), "no cache database yet");
+                    return Ok(None);
+                }
+                let state = Db::open(path)?.load_state()?;
+                tracing::trace!(path = %path.display(), providers = state.as_ref().map(|s| s.snapshots.len()), "read cache");
+                Ok(state)
+            }
+        }
+    }
+
+    pub fn save(&self, state: &CacheState) -> Result<()> {
+        match self {
+            Backend::Json(path) => state.save_to(path),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => {
+                Db::open(path)?.save_state(state)?;
+                tracing::debug!(path = %path.display(), providers = state.snapshots.len(), "wrote cache");
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the cache, leaving history alone; false when there was none.
+    /// Callers hold the cache lock so a refresh in progress doesn't write it
+    /// straight back.
+    pub fn clear(&self) -> Result<bool> {
+        match self {
+            Backend::Json(path) => match std::fs::remove_file(path) {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e).with_context(|| format!("Failed to delete {}", path.display())),
+            },
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => match path.exists() {
+                true => Db::open(path)?.clear_state(),
+                false => Ok(false),
+            },
+        }
+    }
+}
+
+/// Every backend this build has, each with its files in its own directory
+/// under `dir`, for running storage tests against all of them
+#[cfg(test)]
+pub fn test_backends(dir: &Path) -> Vec<Backend> {
+    vec![
+        Backend::Json(dir.join("json").join("state.json")),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(dir.join("sqlite").join("quotabar.db")),
+    ]
+}
+
+/// Time from `updated_at` to `now`, or None for a timestamp ahead of `now`,
+/// written while some clock was off. Such data is never trusted as recent:
+/// it is stale to the bars and popup, and never fresh enough to skip a fetch.
+pub fn checked_age(updated_at: DateTime<Utc>, now: DateTime<Utc>) -> Option<Duration> {
+    (now - updated_at).to_std().ok()
+}
+
+/// Time from `updated_at` to `now` for display; a timestamp ahead of `now`
+/// shows as just taken
+pub fn age(updated_at: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    checked_age(updated_at, now).unwrap_or(Duration::ZERO)
+}
+
+/// Whether data taken at `updated_at` is older than `max_age`, see
+/// [`checked_age`]. This is what the bars and popup mean by stale; deciding
+/// whether to refetch is [`crate::fetch::is_fresh`]'s job.
+pub fn is_stale(updated_at: DateTime<Utc>, max_age: Duration, now: DateTime<Utc>) -> bool {
+    checked_age(updated_at, now).is_none_or(|age| age > max_age)
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// Apply the `steps` a state.json object still needs and stamp it with the
+/// version they reach. A file from a newer quotabar is read as it is, which
+/// the serde defaults usually allow.
+fn upgrade(object: &mut Map<String, Value>, steps: &[Step]) -> Result<()> {
+    let version = match object.get("schema_version") {
+        None => first_version(),
+        Some(value) => match value.as_u64() {
+            Some(v) if v >= 1 => v as u32,
+            _ => bail!(
+                "schema_version must be a whole number from 1, got {}",
+                value
+            ),
+        },
+    };
+    let current = steps.len() as u32 + 1;
+    if version >= current {
+        return Ok(());
+    }
+    for step in &steps[(version - 1) as usize..] {
+        step(object);
+    }
+    object.insert("schema_version".to_string(), current.into());
+    Ok(())
+}
+
+/// Version 1 kept each snapshot's windows in fixed slots; version 2 lists
+/// them in order, each with its kind
+fn windows_from_slots(object: &mut Map<String, Value>) {
+    if let Some(Value::Object(snapshots)) = object.get_mut("snapshots") {
+        for snapshot in snapshots.values_mut().filter_map(Value::as_object_mut) {
+            slots_to_windows(snapshot);
+        }
+    }
+}
+
+/// Move a snapshot's `primary`, `secondary`, `tertiary` and `oauth_apps`
+/// into a `windows` list, giving each window the kind its slot meant. One
+/// that already has the list is left alone, so mock files can be either.
+pub fn slots_to_windows(snapshot: &mut Map<String, Value>) {
+    if snapshot.contains_key("windows") {
+        return;
+    }
+    let mut windows = Vec::new();
+    for slot in ["primary", "secondary", "tertiary", "oauth_apps"] {
+        let mut window = match snapshot.remove(slot) {
+            None | Some(Value::Null) => continue,
+            Some(window) => window,
+        };
+        // Anything but an object is left to fail parsing with the snapshot
+        if let Some(fields) = window.as_object_mut() {
+            let kind = slot_kind(slot, fields);
+            fields.insert("kind".to_string(), serde_json::json!(kind));
+        }
+        windows.push(window);
+    }
+    snapshot.insert("windows".to_string(), Value::Array(windows));
+}
+
+/// The kind of the window cached in `slot`: the session and week by their
+/// length, Claude's model week by its label (Sonnet unless it says Opus),
+/// and the monthly windows of Windsurf and Kiro, which have no length
+fn slot_kind(slot: &str, window: &Map<String, Value>) -> WindowKind {
+    let label = window
+        .get("label")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_lowercase();
+    let minutes = window.get("window_minutes").and_then(Value::as_i64);
+    match (slot, minutes) {
+        ("tertiary", _) if label.contains("opus") => WindowKind::Model("opus".to_string()),
+        ("tertiary", _) => WindowKind::Model("sonnet".to_string()),
+        ("oauth_apps", _) => WindowKind::Other("oauth_apps".to_string()),
+        (_, Some(minutes)) => WindowKind::from_minutes(minutes as i32),
+        _ if label.starts_with("monthly") => WindowKind::Other("monthly".to_string()),
+        ("primary", None) => WindowKind::Session,
+        _ => WindowKind::Weekly,
+    }
+}
+
+/// How a state.json was damaged beyond what parsing shows
+#[derive(Debug, Clone, Copy, Default)]
+struct Damage {
+    /// Not UTF-8, and decoded with replacement characters
+    lossy: bool,
+}
+
+impl Damage {
+    /// Why an entry that parsed still can't be trusted: only a lossy
+    /// decode puts replacement characters in it
+    fn taints(self, value: &Value) -> Option<&'static str> {
+        (self.lossy && value.to_string().contains(char::REPLACEMENT_CHARACTER))
+            .then_some("not valid UTF-8")
+    }
+}
+
+/// Parse one entry of a state.json object, moving it to `dropped` under
+/// `key` when it doesn't parse
+fn parse_entry<T: DeserializeOwned>(
+    key: String,
+    value: Value,
+    damage: Damage,
+    dropped: &mut Map<String, Value>,
+) -> Option<T> {
+    let parsed = match damage.taints(&value) {
+        Some(reason) => Err(reason.to_string()),
+        None => serde_json::from_value(value.clone()).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|e| drop_entry(key, value, e, dropped)).ok()
+}
+
+fn drop_entry(key: String, value: Value, reason: impl Display, dropped: &mut Map<String, Value>) {
+    tracing::warn!("dropping cached {}: {}", key, reason);
+    dropped.insert(key, value);
+}
+
+/// `field` of a state.json object, if it's there and parses
+fn take_entry<T: DeserializeOwned>(
+    object: &mut Map<String, Value>,
+    field: &str,
+    damage: Damage,
+    dropped: &mut Map<String, Value>,
+) -> Option<T> {
+    let value = object.remove(field)?;
+    parse_entry(field.to_string(), value, damage, dropped)
+}
+
+/// `field` of a state.json object, a map keyed by provider, keeping the
+/// entries that parse
+fn take_entries<T: DeserializeOwned>(
+    object: &mut Map<String, Value>,
+    field: &str,
+    damage: Damage,
+    dropped: &mut Map<String, Value>,
+) -> HashMap<Provider, T> {
+    let entries = match object.remove(field) {
+        None => return HashMap::new(),
+        Some(Value::Object(entries)) => entries,
+        Some(other) => {
+            parse_entry::<Map<String, Value>>(field.to_string(), other, damage, dropped);
+            return HashMap::new();
+        }
+    };
+    entries
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let name = format!("{}.{}", field, key);
+            match serde_json::from_value::<Provider>(Value::String(key)) {
+                Ok(provider) => parse_entry(name, value, damage, dropped).map(|v| (provider, v)),
+                Err(e) => {
+                    drop_entry(name, value, e, dropped);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// `field` of a state.json object, a list, keeping the items that parse
+fn take_list<T: DeserializeOwned>(
+    object: &mut Map<String, Value>,
+    field: &str,
+    damage: Damage,
+    dropped: &mut Map<String, Value>,
+) -> Vec<T> {
+    let items = match object.remove(field) {
+        None => return Vec::new(),
+        Some(Value::Array(items)) => items,
+        Some(other) => {
+            parse_entry::<Vec<Value>>(field.to_string(), other, damage, dropped);
+            return Vec::new();
+        }
+    };
+    items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, item)| parse_entry(format!("{}[{}]", field, i), item, damage, dropped))
+        .collect()
+}
+
+/// JSON cut short, closed after its last complete value by closing the
+/// brackets still open there. Returns the closed text and what was cut
+/// off, or None when nothing inside the top level was complete.
+fn close_truncated(text: &str) -> Option<(String, &str)> {
+    let mut open = Vec::new();
+    let mut cut = None;
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                open.push(if c == '{' { '}' } else { ']' });
+                cut = Some((i + 1, open.clone()));
+            }
+            '}' | ']' => {
+                open.pop();
+                cut = Some((i + 1, open.clone()));
+            }
+            // Whatever came before a comma is complete
+            ',' => cut = Some((i, open.clone())),
+            _ => {}
+        }
+    }
+    let (at, open) = cut.filter(|(_, open)| !open.is_empty())?;
+    let mut closed = text[..at].to_string();
+    closed.extend(open.iter().rev());
+    Some((closed, &text[at..]))
+}
+
+/// `state.json.quarantine`, beside the file
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".quarantine");
+    path.with_file_name(name)
+}
+
+/// `state.json.corrupt-20261017T184500`, beside the file
+fn corrupt_path(path: &Path, now: DateTime<Utc>) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", now.format("%Y%m%dT%H%M%S")));
+    path.with_file_name(name)
+}
+
+/// [`CacheLock::acquire_within`], failing with a warning on timeout
+fn lock_within(path: &Path, timeout: Duration) -> Result<CacheLock> {
+    lock_or_warn(CacheLock::acquire_within(path, timeout)?, path, timeout)
+}
+
+fn lock_or_warn(lock: Option<CacheLock>, path: &Path, timeout: Duration) -> Result<CacheLock> {
+    match lock {
+        Some(lock) => Ok(lock),
+        None => {
+            tracing::warn!(
+                "cache lock {} still held after {:?}",
+                path.display(),
+                timeout
+            );
+            bail!("timed out waiting for the cache lock")
+        }
+    }
+}
+
+/// Advisory lock on the cache, held across read-check-write sequences.
+/// Released when dropped.
+#[derive(Debug)]
+pub struct CacheLock {
+    _file: File,
+}
+
+impl CacheLock {
+    fn open(path: &Path) -> Result<File> {
+        if let Some(parent) = path.parent() {
+            files::create_private_dir(parent)?;
+        }
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?)
+    }
+
+    /// Retry the lock until `timeout` passes; None if it never came free
+    pub fn acquire_within(path: &Path, timeout: Duration) -> Result<Option<Self>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(Some(lock));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(LOCK_POLL.min(deadline - Instant::now()));
+        }
+    }
+
+    /// [`acquire_within`](Self::acquire_within) for async callers: waits on
+    /// the runtime's timer, so other tasks keep running meanwhile
+    pub async fn acquire_within_async(path: &Path, timeout: Duration) -> Result<Option<Self>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(Some(lock));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(LOCK_POLL.min(deadline - Instant::now())).await;
+        }
+    }
+
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = Self::open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Parse a fixture, then check writing and reading it back changes nothing
+    fn round_trip(content: &str) -> CacheState {
+        let state = CacheState::parse(content).unwrap();
+        let written = serde_json::to_string(&state).unwrap();
+        let reread = CacheState::parse(&written).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reread).unwrap(),
+            serde_json::to_value(&state).unwrap()
+        );
+        state
+    }
+
+    #[test]
+    fn test_loads_the_first_layout() {
+        let state = round_trip(include_str!("../tests/fixtures/cache/v1-baseline.json"));
+        assert_eq!(state.schema_version, CACHE_VERSION);
+        assert_eq!(state.snapshots.len(), 2);
+        let claude = &state.snapshots[&Provider::Claude];
+        assert_eq!(claude.primary().unwrap().used_percent, 31.0);
+        assert_eq!(claude.secondary().unwrap().used_percent, 51.0);
+        assert!(claude.model_window().is_none());
+        let codex = &state.snapshots[&Provider::Codex];
+        assert_eq!(codex.windows.len(), 1);
+        assert_eq!(codex.windows[0].kind, WindowKind::Session);
+        assert!(
+            !state.snapshots[&Provider::Codex]
+                .cost
+                .as_ref()
+                .unwrap()
+                .extra_usage
+        );
+        assert!(state.last_errors.is_empty());
+    }
+
+    #[test]
+    fn test_loads_unversioned_files_and_drops_unknown_providers() {
+        let state = round_trip(include_str!("../tests/fixtures/cache/v1-unversioned.json"));
+        assert_eq!(state.schema_version, CACHE_VERSION);
+        assert_eq!(
+            state.snapshots.keys().collect::<Vec<_>>(),
+            [&Provider::Claude]
+        );
+        assert_eq!(state.last_errors[&Provider::Codex].reason, "HTTP 429");
+        assert!(state.backoff_until.contains_key(&Provider::Codex));
+        assert_eq!(
+            state.snapshots[&Provider::Claude]
+                .rate_limit
+                .as_ref()
+                .unwrap()
+                .remaining,
+            Some(97)
+        );
+    }
+
+    #[test]
+    fn test_window_slots_become_kinded_windows() {
+        let window = |minutes: Value, label: Value| {
+            serde_json::json!({
+                "used_percent": 10.0,
+                "window_minutes": minutes,
+                "resets_at": null,
+                "reset_description": null,
+                "label": label,
+            })
+        };
+        let state = serde_json::json!({
+            "snapshots": {
+                "claude": {
+                    "provider": "claude",
+                    "primary": null,
+                    "secondary": window(10080.into(), Value::Null),
+                    "tertiary": window(10080.into(), "Weekly — Opus".into()),
+                    "oauth_apps": window(10080.into(), "Weekly — OAuth apps".into()),
+                    "cost": null,
+                    "identity": null,
+                    "updated_at": "2025-11-02T15:00:00Z"
+                },
+                "kiro": {
+                    "provider": "kiro",
+                    "primary": window(Value::Null, "Monthly interactions".into()),
+                    "secondary": null,
+                    "tertiary": null,
+                    "cost": null,
+                    "identity": null,
+                    "updated_at": "2025-11-02T15:00:00Z"
+                }
+            },
+            "updated_at": "2025-11-02T15:00:00Z"
+        });
+        let state = CacheState::parse(&state.to_string()).unwrap();
+        let kinds = |provider| -> Vec<WindowKind> {
+            state.snapshots[&provider]
+                .windows
+                .iter()
+                .map(|w| w.kind.clone())
+                .collect()
+        };
+        assert_eq!(
+            kinds(Provider::Claude),
+            [
+                WindowKind::Weekly,
+                WindowKind::Model("opus".to_string()),
+                WindowKind::Other("oauth_apps".to_string()),
+            ]
+        );
+        assert_eq!(
+            state.snapshots[&Provider::Claude].windows[1].name(),
+            "Weekly — Opus"
+        );
+        assert_eq!(
+            kinds(Provider::Kiro),
+            [WindowKind::Other("monthly".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_upgrade_runs_the_missing_steps() {
+        let steps: &[Step] = &[
+            |o| {
+                o.insert("first".to_string(), true.into());
+            },
+            |o| {
+                o.insert("second".to_string(), true.into());
+            },
+        ];
+        let object = |json: &str| -> Map<String, Value> { serde_json::from_str(json).unwrap() };
+
+        let mut unversioned = object("{}");
+        upgrade(&mut unversioned, steps).unwrap();
+        assert_eq!(
+            Value::Object(unversioned),
+            serde_json::json!({"first": true, "second": true, "schema_version": 3})
+        );
+
+        let mut two = object(r#"{"schema_version": 2}"#);
+        upgrade(&mut two, steps).unwrap();
+        assert_eq!(
+            Value::Object(two),
+            serde_json::json!({"second": true, "schema_version": 3})
+        );
+
+        // Current and newer files are left alone
+        for json in [r#"{"schema_version": 3}"#, r#"{"schema_version": 7}"#] {
+            let mut current = object(json);
+            upgrade(&mut current, steps).unwrap();
+            assert_eq!(current, object(json));
+        }
+        assert!(upgrade(&mut object(r#"{"schema_version": "2"}"#), steps).is_err());
+        assert!(upgrade(&mut object(r#"{"schema_version": 0}"#), steps).is_err());
+    }
+
+    #[test]
+    fn test_unreadable_cache_is_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        assert!(CacheState::load_from(&path).unwrap().is_none());
+
+        for content in ["{not json", "[]", r#"{"snapshots": {}}"#] {
+            std::fs::write(&path, content).unwrap();
+            assert!(
+                CacheState::load_from(&path).unwrap().is_none(),
+                "{}",
+                content
+            );
+            assert!(!path.exists());
+            let aside: Vec<String> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            assert_eq!(aside.len(), 1, "{:?}", aside);
+            assert!(aside[0].starts_with("state.json.corrupt-"), "{:?}", aside);
+            assert_eq!(
+                std::fs::read_to_string(dir.path().join(&aside[0])).unwrap(),
+                content
+            );
+            std::fs::remove_file(dir.path().join(&aside[0])).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_damaged_caches_keep_what_parses() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let quarantine = dir.path().join("state.json.quarantine");
+        let fixtures: [(&str, &[u8], &[&str]); 3] = [
+            (
+                "bad entry",
+                include_bytes!("../tests/fixtures/cache/damaged-entry.json"),
+                &["snapshots.codex"],
+            ),
+            (
+                "truncated",
+                include_bytes!("../tests/fixtures/cache/truncated.json"),
+                &["(truncated)", "snapshots.codex"],
+            ),
+            (
+                "invalid UTF-8",
+                include_bytes!("../tests/fixtures/cache/invalid-utf8.json"),
+                &["snapshots.codex"],
+            ),
+        ];
+        for (name, content, expected) in fixtures {
+            std::fs::write(&path, content).unwrap();
+            let state = CacheState::load_from(&path).unwrap().unwrap();
+            assert_eq!(
+                state.snapshots.keys().collect::<Vec<_>>(),
+                [&Provider::Claude],
+                "{}",
+                name
+            );
+            let claude = &state.snapshots[&Provider::Claude];
+            assert_eq!(claude.secondary().unwrap().used_percent, 51.0);
+            assert_eq!(state.updated_at, claude.updated_at);
+
+            let record: Value =
+                serde_json::from_str(&std::fs::read_to_string(&quarantine).unwrap()).unwrap();
+            let entries = record["entries"].as_object().unwrap();
+            assert_eq!(entries.keys().collect::<Vec<_>>(), expected, "{}", name);
+            assert_eq!(entries["snapshots.codex"]["provider"], "codex");
+
+            // Reading never writes the cache, and the same damage isn't
+            // quarantined again
+            assert_eq!(std::fs::read(&path).unwrap(), content, "{}", name);
+            let modified = std::fs::metadata(&quarantine).unwrap().modified().unwrap();
+            let again = CacheState::load_from(&path).unwrap().unwrap();
+            assert_eq!(again.snapshots.len(), 1);
+            assert_eq!(
+                std::fs::metadata(&quarantine).unwrap().modified().unwrap(),
+                modified,
+                "{}",
+                name
+            );
+
+            // The next save drops the damage for good
+            std::fs::remove_file(&quarantine).unwrap();
+            again.save_to(&path).unwrap();
+            CacheState::load_from(&path).unwrap().unwrap();
+            assert!(!quarantine.exists(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_salvage_drops_single_entries() {
+        let (state, dropped) = CacheState::salvage(
+            serde_json::json!({
+                "snapshots": {"gemini": {}},
+                "backoff_until": {"claude": "soon", "codex": "2026-10-17T18:45:00Z"},
+                "events": [{"kind": "sideways"}],
+                "updated_at": "2026-10-17T18:45:00Z",
+            }),
+            Damage::default(),
+        )
+        .unwrap();
+        assert!(state.snapshots.is_empty() && state.events.is_empty());
+        assert_eq!(
+            state.backoff_until.keys().collect::<Vec<_>>(),
+            [&Provider::Codex]
+        );
+        assert_eq!(
+            dropped.keys().collect::<Vec<_>>(),
+            ["backoff_until.claude", "events[0]", "snapshots.gemini"]
+        );
+        assert_eq!(dropped["backoff_until.claude"], "soon");
+
+        // A field of the wrong shape goes whole
+        let (_, dropped) = CacheState::salvage(
+            serde_json::json!({"snapshots": [], "updated_at": "2026-10-17T18:45:00Z"}),
+            Damage::default(),
+        )
+        .unwrap();
+        assert_eq!(dropped["snapshots"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_close_truncated() {
+        let closed = |text| close_truncated(text).map(|(closed, cut)| (closed, cut.to_string()));
+        assert_eq!(
+            closed(r#"{"a": 1, "b": [2, 3"#),
+            Some((r#"{"a": 1, "b": [2]}"#.to_string(), ", 3".to_string()))
+        );
+        assert_eq!(
+            closed(r#"{"a": {"x": "}],\""}, "b": tr"#),
+            Some((
+                r#"{"a": {"x": "}],\""}}"#.to_string(),
+                r#", "b": tr"#.to_string()
+            ))
+        );
+        assert_eq!(
+            closed(r#"{"snapshots": {"#),
+            Some((r#"{"snapshots": {}}"#.to_string(), String::new()))
+        );
+        assert_eq!(
+            closed(r#"{"snap"#),
+            Some(("{}".to_string(), r#""snap"#.to_string()))
+        );
+        assert_eq!(closed("   "), None);
+        assert_eq!(closed("{}"), None);
+    }
+
+    #[test]
+    fn test_age() {
+        let now = Utc::now();
+        let minutes = |m| now - chrono::Duration::minutes(m);
+        assert_eq!(age(minutes(5), now), Duration::from_secs(300));
+        assert_eq!(age(now, now), Duration::ZERO);
+        // A clock running ahead doesn't make data older than new
+        assert_eq!(age(now + chrono::Duration::minutes(5), now), Duration::ZERO);
+
+        let fifteen = Duration::from_secs(15 * 60);
+        assert!(!is_stale(minutes(15), fifteen, now));
+        assert!(is_stale(minutes(16), fifteen, now));
+        // Skewed data is stale, as it is never fresh to a fetch
+        let skewed = now + chrono::Duration::hours(1);
+        assert!(is_stale(skewed, fifteen, now));
+        assert!(!crate::fetch::is_fresh(skewed, now, fifteen));
+        assert_eq!(age(skewed, now), Duration::ZERO);
+        assert!(!is_stale(now, Duration::ZERO, now));
+    }
+
+    #[test]
+    fn test_provider_age() {
+        let now = Utc::now();
+        let mut state = CacheState::empty(now - chrono::Duration::minutes(10));
+        let mut snapshot = crate::mock::mock_snapshots()[&Provider::Claude].clone();
+        snapshot.updated_at = now - chrono::Duration::minutes(30);
+        state.snapshots.insert(Provider::Claude, snapshot);
+
+        let twenty = Duration::from_secs(20 * 60);
+        assert_eq!(state.age(now), Duration::from_secs(600));
+        assert!(!state.is_stale(twenty, now));
+        assert_eq!(
+            state.provider_age(Provider::Claude, now),
+            Some(Duration::from_secs(1800))
+        );
+        assert!(state.is_provider_stale(Provider::Claude, twenty, now));
+        assert_eq!(state.provider_age(Provider::Codex, now), None);
+        assert!(state.is_provider_stale(Provider::Codex, twenty, now));
+    }
+
+    #[test]
+    fn test_record_events() {
+        let now = Utc::now();
+        let claude = |used: f64, at| {
+            let mut snapshot = crate::mock::mock_snapshots()[&Provider::Claude].clone();
+            snapshot.windows[0].used_percent = used;
+            snapshot.updated_at = at;
+            snapshot
+        };
+        let state_with = |used, at| {
+            let mut state = CacheState::empty(at);
+            state.snapshots.insert(Provider::Claude, claude(used, at));
+            state
+        };
+
+        let earlier = now - chrono::Duration::minutes(10);
+        let mut before = state_with(50.0, earlier);
+        // Only providers fetched this time are compared
+        let mut state = state_with(80.0, now);
+        state.record_events(Some(&before), &[Provider::Codex], |_| Thresholds::default());
+        assert!(state.events.is_empty());
+        state.record_events(None, &[Provider::Claude], |_| Thresholds::default());
+        assert!(state.events.is_empty());
+
+        state.record_events(Some(&before), &[Provider::Claude], |_| {
+            Thresholds::default()
+        });
+        assert_eq!(state.events.len(), 1);
+        assert_eq!(state.events[0].kind, events::EventKind::CrossedWarning);
+        assert_eq!(state.recent_events(earlier).len(), 1);
+        assert!(state.recent_events(now).is_empty());
+
+        // Survives a save, and the oldest go first
+        let dir = tempfile::tempdir().unwrap();
+        for backend in test_backends(dir.path()) {
+            backend.save(&state).unwrap();
+            assert_eq!(backend.load().unwrap().unwrap().events, state.events);
+        }
+        before.events = vec![state.events[0].clone(); MAX_EVENTS];
+        before.events[0].window = "oldest".to_string();
+        let mut next = state_with(95.0, now);
+        next.events = before.events.clone();
+        next.record_events(Some(&state), &[Provider::Claude], |_| Thresholds::default());
+        assert_eq!(next.events.len(), MAX_EVENTS);
+        assert!(next.events.iter().all(|e| e.window != "oldest"));
+        assert_eq!(
+            next.events.last().unwrap().kind,
+            events::EventKind::CrossedCritical
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_saved_cache_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for backend in test_backends(&dir.path().join("quotabar")) {
+            backend.save(&CacheState::empty(Utc::now())).unwrap();
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            for file in backend.files().iter().filter(|f| f.exists()) {
+                assert_eq!(mode(file), 0o600, "{}", file.display());
+            }
+            assert_eq!(mode(backend.path().parent().unwrap()), 0o700);
+        }
+    }
+
+    #[test]
+    fn test_each_backend_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state =
+            CacheState::parse(include_str!("../tests/fixtures/cache/v1-unversioned.json")).unwrap();
+        let before = state.clone();
+        let claude = state.snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.updated_at += chrono::Duration::hours(1);
+        claude.windows[0].used_percent = 95.0;
+        state.record_events(Some(&before), &[Provider::Claude], |_| {
+            Thresholds::default()
+        });
+        assert!(!state.events.is_empty());
+
+        for backend in test_backends(dir.path()) {
+            assert!(backend.load().unwrap().is_none(), "{:?}", backend);
+            assert!(!backend.exists().unwrap());
+            backend.save(&state).unwrap();
+            assert!(backend.exists().unwrap());
+            let loaded = backend.load().unwrap().unwrap();
+            assert_eq!(
+                serde_json::to_value(&loaded).unwrap(),
+                serde_json::to_value(&state).unwrap(),
+                "{:?}",
+                backend
+            );
+            // A save replaces everything, including providers that are gone
+            backend.save(&CacheState::empty(state.updated_at)).unwrap();
+            let loaded = backend.load().unwrap().unwrap();
+            assert!(loaded.snapshots.is_empty() && loaded.last_errors.is_empty());
+            assert!(loaded.backoff_until.is_empty() && loaded.events.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_read_minimal_matches_full_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let mocked = CacheState {
+            snapshots: crate::mock::mock_snapshots(),
+            ..CacheState::empty(Utc::now())
+        };
+        let updated_at = r#""updated_at": "2026-10-17T18:45:00Z""#;
+        let contents = [
+            include_str!("../tests/fixtures/cache/v1-baseline.json").to_string(),
+            include_str!("../tests/fixtures/cache/v1-unversioned.json").to_string(),
+            serde_json::to_string_pretty(&mocked).unwrap(),
+            // Damaged, so read in full and moved aside
+            "{not json".to_string(),
+            format!(
+                r#"{{"schema_version": 0, "snapshots": {{}}, {}}}"#,
+                updated_at
+            ),
+            r#"{"snapshots": {}}"#.to_string(),
+            // Snapshots the full read drops
+            format!(
+                r#"{{"snapshots": {{"claude": 5, "gemini": {{}}}}, {}}}"#,
+                updated_at
+            ),
+            // A key the quick read can't borrow
+            format!(
+                r#"{{"snapshots": {{"cl\u0061ude": {}}}, {}}}"#,
+                serde_json::to_string(&mocked.snapshots[&Provider::Claude]).unwrap(),
+                updated_at
+            ),
+        ];
+        for (i, content) in contents.iter().enumerate() {
+            for provider in Provider::ALL {
+                let (quick, full) = (dir.path().join("quick.json"), dir.path().join("full.json"));
+                std::fs::write(&quick, content).unwrap();
+                std::fs::write(&full, content).unwrap();
+                let expected = CacheState::load_from(&full)
+                    .unwrap()
+                    .and_then(|s| s.snapshots.get(&provider).cloned());
+                let actual = read_minimal(&quick, provider).unwrap();
+                assert_eq!(
+                    serde_json::to_value(&actual).unwrap(),
+                    serde_json::to_value(&expected).unwrap(),
+                    "content {} {:?}",
+                    i,
+                    provider
+                );
+                assert_eq!(quick.exists(), full.exists(), "content {}", i);
+                for entry in std::fs::read_dir(dir.path()).unwrap() {
+                    std::fs::remove_file(entry.unwrap().path()).unwrap();
+                }
+            }
+        }
+        assert!(
+            read_minimal(&dir.path().join("state.json"), Provider::Claude)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        for backend in test_backends(dir.path()) {
+            assert!(!backend.clear().unwrap(), "{:?}", backend);
+            backend.save(&CacheState::empty(Utc::now())).unwrap();
+            assert!(backend.clear().unwrap(), "{:?}", backend);
+            assert!(!backend.exists().unwrap());
+            assert!(backend.load().unwrap().is_none());
+            assert!(!backend.clear().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_for_storage() {
+        let dir = Path::new("/c/quotabar");
+        assert_eq!(
+            Backend::for_storage(Storage::Json, dir),
+            Backend::Json(dir.join("state.json"))
+        );
+        #[cfg(feature = "sqlite")]
+        assert_eq!(
+            Backend::for_storage(Storage::Sqlite, dir),
+            Backend::Sqlite(dir.join("quotabar.db"))
+        );
+        #[cfg(not(feature = "sqlite"))]
+        assert_eq!(
+            Backend::for_storage(Storage::Sqlite, dir),
+            Backend::Json(dir.join("state.json"))
+        );
+    }
+
+    #[test]
+    fn test_corrupt_path() {
+        let now = DateTime::parse_from_rfc3339("2026-10-17T18:45:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            corrupt_path(Path::new("/c/quotabar/state.json"), now),
+            Path::new("/c/quotabar/state.json.corrupt-20261017T184500")
+        );
+    }
+
+    #[test]
+    fn test_redacted_cache_has_no_identity() {
+        let mut state = CacheState {
+            snapshots: crate::mock::mock_snapshots(),
+            ..CacheState::empty(Utc::now())
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("user@example.com"));
+
+        state.redact_identities();
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(!json.contains("user@example.com"), "{}", json);
+        assert!(!json.contains("Personal"), "{}", json);
+        // The plan isn't identifying and the popup still labels it
+        assert!(json.contains("\"plan\":\"Max\""), "{}", json);
+    }
+
+    #[test]
+    fn test_lock_contention() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.lock");
+
+        let held = CacheLock::try_acquire(&path).unwrap().unwrap();
+        assert!(CacheLock::try_acquire(&path).unwrap().is_none());
+
+        drop(held);
+        let again = CacheLock::try_acquire(&path).unwrap();
+        assert!(again.is_some());
+    }
+
+    #[test]
+    fn test_bounded_wait() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.lock");
+        let held = CacheLock::try_acquire(&path).unwrap().unwrap();
+
+        let started = Instant::now();
+        assert!(CacheLock::acquire_within(&path, Duration::from_millis(120))
+            .unwrap()
+            .is_none());
+        assert!(started.elapsed() >= Duration::from_millis(120));
+        assert!(lock_within(&path, Duration::ZERO).is_err());
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(held);
+        });
+        assert!(CacheLock::acquire_within(&path, Duration::from_secs(5))
+            .unwrap()
+            .is_some());
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_updates_lose_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        for backend in test_backends(dir.path()) {
+            let lock_path = backend.path().with_file_name("state.lock");
+            let writers = [
+                Provider::Claude,
+                Provider::Codex,
+                Provider::OpenCode,
+                Provider::Kiro,
+            ];
+
+            let threads: Vec<_> = writers
+                .into_iter()
+                .map(|provider| {
+                    let (backend, lock_path) = (backend.clone(), lock_path.clone());
+                    std::thread::spawn(move || {
+                        let mut snapshot = crate::mock::mock_snapshots()
+                            .remove(&Provider::Claude)
+                            .unwrap();
+                        snapshot.provider = provider;
+                        for round in 0..25 {
+                            snapshot.windows[0].used_percent = round as f64;
+                            let snapshot = snapshot.clone();
+                            CacheState::update_at(&backend, &lock_path, |state| {
+                                state.snapshots.insert(provider, snapshot);
+                            })
+                            .unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            let state = backend.load().unwrap().unwrap();
+            assert_eq!(state.snapshots.len(), writers.len(), "{:?}", backend);
+            for provider in writers {
+                let last = &state.snapshots[&provider].windows[0];
+                assert_eq!(last.used_percent, 24.0, "{:?} {:?}", backend, provider);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blocking_lock_waits_for_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.lock");
+        let held = CacheLock::try_acquire(&path).unwrap().unwrap();
+
+        let waiter_path = path.clone();
+        let waiter = std::thread::spawn(move || {
+            let started = std::time::Instant::now();
+            let _lock = lock_within(&waiter_path, LOCK_TIMEOUT).unwrap();
+            started.elapsed()
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        drop(held);
+        let waited = waiter.join().unwrap();
+        assert!(waited >= std::time::Duration::from_millis(90));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_async_wait_leaves_runtime_free() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.lock");
+        let _held = CacheLock::try_acquire(&path).unwrap().unwrap();
+
+        let ticks = tokio::spawn(async {
+            let mut ticks = 0;
+            for _ in 0..5 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks += 1;
+            }
+            ticks
+        });
+        let lock = CacheLock::acquire_within_async(&path, Duration::from_millis(150))
+            .await
+            .unwrap();
+        assert!(lock.is_none());
+        assert!(ticks.is_finished());
+        assert_eq!(ticks.await.unwrap(), 5);
+    }
 }
@@ -0,0 +1,351 @@
+//! `quotabar cache`: what's cached and how old it is, and removing it
+
+use crate::cache::{Backend, CacheState, FetchFailure};
+use crate::events::UsageEvent;
+use crate::models::Provider;
+use crate::timefmt::{self, RelativeStyle};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+/// How far back `cache info` lists events
+const EVENTS_SINCE_HOURS: i64 = 24;
+
+/// Output of `quotabar cache info --json`
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CacheInfo {
+    /// state.json, or quotabar.db with `storage = "sqlite"`
+    pub path: String,
+    /// False until something has been cached; the fields below are then empty
+    pub exists: bool,
+    pub size_bytes: Option<u64>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Seconds since the cache was written, 0 if that's ahead of this clock
+    pub age_seconds: Option<u64>,
+    /// Providers with a snapshot or a failure on record, in display order
+    pub providers: Vec<ProviderInfo>,
+    /// Events from the last day, oldest first
+    pub events: Vec<UsageEvent>,
+}
+
+/// One provider's entry in [`CacheInfo`]
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ProviderInfo {
+    pub provider: Provider,
+    /// When the snapshot was taken; None when only a failure is cached
+    pub updated_at: Option<DateTime<Utc>>,
+    pub age_seconds: Option<u64>,
+    pub last_error: Option<FetchFailure>,
+    /// When a provider that answered 429 may be fetched again
+    pub backoff_until: Option<DateTime<Utc>>,
+}
+
+/// Describe the cache in `backend`, reading it without upgrading or moving
+/// it
+pub fn info(backend: &Backend, now: DateTime<Utc>) -> Result<CacheInfo> {
+    let path = backend.path();
+    let mut info = CacheInfo {
+        path: path.display().to_string(),
+        exists: false,
+        size_bytes: None,
+        updated_at: None,
+        age_seconds: None,
+        providers: Vec::new(),
+        events: Vec::new(),
+    };
+    let Some((state, size)) = read_state(backend)? else {
+        return Ok(info);
+    };
+    info.exists = true;
+    info.size_bytes = Some(size);
+    info.updated_at = Some(state.updated_at);
+    info.age_seconds = Some(state.age(now).as_secs());
+    info.providers = Provider::ALL
+        .into_iter()
+        .filter(|p| state.snapshots.contains_key(p) || state.last_errors.contains_key(p))
+        .map(|provider| ProviderInfo {
+            provider,
+            updated_at: state.get(provider).map(|s| s.updated_at),
+            age_seconds: state.provider_age(provider, now).map(|age| age.as_secs()),
+            last_error: state.last_errors.get(&provider).cloned(),
+            backoff_until: state.backoff_for(provider, now),
+        })
+        .collect();
+    info.events = state
+        .recent_events(now - chrono::Duration::hours(EVENTS_SINCE_HOURS))
+        .into_iter()
+        .cloned()
+        .collect();
+    Ok(info)
+}
+
+/// `cache info` as text
+pub fn render(info: &CacheInfo, now: DateTime<Utc>) -> String {
+    if !info.exists {
+        return format!("No cache at {}", info.path);
+    }
+    let mut lines = vec![format!("Path     {}", info.path)];
+    if let Some(size) = info.size_bytes {
+        lines.push(format!("Size     {} bytes", size));
+    }
+    if let Some(age) = info.age_seconds {
+        lines.push(format!("Updated  {}", ago(age)));
+    }
+    if info.providers.is_empty() {
+        lines.push("No providers cached".to_string());
+    }
+    let width = info
+        .providers
+        .iter()
+        .map(|p| p.provider.display_name().len())
+        .max()
+        .unwrap_or(0);
+    for entry in &info.providers {
+        let mut parts = vec![match entry.age_seconds {
+            Some(age) => ago(age),
+            None => "no snapshot".to_string(),
+        }];
+        if let Some(ref error) = entry.last_error {
+            let age = (now - error.at).num_seconds().max(0) as u64;
+            parts.push(format!("last error {}: {}", ago(age), error.reason));
+        }
+        if let Some(until) = entry.backoff_until {
+            parts.push(format!(
+                "backing off, retry {}",
+                timefmt::relative(until, now, RelativeStyle::Long)
+            ));
+        }
+        lines.push(format!(
+            "{:width$}  {}",
+            entry.provider.display_name(),
+            parts.join(", "),
+            width = width
+        ));
+    }
+    if !info.events.is_empty() {
+        lines.push(format!("Events in the last {}h", EVENTS_SINCE_HOURS));
+    }
+    for event in &info.events {
+        let age = (now - event.at).num_seconds().max(0) as u64;
+        lines.push(format!(
+            "  {}: {} {} {} ({:.0}% → {:.0}%)",
+            ago(age),
+            event.provider.display_name(),
+            event.window,
+            event.kind.describe(),
+            event.before_percent,
+            event.after_percent
+        ));
+    }
+    lines.join("\n")
+}
+
+fn ago(seconds: u64) -> String {
+    timefmt::age(chrono::Duration::seconds(seconds as i64))
+}
+
+/// The cache as indented JSON, or None when there isn't one. state.json is
+/// shown as it is on disk; the database as the state.json it would be.
+pub fn show(backend: &Backend) -> Result<Option<String>> {
+    let value: Value = match backend {
+        Backend::Json(path) => {
+            let Some(content) = read(path)? else {
+                return Ok(None);
+            };
+            serde_json::from_str(&content)
+                .with_context(|| format!("{} isn't valid JSON", path.display()))?
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(_) => match read_state(backend)? {
+            Some((state, _)) => serde_json::to_value(state)?,
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(serde_json::to_string_pretty(&value)?))
+}
+
+/// Ask on the terminal before `cache clear`; without one, only `--yes` will do
+pub fn confirm_clear(path: &Path) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!("stdin isn't a terminal; pass --yes to clear the cache without asking");
+    }
+    print!("Delete {}? [y/N] ", path.display());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// The cache and the size of its file, or None without one
+fn read_state(backend: &Backend) -> Result<Option<(CacheState, u64)>> {
+    match backend {
+        Backend::Json(path) => {
+            let Some(content) = read(path)? else {
+                return Ok(None);
+            };
+            let state = CacheState::parse(&content)
+                .with_context(|| format!("{} isn't a readable cache", path.display()))?;
+            Ok(Some((state, content.len() as u64)))
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(path) => {
+            if !path.exists() {
+                return Ok(None);
+            }
+            let state = crate::sqlite::Db::open(path)?.load_state()?;
+            let size = std::fs::metadata(path)?.len();
+            Ok(state.map(|state| (state, size)))
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CACHE_VERSION;
+    use crate::events::EventKind;
+    use crate::mock::mock_snapshots;
+    use chrono::Duration;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+
+    fn write_state(path: &Path, now: DateTime<Utc>) {
+        let mut snapshots = mock_snapshots();
+        snapshots.retain(|p, _| matches!(p, Provider::Claude | Provider::Codex));
+        snapshots.get_mut(&Provider::Claude).unwrap().updated_at = now - Duration::minutes(3);
+        // Written on a machine whose clock runs ahead
+        snapshots.get_mut(&Provider::Codex).unwrap().updated_at = now + Duration::minutes(2);
+        let state = CacheState {
+            schema_version: CACHE_VERSION,
+            snapshots,
+            updated_at: now - Duration::minutes(1),
+            backoff_until: HashMap::from([(Provider::Kiro, now + Duration::minutes(5))]),
+            last_errors: HashMap::from([(
+                Provider::Kiro,
+                FetchFailure {
+                    reason: "HTTP 429".to_string(),
+                    at: now - Duration::minutes(2),
+                },
+            )]),
+            events: [Duration::days(2), Duration::minutes(3)]
+                .into_iter()
+                .map(|ago| UsageEvent {
+                    kind: EventKind::CrossedWarning,
+                    provider: Provider::Claude,
+                    window: "Weekly limit".to_string(),
+                    before_at: now - ago - Duration::minutes(5),
+                    at: now - ago,
+                    before_percent: 70.0,
+                    after_percent: 80.0,
+                })
+                .collect(),
+        };
+        Backend::Json(path.to_path_buf()).save(&state).unwrap();
+    }
+
+    #[test]
+    fn test_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let backend = Backend::Json(path.clone());
+        let now = Utc::now();
+
+        let missing = info(&backend, now).unwrap();
+        assert!(!missing.exists);
+        assert!(missing.providers.is_empty());
+        assert_eq!(
+            render(&missing, now),
+            format!("No cache at {}", path.display())
+        );
+
+        write_state(&path, now);
+        let info = info(&backend, now).unwrap();
+        assert!(info.exists);
+        assert_eq!(
+            info.size_bytes,
+            Some(std::fs::metadata(&path).unwrap().len())
+        );
+        assert_eq!(info.age_seconds, Some(60));
+        let ages: Vec<_> = info
+            .providers
+            .iter()
+            .map(|p| (p.provider, p.age_seconds))
+            .collect();
+        assert_eq!(
+            ages,
+            [
+                (Provider::Claude, Some(180)),
+                (Provider::Codex, Some(0)),
+                (Provider::Kiro, None)
+            ]
+        );
+        let kiro = &info.providers[2];
+        assert_eq!(kiro.last_error.as_ref().unwrap().reason, "HTTP 429");
+        assert_eq!(kiro.backoff_until, Some(now + Duration::minutes(5)));
+
+        let text = render(&info, now);
+        assert!(text.contains("Claude  3 min ago"), "{}", text);
+        assert!(text.contains("Codex   just now"), "{}", text);
+        assert!(
+            text.contains(
+                "Kiro    no snapshot, last error 2 min ago: HTTP 429, backing off, retry in 5 min"
+            ),
+            "{}",
+            text
+        );
+
+        // Only the last day's events
+        assert_eq!(info.events.len(), 1);
+        assert!(
+            text.ends_with(
+                "Events in the last 24h\n  3 min ago: Claude Weekly limit crossed warning (70% → 80%)"
+            ),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_info_rejects_unreadable_cache_without_moving_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(&path, "{not json").unwrap();
+        let backend = Backend::Json(path.clone());
+        assert!(info(&backend, Utc::now()).is_err());
+        assert!(show(&backend).is_err());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_show_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let backend = Backend::Json(path.clone());
+        assert_eq!(show(&backend).unwrap(), None);
+        assert!(!backend.clear().unwrap());
+
+        std::fs::write(
+            &path,
+            r#"{"snapshots":{},"updated_at":"2026-10-17T18:45:00Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            show(&backend).unwrap().unwrap(),
+            "{\n  \"snapshots\": {},\n  \"updated_at\": \"2026-10-17T18:45:00Z\"\n}"
+        );
+        assert!(backend.clear().unwrap());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,220 @@
+//! What changed in usage since the popup was last opened
+
+use crate::cache::CacheState;
+use crate::config::Thresholds;
+use crate::events;
+use crate::files;
+use crate::models::{Provider, UsageSnapshot};
+use crate::timefmt::{self, ClockFormat};
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The snapshots the popup showed when it was last opened, kept in
+/// popup-state.json next to the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Seen {
+    pub at: DateTime<Utc>,
+    pub snapshots: HashMap<Provider, UsageSnapshot>,
+}
+
+impl Seen {
+    pub fn path() -> PathBuf {
+        CacheState::cache_path().with_file_name("popup-state.json")
+    }
+
+    /// The saved state; None when there is none yet or it can't be read
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()).ok()?;
+        serde_json::from_str(&content)
+            .inspect_err(|e| tracing::debug!("ignoring popup state: {}", e))
+            .ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        files::write_atomic(&Self::path(), serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A window that moved across a threshold between two looks at it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Usage rose past `threshold` (the highest one passed)
+    Crossed {
+        provider: Provider,
+        window: String,
+        threshold: f64,
+    },
+    /// Usage fell back below the warning threshold, i.e. the window reset
+    Reset { provider: Provider, window: String },
+}
+
+impl Change {
+    /// e.g. "Claude: Weekly limit crossed 75% since yesterday"
+    pub fn describe(&self, since: &str) -> String {
+        match self {
+            Change::Crossed {
+                provider,
+                window,
+                threshold,
+            } => format!(
+                "{}: {} crossed {}% {}",
+                provider.display_name(),
+                window,
+                threshold,
+                since
+            ),
+            Change::Reset { provider, window } => {
+                format!("{}: {} reset", provider.display_name(), window)
+            }
+        }
+    }
+}
+
+/// Threshold crossings from `before` to `after`, in provider and window
+/// order. Only windows present in both count; a provider's thresholds come
+/// from `thresholds`.
+pub fn changes(
+    before: &HashMap<Provider, UsageSnapshot>,
+    after: &HashMap<Provider, UsageSnapshot>,
+    thresholds: impl Fn(Provider) -> Thresholds,
+) -> Vec<Change> {
+    let mut found = Vec::new();
+    for provider in Provider::ALL {
+        let (Some(old), Some(new)) = (before.get(&provider), after.get(&provider)) else {
+            continue;
+        };
+        let levels = thresholds(provider);
+        for (old, new) in events::matching_windows(old, new) {
+            let (was, is) = (old.used_percent, new.used_percent);
+            let crossed = [levels.critical, levels.warning]
+                .into_iter()
+                .find(|&level| was < level && is >= level);
+            if let Some(threshold) = crossed {
+                found.push(Change::Crossed {
+                    provider,
+                    window: new.name(),
+                    threshold,
+                });
+            } else if was >= levels.warning && is < levels.warning {
+                found.push(Change::Reset {
+                    provider,
+                    window: new.name(),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// When `at` was, relative to `now`: "since 09:40" today, "since
+/// yesterday", otherwise "since Oct 12"
+pub fn since(at: DateTime<Local>, now: DateTime<Local>, clock: ClockFormat) -> String {
+    let days = (now.date_naive() - at.date_naive()).num_days();
+    match days {
+        ..=0 => format!("since {}", timefmt::clock(&at, clock)),
+        1 => "since yesterday".to_string(),
+        _ => format!("since {}", at.format("%b %-d")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use chrono::TimeZone;
+
+    fn with_usage(session: f64, weekly: f64) -> HashMap<Provider, UsageSnapshot> {
+        let mut snapshots = mock_snapshots();
+        snapshots.retain(|p, _| *p == Provider::Claude);
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.windows[0].used_percent = session;
+        claude.windows[1].used_percent = weekly;
+        snapshots
+    }
+
+    #[test]
+    fn test_changes() {
+        let thresholds = |_| Thresholds::default();
+        let before = with_usage(50.0, 70.0);
+
+        assert!(changes(&before, &before, thresholds).is_empty());
+        assert_eq!(
+            changes(&before, &with_usage(60.0, 80.0), thresholds),
+            [Change::Crossed {
+                provider: Provider::Claude,
+                window: "Weekly limit".to_string(),
+                threshold: 75.0,
+            }]
+        );
+        // Passing both levels reports only the higher one
+        assert_eq!(
+            changes(&before, &with_usage(95.0, 70.0), thresholds),
+            [Change::Crossed {
+                provider: Provider::Claude,
+                window: "5-hour session".to_string(),
+                threshold: 90.0,
+            }]
+        );
+        assert_eq!(
+            changes(&with_usage(92.0, 70.0), &with_usage(3.0, 70.0), thresholds),
+            [Change::Reset {
+                provider: Provider::Claude,
+                window: "5-hour session".to_string(),
+            }]
+        );
+        // Nothing to compare against
+        assert!(changes(&HashMap::new(), &before, thresholds).is_empty());
+    }
+
+    #[test]
+    fn test_changes_use_provider_thresholds() {
+        let strict = |_| Thresholds {
+            warning: 50.0,
+            critical: 60.0,
+        };
+        assert_eq!(
+            changes(&with_usage(40.0, 10.0), &with_usage(55.0, 10.0), strict),
+            [Change::Crossed {
+                provider: Provider::Claude,
+                window: "5-hour session".to_string(),
+                threshold: 50.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let crossed = Change::Crossed {
+            provider: Provider::Claude,
+            window: "Weekly limit".to_string(),
+            threshold: 75.0,
+        };
+        assert_eq!(
+            crossed.describe("since yesterday"),
+            "Claude: Weekly limit crossed 75% since yesterday"
+        );
+        let reset = Change::Reset {
+            provider: Provider::Codex,
+            window: "5-hour session".to_string(),
+        };
+        assert_eq!(reset.describe("since 09:40"), "Codex: 5-hour session reset");
+    }
+
+    #[test]
+    fn test_since() {
+        let now = Local.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let at = |d, h, m| Local.with_ymd_and_hms(2026, 10, d, h, m, 0).unwrap();
+        let h24 = ClockFormat::TwentyFourHour;
+        assert_eq!(since(at(17, 9, 40), now, h24), "since 09:40");
+        assert_eq!(
+            since(at(17, 21, 5), now, ClockFormat::TwelveHour),
+            "since 9:05 PM"
+        );
+        assert_eq!(since(at(16, 23, 0), now, h24), "since yesterday");
+        assert_eq!(since(at(12, 8, 0), now, h24), "since Oct 12");
+    }
+}
@@ -0,0 +1,232 @@
+use crate::cache::CacheState;
+use crate::config::Config;
+use crate::fetch;
+use crate::models::{Provider, UsageSnapshot, WindowKind};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+
+/// Exit status when every threshold holds
+pub const EXIT_UNDER: i32 = 0;
+/// Exit status when data could not be read or fetched
+pub const EXIT_ERROR: i32 = 1;
+/// Exit status when a threshold is crossed
+pub const EXIT_OVER: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CheckWindow {
+    /// The main window, e.g. the session
+    Primary,
+    /// The weekly window
+    Secondary,
+    /// The first model-specific window
+    Tertiary,
+    #[value(name = "oauth_apps")]
+    OauthApps,
+    /// The most constrained window
+    Any,
+}
+
+/// Thresholds for one provider; either or both may be set
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub window: CheckWindow,
+    /// Highest allowed used percentage
+    pub max: Option<f64>,
+    /// Highest allowed spend, in the snapshot's cost unit
+    pub cost_max: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Under(String),
+    Over(String),
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Under(_) => EXIT_UNDER,
+            Outcome::Over(_) => EXIT_OVER,
+        }
+    }
+}
+
+/// Compare a snapshot against the thresholds. Missing data (no snapshot, no
+/// such window, no cost) is an error rather than a pass.
+pub fn evaluate(
+    provider: Provider,
+    snapshot: Option<&UsageSnapshot>,
+    thresholds: &Thresholds,
+) -> Result<Outcome> {
+    let snapshot =
+        snapshot.with_context(|| format!("No cached data for {}", provider.display_name()))?;
+    let mut parts = Vec::new();
+    let mut over = false;
+
+    if let Some(max) = thresholds.max {
+        let (label, used) = used_percent(snapshot, thresholds.window)?;
+        over |= used > max;
+        parts.push(format!("{} {:.1}% used (max {}%)", label, used, max));
+    }
+    if let Some(cost_max) = thresholds.cost_max {
+        let cost = snapshot
+            .cost
+            .as_ref()
+            .with_context(|| format!("{} reports no cost", provider.display_name()))?;
+        over |= cost.used > cost_max;
+        parts.push(format!(
+            "cost {} (max {})",
+            cost.format_amount(cost.used),
+            cost.format_amount(cost_max)
+        ));
+    }
+    if parts.is_empty() {
+        bail!("Nothing to check: pass --max and/or --cost-max");
+    }
+
+    let message = format!(
+        "{}: {} {}",
+        provider.display_name(),
+        parts.join(", "),
+        if over { "over threshold" } else { "ok" }
+    );
+    Ok(if over {
+        Outcome::Over(message)
+    } else {
+        Outcome::Under(message)
+    })
+}
+
+fn used_percent(snapshot: &UsageSnapshot, window: CheckWindow) -> Result<(&'static str, f64)> {
+    let selected = match window {
+        CheckWindow::Primary => ("primary", snapshot.primary()),
+        CheckWindow::Secondary => ("secondary", snapshot.secondary()),
+        CheckWindow::Tertiary => ("tertiary", snapshot.model_window()),
+        CheckWindow::OauthApps => (
+            "oauth_apps",
+            snapshot.window(&WindowKind::Other("oauth_apps".to_string())),
+        ),
+        CheckWindow::Any => {
+            let used = snapshot
+                .max_used()
+                .context("No rate windows in the snapshot")?;
+            return Ok(("most constrained window", used));
+        }
+    };
+    match selected {
+        (label, Some(window)) => Ok((label, window.used_percent)),
+        (label, None) => bail!("No {} window in the snapshot", label),
+    }
+}
+
+/// Run the check and return the process exit code; the explanation goes to
+/// stderr. Reads the cache unless `fresh` is set.
+pub async fn run(provider: Provider, thresholds: Thresholds, fresh: bool) -> i32 {
+    let snapshots = if fresh {
+        let config = Config::load()
+            .unwrap_or_default()
+            .restricted_to(&[provider]);
+        let mut refresh = fetch::refresh(&config, &[provider], true).await;
+        if let Some(e) = refresh.errors.remove(&provider) {
+            eprintln!("Failed to fetch {}: {:#}", provider.display_name(), e);
+            return EXIT_ERROR;
+        }
+        refresh.snapshots
+    } else {
+        match CacheState::load() {
+            Ok(cache) => cache.map(|c| c.snapshots).unwrap_or_default(),
+            Err(e) => {
+                eprintln!("Failed to read cache: {:#}", e);
+                return EXIT_ERROR;
+            }
+        }
+    };
+    report(evaluate(provider, snapshots.get(&provider), &thresholds))
+}
+
+fn report(outcome: Result<Outcome>) -> i32 {
+    match outcome {
+        Ok(outcome) => {
+            let (Outcome::Under(ref message) | Outcome::Over(ref message)) = outcome;
+            eprintln!("{}", message);
+            outcome.exit_code()
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use std::collections::HashMap;
+
+    fn check(snapshots: &HashMap<Provider, UsageSnapshot>, thresholds: Thresholds) -> i32 {
+        report(evaluate(
+            Provider::Claude,
+            snapshots.get(&Provider::Claude),
+            &thresholds,
+        ))
+    }
+
+    fn window(window: CheckWindow, max: f64) -> Thresholds {
+        Thresholds {
+            window,
+            max: Some(max),
+            cost_max: None,
+        }
+    }
+
+    #[test]
+    fn test_under_and_over() {
+        // Mock Claude: session 72%, week 45%, cost $42.50
+        let snapshots = mock_snapshots();
+        assert_eq!(check(&snapshots, window(CheckWindow::Secondary, 90.0)), 0);
+        assert_eq!(check(&snapshots, window(CheckWindow::Secondary, 40.0)), 2);
+        assert_eq!(check(&snapshots, window(CheckWindow::Primary, 72.0)), 0);
+    }
+
+    #[test]
+    fn test_any_uses_most_constrained_window() {
+        let snapshots = mock_snapshots();
+        assert_eq!(check(&snapshots, window(CheckWindow::Any, 80.0)), 0);
+        assert_eq!(check(&snapshots, window(CheckWindow::Any, 50.0)), 2);
+    }
+
+    #[test]
+    fn test_cost_max() {
+        let snapshots = mock_snapshots();
+        let cost = |cost_max| Thresholds {
+            window: CheckWindow::Any,
+            max: None,
+            cost_max: Some(cost_max),
+        };
+        assert_eq!(check(&snapshots, cost(50.0)), 0);
+        assert_eq!(check(&snapshots, cost(40.0)), 2);
+
+        // Either threshold crossing is enough
+        let both = Thresholds {
+            max: Some(99.0),
+            ..cost(40.0)
+        };
+        assert_eq!(check(&snapshots, both), 2);
+    }
+
+    #[test]
+    fn test_missing_data_is_an_error() {
+        let snapshots = mock_snapshots();
+        assert_eq!(check(&HashMap::new(), window(CheckWindow::Any, 90.0)), 1);
+        assert_eq!(check(&snapshots, window(CheckWindow::Tertiary, 90.0)), 1);
+
+        let codex = Thresholds {
+            window: CheckWindow::Any,
+            max: None,
+            cost_max: Some(10.0),
+        };
+        let outcome = evaluate(Provider::Codex, snapshots.get(&Provider::Codex), &codex);
+        assert_eq!(report(outcome), 1);
+    }
+}
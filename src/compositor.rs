@@ -0,0 +1,74 @@
+//! Asking the running Wayland compositor which output has focus
+
+use serde_json::Value;
+use std::process::Command;
+
+/// Reads the connector name out of a compositor's JSON reply
+type Parse = fn(&Value) -> Option<String>;
+
+/// Connector name (e.g. "DP-1") of the focused output on Hyprland, Sway or
+/// niri, whichever is running; None elsewhere or when the query fails
+pub fn focused_output() -> Option<String> {
+    let env = |name| std::env::var_os(name).is_some();
+    let (program, args, parse): (&str, &[&str], Parse) = if env("HYPRLAND_INSTANCE_SIGNATURE") {
+        ("hyprctl", &["monitors", "-j"], focused_in_list)
+    } else if env("SWAYSOCK") {
+        ("swaymsg", &["-t", "get_outputs", "-r"], focused_in_list)
+    } else if env("NIRI_SOCKET") {
+        ("niri", &["msg", "--json", "focused-output"], output_name)
+    } else {
+        return None;
+    };
+
+    let output = match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            tracing::debug!("{} exited with {}", program, output.status);
+            return None;
+        }
+        Err(e) => {
+            tracing::debug!("could not run {}: {}", program, e);
+            return None;
+        }
+    };
+    let json: Value = serde_json::from_slice(&output.stdout).ok()?;
+    parse(&json)
+}
+
+/// The output marked `"focused": true` in a Hyprland or Sway output list
+fn focused_in_list(outputs: &Value) -> Option<String> {
+    outputs
+        .as_array()?
+        .iter()
+        .find(|o| o["focused"].as_bool() == Some(true))
+        .and_then(output_name)
+}
+
+/// The `name` of a single output object (niri's focused-output)
+fn output_name(output: &Value) -> Option<String> {
+    output["name"].as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_focused_in_list() {
+        let outputs = json!([
+            { "name": "eDP-1", "focused": false },
+            { "name": "DP-1", "focused": true },
+        ]);
+        assert_eq!(focused_in_list(&outputs).as_deref(), Some("DP-1"));
+        assert_eq!(focused_in_list(&json!([{ "name": "eDP-1" }])), None);
+        assert_eq!(focused_in_list(&json!({ "name": "DP-1" })), None);
+    }
+
+    #[test]
+    fn test_output_name() {
+        let output = json!({ "name": "HDMI-A-1", "make": "Dell" });
+        assert_eq!(output_name(&output).as_deref(), Some("HDMI-A-1"));
+        assert_eq!(output_name(&Value::Null), None);
+    }
+}
@@ -1,8 +1,21 @@
-use crate::models::Provider;
-use anyhow::Result;
+use crate::cache::CacheState;
+use crate::configcli::UnknownKey;
+use crate::files;
+use crate::migrate::Migrated;
+use crate::models::{
+    CostSnapshot, IdentitySnapshot, PercentDisplay, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::paths::expand_home;
+use crate::textbar::IconSet;
+use crate::timefmt::{ClockFormat, RelativeStyle, TimeStyle};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Shortest `refresh_interval` honoured, to spare the providers' APIs
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -12,6 +25,22 @@ pub struct Config {
     pub notifications: NotificationConfig,
     #[serde(default)]
     pub providers: HashMap<Provider, ProviderConfig>,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub waybar: WaybarConfig,
+    #[serde(default)]
+    pub polybar: PolybarConfig,
+    #[serde(default)]
+    pub popup: PopupConfig,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    #[serde(default)]
+    pub colors: ColorsConfig,
+    #[serde(default)]
+    pub prompt: PromptConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,22 +48,155 @@ pub struct GeneralConfig {
     #[serde(default = "default_refresh_interval")]
     pub refresh_interval: String,
     #[serde(default)]
-    pub selected_provider: Option<Provider>,
+    pub selected_provider: Option<ProviderChoice>,
+    /// Decimal places for percentages (capped by each window's granularity)
+    #[serde(default)]
+    pub precision: u8,
+    /// Glyphs for terminal output ("nerd" or "ascii")
+    #[serde(default)]
+    pub icon_set: IconSet,
+    /// Show percentages as "used" or "remaining"
+    #[serde(default)]
+    pub display: PercentDisplay,
+    /// Clock times as "24h" or "12h"
+    #[serde(default)]
+    pub time_format: ClockFormat,
+    /// Show resets as "in 3h 12m, 18:45"; off shows only "18:45"
+    #[serde(default = "default_true")]
+    pub relative_times: bool,
+    /// Relative times as "long" ("in 3 days") or "short" ("3d")
+    #[serde(default)]
+    pub relative_style: RelativeStyle,
+    /// Reuse cached snapshots younger than this instead of refetching
+    #[serde(default = "default_min_fetch_interval")]
+    pub min_fetch_interval: String,
+    /// Also write logs here (relative paths are under the cache directory),
+    /// rotated at 1 MiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<String>,
+    /// Keep emails, organizations and account ids out of the cache
+    #[serde(default)]
+    pub redact_identity: bool,
+    /// Keep each provider's last raw API response under the cache directory
+    #[serde(default)]
+    pub debug_responses: bool,
+    /// Keep raw responses even with `redact_identity`, though they name the
+    /// account
+    #[serde(default)]
+    pub debug_responses_with_identity: bool,
+    /// Keep the cache and history as JSON files or in a SQLite database
+    #[serde(default)]
+    pub storage: Storage,
+    /// Stamp snapshots with the host and credentials they were fetched
+    /// under, so ones from another machine are dimmed and always refetched
+    #[serde(default = "default_true")]
+    pub track_origin: bool,
+    /// Command for opening provider pages, e.g. "firefox --new-window {url}";
+    /// unset uses the desktop's default handler
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_url_command: Option<String>,
 }
 
 fn default_refresh_interval() -> String {
     "5m".to_string()
 }
 
+fn default_min_fetch_interval() -> String {
+    "30s".to_string()
+}
+
 impl Default for GeneralConfig {
     fn default() -> Self {
         Self {
             refresh_interval: default_refresh_interval(),
             selected_provider: None,
+            precision: 0,
+            icon_set: IconSet::default(),
+            display: PercentDisplay::default(),
+            time_format: ClockFormat::default(),
+            relative_times: true,
+            relative_style: RelativeStyle::default(),
+            min_fetch_interval: default_min_fetch_interval(),
+            log_file: None,
+            redact_identity: false,
+            debug_responses: false,
+            debug_responses_with_identity: false,
+            storage: Storage::default(),
+            track_origin: true,
+            open_url_command: None,
+        }
+    }
+}
+
+/// Where the cache and history are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// state.json and history.jsonl
+    #[default]
+    Json,
+    /// quotabar.db, in builds with the `sqlite` feature
+    Sqlite,
+}
+
+/// The provider the bar shows: a fixed one, or `"auto"` for whichever
+/// enabled provider is closest to a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderChoice {
+    Auto,
+    Specific(Provider),
+}
+
+impl ProviderChoice {
+    /// Every value `selected_provider` accepts
+    const NAMES: &'static [&'static str] = &[
+        "auto",
+        "claude",
+        "claude-org",
+        "codex",
+        "opencode",
+        "kiro",
+        "windsurf",
+    ];
+
+    /// The fixed provider; None for auto
+    pub fn provider(self) -> Option<Provider> {
+        match self {
+            ProviderChoice::Auto => None,
+            ProviderChoice::Specific(provider) => Some(provider),
         }
     }
 }
 
+impl From<Provider> for ProviderChoice {
+    fn from(provider: Provider) -> Self {
+        ProviderChoice::Specific(provider)
+    }
+}
+
+impl Serialize for ProviderChoice {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ProviderChoice::Auto => serializer.serialize_str("auto"),
+            ProviderChoice::Specific(provider) => provider.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProviderChoice {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == "auto" {
+            return Ok(ProviderChoice::Auto);
+        }
+        Provider::ALL
+            .into_iter()
+            .find(|p| p.id() == name)
+            .map(ProviderChoice::Specific)
+            .ok_or_else(|| serde::de::Error::unknown_variant(&name, Self::NAMES))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationConfig {
     #[serde(default = "default_true")]
@@ -56,66 +218,1558 @@ impl Default for NotificationConfig {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusConfig {
+    /// Draw a usage bar next to each window
+    #[serde(default)]
+    pub bars: bool,
+}
+
+/// What the Waybar module shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaybarMode {
+    /// The selected provider (or the first enabled one with data)
+    #[default]
+    Selected,
+    /// Whichever provider is closest to a limit
+    Max,
+    /// A compact segment per provider
+    All,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaybarConfig {
+    #[serde(default)]
+    pub mode: WaybarMode,
+    /// Bar text template, e.g. "{icon} {session}%[ / {week}%]"; see
+    /// `format::render`. Defaults to the built-in text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Tooltip template; may span several lines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tooltip_format: Option<String>,
+    /// Add a `stale` class once the shown snapshot is older than this
+    #[serde(default = "default_stale_after")]
+    pub stale_after: String,
+    /// Leave the text empty (hiding the module) while every shown window is
+    /// below this % used; 0 never hides
+    #[serde(default)]
+    pub hide_below: f64,
+    /// Emit the built-in tooltip as Pango markup, coloring each window line
+    /// by its status with `[colors]`
+    #[serde(default)]
+    pub tooltip_markup: bool,
+    /// After `fetch` or a daemon cycle updates the cache, send SIGRTMIN+N
+    /// to waybar so modules with `"signal": N` update immediately
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<u8>,
+}
+
+fn default_stale_after() -> String {
+    "15m".to_string()
+}
+
+impl Default for WaybarConfig {
+    fn default() -> Self {
+        Self {
+            mode: WaybarMode::default(),
+            format: None,
+            tooltip_format: None,
+            stale_after: default_stale_after(),
+            hide_below: 0.0,
+            tooltip_markup: false,
+            signal: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolybarConfig {
+    /// Layout template, e.g. "{icon} {session}%"; see `format::render`.
+    /// Defaults to the same text as the Waybar module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+/// Where on the output the popup opens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PopupAnchor {
+    #[default]
+    TopRight,
+    TopLeft,
+    BottomRight,
+    BottomLeft,
+    Center,
+}
+
+/// Which built-in stylesheet the popup uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PopupTheme {
+    #[default]
+    Dark,
+    Light,
+    /// Follow the desktop's color scheme
+    Auto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PopupConfig {
+    /// Turn a provider's age label amber once its data is older than this
+    #[serde(default = "default_stale_after")]
+    pub stale_after: String,
+    #[serde(default)]
+    pub anchor: PopupAnchor,
+    /// Connector name of the monitor to open on (e.g. "DP-1"), or "focused"
+    /// to follow the compositor's focus; unset lets the compositor choose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Gaps to the anchored edges; margins on other edges have no effect
+    #[serde(default = "default_popup_margin_y")]
+    pub margin_top: i32,
+    #[serde(default = "default_popup_margin_x")]
+    pub margin_right: i32,
+    #[serde(default = "default_popup_margin_y")]
+    pub margin_bottom: i32,
+    #[serde(default = "default_popup_margin_x")]
+    pub margin_left: i32,
+    #[serde(default = "default_popup_width")]
+    pub width: i32,
+    /// The popup grows with its sections up to this many pixels, then
+    /// scrolls; unset is 80% of the monitor's height
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<i32>,
+    /// Close after this many seconds without pointer or key activity; 0
+    /// keeps it open
+    #[serde(default)]
+    pub auto_close_secs: u64,
+    /// Close when another window takes focus
+    #[serde(default)]
+    pub close_on_focus_loss: bool,
+    /// Providers whose sections start collapsed to their header; updated by
+    /// clicking a header
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collapsed: Vec<Provider>,
+    /// Provider ids in the order their sections appear; unlisted providers
+    /// follow in the default order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// Provider ids left out of the popup; they are still fetched for the bar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hidden: Vec<String>,
+    /// Open a regular window even where the layer shell is available
+    #[serde(default)]
+    pub force_window: bool,
+    /// Show the account email under each provider's name, and in `status`
+    #[serde(default = "default_true")]
+    pub show_email: bool,
+    /// Draw the last day's usage under each window's bar, from history.jsonl
+    #[serde(default = "default_true")]
+    pub sparklines: bool,
+    /// `#RRGGBB` for provider icons; unset follows the theme's text color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_color: Option<String>,
+    /// Built-in stylesheet; a `style.css` replaces it
+    #[serde(default)]
+    pub theme: PopupTheme,
+}
+
+fn default_popup_margin_y() -> i32 {
+    40
+}
+
+fn default_popup_margin_x() -> i32 {
+    10
+}
+
+fn default_popup_width() -> i32 {
+    320
+}
+
+impl Default for PopupConfig {
+    fn default() -> Self {
+        Self {
+            stale_after: default_stale_after(),
+            anchor: PopupAnchor::default(),
+            output: None,
+            margin_top: default_popup_margin_y(),
+            margin_right: default_popup_margin_x(),
+            margin_bottom: default_popup_margin_y(),
+            margin_left: default_popup_margin_x(),
+            width: default_popup_width(),
+            max_height: None,
+            auto_close_secs: 0,
+            close_on_focus_loss: false,
+            collapsed: Vec::new(),
+            order: Vec::new(),
+            hidden: Vec::new(),
+            force_window: false,
+            show_email: true,
+            sparklines: true,
+            icon_color: None,
+            theme: PopupTheme::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptConfig {
+    /// Only print when the most constrained window is at least this % used
+    #[serde(default = "default_prompt_threshold")]
+    pub threshold: f64,
+}
+
+fn default_prompt_threshold() -> f64 {
+    75.0
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_prompt_threshold(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Record a sample in history.jsonl after each successful fetch
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Drop usage samples older than this from history.jsonl
+    #[serde(default = "default_history_retention")]
+    pub retention: String,
+}
+
+fn default_history_retention() -> String {
+    "30d".to_string()
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention: default_history_retention(),
+        }
+    }
+}
+
+/// Used percentages at which a window turns "warning" and "critical"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds {
+    #[serde(default = "default_warning_threshold")]
+    pub warning: f64,
+    #[serde(default = "default_critical_threshold")]
+    pub critical: f64,
+}
+
+fn default_warning_threshold() -> f64 {
+    75.0
+}
+
+fn default_critical_threshold() -> f64 {
+    90.0
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            warning: default_warning_threshold(),
+            critical: default_critical_threshold(),
+        }
+    }
+}
+
+impl Thresholds {
+    /// "critical", "warning" or "normal" for a used percentage
+    pub fn classify(&self, used_percent: f64) -> &'static str {
+        if used_percent >= self.critical {
+            "critical"
+        } else if used_percent >= self.warning {
+            "warning"
+        } else {
+            "normal"
+        }
+    }
+
+    fn validate(&self, table: &str) -> Result<()> {
+        if self.warning >= self.critical {
+            anyhow::bail!(
+                "[{}] warning ({}) must be below critical ({})",
+                table,
+                self.warning,
+                self.critical
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Per-provider `[providers.<name>.thresholds]`; unset values fall back to
+/// the global `[thresholds]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical: Option<f64>,
+}
+
+/// `#RRGGBB` colors for text outputs (polybar, i3blocks, Waybar tooltip markup)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorsConfig {
+    /// Color when usage is healthy; unset keeps the bar's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal: Option<String>,
+    #[serde(default = "default_warning_color")]
+    pub warning: String,
+    #[serde(default = "default_critical_color")]
+    pub critical: String,
+    /// Popup only: selection and focus highlights; unset keeps the theme's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent: Option<String>,
+    /// Popup only: window background; unset keeps the theme's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+}
+
+fn default_warning_color() -> String {
+    "#e5c07b".to_string()
+}
+
+fn default_critical_color() -> String {
+    "#e06c75".to_string()
+}
+
+impl Default for ColorsConfig {
+    fn default() -> Self {
+        Self {
+            normal: None,
+            warning: default_warning_color(),
+            critical: default_critical_color(),
+            accent: None,
+            background: None,
+        }
+    }
+}
+
+impl ColorsConfig {
+    /// Color for a status class, or None to keep the bar's default
+    pub fn for_class(&self, class: &str) -> Option<&str> {
+        match class {
+            "critical" => Some(&self.critical),
+            "warning" => Some(&self.warning),
+            _ => self.normal.as_deref(),
+        }
+    }
+}
+
+/// How a provider is presented, resolved by `Config::display_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayInfo {
+    /// May be empty when the user turned the icon off
+    pub icon: String,
+    pub label: String,
+}
+
+impl DisplayInfo {
+    /// Merge a provider's config over `default_icon` and the built-in name
+    pub fn resolve(
+        snapshot: &UsageSnapshot,
+        overrides: Option<&ProviderConfig>,
+        default_icon: &str,
+    ) -> Self {
+        Self::named(snapshot.label(), overrides, default_icon)
+    }
+
+    /// [`resolve`](Self::resolve) for a provider without a snapshot, e.g. a
+    /// disabled one
+    pub fn for_provider(provider: Provider, overrides: Option<&ProviderConfig>) -> Self {
+        Self::named(provider.display_name(), overrides, provider.icon())
+    }
+
+    fn named(label: &str, overrides: Option<&ProviderConfig>, default_icon: &str) -> Self {
+        Self {
+            icon: overrides
+                .and_then(|c| c.icon.clone())
+                .unwrap_or_else(|| default_icon.to_string()),
+            label: overrides
+                .and_then(|c| c.label.clone())
+                .unwrap_or_else(|| label.to_string()),
+        }
+    }
+
+    /// `text` after the icon and a space, or alone without an icon
+    pub fn prefix(&self, text: &str) -> String {
+        if self.icon.is_empty() {
+            text.to_string()
+        } else {
+            format!("{} {}", self.icon, text)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderConfig {
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// Claude only: also show organization-wide usage (needs an Admin API key)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub org_usage: bool,
+    /// Claude only: Admin API key for `org_usage`; falls back to
+    /// `ANTHROPIC_ADMIN_KEY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_api_key: Option<String>,
+    /// Claude only: monthly organization budget in USD, used as the spend limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_budget: Option<f64>,
+    /// Overrides for the global `[thresholds]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<ThresholdOverrides>,
+    /// Glyph shown instead of the built-in one; "" shows none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Name shown instead of the provider (or organization) name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// SVG or image file drawn in the popup instead of the built-in icon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_path: Option<String>,
+    /// Claude only: credentials file to read instead of
+    /// `~/.claude/.credentials.json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_path: Option<String>,
+    /// Codex only: directory holding auth.json and config.toml, ahead of
+    /// `CODEX_HOME`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codex_home: Option<String>,
+    /// Codex only: auth.json to read instead of the one in the Codex home
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_path: Option<String>,
+    /// Environment variable holding a bearer token; when it's set, no
+    /// credentials file is read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    /// Parts of the popup section and `status` block to leave out
+    #[serde(default)]
+    pub display: SectionDisplay,
 }
 
 impl Default for ProviderConfig {
     fn default() -> Self {
-        Self { enabled: true }
+        Self {
+            enabled: true,
+            org_usage: false,
+            admin_api_key: None,
+            org_budget: None,
+            thresholds: None,
+            icon: None,
+            label: None,
+            icon_path: None,
+            credentials_path: None,
+            codex_home: None,
+            auth_path: None,
+            token_env: None,
+            display: SectionDisplay::default(),
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// Built-in settings for a provider missing from `[providers]`
+    pub fn default_for(provider: Provider) -> Self {
+        match provider {
+            Provider::OpenCode | Provider::Kiro | Provider::Windsurf | Provider::ClaudeOrg => {
+                Self {
+                    enabled: false,
+                    ..Self::default()
+                }
+            }
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Which optional rows a provider's popup section and `status` block show.
+/// Purely presentational: hidden windows still count toward warnings and
+/// the bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionDisplay {
+    /// Spend and budget
+    #[serde(default = "default_true")]
+    pub show_cost: bool,
+    /// The weekly window
+    #[serde(default = "default_true")]
+    pub show_secondary: bool,
+    /// Model-specific windows, e.g. Claude's Sonnet and Opus weeks
+    #[serde(default = "default_true")]
+    pub show_tertiary: bool,
+    /// Account, organization and plan
+    #[serde(default = "default_true")]
+    pub show_identity: bool,
+}
+
+impl Default for SectionDisplay {
+    fn default() -> Self {
+        Self {
+            show_cost: true,
+            show_secondary: true,
+            show_tertiary: true,
+            show_identity: true,
+        }
+    }
+}
+
+/// The parts of a snapshot a section shows
+#[derive(Debug, Clone)]
+pub struct Rows<'a> {
+    /// The snapshot's windows, in order
+    pub windows: Vec<&'a RateWindow>,
+    pub cost: Option<&'a CostSnapshot>,
+    /// The organization's top spender, shown with the cost
+    pub busiest_member: Option<String>,
+    pub identity: Option<&'a IdentitySnapshot>,
+}
+
+impl SectionDisplay {
+    /// What of `snapshot` to show
+    pub fn rows<'a>(&self, snapshot: &'a UsageSnapshot) -> Rows<'a> {
+        Rows {
+            windows: snapshot
+                .windows
+                .iter()
+                .filter(|w| match w.kind {
+                    WindowKind::Weekly => self.show_secondary,
+                    WindowKind::Model(_) => self.show_tertiary,
+                    _ => true,
+                })
+                .collect(),
+            cost: snapshot.cost.as_ref().filter(|_| self.show_cost),
+            busiest_member: snapshot.busiest_member_summary().filter(|_| self.show_cost),
+            identity: snapshot.identity.as_ref().filter(|_| self.show_identity),
+        }
+    }
+
+    /// Every optional row is off
+    fn hides_everything(&self) -> bool {
+        !(self.show_cost || self.show_secondary || self.show_tertiary || self.show_identity)
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let mut providers = HashMap::new();
-        providers.insert(Provider::Claude, ProviderConfig::default());
-        providers.insert(Provider::Codex, ProviderConfig::default());
-        providers.insert(Provider::OpenCode, ProviderConfig { enabled: false });
+        // ClaudeOrg is switched on through `[providers.claude] org_usage`
+        let providers = Provider::ALL
+            .into_iter()
+            .filter(|p| *p != Provider::ClaudeOrg)
+            .map(|p| (p, ProviderConfig::default_for(p)))
+            .collect();
 
         Self {
             general: GeneralConfig::default(),
             notifications: NotificationConfig::default(),
             providers,
+            status: StatusConfig::default(),
+            waybar: WaybarConfig::default(),
+            polybar: PolybarConfig::default(),
+            popup: PopupConfig::default(),
+            thresholds: Thresholds::default(),
+            colors: ColorsConfig::default(),
+            prompt: PromptConfig::default(),
+            history: HistoryConfig::default(),
         }
     }
 }
 
 impl Config {
+    /// `$QUOTABAR_CONFIG`, or config.toml in the config directory
     pub fn config_path() -> PathBuf {
-        dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("quotabar")
-            .join("config.toml")
+        crate::paths::config_file().path
+    }
+
+    /// `[general] storage` alone, without reading or checking the rest of
+    /// the config; the default when there's no config or it doesn't parse
+    pub fn storage() -> Storage {
+        #[derive(Default, Deserialize)]
+        struct Peek {
+            #[serde(default)]
+            general: PeekGeneral,
+        }
+        #[derive(Default, Deserialize)]
+        struct PeekGeneral {
+            #[serde(default)]
+            storage: Storage,
+        }
+        std::fs::read_to_string(Self::config_path())
+            .ok()
+            .and_then(|content| toml::from_str::<Peek>(&content).ok())
+            .unwrap_or_default()
+            .general
+            .storage
     }
 
     pub fn load() -> Result<Self> {
         let path = Self::config_path();
         if path.exists() {
             let content = std::fs::read_to_string(&path)?;
-            let config: Config = toml::from_str(&content)?;
+            let (config, unknown, migrated) = Self::parse_checked(&content).inspect_err(|e| {
+                tracing::warn!(path = %path.display(), "config is invalid: {:#}", e);
+            })?;
+            report_problems(&path, &problems(&config, &unknown));
+            if let Some(ref migrated) = migrated {
+                report_migration(&path, migrated);
+            }
+            tracing::debug!(path = %path.display(), "loaded config");
             Ok(config)
         } else {
+            tracing::debug!(path = %path.display(), "no config file, using defaults");
             Ok(Config::default())
         }
     }
 
+    /// Parse and validate config file contents; keys quotabar doesn't know
+    /// are ignored
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(Self::parse_checked(content)?.0)
+    }
+
+    /// [`Config::parse`], also returning the keys it ignored and, for a file
+    /// written for an older version, the migration it was read through
+    pub fn parse_checked(content: &str) -> Result<(Self, Vec<UnknownKey>, Option<Migrated>)> {
+        let mut table: toml::Table =
+            toml::from_str(content).map_err(|e| anyhow::anyhow!("{}", e.message()))?;
+        // Older files are read as migrated; `config migrate` writes that back
+        let migrated = crate::migrate::upgrade(content)?;
+        if let Some(ref migrated) = migrated {
+            table = toml::from_str(&migrated.document.to_string())
+                .map_err(|e| anyhow::anyhow!("{}", e.message()))?;
+        }
+        canonicalize(&mut table);
+        let unknown = crate::configcli::unknown_keys(&table);
+        // Unknown provider tables would fail the whole file; drop them with
+        // the other unknown keys
+        if let Some(toml::Value::Table(providers)) = table.get_mut("providers") {
+            for key in &unknown {
+                if let Some(id) = key.key.strip_prefix("providers.") {
+                    providers.remove(id);
+                }
+            }
+        }
+        let config: Config = table
+            .try_into()
+            .map_err(|e: toml::de::Error| anyhow::anyhow!("{}", e.message()))?;
+        config.validate()?;
+        Ok((config, unknown, migrated))
+    }
+
+    /// Check settings that parse but make no sense together
+    pub fn validate(&self) -> Result<()> {
+        self.thresholds.validate("thresholds")?;
+        parse_interval(&self.general.refresh_interval).context("[general] refresh_interval")?;
+        parse_duration(&self.general.min_fetch_interval).context("[general] min_fetch_interval")?;
+        parse_duration(&self.waybar.stale_after).context("[waybar] stale_after")?;
+        parse_duration(&self.popup.stale_after).context("[popup] stale_after")?;
+        parse_duration(&self.history.retention).context("[history] retention")?;
+        if let Some(ref command) = self.general.open_url_command {
+            crate::browser::command_line(command, "").context("[general] open_url_command")?;
+        }
+        if let Some(n) = self.waybar.signal {
+            crate::barsignal::validate(n).context("[waybar] signal")?;
+        }
+        if self.popup.width <= 0 {
+            anyhow::bail!("[popup] width must be positive, got {}", self.popup.width);
+        }
+        if let Some(height) = self.popup.max_height.filter(|&h| h <= 0) {
+            anyhow::bail!("[popup] max_height must be positive, got {}", height);
+        }
+        if let Some(ref color) = self.popup.icon_color {
+            if !crate::icons::is_hex_color(color) {
+                anyhow::bail!("[popup] icon_color must be #RRGGBB, got {:?}", color);
+            }
+        }
+        for (key, color) in [
+            ("accent", &self.colors.accent),
+            ("background", &self.colors.background),
+        ] {
+            if let Some(color) = color.as_deref().filter(|c| !crate::icons::is_hex_color(c)) {
+                anyhow::bail!("[colors] {} must be #RRGGBB, got {:?}", key, color);
+            }
+        }
+        for provider in Provider::ALL {
+            if self
+                .providers
+                .get(&provider)
+                .is_some_and(|c| c.thresholds.is_some())
+            {
+                self.thresholds_for(provider)
+                    .validate(&format!("providers.{}.thresholds", provider.id()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings that are valid but probably not what was meant, worded for
+    /// printing after the config file's path
+    pub fn warnings(&self) -> Vec<String> {
+        self.enabled_providers()
+            .into_iter()
+            .filter(|p| self.display_for(*p).hides_everything())
+            .map(|p| {
+                format!(
+                    "[providers.{}.display] hides every optional row of an enabled provider; \
+                     set enabled = false to drop it instead",
+                    p.id()
+                )
+            })
+            .collect()
+    }
+
+    /// `[providers.<name>.display]`, or everything shown
+    pub fn display_for(&self, provider: Provider) -> SectionDisplay {
+        self.providers
+            .get(&provider)
+            .map(|c| c.display)
+            .unwrap_or_default()
+    }
+
+    /// The global thresholds with any `[providers.<name>.thresholds]`
+    /// applied; org usage follows the Claude entry
+    pub fn thresholds_for(&self, provider: Provider) -> Thresholds {
+        let key = match provider {
+            Provider::ClaudeOrg => Provider::Claude,
+            other => other,
+        };
+        let overrides = self
+            .providers
+            .get(&key)
+            .and_then(|c| c.thresholds)
+            .unwrap_or_default();
+        Thresholds {
+            warning: overrides.warning.unwrap_or(self.thresholds.warning),
+            critical: overrides.critical.unwrap_or(self.thresholds.critical),
+        }
+    }
+
+    /// Icon and name for a snapshot, with `[providers.<name>] icon` and
+    /// `label` taking precedence over `default_icon` and the built-in name
+    pub fn display_info(&self, snapshot: &UsageSnapshot, default_icon: &str) -> DisplayInfo {
+        DisplayInfo::resolve(
+            snapshot,
+            self.providers.get(&snapshot.provider),
+            default_icon,
+        )
+    }
+
+    /// Status class for a provider's used percentage. Every output that
+    /// colors usage goes through here so they agree.
+    pub fn status_class(&self, provider: Provider, used_percent: f64) -> &'static str {
+        self.thresholds_for(provider).classify(used_percent)
+    }
+
+    /// Write to the config file, touching only the values that differ from
+    /// what it says now, so comments, formatting and keys quotabar doesn't
+    /// know about survive
     pub fn save(&self) -> Result<()> {
         let path = Self::config_path();
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
+        let current = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        files::write_atomic(&path, self.merge_into(&current)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// `content` updated to this config, as written by [`Config::save`]
+    fn merge_into(&self, content: &str) -> Result<String> {
+        let mut doc: toml_edit::DocumentMut =
+            content.parse().context("Config file is not valid TOML")?;
+        // What the file amounts to now, defaults included, so unchanged
+        // values aren't spelled out and unset ones can be told from unknown
+        let before = toml::from_str::<toml::Table>(content)
+            .ok()
+            .and_then(|mut table| {
+                canonicalize(&mut table);
+                table.try_into::<Config>().ok()
+            })
+            .unwrap_or_default();
+        let before = toml::Value::try_from(before)?;
+        let after = toml::Value::try_from(self)?;
+        if let (Some(before), Some(after)) = (before.as_table(), after.as_table()) {
+            merge_table(doc.as_table_mut(), before, after)?;
         }
-        let content = toml::to_string_pretty(self)?;
-        std::fs::write(&path, content)?;
-        Ok(())
+        Ok(doc.to_string())
     }
 
     pub fn is_provider_enabled(&self, provider: Provider) -> bool {
+        if provider == Provider::ClaudeOrg {
+            return self
+                .providers
+                .get(&Provider::Claude)
+                .is_some_and(|c| c.org_usage);
+        }
         self.providers
             .get(&provider)
             .map(|c| c.enabled)
-            .unwrap_or(true)
+            .unwrap_or_else(|| ProviderConfig::default_for(provider).enabled)
+    }
+
+    /// Resolved `general.log_file`: `~/` expands to the home directory and
+    /// relative paths live next to the cache
+    pub fn log_file_path(&self) -> Option<PathBuf> {
+        let path = expand_home(self.general.log_file.as_deref()?)?;
+        Some(if path.is_relative() {
+            CacheState::cache_path().with_file_name(path)
+        } else {
+            path
+        })
+    }
+
+    /// Where fetches keep raw responses: `raw/` beside the cache when
+    /// `debug_responses` is on, unless `redact_identity` rules it out
+    pub fn debug_responses_dir(&self) -> Option<PathBuf> {
+        let general = &self.general;
+        let allowed = !general.redact_identity || general.debug_responses_with_identity;
+        (general.debug_responses && allowed).then(|| CacheState::cache_path().with_file_name("raw"))
+    }
+
+    /// Resolved `[providers.<name>] icon_path`, with `~/` expanded
+    pub fn icon_path(&self, provider: Provider) -> Option<PathBuf> {
+        expand_home(self.providers.get(&provider)?.icon_path.as_deref()?)
+    }
+
+    /// Parsed `general.refresh_interval`, no shorter than
+    /// [`MIN_REFRESH_INTERVAL`]. Invalid values are rejected when the config
+    /// is loaded; one set in code falls back to the default.
+    pub fn refresh_interval(&self) -> Duration {
+        let interval = parse_interval(&self.general.refresh_interval).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using {}", e, default_refresh_interval());
+            parse_interval(&default_refresh_interval()).unwrap()
+        });
+        clamp_refresh_interval(interval)
+    }
+
+    /// How times are written, from `[general]`
+    pub fn time_style(&self) -> TimeStyle {
+        TimeStyle {
+            clock: self.general.time_format,
+            relative: self.general.relative_times,
+            relative_style: self.general.relative_style,
+        }
+    }
+
+    /// Parsed `waybar.stale_after`; only a config that skipped validation
+    /// falls back to the default
+    pub fn stale_after(&self) -> Duration {
+        parse_duration(&self.waybar.stale_after).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using {}", e, default_stale_after());
+            parse_duration(&default_stale_after()).unwrap()
+        })
+    }
+
+    /// Parsed `popup.stale_after`; only a config that skipped validation
+    /// falls back to the default
+    pub fn popup_stale_after(&self) -> Duration {
+        parse_duration(&self.popup.stale_after).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using {}", e, default_stale_after());
+            parse_duration(&default_stale_after()).unwrap()
+        })
+    }
+
+    /// Parsed `general.min_fetch_interval`; only a config that skipped
+    /// validation falls back to the default
+    pub fn min_fetch_interval(&self) -> Duration {
+        parse_duration(&self.general.min_fetch_interval).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using {}", e, default_min_fetch_interval());
+            parse_duration(&default_min_fetch_interval()).unwrap()
+        })
+    }
+
+    /// Parsed `history.retention`; only a config that skipped validation
+    /// falls back to the default
+    pub fn history_retention(&self) -> Duration {
+        parse_duration(&self.history.retention).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using {}", e, default_history_retention());
+            parse_duration(&default_history_retention()).unwrap()
+        })
+    }
+
+    /// A copy with exactly `providers` enabled and the first one selected,
+    /// for one-off `--provider` filters
+    pub fn restricted_to(&self, providers: &[Provider]) -> Config {
+        let mut config = self.clone();
+        for provider in Provider::ALL {
+            if provider == Provider::ClaudeOrg {
+                continue;
+            }
+            config
+                .providers
+                .entry(provider)
+                .or_insert_with(|| ProviderConfig::default_for(provider))
+                .enabled = providers.contains(&provider);
+        }
+        config
+            .providers
+            .entry(Provider::Claude)
+            .or_insert_with(|| ProviderConfig::default_for(Provider::Claude))
+            .org_usage = providers.contains(&Provider::ClaudeOrg);
+        config.general.selected_provider = providers.first().copied().map(Into::into);
+        tracing::debug!(?providers, "provider filter overrides the config");
+        config
+    }
+
+    /// The enabled provider with data closest to a limit, which `"auto"`
+    /// shows. Ties go to the provider listed first; providers without rate
+    /// windows are only picked when no other has data.
+    pub fn most_constrained(
+        &self,
+        snapshots: &HashMap<Provider, UsageSnapshot>,
+    ) -> Option<Provider> {
+        let shown: Vec<&UsageSnapshot> = self
+            .enabled_providers()
+            .into_iter()
+            .filter_map(|p| snapshots.get(&p))
+            .collect();
+        shown
+            .iter()
+            .filter_map(|s| Some((s.min_remaining()?, s.provider)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, p)| p)
+            .or_else(|| shown.first().map(|s| s.provider))
+    }
+
+    /// Enabled providers in default display order
+    pub fn enabled_providers(&self) -> Vec<Provider> {
+        Provider::ALL
+            .into_iter()
+            .filter(|p| self.is_provider_enabled(*p))
+            .collect()
+    }
+
+    /// Enabled providers as the popup lays them out: those in `[popup]
+    /// order` first, the rest in default order, minus `[popup] hidden`.
+    /// Unknown names are logged and skipped.
+    pub fn popup_providers(&self) -> Vec<Provider> {
+        let order = provider_list(&self.popup.order, "order");
+        let hidden = provider_list(&self.popup.hidden, "hidden");
+        let mut providers = self.enabled_providers();
+        // Stable, so unlisted providers keep their default order
+        providers.sort_by_key(|p| order.iter().position(|o| o == p).unwrap_or(order.len()));
+        providers.retain(|p| !hidden.contains(p));
+        providers
+    }
+}
+
+/// `(section, key)` pairs naming providers, anchors or modes, which are
+/// matched case-insensitively
+const CASE_INSENSITIVE: &[(&str, &str)] = &[
+    ("general", "selected_provider"),
+    ("general", "icon_set"),
+    ("general", "display"),
+    ("general", "time_format"),
+    ("general", "relative_style"),
+    ("waybar", "mode"),
+    ("popup", "anchor"),
+    ("popup", "theme"),
+    ("popup", "collapsed"),
+    ("popup", "order"),
+    ("popup", "hidden"),
+];
+
+/// Lowercase provider names, anchors and modes in a parsed config file so
+/// `"Claude"` or `"Top-Right"` mean the same as their canonical spelling
+fn canonicalize(table: &mut toml::Table) {
+    for (section, key) in CASE_INSENSITIVE {
+        let Some(value) = table.get_mut(*section).and_then(|s| s.get_mut(*key)) else {
+            continue;
+        };
+        let values = match value {
+            toml::Value::Array(items) => items.iter_mut().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            if let toml::Value::String(s) = value {
+                *s = s.to_lowercase();
+            }
+        }
+    }
+    if let Some(toml::Value::Table(providers)) = table.get_mut("providers") {
+        for (id, value) in std::mem::take(providers) {
+            // A table spelled canonically wins over a differently cased one
+            let canonical = id.to_lowercase();
+            if canonical == id {
+                providers.insert(id, value);
+            } else {
+                providers.entry(canonical).or_insert(value);
+            }
+        }
+    }
+}
+
+/// The config file's unknown keys, then its [`Config::warnings`]
+pub fn problems(config: &Config, unknown: &[UnknownKey]) -> Vec<String> {
+    unknown
+        .iter()
+        .map(ToString::to_string)
+        .chain(config.warnings())
+        .collect()
+}
+
+/// Print the config file's [`problems`] to stderr, once per process however
+/// often the config is loaded
+pub fn report_problems(path: &Path, problems: &[String]) {
+    static REPORTED: std::sync::Once = std::sync::Once::new();
+    if problems.is_empty() {
+        return;
+    }
+    REPORTED.call_once(|| {
+        for problem in problems {
+            eprintln!("{}: {}", path.display(), problem);
+        }
+    });
+}
+
+/// Point at `config migrate` when the file relies on migrations applied at
+/// every load, once per process
+fn report_migration(path: &Path, migrated: &Migrated) {
+    static REPORTED: std::sync::Once = std::sync::Once::new();
+    if migrated.changes.is_empty() {
+        return;
+    }
+    REPORTED.call_once(|| {
+        eprintln!(
+            "{}: written for config version {}, read as version {} ({}); \
+             run `quotabar config migrate` to update it",
+            path.display(),
+            migrated.from,
+            crate::migrate::CURRENT,
+            migrated.changes.join(", ")
+        );
+    });
+}
+
+/// The providers named in a `[popup]` list, warning about unknown ids
+fn provider_list(ids: &[String], key: &str) -> Vec<Provider> {
+    ids.iter()
+        .filter_map(|id| {
+            let provider = Provider::ALL.into_iter().find(|p| p.id() == id);
+            if provider.is_none() {
+                tracing::warn!("[popup] {}: unknown provider {:?}, ignoring it", key, id);
+            }
+            provider
+        })
+        .collect()
+}
+
+/// Apply the difference between `before` and `after` to `doc`: changed
+/// values are set in place, values `after` dropped are removed, and
+/// anything neither mentions is left alone
+fn merge_table(
+    doc: &mut dyn toml_edit::TableLike,
+    before: &toml::Table,
+    after: &toml::Table,
+) -> Result<()> {
+    for (key, value) in after {
+        let old = before.get(key);
+        if old == Some(value) {
+            continue;
+        }
+        if let toml::Value::Table(table) = value {
+            if doc.get(key).is_none() {
+                let mut new = toml_edit::Table::new();
+                new.set_implicit(true);
+                doc.insert(key, toml_edit::Item::Table(new));
+            }
+            if let Some(existing) = doc.get_mut(key).and_then(|i| i.as_table_like_mut()) {
+                let empty = toml::Table::new();
+                let old = old.and_then(toml::Value::as_table).unwrap_or(&empty);
+                merge_table(existing, old, table)?;
+                continue;
+            }
+        }
+        let mut new: toml_edit::Value = value
+            .to_string()
+            .parse()
+            .with_context(|| format!("Failed to write {}", key))?;
+        match doc.get_mut(key) {
+            Some(item) => {
+                if let Some(existing) = item.as_value() {
+                    *new.decor_mut() = existing.decor().clone();
+                }
+                *item = toml_edit::Item::Value(new);
+            }
+            None => {
+                doc.insert(key, toml_edit::Item::Value(new));
+            }
+        }
+    }
+    for key in before.keys() {
+        if !after.contains_key(key) {
+            doc.remove(key);
+        }
+    }
+    Ok(())
+}
+
+/// Parse a duration setting such as "15m" or "1h30m"; errors say how to
+/// write one
+fn parse_duration(value: &str) -> Result<Duration> {
+    humantime::parse_duration(value.trim()).with_context(|| {
+        format!(
+            "invalid duration {:?}; use a number and unit such as \"90s\", \"5m\", \"1h30m\" \
+             or \"2 hours\" (s, m, h, d)",
+            value
+        )
+    })
+}
+
+/// A refresh interval such as "90s", "5m" or "1h30m"
+pub fn parse_interval(value: &str) -> Result<Duration> {
+    let interval = parse_duration(value)?;
+    if interval.is_zero() {
+        anyhow::bail!("the interval must be greater than zero");
+    }
+    Ok(interval)
+}
+
+/// `interval`, raised to [`MIN_REFRESH_INTERVAL`] with a warning when
+/// shorter
+pub fn clamp_refresh_interval(interval: Duration) -> Duration {
+    if interval < MIN_REFRESH_INTERVAL {
+        tracing::warn!(
+            "refresh interval {} is below the minimum, using {}",
+            humantime::format_duration(interval),
+            humantime::format_duration(MIN_REFRESH_INTERVAL)
+        );
+        return MIN_REFRESH_INTERVAL;
+    }
+    interval
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_display_rows() {
+        let snapshots = crate::mock::mock_snapshots();
+        let mut claude = snapshots[&Provider::Claude].clone();
+        claude.windows.push(RateWindow {
+            kind: WindowKind::Model("sonnet".to_string()),
+            label: Some("Weekly — Sonnet".to_string()),
+            ..claude.windows[1].clone()
+        });
+        assert!(claude.identity.is_some());
+
+        let all = SectionDisplay::default().rows(&claude);
+        assert_eq!(all.windows.len(), 3);
+        assert!(all.cost.is_some());
+        assert!(all.identity.is_some());
+
+        let config = Config::parse(
+            "[providers.claude.display]\nshow_secondary = false\nshow_identity = false\n",
+        )
+        .unwrap();
+        let display = config.display_for(Provider::Claude);
+        let rows = display.rows(&claude);
+        let kinds: Vec<&str> = rows.windows.iter().map(|w| w.kind.id()).collect();
+        assert_eq!(kinds, ["session", "sonnet"]);
+        assert!(rows.identity.is_none());
+        assert_eq!(rows.cost.is_some(), claude.cost.is_some());
+        // Other providers are untouched
+        assert_eq!(
+            config.display_for(Provider::Codex),
+            SectionDisplay::default()
+        );
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_warns_when_enabled_provider_hides_everything() {
+        let hidden = "show_cost = false\nshow_secondary = false\n\
+                      show_tertiary = false\nshow_identity = false\n";
+        let config = Config::parse(&format!("[providers.codex.display]\n{}", hidden)).unwrap();
+        assert_eq!(config.warnings().len(), 1);
+        assert!(config.warnings()[0].starts_with("[providers.codex.display]"));
+
+        let config = Config::parse(&format!(
+            "[providers.codex]\nenabled = false\n[providers.codex.display]\n{}",
+            hidden
+        ))
+        .unwrap();
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_provider_choice_serde() {
+        for (toml, choice) in [
+            ("auto", ProviderChoice::Auto),
+            ("codex", Provider::Codex.into()),
+            ("claude-org", Provider::ClaudeOrg.into()),
+        ] {
+            let content = format!("[general]\nselected_provider = \"{}\"\n", toml);
+            let config = Config::parse(&content).unwrap();
+            assert_eq!(config.general.selected_provider, Some(choice));
+            assert_eq!(
+                toml::to_string(&config.general)
+                    .unwrap()
+                    .lines()
+                    .find(|l| l.starts_with("selected_provider")),
+                Some(format!("selected_provider = \"{}\"", toml).as_str())
+            );
+        }
+        assert_eq!(
+            Config::parse("[general]\nselected_provider = \"AUTO\"\n")
+                .unwrap()
+                .general
+                .selected_provider,
+            Some(ProviderChoice::Auto)
+        );
+
+        let err = Config::parse("[general]\nselected_provider = \"gemini\"\n").unwrap_err();
+        assert!(err.to_string().contains("`auto`"), "{}", err);
+        // Every provider is listed as accepted
+        for provider in Provider::ALL {
+            assert!(ProviderChoice::NAMES.contains(&provider.id()));
+        }
+    }
+
+    #[test]
+    fn test_save_keeps_comments_and_unknown_keys() {
+        let content = r#"# Mine
+[general]
+precision = 1 # one decimal
+
+[popup]
+collapsed = ["codex"]
+experimental = "kept"
+
+[someday]
+unknown = 1
+"#;
+        let mut config = Config::parse(content).unwrap();
+        config.general.selected_provider = Some(Provider::Codex.into());
+        config.thresholds.warning = 60.0;
+        config.popup.collapsed.clear();
+        config
+            .providers
+            .entry(Provider::OpenCode)
+            .or_insert_with(|| ProviderConfig::default_for(Provider::OpenCode))
+            .enabled = true;
+
+        let saved = config.merge_into(content).unwrap();
+        assert!(saved.starts_with("# Mine\n[general]\nprecision = 1 # one decimal\n"));
+        assert!(saved.contains("selected_provider = \"codex\""), "{}", saved);
+        assert!(saved.contains("experimental = \"kept\""), "{}", saved);
+        assert!(saved.contains("[someday]\nunknown = 1"), "{}", saved);
+        assert!(!saved.contains("collapsed"), "{}", saved);
+        // Untouched defaults aren't spelled out
+        assert!(!saved.contains("width"), "{}", saved);
+
+        let reloaded = Config::parse(&saved).unwrap();
+        assert_eq!(
+            reloaded.general.selected_provider,
+            Some(Provider::Codex.into())
+        );
+        assert_eq!(reloaded.thresholds.warning, 60.0);
+        assert!(reloaded.is_provider_enabled(Provider::OpenCode));
+        assert_eq!(config.merge_into(&saved).unwrap(), saved);
+    }
+
+    #[test]
+    fn test_waybar_signal_validated() {
+        let config = Config::parse("[waybar]\nsignal = 8\n").unwrap();
+        assert_eq!(config.waybar.signal, Some(8));
+        let error = Config::parse("[waybar]\nsignal = 0\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("[waybar] signal"),
+            "{:#}",
+            error
+        );
+        assert!(Config::parse("[waybar]\nsignal = 99\n").is_err());
+    }
+
+    #[test]
+    fn test_popup_providers() {
+        let config = Config::parse(
+            "[popup]\norder = [\"codex\", \"nope\", \"opencode\"]\nhidden = [\"opencode\", \"bogus\"]\n\n\
+             [providers.opencode]\nenabled = true\n\n[providers.windsurf]\nenabled = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.popup_providers(),
+            [Provider::Codex, Provider::Claude, Provider::Windsurf]
+        );
+        // Hidden providers are still fetched for the bar
+        assert!(config.enabled_providers().contains(&Provider::OpenCode));
+        assert_eq!(
+            Config::default().popup_providers(),
+            Config::default().enabled_providers()
+        );
+    }
+
+    #[test]
+    fn test_popup_placement() {
+        let config =
+            Config::parse("[popup]\nanchor = \"bottom-left\"\nmargin_left = 60\nwidth = 400\n")
+                .unwrap();
+        assert_eq!(config.popup.anchor, PopupAnchor::BottomLeft);
+        assert_eq!(config.popup.margin_left, 60);
+        assert_eq!(config.popup.margin_bottom, 40);
+        assert_eq!(config.popup.width, 400);
+        assert_eq!(config.popup.max_height, None);
+
+        let error = Config::parse("[popup]\nanchor = \"top\"\n").unwrap_err();
+        assert!(format!("{:#}", error).contains("top-right"), "{:#}", error);
+        assert!(Config::parse("[popup]\nwidth = 0\n").is_err());
+        assert!(Config::parse("[popup]\nmax_height = 0\n").is_err());
+        let config = Config::parse("[popup]\nmax_height = 900\n").unwrap();
+        assert_eq!(config.popup.max_height, Some(900));
+
+        let config = Config::parse("[popup]\ncollapsed = [\"opencode\", \"kiro\"]\n").unwrap();
+        assert_eq!(config.popup.collapsed, [Provider::OpenCode, Provider::Kiro]);
+    }
+
+    #[test]
+    fn test_enum_values_ignore_case() {
+        let content = r#"
+[general]
+selected_provider = "Claude"
+icon_set = "ASCII"
+display = "Remaining"
+
+[waybar]
+mode = "Max"
+
+[popup]
+anchor = "Top-Left"
+collapsed = ["OpenCode"]
+order = ["Codex"]
+
+[providers.Codex]
+enabled = false
+
+[providers.Kiro]
+enabled = true
+
+[providers.kiro]
+enabled = false
+"#;
+        let (config, unknown, _) = Config::parse_checked(content).unwrap();
+        assert_eq!(unknown, vec![]);
+        assert_eq!(
+            config.general.selected_provider,
+            Some(Provider::Claude.into())
+        );
+        assert_eq!(config.general.icon_set, IconSet::Ascii);
+        assert_eq!(config.general.display, PercentDisplay::Remaining);
+        assert_eq!(config.waybar.mode, WaybarMode::Max);
+        assert_eq!(config.popup.anchor, PopupAnchor::TopLeft);
+        assert_eq!(config.popup.collapsed, [Provider::OpenCode]);
+        assert_eq!(config.popup.order, ["codex"]);
+        assert!(!config.is_provider_enabled(Provider::Codex));
+        // The canonically spelled table wins
+        assert!(!config.is_provider_enabled(Provider::Kiro));
+
+        // Saving doesn't rewrite values that only differ in case
+        assert_eq!(config.merge_into(content).unwrap(), content);
+    }
+
+    #[test]
+    fn test_older_versions_load_migrated() {
+        let (config, unknown, migrated) = Config::parse_checked("[popup]\nheight = 600\n").unwrap();
+        assert_eq!(unknown, vec![]);
+        assert_eq!(migrated.unwrap().from, 1);
+        assert_eq!(config.popup.max_height, Some(600));
+
+        let err = Config::parse("version = 99\n").unwrap_err();
+        assert!(
+            err.to_string().contains("only understands up to version"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_icon_settings() {
+        let config = Config::parse(
+            "[popup]\nicon_color = \"#2e3440\"\n\n[providers.codex]\nicon_path = \"/opt/icons/codex.svg\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.popup.icon_color.as_deref(), Some("#2e3440"));
+        assert_eq!(
+            config.icon_path(Provider::Codex),
+            Some(PathBuf::from("/opt/icons/codex.svg"))
+        );
+        assert_eq!(config.icon_path(Provider::Claude), None);
+
+        let error = Config::parse("[popup]\nicon_color = \"white\"\n").unwrap_err();
+        assert!(format!("{:#}", error).contains("icon_color"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_display_info_overrides() {
+        let config = Config::parse(
+            r#"
+[providers.codex]
+icon = "C"
+label = "Work Codex"
+
+[providers.opencode]
+icon = ""
+"#,
+        )
+        .unwrap();
+        let snapshots = crate::mock::mock_snapshots();
+        let info = |p: Provider| config.display_info(&snapshots[&p], p.icon());
+
+        let codex = info(Provider::Codex);
+        assert_eq!(codex.label, "Work Codex");
+        assert_eq!(codex.prefix("85%"), "C 85%");
+
+        // An empty icon means none; the label falls back to the built-in
+        let opencode = info(Provider::OpenCode);
+        assert_eq!(opencode.label, "OpenCode");
+        assert_eq!(opencode.prefix("15%"), "15%");
+
+        let claude = info(Provider::Claude);
+        assert_eq!(
+            claude,
+            DisplayInfo {
+                icon: Provider::Claude.icon().to_string(),
+                label: "Claude".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_threshold_precedence() {
+        let config = Config::parse(
+            r#"
+[thresholds]
+warning = 60.0
+
+[providers.codex.thresholds]
+critical = 80.0
+
+[providers.claude.thresholds]
+warning = 50.0
+critical = 70.0
+"#,
+        )
+        .unwrap();
+
+        // Provider override, then global, then built-in default
+        let codex = config.thresholds_for(Provider::Codex);
+        assert_eq!((codex.warning, codex.critical), (60.0, 80.0));
+        let opencode = config.thresholds_for(Provider::OpenCode);
+        assert_eq!((opencode.warning, opencode.critical), (60.0, 90.0));
+        let org = config.thresholds_for(Provider::ClaudeOrg);
+        assert_eq!((org.warning, org.critical), (50.0, 70.0));
+
+        assert_eq!(config.status_class(Provider::Codex, 79.0), "warning");
+        assert_eq!(config.status_class(Provider::Codex, 80.0), "critical");
+        assert_eq!(config.status_class(Provider::OpenCode, 59.0), "normal");
+        assert_eq!(
+            Config::default().status_class(Provider::Codex, 75.0),
+            "warning"
+        );
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(
+            parse_interval(" 1h30m ").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(parse_interval("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("1h 30m").unwrap(), Duration::from_secs(5400));
+        let error = format!("{:#}", parse_interval("5 bananas").unwrap_err());
+        assert!(error.contains("\"5m\""), "{}", error);
+        assert!(parse_interval("").is_err());
+        assert!(parse_interval("soon").is_err());
+        assert!(parse_interval("5").is_err());
+        assert!(parse_interval("0s").is_err());
+
+        let error = Config::parse("[general]\nrefresh_interval = \"5 bananas\"\n").unwrap_err();
+        let error = format!("{:#}", error);
+        assert!(error.contains("[general] refresh_interval"), "{}", error);
+        assert!(error.contains("1h30m"), "{}", error);
+    }
+
+    #[test]
+    fn test_durations_validated_at_load() {
+        let error = Config::parse("[waybar]\nstale_after = \"later\"\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("[waybar] stale_after"),
+            "{:#}",
+            error
+        );
+        let config = Config::parse("[waybar]\nstale_after = \"30m\"\n").unwrap();
+        assert_eq!(config.stale_after(), Duration::from_secs(1800));
+
+        let error = Config::parse("[popup]\nstale_after = \"old\"\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("[popup] stale_after"),
+            "{:#}",
+            error
+        );
+        let config = Config::parse("[popup]\nstale_after = \"1h\"\n").unwrap();
+        assert_eq!(config.popup_stale_after(), Duration::from_secs(3600));
+
+        let error = Config::parse("[general]\nmin_fetch_interval = \"30\"\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("[general] min_fetch_interval"),
+            "{:#}",
+            error
+        );
+        // Zero turns the reuse window off
+        let config = Config::parse("[general]\nmin_fetch_interval = \"0s\"\n").unwrap();
+        assert_eq!(config.min_fetch_interval(), Duration::ZERO);
+
+        let error = Config::parse("[history]\nretention = \"a month\"\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("[history] retention"),
+            "{:#}",
+            error
+        );
+        let config = Config::parse("[history]\nretention = \"7d\"\n").unwrap();
+        assert_eq!(config.history_retention(), Duration::from_secs(7 * 86400));
+    }
+
+    #[test]
+    fn test_refresh_interval_clamped() {
+        assert_eq!(
+            clamp_refresh_interval(Duration::from_secs(10)),
+            MIN_REFRESH_INTERVAL
+        );
+        assert_eq!(
+            clamp_refresh_interval(Duration::from_secs(300)),
+            Duration::from_secs(300)
+        );
+        let config = Config::parse("[general]\nrefresh_interval = \"5s\"\n").unwrap();
+        assert_eq!(config.refresh_interval(), MIN_REFRESH_INTERVAL);
+        assert_eq!(
+            Config::default().refresh_interval(),
+            Duration::from_secs(300)
+        );
+    }
+
+    #[test]
+    fn test_threshold_validation() {
+        let err = Config::parse("[thresholds]\nwarning = 90.0\ncritical = 80.0\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[thresholds] warning (90) must be below critical (80)"
+        );
+
+        // An override can break the ordering against the global value
+        let err = Config::parse("[providers.codex.thresholds]\nwarning = 95.0\n").unwrap_err();
+        assert!(err.to_string().starts_with("[providers.codex.thresholds]"));
+
+        assert!(Config::parse("[thresholds]\nwarning = 50.0\n").is_ok());
     }
 }
@@ -0,0 +1,896 @@
+use crate::config::{Config, ProviderConfig};
+use crate::files;
+use crate::migrate::Migrated;
+use crate::models::Provider;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Written by `config init`; every value is the built-in default
+pub const DEFAULT_CONFIG: &str = r##"# quotabar configuration
+# Change values with `quotabar config set <key> <value>` or edit this file.
+
+# Format of this file; `quotabar config migrate` updates older ones
+version = 2
+
+[general]
+# How often `daemon`, `watch` and `metrics --listen` refresh, and how old data
+# may get before the bar or popup fetches, e.g. "5m", "1h30m"; at least "30s"
+refresh_interval = "5m"
+# Provider shown in the bar, or "auto" for whichever is closest to a limit;
+# unset shows the first enabled provider with data
+# selected_provider = "claude"
+# Decimal places for percentages (capped by each provider's precision)
+precision = 0
+# Glyphs for terminal output: "nerd" or "ascii"
+icon_set = "nerd"
+# Show percentages as "used" or "remaining" (colors always follow usage)
+display = "used"
+# Clock times as "24h" (18:45) or "12h" (6:45 PM)
+time_format = "24h"
+# Show resets as "in 3h 12m, 18:45"; false shows only the time ("Mon 18:45")
+relative_times = true
+# Relative times as "long" ("in 3 days") or "short" ("3d")
+relative_style = "long"
+# Reuse cached snapshots younger than this instead of refetching
+min_fetch_interval = "30s"
+# Also write logs here (relative to ~/.cache/quotabar), rotated at 1 MiB
+# log_file = "quotabar.log"
+# Keep emails, organizations and account ids out of the cache, e.g. when
+# ~/.cache is synced between machines; the popup still shows them after a fetch
+redact_identity = false
+# Keep each provider's last raw API response in ~/.cache/quotabar/raw, to see
+# what changed when parsing breaks; skipped with redact_identity unless
+# debug_responses_with_identity is also set, as responses name the account
+debug_responses = false
+debug_responses_with_identity = false
+# Keep the cache and history as "json" files or in a "sqlite" database
+# (~/.cache/quotabar/quotabar.db, imported from the JSON files on first use);
+# sqlite needs a build with the sqlite feature and applies after a restart
+storage = "json"
+# Stamp snapshots with the host and credentials they came from, so a synced
+# cache shows another machine's data dimmed, "(from desktop)", and refetches it
+track_origin = true
+# Command that opens provider pages from the popup, with {url} for the address;
+# unset uses the desktop's default browser
+# open_url_command = "firefox --new-window {url}"
+
+[notifications]
+# Desktop notifications when usage crosses a threshold
+enabled = true
+# Also notify when a window is fully used
+on_depleted = true
+
+[status]
+# Draw a usage bar next to each window in `quotabar status`
+bars = false
+
+[waybar]
+# "selected" shows one provider, "max" the one closest to a limit, "all" each
+mode = "selected"
+# Bar text template; a [...] group is dropped when a value inside is missing.
+# Placeholders: {icon} {provider} {session} {session_remaining} {week}
+# {week_remaining} {cost} {cost_used} {cost_limit} {reset_session} {reset_week}
+# format = "{icon} {session}%[ / {week}%]"
+# Tooltip template; "\n" starts a new line
+# tooltip_format = "{provider}\nSession {session}%[ (resets {reset_session})]"
+# Add a "stale" class once the shown data is older than this
+stale_after = "15m"
+# Hide the module (keeping the tooltip) until a window reaches this % used
+hide_below = 0
+# Color the tooltip's window lines by status (Pango markup, [colors] below)
+tooltip_markup = false
+# Send SIGRTMIN+N to waybar after `fetch` or the daemon updates the cache;
+# match the module's "signal": N
+# signal = 8
+
+[polybar]
+# Layout template, e.g. "{icon} {session}%"; defaults to the Waybar text
+# format = "{icon} {session}%"
+
+[popup]
+# Turn a provider's "3 min ago" label amber once its data is older than this
+stale_after = "15m"
+# "top-right", "top-left", "bottom-right", "bottom-left" or "center"
+anchor = "top-right"
+# Monitor to open on, by connector name, or "focused" (Hyprland, Sway, niri);
+# unset lets the compositor choose
+# output = "DP-1"
+# Gaps to the anchored edges, in pixels
+margin_top = 40
+margin_right = 10
+margin_bottom = 40
+margin_left = 10
+# Width in pixels
+width = 320
+# Grow with the sections up to this height, then scroll; unset is 80% of the
+# monitor's height
+# max_height = 900
+# Close after this many idle seconds (no pointer or key activity); 0 never does
+auto_close_secs = 0
+# Close when clicking anywhere else
+close_on_focus_loss = false
+# Providers shown collapsed to their header; clicking a header toggles it
+# collapsed = ["opencode"]
+# Section order; providers not listed follow in the default order
+# order = ["codex", "claude"]
+# Providers left out of the popup but still fetched for the bar
+# hidden = ["opencode"]
+# Open a regular window instead of a layer-shell overlay (placed by the window
+# manager; anchor, margins and output are ignored). Used automatically where
+# the layer shell is missing, e.g. X11 or GNOME.
+force_window = false
+# Show account emails in the popup and `status`; off hides them when screen
+# sharing (organizations are still shown)
+show_email = true
+# A sparkline of the last 24 hours under each window's bar, from history.jsonl
+sparklines = true
+# "#RRGGBB" for provider icons; unset follows the theme's text color
+# icon_color = "#f8f8f2"
+# "dark", "light", or "auto" to follow the desktop's color scheme
+theme = "dark"
+
+[thresholds]
+# Used percentages at which the bar, popup and status turn warning/critical.
+# Override per provider under [providers.<name>.thresholds].
+warning = 75.0
+critical = 90.0
+
+[colors]
+# "#RRGGBB" colors for polybar, i3blocks, the Waybar tooltip markup and the
+# popup; unset normal keeps the bar's default
+# normal = "#98c379"
+warning = "#e5c07b"
+critical = "#e06c75"
+# Popup highlights and background; unset keeps the theme's
+# accent = "#f92672"
+# background = "#1c1c1c"
+
+[prompt]
+# Only print the prompt segment when a window is at least this % used
+threshold = 75.0
+
+[history]
+# Record usage in history.jsonl after each fetch, for `quotabar history`,
+# `export` and the popup sparklines
+enabled = true
+# Drop usage samples older than this
+retention = "30d"
+
+[providers.claude]
+enabled = true
+# Also show organization-wide usage (needs an Admin API key)
+# org_usage = true
+# Admin API key for org_usage; falls back to ANTHROPIC_ADMIN_KEY
+# admin_api_key = "sk-ant-admin..."
+# Monthly organization budget in USD
+# org_budget = 500.0
+# Read OAuth credentials from here instead of ~/.claude/.credentials.json
+# credentials_path = "/run/secrets/claude-credentials.json"
+# Environment variable holding an OAuth token to use instead of any file
+# token_env = "CLAUDE_OAUTH_TOKEN"
+
+# [providers.claude.thresholds]
+# warning = 60.0
+
+[providers.codex]
+enabled = true
+# Replace the built-in glyph and name; icon = "" shows no icon
+# icon = "C"
+# label = "Work"
+# SVG or image drawn in the popup instead of the built-in icon; SVGs using
+# currentColor follow the theme
+# icon_path = "~/.config/quotabar/codex.svg"
+# Directory with auth.json and config.toml; unset uses $CODEX_HOME or ~/.codex
+# codex_home = "/srv/codex"
+# auth.json to read instead of the one in the Codex directory
+# auth_path = "/run/secrets/codex-auth.json"
+# Environment variable holding a token to use instead of auth.json
+# token_env = "CODEX_TOKEN"
+
+# Rows to leave out of the popup and `status`: show_cost, show_secondary (the
+# week), show_tertiary (per-model windows) and show_identity. Hidden windows
+# still count toward warnings.
+# [providers.codex.display]
+# show_secondary = false
+
+[providers.opencode]
+enabled = false
+
+[providers.kiro]
+enabled = false
+
+[providers.windsurf]
+enabled = false
+"##;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Bool,
+    Integer,
+    Float,
+    Text,
+    /// A humantime duration string such as "5m"
+    Duration,
+}
+
+/// Settable keys; `<provider>` stands for any provider id
+const KEYS: &[(&str, Kind)] = &[
+    ("general.refresh_interval", Kind::Duration),
+    ("general.selected_provider", Kind::Text),
+    ("general.precision", Kind::Integer),
+    ("general.icon_set", Kind::Text),
+    ("general.display", Kind::Text),
+    ("general.time_format", Kind::Text),
+    ("general.relative_times", Kind::Bool),
+    ("general.relative_style", Kind::Text),
+    ("general.min_fetch_interval", Kind::Duration),
+    ("general.log_file", Kind::Text),
+    ("general.redact_identity", Kind::Bool),
+    ("general.debug_responses", Kind::Bool),
+    ("general.debug_responses_with_identity", Kind::Bool),
+    ("general.storage", Kind::Text),
+    ("general.track_origin", Kind::Bool),
+    ("general.open_url_command", Kind::Text),
+    ("notifications.enabled", Kind::Bool),
+    ("notifications.on_depleted", Kind::Bool),
+    ("status.bars", Kind::Bool),
+    ("waybar.mode", Kind::Text),
+    ("waybar.format", Kind::Text),
+    ("waybar.tooltip_format", Kind::Text),
+    ("waybar.stale_after", Kind::Duration),
+    ("waybar.hide_below", Kind::Float),
+    ("waybar.tooltip_markup", Kind::Bool),
+    ("waybar.signal", Kind::Integer),
+    ("polybar.format", Kind::Text),
+    ("popup.stale_after", Kind::Duration),
+    ("popup.anchor", Kind::Text),
+    ("popup.output", Kind::Text),
+    ("popup.margin_top", Kind::Integer),
+    ("popup.margin_right", Kind::Integer),
+    ("popup.margin_bottom", Kind::Integer),
+    ("popup.margin_left", Kind::Integer),
+    ("popup.width", Kind::Integer),
+    ("popup.max_height", Kind::Integer),
+    ("popup.auto_close_secs", Kind::Integer),
+    ("popup.close_on_focus_loss", Kind::Bool),
+    ("popup.force_window", Kind::Bool),
+    ("popup.show_email", Kind::Bool),
+    ("popup.sparklines", Kind::Bool),
+    ("popup.icon_color", Kind::Text),
+    ("popup.theme", Kind::Text),
+    ("thresholds.warning", Kind::Float),
+    ("thresholds.critical", Kind::Float),
+    ("colors.normal", Kind::Text),
+    ("colors.warning", Kind::Text),
+    ("colors.critical", Kind::Text),
+    ("colors.accent", Kind::Text),
+    ("colors.background", Kind::Text),
+    ("prompt.threshold", Kind::Float),
+    ("history.enabled", Kind::Bool),
+    ("history.retention", Kind::Duration),
+    ("providers.<provider>.enabled", Kind::Bool),
+    ("providers.<provider>.org_usage", Kind::Bool),
+    ("providers.<provider>.admin_api_key", Kind::Text),
+    ("providers.<provider>.org_budget", Kind::Float),
+    ("providers.<provider>.thresholds.warning", Kind::Float),
+    ("providers.<provider>.thresholds.critical", Kind::Float),
+    ("providers.<provider>.icon", Kind::Text),
+    ("providers.<provider>.label", Kind::Text),
+    ("providers.<provider>.icon_path", Kind::Text),
+    ("providers.<provider>.credentials_path", Kind::Text),
+    ("providers.<provider>.codex_home", Kind::Text),
+    ("providers.<provider>.auth_path", Kind::Text),
+    ("providers.<provider>.token_env", Kind::Text),
+    ("providers.<provider>.display.show_cost", Kind::Bool),
+    ("providers.<provider>.display.show_secondary", Kind::Bool),
+    ("providers.<provider>.display.show_tertiary", Kind::Bool),
+    ("providers.<provider>.display.show_identity", Kind::Bool),
+];
+
+/// Keys holding lists, which `config set` can't write but the file may set
+const LIST_KEYS: &[&str] = &["popup.collapsed", "popup.order", "popup.hidden"];
+
+/// Keys managed by quotabar itself rather than `config set`
+const MANAGED_KEYS: &[&str] = &["version"];
+
+/// A key in the config file that quotabar doesn't read, usually a typo
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownKey {
+    pub key: String,
+    /// The closest valid key at the same level, when one is close enough
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown key {:?}", self.key)?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, "; did you mean {:?}?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys in a parsed config file that no setting reads, with the nearest
+/// valid name for each
+pub fn unknown_keys(table: &toml::Table) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    collect_unknown(table, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown(table: &toml::Table, prefix: &str, unknown: &mut Vec<UnknownKey>) {
+    let siblings = children(&pattern(prefix));
+    for (name, value) in table {
+        let key = match prefix {
+            "" => name.clone(),
+            prefix => format!("{}.{}", prefix, name),
+        };
+        let pattern = pattern(&key);
+        match value {
+            toml::Value::Table(table) if !children(&pattern).is_empty() => {
+                collect_unknown(table, &key, unknown);
+            }
+            _ if known_keys().any(|k| k == pattern) => {}
+            _ => unknown.push(UnknownKey {
+                suggestion: nearest(name, &siblings).map(|s| match prefix {
+                    "" => s.to_string(),
+                    prefix => format!("{}.{}", prefix, s),
+                }),
+                key,
+            }),
+        }
+    }
+}
+
+fn known_keys() -> impl Iterator<Item = &'static str> {
+    KEYS.iter()
+        .map(|(k, _)| *k)
+        .chain(LIST_KEYS.iter().copied())
+        .chain(MANAGED_KEYS.iter().copied())
+}
+
+/// `key` with a provider id replaced by `<provider>`, as in [`KEYS`]
+fn pattern(key: &str) -> String {
+    match key.split_once('.') {
+        Some(("providers", rest)) => {
+            let (id, field) = rest.split_once('.').unwrap_or((rest, ""));
+            if !provider_ids().contains(&id) {
+                return key.to_string();
+            }
+            match field {
+                "" => "providers.<provider>".to_string(),
+                field => format!("providers.<provider>.{}", field),
+            }
+        }
+        _ => key.to_string(),
+    }
+}
+
+/// The names valid directly below the table `pattern` ("" for the top
+/// level), with `<provider>` spelled out as each provider id
+fn children(pattern: &str) -> Vec<&'static str> {
+    let mut names = Vec::new();
+    for key in known_keys() {
+        let rest = match pattern {
+            "" => Some(key),
+            pattern => key
+                .strip_prefix(pattern)
+                .and_then(|rest| rest.strip_prefix('.')),
+        };
+        let Some(name) = rest.and_then(|rest| rest.split('.').next()) else {
+            continue;
+        };
+        let expanded = match name {
+            "<provider>" => provider_ids(),
+            name => vec![name],
+        };
+        for name in expanded {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// The candidate closest to `name` by edit distance, if it's within a third
+/// of the name's length (at least one edit)
+fn nearest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance counting single-character inserts, deletes, substitutions
+/// and swaps of neighbours ("wdith" is one edit from "width")
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j]: between the first i chars of a and the first j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = best;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// Look up a dotted key, matching `providers.<id>.*` against provider ids
+fn lookup(key: &str) -> Result<Kind> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let pattern = match parts.as_slice() {
+        ["providers", id, field @ ..] if !field.is_empty() => {
+            let provider = Provider::ALL
+                .into_iter()
+                .filter(|p| *p != Provider::ClaudeOrg)
+                .find(|p| p.id() == *id);
+            if provider.is_none() {
+                bail!(
+                    "Unknown provider {:?} in {:?}; expected one of: {}",
+                    id,
+                    key,
+                    provider_ids().join(", ")
+                );
+            }
+            format!("providers.<provider>.{}", field.join("."))
+        }
+        _ => key.to_string(),
+    };
+    if let Some((_, kind)) = KEYS.iter().find(|(k, _)| *k == pattern) {
+        return Ok(*kind);
+    }
+    let section = parts[0];
+    let siblings: Vec<&str> = KEYS
+        .iter()
+        .map(|(k, _)| *k)
+        .filter(|k| k.split('.').next() == Some(section))
+        .collect();
+    if siblings.is_empty() {
+        bail!("Unknown config key {:?}; valid keys: {}", key, key_list());
+    }
+    bail!(
+        "Unknown config key {:?}; keys in [{}]: {}",
+        key,
+        section,
+        siblings.join(", ")
+    )
+}
+
+fn provider_ids() -> Vec<&'static str> {
+    Provider::ALL
+        .into_iter()
+        .filter(|p| *p != Provider::ClaudeOrg)
+        .map(|p| p.id())
+        .collect()
+}
+
+fn key_list() -> String {
+    KEYS.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(", ")
+}
+
+/// The effective value of `key`: the config file merged over the defaults.
+/// Returns `None` for optional keys that are unset.
+pub fn get(config: &Config, key: &str) -> Result<Option<String>> {
+    lookup(key)?;
+    let mut effective = config.clone();
+    for id in provider_ids() {
+        let provider = Provider::ALL.into_iter().find(|p| p.id() == id);
+        if let Some(provider) = provider {
+            effective
+                .providers
+                .entry(provider)
+                .or_insert_with(|| ProviderConfig::default_for(provider));
+        }
+    }
+
+    let mut value = toml::Value::try_from(&effective)?;
+    for part in key.split('.') {
+        match value.get(part) {
+            Some(next) => value = next.clone(),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(match value {
+        toml::Value::String(s) => s,
+        other => other.to_string(),
+    }))
+}
+
+/// Parse `raw` as the key's type and write it into the document, keeping
+/// comments, formatting and keys quotabar doesn't know about
+fn set_in_document(doc: &mut DocumentMut, key: &str, raw: &str) -> Result<()> {
+    let kind = lookup(key)?;
+    let invalid =
+        |expected: &str| format!("Invalid value {:?} for {}: expected {}", raw, key, expected);
+    let mut value: toml_edit::Value = match kind {
+        Kind::Bool => raw
+            .parse::<bool>()
+            .with_context(|| invalid("true or false"))?
+            .into(),
+        Kind::Integer => raw
+            .parse::<i64>()
+            .with_context(|| invalid("a whole number"))?
+            .into(),
+        Kind::Float => raw
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .with_context(|| invalid("a number"))?
+            .into(),
+        Kind::Duration => {
+            humantime::parse_duration(raw).with_context(|| invalid("a duration like \"5m\""))?;
+            raw.into()
+        }
+        Kind::Text => raw.into(),
+    };
+
+    let parts: Vec<&str> = key.split('.').collect();
+    let (field, tables) = parts.split_last().context("Empty key")?;
+    let mut table = doc.as_table_mut();
+    for part in tables {
+        let item = table.entry(part).or_insert_with(|| {
+            let mut new = Table::new();
+            new.set_implicit(true);
+            Item::Table(new)
+        });
+        table = item
+            .as_table_mut()
+            .with_context(|| format!("[{}] in the config file is not a table", part))?;
+    }
+    // Assign in place so the key's own comments and position survive
+    match table.get_mut(field) {
+        Some(item) => {
+            if let Some(existing) = item.as_value() {
+                *value.decor_mut() = existing.decor().clone();
+            }
+            *item = Item::Value(value);
+        }
+        None => {
+            table.insert(field, Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Apply `key = value` to the config text, then check the result still
+/// loads
+fn set_text(content: &str, key: &str, raw: &str) -> Result<String> {
+    let mut doc: DocumentMut = content.parse().context("Config file is not valid TOML")?;
+    set_in_document(&mut doc, key, raw)?;
+    let updated = doc.to_string();
+    Config::parse(&updated)
+        .map_err(|e| anyhow::anyhow!("Invalid value {:?} for {}: {:#}", raw, key, e))?;
+    Ok(updated)
+}
+
+pub fn set(path: &Path, key: &str, raw: &str) -> Result<()> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let updated = set_text(&content, key, raw)?;
+    write(path, &updated)
+}
+
+/// Write `content` as the config; refuses to replace an existing file
+/// unless `force`
+pub fn init(path: &Path, content: &str, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "{} already exists; use `quotabar config set` or `config edit`, or \
+             --force to replace it",
+            path.display()
+        );
+    }
+    write(path, content)
+}
+
+/// The commented default config with only the `detected` providers enabled
+/// among those detection decides on, and the first of them selected. With
+/// nothing detected it's the plain default.
+pub fn detected_config(detected: &[Provider]) -> Result<String> {
+    let Some(first) = detected.first() else {
+        return Ok(DEFAULT_CONFIG.to_string());
+    };
+    // Filled in where the default leaves it commented out
+    let mut content = DEFAULT_CONFIG.replacen(
+        "# selected_provider = \"claude\"",
+        &format!("selected_provider = \"{}\"", first.id()),
+        1,
+    );
+    for provider in crate::detect::detectable() {
+        let enabled = detected.contains(&provider);
+        content = set_text(
+            &content,
+            &format!("providers.{}.enabled", provider.id()),
+            &enabled.to_string(),
+        )?;
+    }
+    Ok(content)
+}
+
+/// Open the config in $VISUAL/$EDITOR, creating it first, and report parse
+/// errors afterwards instead of letting them fall back to defaults silently
+pub fn edit(path: &Path) -> Result<()> {
+    if !path.exists() {
+        write(path, DEFAULT_CONFIG)?;
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        bail!("{} exited with {}", editor, status);
+    }
+    for problem in validate(path)? {
+        eprintln!("{}: {}", path.display(), problem);
+    }
+    Ok(())
+}
+
+/// Check the config file strictly: errors fail as usual, and unknown keys
+/// and warnings are returned for the caller to report
+pub fn validate(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (config, unknown, _) = Config::parse_checked(&content)
+        .with_context(|| format!("{} has errors and will be ignored", path.display()))?;
+    Ok(crate::config::problems(&config, &unknown))
+}
+
+/// Rewrite the file in the current format, keeping comments and unknown keys;
+/// None when it already is
+pub fn migrate(path: &Path) -> Result<Option<Migrated>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let Some(migrated) = crate::migrate::upgrade(&content)? else {
+        return Ok(None);
+    };
+    let updated = migrated.document.to_string();
+    Config::parse(&updated)
+        .with_context(|| format!("{} has errors; fix them before migrating", path.display()))?;
+    write(path, &updated)?;
+    Ok(Some(migrated))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    files::write_atomic(path, content.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const COMMENTED: &str = r#"# My quotabar setup
+[general]
+# pinned while I try Codex
+selected_provider = "claude" # was codex
+precision = 1
+
+[providers.codex]
+enabled = true
+experimental_flag = "kept"
+
+[someday]
+unknown = 1
+"#;
+
+    #[test]
+    fn test_set_preserves_comments_and_unknown_keys() {
+        let updated = set_text(COMMENTED, "general.selected_provider", "codex").unwrap();
+        let updated = set_text(&updated, "providers.codex.enabled", "false").unwrap();
+        assert_eq!(
+            updated,
+            COMMENTED
+                .replace(
+                    "selected_provider = \"claude\"",
+                    "selected_provider = \"codex\""
+                )
+                .replace("enabled = true", "enabled = false")
+        );
+    }
+
+    #[test]
+    fn test_set_adds_missing_tables() {
+        let updated = set_text(COMMENTED, "providers.kiro.enabled", "true").unwrap();
+        // The new table lands next to its siblings
+        assert_eq!(
+            updated,
+            COMMENTED.replace("[someday]", "[providers.kiro]\nenabled = true\n\n[someday]")
+        );
+
+        let updated = set_text("", "history.retention", "90d").unwrap();
+        assert_eq!(updated, "[history]\nretention = \"90d\"\n");
+    }
+
+    #[test]
+    fn test_set_rejects_bad_keys_and_values() {
+        let error = |key, value| format!("{:#}", set_text(COMMENTED, key, value).unwrap_err());
+        assert!(error("general.nope", "1").contains("keys in [general]"));
+        assert!(error("nope", "1").contains("valid keys"));
+        assert!(error("providers.bogus.enabled", "true").contains("Unknown provider"));
+        assert!(error("general.precision", "two").contains("a whole number"));
+        assert!(error("general.precision", "300").contains("general.precision"));
+        assert!(error("general.refresh_interval", "soon").contains("a duration"));
+        assert!(error("general.selected_provider", "gemini").contains("unknown variant"));
+        assert!(error("status.bars", "yes").contains("true or false"));
+        assert!(error("thresholds.warning", "95").contains("must be below critical (90)"));
+    }
+
+    #[test]
+    fn test_set_provider_thresholds() {
+        let updated = set_text("", "providers.codex.thresholds.critical", "80").unwrap();
+        assert_eq!(updated, "[providers.codex.thresholds]\ncritical = 80.0\n");
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(config.status_class(Provider::Codex, 85.0), "critical");
+
+        let error = set_text(&updated, "providers.codex.thresholds.warning", "85").unwrap_err();
+        assert!(format!("{:#}", error).contains("[providers.codex.thresholds]"));
+    }
+
+    #[test]
+    fn test_get_effective_values() {
+        let config: Config = toml::from_str(COMMENTED).unwrap();
+        assert_eq!(
+            get(&config, "general.selected_provider")
+                .unwrap()
+                .as_deref(),
+            Some("claude")
+        );
+        assert_eq!(
+            get(&config, "general.precision").unwrap().as_deref(),
+            Some("1")
+        );
+        // Defaults fill in what the file leaves out
+        assert_eq!(
+            get(&config, "general.refresh_interval").unwrap().as_deref(),
+            Some("5m")
+        );
+        assert_eq!(
+            get(&config, "providers.kiro.enabled").unwrap().as_deref(),
+            Some("false")
+        );
+        assert_eq!(get(&config, "polybar.format").unwrap(), None);
+        assert!(get(&config, "general.nope").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_suggest_nearest() {
+        let (_, unknown, _) = Config::parse_checked(
+            r##"
+[general]
+refresh_intreval = "10m"
+selected_provider = "codex"
+
+[colour]
+warning = "#ffffff"
+
+[popup]
+collapsed = ["codex"]
+wdith = 400
+zzz = 1
+
+[providers.claud]
+enabled = true
+
+[providers.codex]
+Enabled = true
+
+[providers.codex.thresholds]
+critcal = 80.0
+"##,
+        )
+        .unwrap();
+        let found: Vec<(&str, Option<&str>)> = unknown
+            .iter()
+            .map(|k| (k.key.as_str(), k.suggestion.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("colour", Some("colors")),
+                ("general.refresh_intreval", Some("general.refresh_interval")),
+                ("popup.wdith", Some("popup.width")),
+                ("popup.zzz", None),
+                ("providers.claud", Some("providers.claude")),
+                ("providers.codex.Enabled", Some("providers.codex.enabled")),
+                (
+                    "providers.codex.thresholds.critcal",
+                    Some("providers.codex.thresholds.critical")
+                ),
+            ]
+        );
+        assert_eq!(
+            unknown[1].to_string(),
+            "unknown key \"general.refresh_intreval\"; did you mean \"general.refresh_interval\"?"
+        );
+        assert_eq!(unknown[3].to_string(), "unknown key \"popup.zzz\"");
+
+        let (_, unknown, _) = Config::parse_checked(DEFAULT_CONFIG).unwrap();
+        assert_eq!(unknown, vec![]);
+    }
+
+    #[test]
+    fn test_default_config_matches_defaults() {
+        let parsed: Config = toml::from_str(DEFAULT_CONFIG).unwrap();
+        // A fresh config needs no migrating
+        assert!(crate::migrate::upgrade(DEFAULT_CONFIG).unwrap().is_none());
+        assert_eq!(
+            toml::Value::try_from(&parsed).unwrap(),
+            toml::Value::try_from(Config::default()).unwrap()
+        );
+        for (key, _) in KEYS {
+            let key = key.replace("<provider>", "claude");
+            assert_eq!(
+                get(&parsed, &key).unwrap(),
+                get(&Config::default(), &key).unwrap(),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_detected_config() {
+        let content = detected_config(&[Provider::Codex, Provider::Windsurf]).unwrap();
+        let config = Config::parse(&content).unwrap();
+        assert_eq!(
+            config.general.selected_provider,
+            Some(Provider::Codex.into())
+        );
+        assert_eq!(
+            config.enabled_providers(),
+            [Provider::Codex, Provider::Windsurf]
+        );
+        // Still the commented file, not a bare dump
+        assert!(content.contains("# Provider shown in the bar"));
+        assert!(!content.contains("# selected_provider"));
+
+        assert_eq!(detected_config(&[]).unwrap(), DEFAULT_CONFIG);
+    }
+
+    #[test]
+    fn test_init_needs_force_to_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotabar").join("config.toml");
+        init(&path, DEFAULT_CONFIG, false).unwrap();
+        std::fs::write(&path, "[general]\nprecision = 2\n").unwrap();
+
+        let err = init(&path, DEFAULT_CONFIG, false).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[general]\nprecision = 2\n"
+        );
+
+        init(&path, DEFAULT_CONFIG, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+    }
+}
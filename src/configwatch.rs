@@ -0,0 +1,156 @@
+//! Noticing edits to config.toml in the daemon and an open popup
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Editors often write twice in quick succession (truncate then write, or a
+/// backup then a rename); changes this close together count once
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Call `on_change` whenever `path` is written, replaced or removed. The
+/// directory is watched rather than the file, so saves that swap in a new
+/// file are seen too.
+pub fn watch(path: &Path, on_change: impl Fn() + Send + 'static) -> Result<RecommendedWatcher> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let file = path.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        if result.is_ok_and(|event| touches(&event, &file)) {
+            on_change();
+        }
+    })
+    .context("Failed to start watching the config")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    Ok(watcher)
+}
+
+/// Whether `event` changes `file`; reads, including quotabar's own, don't
+fn touches(event: &Event, file: &Path) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) && event
+        .paths
+        .iter()
+        .any(|p| p.file_name() == file.file_name())
+}
+
+/// The config as it is now on disk; None, after logging why, when it can't
+/// be used and the previous one should stay
+pub fn reload() -> Option<Config> {
+    match Config::load() {
+        Ok(config) => {
+            tracing::info!("config reloaded");
+            Some(config)
+        }
+        Err(e) => {
+            tracing::warn!("keeping the previous config: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Wait until no change has arrived for [`DEBOUNCE`], swallowing the ones
+/// that do
+pub async fn settle(changes: &mut UnboundedReceiver<()>) {
+    while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, changes.recv()).await {}
+}
+
+/// [`DEBOUNCE`] for callers that poll, such as a GTK timer
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn changed(&mut self, at: Instant) {
+        self.last_change = Some(at);
+    }
+
+    /// True once per burst of changes, when it has been quiet for [`DEBOUNCE`]
+    pub fn settled(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(at) if now.duration_since(at) >= DEBOUNCE => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_touches_only_writes_to_the_file() {
+        let file = PathBuf::from("/home/u/.config/quotabar/config.toml");
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        assert!(touches(
+            &event(
+                EventKind::Modify(ModifyKind::Any),
+                "/home/u/.config/quotabar/config.toml"
+            ),
+            &file
+        ));
+        // Saved through a rename
+        assert!(touches(
+            &event(
+                EventKind::Create(CreateKind::File),
+                "/home/u/.config/quotabar/config.toml"
+            ),
+            &file
+        ));
+        assert!(!touches(
+            &event(
+                EventKind::Modify(ModifyKind::Any),
+                "/home/u/.config/quotabar/style.css"
+            ),
+            &file
+        ));
+        assert!(!touches(
+            &event(
+                EventKind::Access(AccessKind::Any),
+                "/home/u/.config/quotabar/config.toml"
+            ),
+            &file
+        ));
+    }
+
+    #[test]
+    fn test_debouncer_waits_for_quiet() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::default();
+        assert!(!debouncer.settled(start));
+
+        // An editor's two writes count once, after the second settles
+        debouncer.changed(start);
+        debouncer.changed(start + Duration::from_millis(100));
+        assert!(!debouncer.settled(start + Duration::from_millis(350)));
+        assert!(debouncer.settled(start + Duration::from_millis(400)));
+        assert!(!debouncer.settled(start + Duration::from_millis(800)));
+    }
+
+    #[tokio::test]
+    async fn test_settle_swallows_a_burst() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for _ in 0..3 {
+            tx.send(()).unwrap();
+        }
+        settle(&mut rx).await;
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use crate::cache::Backend;
+use crate::config::Config;
+use crate::configwatch;
+use crate::fetch;
+use crate::models::Provider;
+use crate::paths;
+use crate::providers;
+use crate::ratelimit::{self, RateLimitInfo};
+use crate::socket;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Cap on cycles skipped after repeated auth failures (2^4 - 1)
+const MAX_AUTH_BACKOFF_EXPONENT: u32 = 4;
+
+/// Providers whose credentials keep getting rejected are skipped for a
+/// growing number of cycles (1, 3, 7, then 15) instead of every tick
+#[derive(Debug, Default)]
+struct AuthBackoff {
+    /// Consecutive auth failures and cycles left to skip
+    providers: HashMap<Provider, (u32, u32)>,
+}
+
+impl AuthBackoff {
+    /// Whether to skip `provider` this cycle; counts the skip down
+    fn skip(&mut self, provider: Provider) -> bool {
+        match self.providers.get_mut(&provider) {
+            Some((_, remaining)) if *remaining > 0 => {
+                *remaining -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, provider: Provider, unauthorized: bool) {
+        if !unauthorized {
+            self.providers.remove(&provider);
+            return;
+        }
+        let (failures, remaining) = self.providers.entry(provider).or_default();
+        *failures += 1;
+        *remaining = (1 << (*failures).min(MAX_AUTH_BACKOFF_EXPONENT)) - 1;
+    }
+}
+
+/// Refresh the cache every `refresh_interval` (with ±10% jitter) until
+/// SIGTERM or Ctrl-C, serving it on the snapshot socket meanwhile. SIGUSR1,
+/// or a refresh request on the socket, refetches immediately, bypassing
+/// `min_fetch_interval` and any auth backoff. A usage endpoint close to its
+/// own rate limit stretches the wait until it resets. Edits to the config
+/// apply from the next cycle; one that doesn't parse keeps the previous config.
+pub async fn run(waybar_signal: Option<u8>) -> Result<()> {
+    let mut config = Config::load()?;
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let mut term = signal(SignalKind::terminate())?;
+    let mut backoff = AuthBackoff::default();
+    let mut force = false;
+
+    let (refresh_tx, mut refresh_requests) = mpsc::unbounded_channel();
+    let _server = match paths::socket_path() {
+        Some(path) => socket::serve(&path, Backend::current().clone(), refresh_tx)
+            .inspect_err(|e| warn!("not serving snapshots: {:#}", e))
+            .ok(),
+        None => {
+            info!("XDG_RUNTIME_DIR is unset, not serving snapshots");
+            None
+        }
+    };
+
+    let (tx, mut changes) = mpsc::unbounded_channel();
+    let watcher = configwatch::watch(&Config::config_path(), move || {
+        let _ = tx.send(());
+    })
+    .inspect_err(|e| warn!("reloading the config every cycle instead: {:#}", e))
+    .ok();
+
+    info!(
+        "daemon started, refreshing every {}",
+        humantime::format_duration(config.refresh_interval())
+    );
+    'daemon: loop {
+        if watcher.is_none() {
+            config = configwatch::reload().unwrap_or(config);
+        }
+        let (updated, headroom) = tokio::select! {
+            cycled = cycle(&config, &mut backoff, force) => cycled,
+            _ = term.recv() => break,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        if let (Some(n), true) = (waybar_signal.or(config.waybar.signal), updated) {
+            if let Err(e) = crate::barsignal::send(n) {
+                warn!("failed to signal waybar: {:#}", e);
+            }
+        }
+
+        // A reload mid-sleep moves the next cycle to the new interval
+        let finished = tokio::time::Instant::now();
+        let finished_at = Utc::now();
+        let jitter = 0.9 + fastrand::f64() * 0.2;
+        let stretched = poll_interval(config.refresh_interval(), &headroom, finished_at);
+        if stretched > config.refresh_interval() {
+            info!(
+                "usage endpoint is low on requests, next refresh in {}",
+                humantime::format_duration(stretched)
+            );
+        }
+        loop {
+            let interval = poll_interval(config.refresh_interval(), &headroom, finished_at);
+            let next = finished + interval.mul_f64(jitter);
+            tokio::select! {
+                _ = tokio::time::sleep_until(next) => {
+                    force = false;
+                    break;
+                }
+                _ = usr1.recv() => {
+                    info!("SIGUSR1 received, refreshing now");
+                    backoff = AuthBackoff::default();
+                    force = true;
+                    break;
+                }
+                Some(()) = refresh_requests.recv() => {
+                    info!("refresh requested on the socket, refreshing now");
+                    backoff = AuthBackoff::default();
+                    force = true;
+                    break;
+                }
+                Some(()) = changes.recv() => {
+                    configwatch::settle(&mut changes).await;
+                    if let Some(reloaded) = configwatch::reload() {
+                        config = reloaded;
+                    }
+                }
+                _ = term.recv() => break 'daemon,
+                _ = tokio::signal::ctrl_c() => break 'daemon,
+            }
+        }
+    }
+
+    info!("daemon stopped");
+    Ok(())
+}
+
+/// `base`, stretched for the provider whose usage endpoint is closest to its
+/// own rate limit
+fn poll_interval(
+    base: std::time::Duration,
+    headroom: &[RateLimitInfo],
+    now: DateTime<Utc>,
+) -> std::time::Duration {
+    headroom
+        .iter()
+        .map(|info| ratelimit::effective_interval(base, Some(info), now))
+        .max()
+        .unwrap_or(base)
+}
+
+/// One refresh; returns whether the cache was rewritten with fresh data, and
+/// the rate-limit headroom the refreshed providers' endpoints reported
+async fn cycle(
+    config: &Config,
+    backoff: &mut AuthBackoff,
+    force: bool,
+) -> (bool, Vec<RateLimitInfo>) {
+    let mut targets = Vec::new();
+    for provider in config.enabled_providers() {
+        if backoff.skip(provider) {
+            info!(
+                "{}: skipped, credentials were rejected",
+                provider.display_name()
+            );
+        } else {
+            targets.push(provider);
+        }
+    }
+
+    let started = Instant::now();
+    let result = fetch::refresh(config, &targets, force).await;
+    for &provider in &targets {
+        let unauthorized = result
+            .errors
+            .get(&provider)
+            .is_some_and(providers::is_unauthorized);
+        backoff.record(provider, unauthorized);
+    }
+
+    for provider in &targets {
+        if let Some(error) = result.errors.get(provider) {
+            warn!("{}: {:#}", provider.display_name(), error);
+        }
+    }
+    let saved = match result.save_error {
+        Some(ref e) => {
+            warn!("failed to write cache: {:#}", e);
+            false
+        }
+        None => true,
+    };
+    info!(
+        "refreshed {} provider(s) in {}ms ({} fetched, {} reused, {} backed off, {} failed)",
+        targets.len(),
+        started.elapsed().as_millis(),
+        result.fetched.len(),
+        result.reused.len(),
+        result.backed_off.len(),
+        result.errors.len()
+    );
+    let headroom = targets
+        .iter()
+        .filter_map(|p| result.snapshots.get(p)?.rate_limit.clone())
+        .collect();
+    (saved && !result.fetched.is_empty(), headroom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_backoff_grows_and_resets() {
+        let mut backoff = AuthBackoff::default();
+        let skipped = |backoff: &mut AuthBackoff| {
+            let mut n = 0;
+            while backoff.skip(Provider::Codex) {
+                n += 1;
+            }
+            n
+        };
+
+        backoff.record(Provider::Codex, true);
+        assert_eq!(skipped(&mut backoff), 1);
+        backoff.record(Provider::Codex, true);
+        assert_eq!(skipped(&mut backoff), 3);
+        for _ in 0..5 {
+            backoff.record(Provider::Codex, true);
+        }
+        assert_eq!(skipped(&mut backoff), 15);
+        assert!(!backoff.skip(Provider::Claude));
+
+        backoff.record(Provider::Codex, false);
+        assert!(!backoff.skip(Provider::Codex));
+    }
+
+    #[test]
+    fn test_poll_interval_follows_lowest_headroom() {
+        let now = Utc::now();
+        let base = std::time::Duration::from_secs(300);
+        let info = |remaining, reset_secs| RateLimitInfo {
+            limit: Some(60),
+            remaining: Some(remaining),
+            resets_at: Some(now + chrono::Duration::seconds(reset_secs)),
+        };
+        assert_eq!(poll_interval(base, &[], now), base);
+        assert_eq!(poll_interval(base, &[info(50, 3600)], now), base);
+        // Low on requests: twice the interval, or until the reset if later
+        assert_eq!(
+            poll_interval(base, &[info(50, 60), info(3, 60)], now),
+            base * 2
+        );
+        assert_eq!(
+            poll_interval(base, &[info(3, 60), info(1, 1800)], now),
+            std::time::Duration::from_secs(1800)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Which providers this machine is signed in to, judged from their
+//! credential files alone: nothing is read over the network
+
+use crate::models::Provider;
+use std::path::Path;
+
+/// Somewhere a provider's credentials live
+#[derive(Debug, Clone, Copy)]
+enum Probe {
+    /// A file under the home directory
+    File(&'static str),
+    /// A file in the directory an environment variable names
+    EnvFile(&'static str, &'static str),
+    /// Any `.json` file in a directory under the home directory
+    JsonIn(&'static str),
+}
+
+/// Credential locations, matching the defaults each provider reads. OpenCode
+/// has nothing to find and Claude Org needs an Admin API key, so neither is
+/// detected.
+const PROBES: &[(Provider, Probe)] = &[
+    (Provider::Claude, Probe::File(".claude/.credentials.json")),
+    (Provider::Codex, Probe::EnvFile("CODEX_HOME", "auth.json")),
+    (Provider::Codex, Probe::File(".codex/auth.json")),
+    (Provider::Kiro, Probe::JsonIn(".aws/sso/cache")),
+    (Provider::Windsurf, Probe::File(".codeium/config.json")),
+];
+
+/// Providers detection decides on; the rest keep their defaults
+pub fn detectable() -> Vec<Provider> {
+    let mut providers: Vec<Provider> = PROBES.iter().map(|(p, _)| *p).collect();
+    providers.dedup();
+    providers
+}
+
+/// Providers with credentials under `home` (or where `env` points), in
+/// display order
+pub fn detect(home: &Path, env: impl Fn(&str) -> Option<String>) -> Vec<Provider> {
+    let found = |probe: &Probe| match *probe {
+        Probe::File(path) => home.join(path).is_file(),
+        Probe::EnvFile(var, file) => env(var)
+            .filter(|dir| !dir.is_empty())
+            .is_some_and(|dir| Path::new(&dir).join(file).is_file()),
+        Probe::JsonIn(dir) => std::fs::read_dir(home.join(dir)).is_ok_and(|entries| {
+            entries
+                .flatten()
+                .any(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        }),
+    };
+    Provider::ALL
+        .into_iter()
+        .filter(|provider| {
+            PROBES
+                .iter()
+                .any(|(p, probe)| p == provider && found(probe))
+        })
+        .collect()
+}
+
+/// [`detect`] for the current user
+pub fn detect_here() -> Vec<Provider> {
+    let home = dirs::home_dir().unwrap_or_else(|| ".".into());
+    detect(&home, |var| std::env::var(var).ok())
+}
+
+/// "Found credentials for Claude and Codex", or that there were none
+pub fn summary(found: &[Provider]) -> String {
+    let names: Vec<&str> = found.iter().map(|p| p.display_name()).collect();
+    match names.split_last() {
+        None => "No provider credentials found; keeping the default providers".to_string(),
+        Some((last, [])) => format!("Found credentials for {}", last),
+        Some((last, rest)) => format!("Found credentials for {} and {}", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_detection_matrix() {
+        let cases: &[(&[&str], &[Provider])] = &[
+            (&[], &[]),
+            (&[".claude/.credentials.json"], &[Provider::Claude]),
+            (&[".codex/auth.json"], &[Provider::Codex]),
+            (&["codex-home/auth.json"], &[Provider::Codex]),
+            (&[".aws/sso/cache/abc123.json"], &[Provider::Kiro]),
+            // Only the directory, or something other than a token
+            (&[".aws/sso/cache/notes.txt"], &[]),
+            (&[".codeium/config.json"], &[Provider::Windsurf]),
+            // The wrong file in the right directory
+            (&[".claude/settings.json", ".codex/config.toml"], &[]),
+            (
+                &[
+                    ".codeium/config.json",
+                    ".codex/auth.json",
+                    ".claude/.credentials.json",
+                ],
+                &[Provider::Claude, Provider::Codex, Provider::Windsurf],
+            ),
+        ];
+        for (files, expected) in cases {
+            let home = tempfile::tempdir().unwrap();
+            for file in *files {
+                let path = home.path().join(file);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, "{}").unwrap();
+            }
+            let codex_home = home.path().join("codex-home").display().to_string();
+            let env = |var: &str| (var == "CODEX_HOME").then(|| codex_home.clone());
+            assert_eq!(detect(home.path(), env), *expected, "{:?}", files);
+        }
+    }
+
+    #[test]
+    fn test_directories_are_not_credentials() {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(home.path().join(".claude/.credentials.json")).unwrap();
+        std::fs::create_dir_all(home.path().join(".aws/sso/cache")).unwrap();
+        assert_eq!(detect(home.path(), |_| Some(String::new())), []);
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            summary(&[Provider::Claude, Provider::Codex, Provider::Kiro]),
+            "Found credentials for Claude, Codex and Kiro"
+        );
+        assert_eq!(summary(&[Provider::Codex]), "Found credentials for Codex");
+        assert!(summary(&[]).starts_with("No provider credentials"));
+    }
+
+    #[test]
+    fn test_detectable() {
+        assert_eq!(
+            detectable(),
+            [
+                Provider::Claude,
+                Provider::Codex,
+                Provider::Kiro,
+                Provider::Windsurf
+            ]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use crate::cache::{Backend, CacheState};
+use crate::config::{Config, Storage};
+use crate::configcli;
+use crate::detect;
+use crate::logging::redact;
+use crate::models::{Provider, UsageSnapshot};
+use crate::paths;
+use crate::providers;
+use chrono::{DateTime, Utc};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One diagnostic result, printed as a ✓/✗ line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+    /// How to fix a failed check
+    pub hint: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: false,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// A duration like "3h 12m" (rounded to the minute) or "4d"
+pub fn approx(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes().unsigned_abs();
+    match minutes {
+        0 => "under a minute".to_string(),
+        1..1440 => humantime::format_duration(Duration::from_secs(minutes * 60)).to_string(),
+        _ => format!("{}d", minutes / 1440),
+    }
+}
+
+pub fn check_config(path: &Path) -> Check {
+    if !path.exists() {
+        return Check::pass(
+            "Config",
+            format!("{} not found, using defaults", path.display()),
+        );
+    }
+    let parsed = std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Config::parse(&content));
+    match parsed {
+        Ok(_) => Check::pass("Config", format!("{} parsed", path.display())),
+        Err(e) => Check::fail(
+            "Config",
+            format!("{}: {:#}", path.display(), e),
+            "Fix the TOML syntax or remove the file to use defaults",
+        ),
+    }
+}
+
+/// The effective config and cache locations, and whether they come from the
+/// environment
+pub fn path_checks() -> Vec<Check> {
+    [
+        ("Config file", paths::config_file()),
+        ("Config dir", paths::config_dir()),
+        ("Cache dir", paths::cache_dir()),
+    ]
+    .into_iter()
+    .map(|(name, resolved)| {
+        Check::pass(
+            name,
+            format!("{} ({})", resolved.path.display(), resolved.source),
+        )
+    })
+    .collect()
+}
+
+/// Write and remove a probe file next to the cache
+pub fn check_cache_writable(cache_path: &Path) -> Check {
+    let Some(dir) = cache_path.parent() else {
+        return Check::fail(
+            "Cache",
+            "cache path has no parent directory",
+            "Set QUOTABAR_CACHE_DIR or XDG_CACHE_HOME",
+        );
+    };
+    let probe = dir.join(".doctor-probe");
+    let result = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match result {
+        Ok(()) => Check::pass("Cache", format!("{} is writable", dir.display())),
+        Err(e) => Check::fail(
+            "Cache",
+            format!("{} is not writable: {}", dir.display(), e),
+            format!("Check the permissions of {}", dir.display()),
+        ),
+    }
+}
+
+/// Which storage holds the cache, and whether this build has it
+fn check_storage(config: &Config) -> Check {
+    match config.general.storage {
+        Storage::Sqlite if !cfg!(feature = "sqlite") => Check::fail(
+            "Storage",
+            "storage = \"sqlite\", but this build has no SQLite support; using JSON files",
+            "Rebuild with `--features sqlite`, or set storage = \"json\"",
+        ),
+        storage => {
+            let backend = Backend::for_storage(storage, &paths::cache_dir().path);
+            Check::pass("Storage", backend.path().display().to_string())
+        }
+    }
+}
+
+/// Whether fetches keep raw responses, and where; informational only
+fn check_debug_responses(config: &Config) -> Check {
+    let detail = match (config.general.debug_responses, config.debug_responses_dir()) {
+        (false, _) => "off".to_string(),
+        (true, Some(dir)) => format!("on, saving to {}", dir.display()),
+        (true, None) => "on, but nothing is saved while redact_identity is on \
+                         (set debug_responses_with_identity to save anyway)"
+            .to_string(),
+    };
+    Check::pass("Debug responses", detail)
+}
+
+/// Any HTTP response counts as reachable; auth is checked by the fetch itself
+async fn check_endpoint(url: &str) -> Check {
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build();
+    let started = Instant::now();
+    let response = match client {
+        Ok(client) => client.head(url).send().await,
+        Err(e) => return Check::fail("Endpoint", e.to_string(), "Check TLS setup"),
+    };
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string());
+    match response {
+        Ok(r) => Check::pass(
+            "Endpoint",
+            format!(
+                "{} reachable (HTTP {}, {}ms)",
+                host,
+                r.status().as_u16(),
+                started.elapsed().as_millis()
+            ),
+        ),
+        Err(e) => Check::fail(
+            "Endpoint",
+            format!("{} unreachable: {}", host, e),
+            "Check your network connection, proxy, or firewall",
+        ),
+    }
+}
+
+fn unsupported(provider: Provider) -> Check {
+    Check::fail(
+        "Support",
+        format!("{} has no fetcher in this build", provider.display_name()),
+        "Disable it in config.toml or rebuild with the needed features",
+    )
+}
+
+/// The provider checks that don't touch the network, for callers that
+/// can't wait on an endpoint probe
+pub fn local_checks(provider: Provider, config: &Config, now: DateTime<Utc>) -> Vec<Check> {
+    match providers::fetcher_for(provider, config) {
+        Some(fetcher) => fetcher.diagnose(now),
+        None => vec![unsupported(provider)],
+    }
+}
+
+async fn provider_checks(provider: Provider, config: &Config, now: DateTime<Utc>) -> Vec<Check> {
+    let Some(fetcher) = providers::fetcher_for(provider, config) else {
+        return vec![unsupported(provider)];
+    };
+    let mut checks = fetcher.diagnose(now);
+    if let Some(url) = fetcher.endpoint() {
+        checks.push(check_endpoint(&url).await);
+    }
+    checks
+}
+
+/// The usage endpoint's rate-limit headroom as of the last fetch;
+/// informational, since running low only slows polling down
+fn check_rate_limit(snapshot: Option<&UsageSnapshot>, now: DateTime<Utc>) -> Check {
+    let detail = match snapshot.and_then(|s| s.rate_limit.as_ref()) {
+        Some(info) if info.is_low() => {
+            format!("{}; polling slowed until it resets", info.describe(now))
+        }
+        Some(info) => info.describe(now),
+        None if snapshot.is_some() => "not reported by the usage endpoint".to_string(),
+        None => "nothing fetched yet".to_string(),
+    };
+    Check::pass("Rate limit", detail)
+}
+
+fn print_check(check: &Check, indent: &str) {
+    let mark = if check.ok { "✓" } else { "✗" };
+    println!(
+        "{}{} {:<12} {}",
+        indent,
+        mark,
+        check.name,
+        redact(&check.detail)
+    );
+    if let Some(ref hint) = check.hint {
+        println!("{}    → {}", indent, redact(hint));
+    }
+}
+
+/// Run all checks and print them; returns whether everything passed. With
+/// `network`, each provider also shows its endpoint's rate-limit headroom.
+pub async fn run(network: bool) -> bool {
+    let now = Utc::now();
+    let config_path = Config::config_path();
+    let mut general = path_checks();
+    general.push(check_config(&config_path));
+    general.push(check_cache_writable(&CacheState::cache_path()));
+    let config = Config::load().unwrap_or_default();
+    general.push(check_storage(&config));
+    general.push(check_debug_responses(&config));
+    let mut ok = general.iter().all(|c| c.ok);
+    for check in &general {
+        print_check(check, "");
+    }
+    let cached = network.then(|| CacheState::load().ok().flatten()).flatten();
+
+    for provider in Provider::ALL {
+        if !config.is_provider_enabled(provider) {
+            println!("\n{} (disabled, skipped)", provider.display_name());
+            continue;
+        }
+        println!("\n{}", provider.display_name());
+        for check in provider_checks(provider, &config, now).await {
+            ok &= check.ok;
+            print_check(&check, "  ");
+        }
+        if network {
+            let snapshot = cached.as_ref().and_then(|c| c.get(provider));
+            print_check(&check_rate_limit(snapshot, now), "  ");
+        }
+    }
+    if !config_path.exists() {
+        offer_config(&config_path);
+    }
+    ok
+}
+
+/// With no config file yet, offer to write one for the providers whose
+/// credentials are here; without a terminal to ask on, say how instead
+fn offer_config(path: &Path) {
+    let found = detect::detect_here();
+    println!("\n{}", detect::summary(&found));
+    if found.is_empty() {
+        return;
+    }
+    if !io::stdin().is_terminal() {
+        println!("Run `quotabar config init --detect` to write a config for them");
+        return;
+    }
+    print!("Write {} with them enabled? [y/N] ", path.display());
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+        return;
+    }
+    let written = configcli::detected_config(&found)
+        .and_then(|content| configcli::init(path, &content, false));
+    match written {
+        Ok(()) => println!("Wrote {}", path.display()),
+        Err(e) => eprintln!("{:#}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert!(check_config(&path).ok);
+
+        std::fs::write(&path, "[general]\nprecision = 1\n").unwrap();
+        assert!(check_config(&path).ok);
+
+        std::fs::write(&path, "[general\nprecision = ").unwrap();
+        let check = check_config(&path);
+        assert!(!check.ok);
+        assert!(check.hint.is_some());
+    }
+
+    #[test]
+    fn test_check_cache_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("quotabar").join("state.json");
+        assert!(check_cache_writable(&cache).ok);
+        assert_eq!(
+            std::fs::read_dir(cache.parent().unwrap()).unwrap().count(),
+            0
+        );
+
+        // A file where the directory should be
+        let blocked = dir.path().join("file");
+        std::fs::write(&blocked, "").unwrap();
+        assert!(!check_cache_writable(&blocked.join("state.json")).ok);
+    }
+
+    #[test]
+    fn test_check_storage() {
+        let mut config = Config::default();
+        let check = check_storage(&config);
+        assert!(check.ok);
+        assert!(check.detail.ends_with("state.json"), "{}", check.detail);
+
+        config.general.storage = Storage::Sqlite;
+        let check = check_storage(&config);
+        assert_eq!(check.ok, cfg!(feature = "sqlite"));
+        if check.ok {
+            assert!(check.detail.ends_with("quotabar.db"), "{}", check.detail);
+        }
+    }
+
+    #[test]
+    fn test_check_debug_responses() {
+        let mut config = Config::default();
+        assert_eq!(check_debug_responses(&config).detail, "off");
+
+        config.general.debug_responses = true;
+        let check = check_debug_responses(&config);
+        assert!(
+            check.detail.starts_with("on, saving to "),
+            "{}",
+            check.detail
+        );
+        assert!(check.detail.ends_with("raw"), "{}", check.detail);
+
+        config.general.redact_identity = true;
+        let check = check_debug_responses(&config);
+        assert!(check.detail.contains("redact_identity"), "{}", check.detail);
+        assert!(check.ok);
+
+        config.general.debug_responses_with_identity = true;
+        assert!(check_debug_responses(&config)
+            .detail
+            .starts_with("on, saving"));
+    }
+
+    #[test]
+    fn test_check_rate_limit() {
+        let now = Utc::now();
+        let mut snapshot = crate::mock::mock_snapshots()[&Provider::Claude].clone();
+        assert_eq!(check_rate_limit(None, now).detail, "nothing fetched yet");
+        assert_eq!(
+            check_rate_limit(Some(&snapshot), now).detail,
+            "not reported by the usage endpoint"
+        );
+
+        snapshot.rate_limit = Some(crate::ratelimit::RateLimitInfo {
+            limit: Some(60),
+            remaining: Some(58),
+            resets_at: Some(now + chrono::Duration::seconds(40)),
+        });
+        let check = check_rate_limit(Some(&snapshot), now);
+        assert_eq!(check.detail, "58/60 requests remaining, resets in 40s");
+
+        snapshot.rate_limit.as_mut().unwrap().remaining = Some(2);
+        let check = check_rate_limit(Some(&snapshot), now);
+        assert!(check.ok);
+        assert!(check.detail.ends_with("polling slowed until it resets"));
+    }
+
+    #[test]
+    fn test_approx() {
+        assert_eq!(approx(chrono::Duration::seconds(30)), "under a minute");
+        assert_eq!(approx(chrono::Duration::minutes(192)), "3h 12m");
+        assert_eq!(approx(chrono::Duration::minutes(-5)), "5m");
+        assert_eq!(approx(chrono::Duration::hours(100)), "4d");
+    }
+}
@@ -0,0 +1,291 @@
+use crate::cache::CacheState;
+use crate::config::Config;
+use crate::models::{Provider, UsageSnapshot};
+use chrono::{DateTime, Utc};
+
+/// Variable names emitted by `quotabar env`, relative to the prefix.
+/// `<PROVIDER>` is the provider id and `<WINDOW>` the window's kind: session,
+/// daily, week, a model such as sonnet, or e.g. oauth_apps or monthly.
+pub const FIELDS: &[(&str, &str)] = &[
+    (
+        "CACHE_AGE_SECONDS",
+        "Seconds since the cache was last written",
+    ),
+    (
+        "STATUS",
+        "Worst status across providers: normal|warning|critical|unknown",
+    ),
+    ("PROVIDERS", "Space-separated provider ids with data"),
+    (
+        "<PROVIDER>_STATUS",
+        "Worst status across the provider's windows",
+    ),
+    ("<PROVIDER>_PLAN", "Plan name"),
+    ("<PROVIDER>_EMAIL", "Account email"),
+    ("<PROVIDER>_ORGANIZATION", "Organization or workspace name"),
+    (
+        "<PROVIDER>_UPDATED_AT",
+        "RFC 3339 time the snapshot was captured",
+    ),
+    ("<PROVIDER>_<WINDOW>_USED", "Used percentage"),
+    ("<PROVIDER>_<WINDOW>_REMAINING", "Remaining percentage"),
+    ("<PROVIDER>_<WINDOW>_STATUS", "normal|warning|critical"),
+    ("<PROVIDER>_<WINDOW>_RESETS_AT", "RFC 3339 reset time"),
+    (
+        "<PROVIDER>_<WINDOW>_RESETS_IN_SECONDS",
+        "Seconds until reset",
+    ),
+    ("<PROVIDER>_COST_USED", "Amount spent"),
+    ("<PROVIDER>_COST_LIMIT", "Spending limit"),
+    (
+        "<PROVIDER>_COST_PERCENT",
+        "Spend as a percentage of the limit",
+    ),
+    ("<PROVIDER>_COST_CURRENCY", "Currency code"),
+];
+
+/// Build `NAME=value` assignments for the given providers from the cache
+pub fn env_lines(
+    cache: Option<&CacheState>,
+    providers: &[Provider],
+    prefix: &str,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut present = Vec::new();
+    let mut worst = None;
+
+    if let Some(cache) = cache {
+        let age = (now - cache.updated_at).num_seconds().max(0);
+        vars.push((env_name(prefix, &["CACHE_AGE_SECONDS"]), age.to_string()));
+    }
+
+    for provider in providers {
+        let Some(snapshot) = cache.and_then(|c| c.get(*provider)) else {
+            continue;
+        };
+        present.push(provider.id());
+        let status = snapshot.status_class(config);
+        worst = worst.max(Some(severity(status)));
+        push_snapshot(&mut vars, prefix, snapshot, status, config, now);
+    }
+
+    let status = match worst {
+        Some(2) => "critical",
+        Some(1) => "warning",
+        Some(_) => "normal",
+        None => "unknown",
+    };
+    vars.push((env_name(prefix, &["STATUS"]), status.to_string()));
+    vars.push((env_name(prefix, &["PROVIDERS"]), present.join(" ")));
+
+    vars.into_iter()
+        .map(|(name, value)| format!("{}={}", name, shell_quote(&value)))
+        .collect()
+}
+
+fn push_snapshot(
+    vars: &mut Vec<(String, String)>,
+    prefix: &str,
+    snapshot: &UsageSnapshot,
+    status: &str,
+    config: &Config,
+    now: DateTime<Utc>,
+) {
+    let id = snapshot.provider.id();
+    let precision = config.general.precision;
+    vars.push((env_name(prefix, &[id, "STATUS"]), status.to_string()));
+
+    if let Some(ref identity) = snapshot.identity {
+        if let Some(ref plan) = identity.plan {
+            vars.push((env_name(prefix, &[id, "PLAN"]), plan.clone()));
+        }
+        if let Some(ref email) = identity.email {
+            vars.push((env_name(prefix, &[id, "EMAIL"]), email.clone()));
+        }
+        if let Some(ref organization) = identity.organization {
+            vars.push((
+                env_name(prefix, &[id, "ORGANIZATION"]),
+                organization.clone(),
+            ));
+        }
+    }
+    vars.push((
+        env_name(prefix, &[id, "UPDATED_AT"]),
+        snapshot.updated_at.to_rfc3339(),
+    ));
+
+    for window in &snapshot.windows {
+        let window_id = window.kind.id();
+        vars.push((
+            env_name(prefix, &[id, window_id, "USED"]),
+            window.format_percent(window.used_percent, precision),
+        ));
+        vars.push((
+            env_name(prefix, &[id, window_id, "REMAINING"]),
+            window.format_percent(window.remaining_percent(), precision),
+        ));
+        vars.push((
+            env_name(prefix, &[id, window_id, "STATUS"]),
+            config
+                .status_class(snapshot.provider, window.used_percent)
+                .to_string(),
+        ));
+        if let Some(resets_at) = window.resets_at {
+            vars.push((
+                env_name(prefix, &[id, window_id, "RESETS_AT"]),
+                resets_at.to_rfc3339(),
+            ));
+            vars.push((
+                env_name(prefix, &[id, window_id, "RESETS_IN_SECONDS"]),
+                (resets_at - now).num_seconds().max(0).to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref cost) = snapshot.cost {
+        vars.push((
+            env_name(prefix, &[id, "COST_USED"]),
+            format!("{:.2}", cost.used),
+        ));
+        vars.push((
+            env_name(prefix, &[id, "COST_LIMIT"]),
+            format!("{:.2}", cost.limit),
+        ));
+        vars.push((
+            env_name(prefix, &[id, "COST_PERCENT"]),
+            format!("{:.0}", cost.used_percent()),
+        ));
+        vars.push((
+            env_name(prefix, &[id, "COST_CURRENCY"]),
+            cost.currency_code.clone(),
+        ));
+    }
+}
+
+fn severity(class: &str) -> u8 {
+    match class {
+        "critical" => 2,
+        "warning" => 1,
+        _ => 0,
+    }
+}
+
+/// Join name parts into a valid shell identifier: uppercase, with any run of
+/// non-alphanumeric characters collapsed into a single underscore
+pub fn env_name(prefix: &str, parts: &[&str]) -> String {
+    let mut name = String::new();
+    for part in parts {
+        let mangled = mangle(part);
+        if mangled.is_empty() {
+            continue;
+        }
+        if !name.is_empty() {
+            name.push('_');
+        }
+        name.push_str(&mangled);
+    }
+
+    let mut full = format!("{}{}", prefix, name);
+    if full.starts_with(|c: char| c.is_ascii_digit()) {
+        full.insert(0, '_');
+    }
+    full
+}
+
+fn mangle(part: &str) -> String {
+    let mut out = String::new();
+    let mut pending_sep = false;
+    for c in part.chars() {
+        if c.is_ascii_alphanumeric() {
+            if pending_sep && !out.is_empty() {
+                out.push('_');
+            }
+            pending_sep = false;
+            out.push(c.to_ascii_uppercase());
+        } else {
+            pending_sep = true;
+        }
+    }
+    out
+}
+
+/// Quote a value for POSIX shells, leaving simple values bare
+pub fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+
+    #[test]
+    fn test_env_name_mangling() {
+        assert_eq!(
+            env_name("QB_", &["claude", "session", "USED"]),
+            "QB_CLAUDE_SESSION_USED"
+        );
+        assert_eq!(
+            env_name("QB_", &["claude", "Weekly — Sonnet", "used"]),
+            "QB_CLAUDE_WEEKLY_SONNET_USED"
+        );
+        assert_eq!(env_name("", &["  spaced  out "]), "SPACED_OUT");
+        assert_eq!(env_name("", &["7d window"]), "_7D_WINDOW");
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("72"), "72");
+        assert_eq!(
+            shell_quote("2024-01-15T10:30:00+00:00"),
+            "2024-01-15T10:30:00+00:00"
+        );
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("claude codex"), "'claude codex'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$(rm -rf)"), "'$(rm -rf)'");
+    }
+
+    #[test]
+    fn test_env_lines_from_cache() {
+        let now = Utc::now();
+        let cache = CacheState {
+            snapshots: mock_snapshots(),
+            ..CacheState::empty(now - chrono::Duration::seconds(42))
+        };
+        let mut config = Config::default();
+        let lines = env_lines(Some(&cache), &[Provider::Claude], "QB_", &config, now);
+        assert!(lines.contains(&"QB_CACHE_AGE_SECONDS=42".to_string()));
+        assert!(lines.contains(&"QB_CLAUDE_SESSION_USED=72".to_string()));
+        assert!(lines.contains(&"QB_CLAUDE_SESSION_REMAINING=28".to_string()));
+        assert!(lines.contains(&"QB_CLAUDE_PLAN=Max".to_string()));
+        assert!(lines.contains(&"QB_STATUS=normal".to_string()));
+        assert!(lines.contains(&"QB_PROVIDERS=claude".to_string()));
+        assert!(!lines.iter().any(|l| l.starts_with("QB_CODEX_")));
+
+        // Statuses follow the configured thresholds
+        config.thresholds.warning = 70.0;
+        let lines = env_lines(Some(&cache), &[Provider::Claude], "QB_", &config, now);
+        assert!(lines.contains(&"QB_CLAUDE_SESSION_STATUS=warning".to_string()));
+        assert!(lines.contains(&"QB_STATUS=warning".to_string()));
+    }
+
+    #[test]
+    fn test_env_lines_without_cache() {
+        let lines = env_lines(
+            None,
+            &[Provider::Claude],
+            "QB_",
+            &Config::default(),
+            Utc::now(),
+        );
+        assert_eq!(lines, vec!["QB_STATUS=unknown", "QB_PROVIDERS=''"]);
+    }
+}
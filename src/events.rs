@@ -0,0 +1,308 @@
+//! Threshold crossings, depletions, resets and overage found by comparing a
+//! provider's new snapshot with the one it replaces; kept in the cache so
+//! notifications, the popup and hooks share one definition
+
+use crate::config::Thresholds;
+use crate::models::{CostSnapshot, Provider, RateWindow, UsageSnapshot};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How many events the cache keeps, newest last
+pub const MAX_EVENTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Usage rose to the warning threshold
+    CrossedWarning,
+    /// Usage rose to the critical threshold
+    CrossedCritical,
+    /// The window is fully used
+    Depleted,
+    /// Usage fell from at or above the warning threshold to below it, which
+    /// only a new window does
+    Reset,
+    /// Spend went past its limit, or extra usage started being billed
+    OverageStarted,
+}
+
+impl EventKind {
+    /// e.g. "crossed warning"
+    pub fn describe(self) -> &'static str {
+        match self {
+            EventKind::CrossedWarning => "crossed warning",
+            EventKind::CrossedCritical => "crossed critical",
+            EventKind::Depleted => "depleted",
+            EventKind::Reset => "reset",
+            EventKind::OverageStarted => "overage started",
+        }
+    }
+}
+
+/// Something that happened to one window between two snapshots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct UsageEvent {
+    pub kind: EventKind,
+    pub provider: Provider,
+    /// The window's name, e.g. "Weekly limit", or "Spend"/"Extra usage"
+    pub window: String,
+    /// When the earlier snapshot was taken
+    pub before_at: DateTime<Utc>,
+    /// When the later snapshot was taken, i.e. by when it happened
+    pub at: DateTime<Utc>,
+    pub before_percent: f64,
+    pub after_percent: f64,
+}
+
+/// Events from `before` to `after`, two snapshots of one provider, in window
+/// order. A window only counts when both snapshots have one of its kind;
+/// each window yields at most one event, the most severe.
+pub fn diff(
+    before: &UsageSnapshot,
+    after: &UsageSnapshot,
+    thresholds: Thresholds,
+) -> Vec<UsageEvent> {
+    let event = |kind, window: String, was: f64, is: f64| UsageEvent {
+        kind,
+        provider: after.provider,
+        window,
+        before_at: before.updated_at,
+        at: after.updated_at,
+        before_percent: was,
+        after_percent: is,
+    };
+    let mut events = Vec::new();
+    for (old, new) in matching_windows(before, after) {
+        let (was, is) = (old.used_percent, new.used_percent);
+        if let Some(kind) = window_change(was, is, thresholds, new.granularity.tolerance()) {
+            events.push(event(kind, new.name(), was, is));
+        }
+    }
+    if let (Some(old), Some(new)) = (&before.cost, &after.cost) {
+        if overage_started(old, new) {
+            events.push(event(
+                EventKind::OverageStarted,
+                cost_name(new),
+                old.used_percent(),
+                new.used_percent(),
+            ));
+        }
+    }
+    events
+}
+
+/// The event for one window going from `was` to `is`. A drop of no more
+/// than `tolerance`, one step at the source's granularity, can be rounding
+/// rather than a new window, so it is not a reset.
+fn window_change(was: f64, is: f64, thresholds: Thresholds, tolerance: f64) -> Option<EventKind> {
+    let rose_to = |level: f64| was < level && is >= level;
+    if rose_to(100.0) {
+        Some(EventKind::Depleted)
+    } else if rose_to(thresholds.critical) {
+        Some(EventKind::CrossedCritical)
+    } else if rose_to(thresholds.warning) {
+        Some(EventKind::CrossedWarning)
+    } else if was >= thresholds.warning && is < thresholds.warning && was - is > tolerance {
+        Some(EventKind::Reset)
+    } else {
+        None
+    }
+}
+
+fn overage_started(old: &CostSnapshot, new: &CostSnapshot) -> bool {
+    let past_limit = old.overage().is_none() && new.overage().is_some();
+    let billing = new.extra_usage && old.used <= 0.0 && new.used > 0.0;
+    past_limit || billing
+}
+
+fn cost_name(cost: &CostSnapshot) -> String {
+    match cost.extra_usage {
+        true => "Extra usage".to_string(),
+        false => "Spend".to_string(),
+    }
+}
+
+/// Each window of `after` with the window of the same kind in `before`
+pub fn matching_windows<'a>(
+    before: &'a UsageSnapshot,
+    after: &'a UsageSnapshot,
+) -> impl Iterator<Item = (&'a RateWindow, &'a RateWindow)> {
+    after
+        .windows
+        .iter()
+        .filter_map(|new| Some((before.window(&new.kind)?, new)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use crate::models::Granularity;
+    use chrono::Duration;
+    use pretty_assertions::assert_eq;
+
+    fn claude(session: f64, weekly: f64) -> UsageSnapshot {
+        let mut snapshot = mock_snapshots()[&Provider::Claude].clone();
+        snapshot.windows[0].used_percent = session;
+        snapshot.windows[1].used_percent = weekly;
+        snapshot
+    }
+
+    fn kinds(before: &UsageSnapshot, after: &UsageSnapshot) -> Vec<(EventKind, String)> {
+        diff(before, after, Thresholds::default())
+            .into_iter()
+            .map(|e| (e.kind, e.window))
+            .collect()
+    }
+
+    #[test]
+    fn test_window_change_matrix() {
+        use EventKind::*;
+        let defaults = Thresholds::default();
+        let cases = [
+            // Unchanged, or moving within a band
+            (50.0, 50.0, None),
+            (10.0, 74.9, None),
+            (76.0, 89.0, None),
+            (91.0, 99.0, None),
+            (100.0, 100.0, None),
+            // Rising into each band, from below or from the band beneath
+            (50.0, 75.0, Some(CrossedWarning)),
+            (50.0, 80.0, Some(CrossedWarning)),
+            (80.0, 90.0, Some(CrossedCritical)),
+            (50.0, 95.0, Some(CrossedCritical)),
+            (95.0, 100.0, Some(Depleted)),
+            (10.0, 100.0, Some(Depleted)),
+            (10.0, 120.0, Some(Depleted)),
+            // A new window: usage drops sharply from high to low
+            (100.0, 0.0, Some(Reset)),
+            (92.0, 3.0, Some(Reset)),
+            (75.0, 74.0, Some(Reset)),
+            // Falling but still high, or already low
+            (100.0, 80.0, None),
+            (95.0, 91.0, None),
+            (60.0, 0.0, None),
+        ];
+        for (was, is, expected) in cases {
+            assert_eq!(
+                window_change(was, is, defaults, 0.0),
+                expected,
+                "{} -> {}",
+                was,
+                is
+            );
+        }
+
+        let strict = Thresholds {
+            warning: 50.0,
+            critical: 60.0,
+        };
+        assert_eq!(window_change(40.0, 55.0, strict, 0.0), Some(CrossedWarning));
+        assert_eq!(
+            window_change(55.0, 65.0, strict, 0.0),
+            Some(CrossedCritical)
+        );
+        assert_eq!(window_change(55.0, 45.0, strict, 0.0), Some(Reset));
+    }
+
+    #[test]
+    fn test_reset_needs_more_than_one_source_step() {
+        let defaults = Thresholds::default();
+        let whole = Granularity::Percent.tolerance();
+        assert_eq!(window_change(75.0, 74.0, defaults, whole), None);
+        assert_eq!(
+            window_change(75.0, 73.0, defaults, whole),
+            Some(EventKind::Reset)
+        );
+        let tenth = Granularity::Tenth.tolerance();
+        assert_eq!(window_change(75.0, 74.9, defaults, tenth), None);
+        assert_eq!(
+            window_change(75.0, 74.0, defaults, tenth),
+            Some(EventKind::Reset)
+        );
+
+        // Through diff, by the window's own granularity
+        let mut before = claude(75.0, 70.0);
+        before.windows[0].granularity = Granularity::Percent;
+        let mut after = claude(74.0, 70.0);
+        after.windows[0].granularity = Granularity::Percent;
+        assert!(kinds(&before, &after).is_empty());
+        after.windows[0].granularity = Granularity::Unknown;
+        assert_eq!(
+            kinds(&before, &after),
+            [(EventKind::Reset, "5-hour session".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = claude(50.0, 70.0);
+        let mut after = claude(95.0, 76.0);
+        after.updated_at = before.updated_at + Duration::minutes(5);
+
+        let events = diff(&before, &after, Thresholds::default());
+        assert_eq!(
+            events[0],
+            UsageEvent {
+                kind: EventKind::CrossedCritical,
+                provider: Provider::Claude,
+                window: "5-hour session".to_string(),
+                before_at: before.updated_at,
+                at: after.updated_at,
+                before_percent: 50.0,
+                after_percent: 95.0,
+            }
+        );
+        assert_eq!(events[1].kind, EventKind::CrossedWarning);
+        assert_eq!(events[1].window, "Weekly limit");
+        assert_eq!(events.len(), 2);
+
+        assert!(kinds(&before, &before).is_empty());
+        assert_eq!(
+            kinds(&claude(100.0, 70.0), &claude(2.0, 71.0)),
+            [(EventKind::Reset, "5-hour session".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_windows_must_be_in_both() {
+        let before = claude(50.0, 70.0);
+        let mut after = claude(95.0, 95.0);
+        after.windows.remove(0);
+        assert_eq!(
+            kinds(&before, &after),
+            [(EventKind::CrossedCritical, "Weekly limit".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_overage() {
+        let cost = |used: f64, extra_usage| CostSnapshot {
+            used,
+            limit: 50.0,
+            currency_code: "USD".to_string(),
+            period: None,
+            resets_at: None,
+            extra_usage,
+        };
+        let with_cost = |cost| UsageSnapshot {
+            cost: Some(cost),
+            ..claude(10.0, 10.0)
+        };
+        let overage = |old, new| kinds(&with_cost(old), &with_cost(new));
+
+        assert_eq!(
+            overage(cost(40.0, false), cost(55.0, false)),
+            [(EventKind::OverageStarted, "Spend".to_string())]
+        );
+        assert!(overage(cost(55.0, false), cost(60.0, false)).is_empty());
+        assert!(overage(cost(10.0, false), cost(40.0, false)).is_empty());
+        // Extra usage is overage from its first cent
+        assert_eq!(
+            overage(cost(0.0, true), cost(0.5, true)),
+            [(EventKind::OverageStarted, "Extra usage".to_string())]
+        );
+        assert!(overage(cost(0.5, true), cost(3.0, true)).is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::history::{self, Sample};
+use crate::models::Provider;
+use anyhow::Result;
+use chrono::{DateTime, Days, NaiveDate, SecondsFormat, Utc};
+use clap::ValueEnum;
+
+/// CSV header; columns are only ever appended so spreadsheets keep working
+pub const CSV_COLUMNS: [&str; 8] = [
+    "provider",
+    "timestamp",
+    "session_percent",
+    "week_percent",
+    "model_percent",
+    "cost_used",
+    "cost_limit",
+    "plan",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Which samples to export; bounds are `from <= at < to`
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub providers: Vec<Provider>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    fn matches(&self, sample: &Sample) -> bool {
+        (self.providers.is_empty() || self.providers.contains(&sample.provider))
+            && self.from.is_none_or(|from| sample.at >= from)
+            && self.to.is_none_or(|to| sample.at < to)
+    }
+}
+
+/// `--to` takes the same forms as `--from`, but a bare date includes that
+/// whole day
+pub fn parse_to(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    match NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d") {
+        Ok(date) => Ok(date
+            .checked_add_days(Days::new(1))
+            .unwrap_or(date)
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc()),
+        Err(_) => history::parse_since(value, now),
+    }
+}
+
+pub fn export(samples: &[Sample], filter: &Filter, format: ExportFormat) -> Result<String> {
+    let selected: Vec<&Sample> = samples.iter().filter(|s| filter.matches(s)).collect();
+    match format {
+        ExportFormat::Csv => Ok(to_csv(&selected)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&selected)? + "\n"),
+    }
+}
+
+/// Missing windows are empty cells rather than zeros so averages in a
+/// spreadsheet aren't skewed
+fn to_csv(samples: &[&Sample]) -> String {
+    let number = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    let mut out = CSV_COLUMNS.join(",") + "\n";
+    for sample in samples {
+        let row = [
+            sample.provider.id().to_string(),
+            sample.at.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            number(sample.session),
+            number(sample.week),
+            number(sample.sonnet),
+            number(sample.cost),
+            number(sample.cost_limit),
+            csv_field(sample.plan.as_deref().unwrap_or_default()),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn known_history() -> Vec<Sample> {
+        let at = |h| Utc.with_ymd_and_hms(2026, 10, 1, h, 0, 0).unwrap();
+        vec![
+            Sample {
+                at: at(8),
+                provider: Provider::Claude,
+                session: Some(12.5),
+                week: Some(40.0),
+                sonnet: None,
+                cost: Some(3.25),
+                cost_limit: Some(50.0),
+                plan: Some("Max 5x".to_string()),
+            },
+            Sample {
+                at: at(9),
+                provider: Provider::Codex,
+                session: Some(85.0),
+                week: None,
+                sonnet: None,
+                cost: None,
+                cost_limit: None,
+                plan: Some("Team, \"annual\"".to_string()),
+            },
+            Sample {
+                at: Utc.with_ymd_and_hms(2026, 10, 3, 0, 0, 0).unwrap(),
+                provider: Provider::Claude,
+                session: Some(0.0),
+                week: Some(41.0),
+                sonnet: Some(7.0),
+                cost: None,
+                cost_limit: None,
+                plan: None,
+            },
+        ]
+    }
+
+    /// Minimal RFC 4180 reader for the round trip
+    fn parse_csv_line(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(String::new()),
+                _ => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    fn sample_from_row(row: &[String]) -> Sample {
+        let number = |s: &String| (!s.is_empty()).then(|| s.parse().unwrap());
+        Sample {
+            provider: <Provider as ValueEnum>::from_str(&row[0], true).unwrap(),
+            at: DateTime::parse_from_rfc3339(&row[1]).unwrap().into(),
+            session: number(&row[2]),
+            week: number(&row[3]),
+            sonnet: number(&row[4]),
+            cost: number(&row[5]),
+            cost_limit: number(&row[6]),
+            plan: (!row[7].is_empty()).then(|| row[7].clone()),
+        }
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let lines: String = known_history()
+            .iter()
+            .map(|s| serde_json::to_string(s).unwrap() + "\n")
+            .collect();
+        std::fs::write(&path, lines).unwrap();
+
+        let samples = history::read(&path).unwrap();
+        let csv = export(&samples, &Filter::default(), ExportFormat::Csv).unwrap();
+        let mut rows = csv.lines().map(parse_csv_line);
+        assert_eq!(rows.next().unwrap(), CSV_COLUMNS);
+        let parsed: Vec<Sample> = rows.map(|r| sample_from_row(&r)).collect();
+        assert_eq!(parsed, known_history());
+    }
+
+    #[test]
+    fn test_missing_windows_are_empty_cells() {
+        let csv = export(&known_history(), &Filter::default(), ExportFormat::Csv).unwrap();
+        let codex = csv.lines().nth(2).unwrap();
+        assert_eq!(
+            codex,
+            r#"codex,2026-10-01T09:00:00Z,85,,,,,"Team, ""annual""""#
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json = export(&known_history(), &Filter::default(), ExportFormat::Json).unwrap();
+        let parsed: Vec<Sample> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, known_history());
+    }
+
+    #[test]
+    fn test_filters() {
+        let now = Utc::now();
+        let filter = Filter {
+            providers: vec![Provider::Claude],
+            from: Some(history::parse_since("2026-10-01", now).unwrap()),
+            to: Some(parse_to("2026-10-01", now).unwrap()),
+        };
+        let json = export(&known_history(), &filter, ExportFormat::Json).unwrap();
+        let parsed: Vec<Sample> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, known_history()[..1]);
+    }
+}
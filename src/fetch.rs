@@ -0,0 +1,733 @@
+use crate::cache::{self, CacheState, FetchFailure, CACHE_VERSION};
+use crate::config::Config;
+use crate::history;
+use crate::models::{Provider, UsageSnapshot};
+use crate::origin;
+use crate::providers::{self, ProviderError};
+use crate::ratelimit;
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// Outcome of a guarded refresh across several providers
+#[derive(Debug, Default)]
+pub struct Refresh {
+    /// Snapshots from this run, fetched or reused from the cache; a provider
+    /// whose fetch failed keeps its cached one
+    pub snapshots: HashMap<Provider, UsageSnapshot>,
+    /// Providers whose fetch failed
+    pub errors: HashMap<Provider, anyhow::Error>,
+    /// Providers fetched from the network in this run
+    pub fetched: Vec<Provider>,
+    /// Providers served from the cache because it was fresh enough
+    pub reused: Vec<Provider>,
+    /// Providers skipped because an earlier 429 backoff is still running
+    pub backed_off: HashMap<Provider, DateTime<Utc>>,
+    /// Error writing the cache or history, if any
+    pub save_error: Option<anyhow::Error>,
+}
+
+/// When a bar command goes to the network before printing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FetchMode {
+    /// Fetch every time (snapshots within `min_fetch_interval` are reused)
+    Always,
+    /// Print from the cache, fetching only when data is missing or older
+    /// than `refresh_interval`
+    #[default]
+    Auto,
+    /// Only read the cache
+    Never,
+}
+
+impl FetchMode {
+    /// Whether to fetch before printing. In auto mode providers that are
+    /// backing off from a 429 never force a fetch, since it would be skipped,
+    /// and `foreign` snapshots always do, however recent.
+    pub fn should_fetch(
+        self,
+        cached: Option<&CacheState>,
+        providers: &[Provider],
+        max_age: Duration,
+        now: DateTime<Utc>,
+        foreign: impl Fn(&UsageSnapshot) -> bool,
+    ) -> bool {
+        match self {
+            FetchMode::Always => true,
+            FetchMode::Never => false,
+            FetchMode::Auto => providers.iter().any(|&provider| {
+                let backing_off = cached.is_some_and(|c| c.backoff_for(provider, now).is_some());
+                let fresh = cached
+                    .and_then(|c| c.get(provider))
+                    .is_some_and(|s| is_fresh(s.updated_at, now, max_age) && !foreign(s));
+                !backing_off && !fresh
+            }),
+        }
+    }
+}
+
+/// Whether a cached snapshot is recent enough to skip the network. Skewed
+/// timestamps never are, see [`cache::checked_age`].
+pub fn is_fresh(updated_at: DateTime<Utc>, now: DateTime<Utc>, min_interval: Duration) -> bool {
+    cache::checked_age(updated_at, now).is_some_and(|age| age < min_interval)
+}
+
+/// Fetch the given providers concurrently and write the cache. Holds the
+/// cache lock throughout so concurrent invocations don't both hit the network;
+/// unless `force` is set, snapshots newer than `min_fetch_interval` (at most
+/// `refresh_interval`, so a scheduled refresh is never skipped) are reused,
+/// unless they came from another machine or login. A provider whose usage
+/// endpoint is nearly out of requests has that interval stretched.
+/// Providers still backing off from a 429 are never fetched, even with `force`.
+pub async fn refresh(config: &Config, providers: &[Provider], force: bool) -> Refresh {
+    let _lock = CacheState::lock_async().await.ok();
+    refresh_locked(config, providers, force).await
+}
+
+/// [`refresh`], or None without waiting when another process holds the
+/// cache lock; that process is already refreshing, so its results will land
+/// in the cache shortly
+pub async fn try_refresh(config: &Config, providers: &[Provider], force: bool) -> Option<Refresh> {
+    // Like `refresh`, a lock that can't be taken at all isn't a reason to skip
+    let _lock = match CacheState::try_lock() {
+        Ok(None) => return None,
+        lock => lock.ok().flatten(),
+    };
+    Some(refresh_locked(config, providers, force).await)
+}
+
+async fn refresh_locked(config: &Config, providers: &[Provider], force: bool) -> Refresh {
+    let cached = CacheState::load().ok().flatten();
+    let (mut result, state) = run(
+        cached.as_ref(),
+        providers,
+        force,
+        config.min_fetch_interval().min(config.refresh_interval()),
+        Utc::now(),
+        |s| origin::foreign(s, config).is_some(),
+        |provider| providers::fetch(provider, config),
+    )
+    .await;
+
+    if let Some(mut state) = state {
+        state.record_events(cached.as_ref(), &result.fetched, |p| {
+            config.thresholds_for(p)
+        });
+        // This run's snapshots keep their identity in memory for the caller
+        if config.general.redact_identity {
+            state.redact_identities();
+        }
+        let fetched = result.fetched.iter().map(|p| &result.snapshots[p]);
+        result.save_error = state
+            .save()
+            .and_then(|_| match config.history.enabled {
+                true => history::record(fetched, config.history_retention(), Utc::now()),
+                false => Ok(()),
+            })
+            .err();
+    }
+    result
+}
+
+/// Fetch a single provider for display without touching cached snapshots,
+/// honouring and recording 429 backoff
+pub async fn fetch_one(provider: Provider, config: &Config) -> anyhow::Result<UsageSnapshot> {
+    let now = Utc::now();
+    let cached = CacheState::load().ok().flatten();
+    if let Some(until) = cached.and_then(|c| c.backoff_for(provider, now)) {
+        return Err(ProviderError::RateLimited { until }.into());
+    }
+
+    let result = providers::fetch(provider, config).await;
+    if let Some(until) = result
+        .as_ref()
+        .err()
+        .and_then(providers::rate_limited_until)
+    {
+        // Best effort: the caller reports the 429 itself
+        let _ = CacheState::update(|state| {
+            state.backoff_until.insert(provider, until);
+        });
+    }
+    result
+}
+
+/// Start `quotabar fetch` (limited to `providers` if any) detached from this
+/// process, unless a refresh is already holding the cache lock
+pub fn spawn_background_fetch(providers: &[Provider]) -> anyhow::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    if CacheState::try_lock()?.is_none() {
+        return Ok(());
+    }
+    Command::new(std::env::current_exe()?)
+        .arg("fetch")
+        .args(providers.iter().flat_map(|p| ["--provider", p.id()]))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    Ok(())
+}
+
+/// Core of [`refresh`]: decides per provider whether to reuse, skip, or
+/// fetch, fetches the rest concurrently, and returns the cache state to
+/// write if anything changed. Snapshots `foreign` says are another
+/// machine's are never reused.
+async fn run<F, Fut>(
+    cached: Option<&CacheState>,
+    providers: &[Provider],
+    force: bool,
+    min_interval: Duration,
+    now: DateTime<Utc>,
+    foreign: impl Fn(&UsageSnapshot) -> bool,
+    mut fetch: F,
+) -> (Refresh, Option<CacheState>)
+where
+    F: FnMut(Provider) -> Fut,
+    Fut: Future<Output = anyhow::Result<UsageSnapshot>>,
+{
+    let mut result = Refresh::default();
+    let mut backoff_until: HashMap<Provider, DateTime<Utc>> =
+        cached.map(|c| c.backoff_until.clone()).unwrap_or_default();
+    backoff_until.retain(|_, until| *until > now);
+    let mut last_errors: HashMap<Provider, FetchFailure> =
+        cached.map(|c| c.last_errors.clone()).unwrap_or_default();
+    let mut changed = false;
+    let mut pending = Vec::new();
+
+    for &provider in providers {
+        let cached_snapshot = cached.and_then(|c| c.get(provider));
+
+        if let Some(&until) = backoff_until.get(&provider) {
+            tracing::debug!(provider = provider.id(), %until, "still backing off from a 429, skipped");
+            if let Some(snapshot) = cached_snapshot {
+                result.snapshots.insert(provider, snapshot.clone());
+            }
+            result.backed_off.insert(provider, until);
+            continue;
+        }
+
+        let fresh = cached_snapshot.filter(|s| {
+            let interval = ratelimit::effective_interval(min_interval, s.rate_limit.as_ref(), now);
+            !force && is_fresh(s.updated_at, now, interval) && !foreign(s)
+        });
+        if let Some(snapshot) = fresh {
+            tracing::debug!(
+                provider = provider.id(),
+                updated_at = %snapshot.updated_at,
+                "cached snapshot is within min_fetch_interval, reused"
+            );
+            result.snapshots.insert(provider, snapshot.clone());
+            result.reused.push(provider);
+            continue;
+        }
+        pending.push(provider);
+    }
+
+    // Everything left goes to the network at once
+    let fetched = join_all(pending.iter().map(|&provider| fetch(provider))).await;
+    for (provider, fetched) in pending.into_iter().zip(fetched) {
+        match fetched {
+            Ok(snapshot) => {
+                result.snapshots.insert(provider, snapshot);
+                result.fetched.push(provider);
+                last_errors.remove(&provider);
+                changed = true;
+            }
+            Err(e) => {
+                if let Some(until) = providers::rate_limited_until(&e) {
+                    backoff_until.insert(provider, until);
+                }
+                // Its last good snapshot is still the best there is
+                if let Some(snapshot) = cached.and_then(|c| c.get(provider)) {
+                    result.snapshots.insert(provider, snapshot.clone());
+                }
+                // Recorded so readers of the cache can tell the data is old
+                last_errors.insert(
+                    provider,
+                    FetchFailure {
+                        reason: providers::short_reason(&e),
+                        at: now,
+                    },
+                );
+                changed = true;
+                result.errors.insert(provider, e);
+            }
+        }
+    }
+
+    // Only what was fetched replaces anything, so failed providers and those
+    // outside this run keep their cached snapshots and own updated_at
+    let state = changed.then(|| {
+        let mut snapshots = cached.map(|c| c.snapshots.clone()).unwrap_or_default();
+        for provider in &result.fetched {
+            snapshots.insert(*provider, result.snapshots[provider].clone());
+        }
+        CacheState {
+            schema_version: CACHE_VERSION,
+            snapshots,
+            updated_at: Utc::now(),
+            backoff_until,
+            last_errors,
+            events: cached.map(|c| c.events.clone()).unwrap_or_default(),
+        }
+    });
+    (result, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use crate::origin::Origin;
+    use crate::ratelimit::RateLimitInfo;
+    use chrono::Duration as ChronoDuration;
+
+    #[test]
+    fn test_is_fresh() {
+        let now = Utc::now();
+        let min = Duration::from_secs(30);
+        assert!(is_fresh(now, now, min));
+        assert!(is_fresh(now - ChronoDuration::seconds(29), now, min));
+        assert!(!is_fresh(now - ChronoDuration::seconds(30), now, min));
+        assert!(!is_fresh(now - ChronoDuration::hours(1), now, min));
+    }
+
+    #[test]
+    fn test_future_timestamp_is_stale() {
+        let now = Utc::now();
+        let skewed = now + ChronoDuration::minutes(5);
+        assert!(!is_fresh(skewed, now, Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_zero_interval_never_fresh() {
+        let now = Utc::now();
+        assert!(!is_fresh(now, now, Duration::ZERO));
+    }
+
+    fn cached_state(now: DateTime<Utc>, backoff: Option<DateTime<Utc>>) -> CacheState {
+        let mut snapshots = mock_snapshots();
+        for snapshot in snapshots.values_mut() {
+            snapshot.updated_at = now - ChronoDuration::hours(1);
+        }
+        CacheState {
+            schema_version: CACHE_VERSION,
+            snapshots,
+            updated_at: now - ChronoDuration::hours(1),
+            backoff_until: backoff
+                .map(|until| HashMap::from([(Provider::Claude, until)]))
+                .unwrap_or_default(),
+            last_errors: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fetch_mode_decisions() {
+        let now = Utc::now();
+        let hour = Duration::from_secs(3600);
+        let providers = [Provider::Claude, Provider::Codex];
+        let should = |mode: FetchMode, cached: Option<&CacheState>, max_age| {
+            mode.should_fetch(cached, &providers, max_age, now, |_| false)
+        };
+
+        // Snapshots are an hour old: fresh under 2h, stale under 30m
+        let cached = cached_state(now, None);
+        assert!(!should(FetchMode::Auto, Some(&cached), 2 * hour));
+        assert!(should(FetchMode::Auto, Some(&cached), hour / 2));
+        assert!(should(FetchMode::Auto, None, 2 * hour));
+        assert!(should(FetchMode::Always, Some(&cached), 2 * hour));
+        assert!(!should(FetchMode::Never, None, 2 * hour));
+        assert!(!should(FetchMode::Never, Some(&cached), hour / 2));
+
+        // A missing provider needs a fetch
+        let mut missing = cached_state(now, None);
+        missing.snapshots.remove(&Provider::Codex);
+        assert!(should(FetchMode::Auto, Some(&missing), 2 * hour));
+
+        // A stale provider that is backing off does not
+        let mut backing_off = cached_state(now, Some(now + ChronoDuration::minutes(5)));
+        backing_off
+            .snapshots
+            .get_mut(&Provider::Codex)
+            .unwrap()
+            .updated_at = now;
+        assert!(!should(FetchMode::Auto, Some(&backing_off), hour / 2));
+        backing_off
+            .snapshots
+            .get_mut(&Provider::Codex)
+            .unwrap()
+            .updated_at = now - ChronoDuration::hours(1);
+        assert!(should(FetchMode::Auto, Some(&backing_off), hour / 2));
+    }
+
+    #[tokio::test]
+    async fn test_active_backoff_short_circuits_to_cache() {
+        let now = Utc::now();
+        let until = now + ChronoDuration::minutes(5);
+        let cached = cached_state(now, Some(until));
+        let mut calls = Vec::new();
+
+        let (result, state) = run(
+            Some(&cached),
+            &[Provider::Claude],
+            true,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| {
+                calls.push(p);
+                async move { Err(anyhow::anyhow!("should not be called")) }
+            },
+        )
+        .await;
+
+        assert!(calls.is_empty());
+        assert!(result.fetched.is_empty());
+        assert_eq!(result.backed_off.get(&Provider::Claude), Some(&until));
+        assert!(result.snapshots.contains_key(&Provider::Claude));
+        assert!(result.errors.is_empty());
+        assert!(state.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_foreign_snapshots_are_always_replaced() {
+        let now = Utc::now();
+        let hour = Duration::from_secs(3600);
+        let laptop = Origin::new("laptop", Some("/home/me/.claude/.credentials.json"));
+        let desktop = Origin::new("desktop", Some("/home/me/.claude/.credentials.json"));
+        let foreign = |s: &UsageSnapshot| {
+            s.origin
+                .as_ref()
+                .is_some_and(|o| o.fingerprint != laptop.fingerprint)
+        };
+        // Both just fetched, Claude on the desktop and Codex here
+        let mut cached = cached_state(now, None);
+        for snapshot in cached.snapshots.values_mut() {
+            snapshot.updated_at = now;
+            snapshot.origin = Some(laptop.clone());
+        }
+        cached.snapshots.get_mut(&Provider::Claude).unwrap().origin = Some(desktop);
+
+        let should = |providers: &[Provider]| {
+            FetchMode::Auto.should_fetch(Some(&cached), providers, hour, now, foreign)
+        };
+        assert!(should(&[Provider::Claude]));
+        assert!(!should(&[Provider::Codex]));
+
+        let (result, state) = run(
+            Some(&cached),
+            &[Provider::Claude, Provider::Codex],
+            false,
+            hour,
+            now,
+            foreign,
+            |p| {
+                let mut snapshot = mock_snapshots().remove(&p).unwrap();
+                snapshot.origin = Some(laptop.clone());
+                async move { Ok(snapshot) }
+            },
+        )
+        .await;
+        assert_eq!(result.fetched, vec![Provider::Claude]);
+        assert_eq!(result.reused, vec![Provider::Codex]);
+        let state = state.unwrap();
+        assert!(!state.snapshots.values().any(foreign));
+
+        // With tracking off nothing is foreign, so the fresh snapshot stays
+        let (result, _) = run(
+            Some(&cached),
+            &[Provider::Claude],
+            false,
+            hour,
+            now,
+            |_| false,
+            |_| async move { Err(anyhow::anyhow!("should not be called")) },
+        )
+        .await;
+        assert_eq!(result.reused, vec![Provider::Claude]);
+    }
+
+    #[tokio::test]
+    async fn test_low_headroom_stretches_reuse() {
+        let now = Utc::now();
+        // An hour old, which a 30 minute interval would refetch
+        let mut cached = cached_state(now, None);
+        cached
+            .snapshots
+            .get_mut(&Provider::Codex)
+            .unwrap()
+            .rate_limit = Some(RateLimitInfo {
+            limit: Some(60),
+            remaining: Some(2),
+            resets_at: Some(now + ChronoDuration::hours(2)),
+        });
+
+        let (result, _) = run(
+            Some(&cached),
+            &[Provider::Claude, Provider::Codex],
+            false,
+            Duration::from_secs(1800),
+            now,
+            |_| false,
+            |p| {
+                let snapshot = mock_snapshots().remove(&p).unwrap();
+                async move { Ok(snapshot) }
+            },
+        )
+        .await;
+        assert_eq!(result.fetched, vec![Provider::Claude]);
+        assert_eq!(result.reused, vec![Provider::Codex]);
+
+        // Forcing fetches anyway
+        let (result, _) = run(
+            Some(&cached),
+            &[Provider::Codex],
+            true,
+            Duration::from_secs(1800),
+            now,
+            |_| false,
+            |p| {
+                let snapshot = mock_snapshots().remove(&p).unwrap();
+                async move { Ok(snapshot) }
+            },
+        )
+        .await;
+        assert_eq!(result.fetched, vec![Provider::Codex]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_backoff_fetches_and_clears() {
+        let now = Utc::now();
+        let cached = cached_state(now, Some(now - ChronoDuration::seconds(1)));
+
+        let (result, state) = run(
+            Some(&cached),
+            &[Provider::Claude],
+            false,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| {
+                let mut snapshot = mock_snapshots().remove(&p).unwrap();
+                snapshot.updated_at = now;
+                async move { Ok(snapshot) }
+            },
+        )
+        .await;
+
+        assert!(result.backed_off.is_empty());
+        assert_eq!(result.snapshots[&Provider::Claude].updated_at, now);
+        assert!(state.unwrap().backoff_until.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_fetch_records_backoff() {
+        let now = Utc::now();
+        let until = now + ChronoDuration::seconds(90);
+        let cached = cached_state(now, None);
+
+        let (result, state) = run(
+            Some(&cached),
+            &[Provider::Claude],
+            false,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |_| async move { Err(ProviderError::RateLimited { until }.into()) },
+        )
+        .await;
+
+        let error = result.errors.get(&Provider::Claude).unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("rate limited, retrying after "));
+        // The stale snapshot is kept rather than dropped
+        assert!(result.snapshots.contains_key(&Provider::Claude));
+        let state = state.unwrap();
+        assert_eq!(state.backoff_until.get(&Provider::Claude), Some(&until));
+        assert!(state.snapshots.contains_key(&Provider::Claude));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_errors_recorded_until_success() {
+        let now = Utc::now();
+        let cached = cached_state(now, None);
+
+        let (_, state) = run(
+            Some(&cached),
+            &[Provider::Claude, Provider::Codex],
+            true,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| async move {
+                match p {
+                    Provider::Codex => Err(anyhow::anyhow!("token expired")),
+                    _ => Ok(mock_snapshots().remove(&p).unwrap()),
+                }
+            },
+        )
+        .await;
+        let state = state.unwrap();
+        assert_eq!(
+            state.last_errors.get(&Provider::Codex),
+            Some(&FetchFailure {
+                reason: "token expired".to_string(),
+                at: now,
+            })
+        );
+        assert!(!state.last_errors.contains_key(&Provider::Claude));
+        // The stale snapshot stays alongside the error
+        assert!(state.snapshots.contains_key(&Provider::Codex));
+
+        let (_, state) = run(
+            Some(&state),
+            &[Provider::Codex],
+            true,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| async move { Ok(mock_snapshots().remove(&p).unwrap()) },
+        )
+        .await;
+        assert!(state.unwrap().last_errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_providers_fetch_concurrently() {
+        let now = Utc::now();
+        // Each fetch waits for the other, so a sequential run never finishes
+        let barrier = std::sync::Arc::new(tokio::sync::Barrier::new(2));
+        let refresh = run(
+            None,
+            &[Provider::Claude, Provider::Codex],
+            false,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| {
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait().await;
+                    Ok(mock_snapshots().remove(&p).unwrap())
+                }
+            },
+        );
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), refresh)
+            .await
+            .expect("fetches ran one after another");
+        assert_eq!(result.fetched, vec![Provider::Claude, Provider::Codex]);
+    }
+
+    #[tokio::test]
+    async fn test_save_merges_unfetched_providers() {
+        let now = Utc::now();
+        let cached = cached_state(now, None);
+
+        let (result, state) = run(
+            Some(&cached),
+            &[Provider::Codex],
+            true,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| {
+                let mut snapshot = mock_snapshots().remove(&p).unwrap();
+                snapshot.updated_at = now;
+                async move { Ok(snapshot) }
+            },
+        )
+        .await;
+
+        assert_eq!(result.snapshots.len(), 1);
+        assert_eq!(result.fetched, vec![Provider::Codex]);
+        let state = state.unwrap();
+        assert_eq!(state.snapshots.len(), cached.snapshots.len());
+        assert_eq!(state.snapshots[&Provider::Codex].updated_at, now);
+        assert_eq!(
+            state.snapshots[&Provider::Claude].updated_at,
+            cached.snapshots[&Provider::Claude].updated_at
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alternating_failures_never_evict_good_snapshots() {
+        let start = Utc::now();
+        let both = [Provider::Claude, Provider::Codex];
+        let mut state: Option<CacheState> = None;
+        // Claude fails on odd runs, Codex on even ones, after a first good run
+        for round in 0..6i64 {
+            let now = start + ChronoDuration::minutes(round);
+            let failing = match round {
+                0 => None,
+                r if r % 2 == 1 => Some(Provider::Claude),
+                _ => Some(Provider::Codex),
+            };
+            let (result, next) = run(
+                state.as_ref(),
+                &both,
+                true,
+                Duration::ZERO,
+                now,
+                |_| false,
+                |p| {
+                    let mut snapshot = mock_snapshots().remove(&p).unwrap();
+                    snapshot.updated_at = now;
+                    async move {
+                        match failing {
+                            Some(f) if f == p => Err(anyhow::anyhow!("HTTP 500")),
+                            _ => Ok(snapshot),
+                        }
+                    }
+                },
+            )
+            .await;
+            let next = next.unwrap();
+
+            for provider in both {
+                assert!(result.snapshots.contains_key(&provider), "round {}", round);
+                let saved = &next.snapshots[&provider];
+                if Some(provider) == failing {
+                    // Kept as last fetched, a minute earlier, next to the error
+                    assert_eq!(saved.updated_at, now - ChronoDuration::minutes(1));
+                    assert!(next.last_errors.contains_key(&provider));
+                } else {
+                    assert_eq!(saved.updated_at, now);
+                    assert!(!next.last_errors.contains_key(&provider));
+                }
+            }
+            state = Some(next);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_without_cache_leaves_provider_out() {
+        let now = Utc::now();
+        let (result, state) = run(
+            None,
+            &[Provider::Claude, Provider::Codex],
+            true,
+            Duration::ZERO,
+            now,
+            |_| false,
+            |p| async move {
+                match p {
+                    Provider::Claude => Err(anyhow::anyhow!("HTTP 500")),
+                    _ => Ok(mock_snapshots().remove(&p).unwrap()),
+                }
+            },
+        )
+        .await;
+        assert!(!result.snapshots.contains_key(&Provider::Claude));
+        let state = state.unwrap();
+        assert_eq!(state.snapshots.len(), 1);
+        assert!(state.last_errors.contains_key(&Provider::Claude));
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+/// Shown for a known placeholder whose data is missing
+const MISSING: &str = "--";
+
+/// Values for template placeholders; `None` means the data is missing
+pub type Values = HashMap<&'static str, Option<String>>;
+
+/// Substitute `{name}` placeholders in a user template. A `[...]` group is
+/// dropped whole when any placeholder inside it is missing, so
+/// `{session}%[ / {week}%]` loses the week part when there is no weekly
+/// window; otherwise the brackets are removed. Missing values outside a
+/// group render as "--". Unknown placeholders and unmatched braces or
+/// brackets are kept as-is so typos stay visible.
+pub fn render(template: &str, values: &Values) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('[') {
+        let Some(len) = rest[start + 1..].find(']') else {
+            break;
+        };
+        out.push_str(&substitute(&rest[..start], values).0);
+        let (group, missing) = substitute(&rest[start + 1..start + 1 + len], values);
+        if !missing {
+            out.push_str(&group);
+        }
+        rest = &rest[start + len + 2..];
+    }
+
+    out.push_str(&substitute(rest, values).0);
+    out
+}
+
+/// Substitute placeholders in text without groups, reporting whether any
+/// known placeholder was missing
+fn substitute(text: &str, values: &Values) -> (String, bool) {
+    let mut out = String::with_capacity(text.len());
+    let mut missing = false;
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return (out, missing);
+        };
+        let name = &after[..end];
+        match values.get(name) {
+            Some(Some(value)) => out.push_str(value),
+            Some(None) => {
+                missing = true;
+                out.push_str(MISSING);
+            }
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    (out, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Values {
+        HashMap::from([
+            ("icon", Some("󰧑".to_string())),
+            ("session", Some("82".to_string())),
+            ("week", None),
+        ])
+    }
+
+    #[test]
+    fn test_substitutes_placeholders() {
+        assert_eq!(render("{icon} {session}%", &values()), "󰧑 82%");
+        assert_eq!(render("{session}{session}", &values()), "8282");
+    }
+
+    #[test]
+    fn test_missing_value_renders_dashes() {
+        assert_eq!(render("{session}% / {week}%", &values()), "82% / --%");
+    }
+
+    #[test]
+    fn test_unknown_and_unbalanced_kept() {
+        assert_eq!(render("{nope} {session}", &values()), "{nope} 82");
+        assert_eq!(render("{session} {open", &values()), "82 {open");
+        assert_eq!(render("plain text", &values()), "plain text");
+    }
+
+    #[test]
+    fn test_group_dropped_when_value_missing() {
+        let template = "{icon} {session}%[ / {week}%]";
+        assert_eq!(render(template, &values()), "󰧑 82%");
+
+        let mut week = values();
+        week.insert("week", Some("45".to_string()));
+        assert_eq!(render(template, &week), "󰧑 82% / 45%");
+    }
+
+    #[test]
+    fn test_group_needs_every_value() {
+        // One missing placeholder drops the whole group
+        assert_eq!(render("[{session} {week}]|", &values()), "|");
+        assert_eq!(render("[{session}%][{week}%]", &values()), "82%");
+        // No placeholders, or only unknown ones, keeps the contents
+        assert_eq!(render("[plain] [{nope}]", &values()), "plain {nope}");
+        assert_eq!(render("[]{session}", &values()), "82");
+    }
+
+    #[test]
+    fn test_unmatched_brackets_kept() {
+        assert_eq!(render("{session} [{week}", &values()), "82 [--");
+        assert_eq!(render("{session}] x", &values()), "82] x");
+    }
+
+    #[test]
+    fn test_multiline_groups() {
+        let template = "Session {session}%[\nWeek {week}%]";
+        assert_eq!(render(template, &values()), "Session 82%");
+        let template = "[Session {session}%\n]Week {week}%";
+        assert_eq!(render(template, &values()), "Session 82%\nWeek --%");
+    }
+}
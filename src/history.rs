@@ -0,0 +1,576 @@
+use crate::cache::Backend;
+use crate::files;
+use crate::models::{Provider, RateWindow, UsageSnapshot, WindowKind};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::Db;
+use crate::textbar::{self, IconSet};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// One line of history.jsonl: the windows and cost of a fetched snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub at: DateTime<Utc>,
+    pub provider: Provider,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub week: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sonnet: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,
+}
+
+impl Sample {
+    pub fn from_snapshot(snapshot: &UsageSnapshot) -> Self {
+        let [session, week, sonnet] = tracked(snapshot);
+        Self {
+            at: snapshot.updated_at,
+            provider: snapshot.provider,
+            session: session.map(|w| w.used_percent),
+            week: week.map(|w| w.used_percent),
+            sonnet: sonnet.map(|w| w.used_percent),
+            cost: snapshot.cost.as_ref().map(|c| c.used),
+            cost_limit: snapshot.cost.as_ref().map(|c| c.limit),
+            plan: snapshot.identity.as_ref().and_then(|i| i.plan.clone()),
+        }
+    }
+
+    /// Session, week and Sonnet percentages
+    fn windows(&self) -> [Option<f64>; 3] {
+        [self.session, self.week, self.sonnet]
+    }
+}
+
+/// The windows of `snapshot` history keeps: the main one, the week, and
+/// Claude's Sonnet week
+fn tracked(snapshot: &UsageSnapshot) -> [Option<&RateWindow>; 3] {
+    [
+        snapshot.primary(),
+        snapshot.secondary(),
+        snapshot.window(&WindowKind::Model("sonnet".to_string())),
+    ]
+}
+
+/// Which of the session, week and Sonnet values `window` of `snapshot` is
+/// kept as, for [`series`]; None for windows history doesn't keep
+pub fn window_index(snapshot: &UsageSnapshot, window: &RateWindow) -> Option<usize> {
+    tracked(snapshot)
+        .into_iter()
+        .position(|w| w.is_some_and(|w| std::ptr::eq(w, window)))
+}
+
+/// history.jsonl, which lives beside the cache file or database at `cache`
+pub fn history_path(cache: &Path) -> PathBuf {
+    cache.with_file_name("history.jsonl")
+}
+
+/// history.jsonl isn't pruned until it's at least this big
+const PRUNE_BYTES: u64 = 256 * 1024;
+
+/// Record samples for freshly fetched snapshots in the configured storage.
+/// Samples older than `retention` are pruned, lazily for history.jsonl (see
+/// [`append`]). Callers hold the cache lock.
+pub fn record<'a>(
+    snapshots: impl IntoIterator<Item = &'a UsageSnapshot>,
+    retention: Duration,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let samples: Vec<Sample> = snapshots.into_iter().map(Sample::from_snapshot).collect();
+    record_to(Backend::current(), &samples, retention, now)
+}
+
+/// [`record`] into `backend`
+pub fn record_to(
+    backend: &Backend,
+    samples: &[Sample],
+    retention: Duration,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    match backend {
+        Backend::Json(path) => {
+            let slack = chrono::Duration::from_std(retention / 8).unwrap_or(chrono::Duration::MAX);
+            append(
+                &history_path(path),
+                samples,
+                cutoff(now, retention),
+                PRUNE_BYTES,
+                slack,
+            )
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(path) => Db::open(path)?.append_samples(samples, cutoff(now, retention)),
+    }
+}
+
+/// Samples taken at or after `since` from the configured storage, oldest
+/// first; unreadable ones are skipped with a warning
+pub fn load(since: DateTime<Utc>) -> Result<Vec<Sample>> {
+    load_from(Backend::current(), since)
+}
+
+/// [`load`] from `backend`
+pub fn load_from(backend: &Backend, since: DateTime<Utc>) -> Result<Vec<Sample>> {
+    match backend {
+        Backend::Json(path) => {
+            let samples = read(&history_path(path))?;
+            Ok(samples.into_iter().filter(|s| s.at >= since).collect())
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite(path) => {
+            let (samples, skipped) = Db::open(path)?.samples_since(since)?;
+            if skipped > 0 {
+                tracing::warn!(
+                    "skipped {} unreadable sample(s) in {}",
+                    skipped,
+                    path.display()
+                );
+            }
+            Ok(samples)
+        }
+    }
+}
+
+fn cutoff(now: DateTime<Utc>, retention: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|d| now.checked_sub_signed(d))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Add `samples` to the end of the file. Most writes only append; the file
+/// is rewritten without samples from before `cutoff` once it's `min_bytes`
+/// or more and its oldest sample is `slack` past the cutoff, so a full
+/// history isn't rewritten on every fetch.
+fn append(
+    path: &Path,
+    samples: &[Sample],
+    cutoff: DateTime<Utc>,
+    min_bytes: u64,
+    slack: chrono::Duration,
+) -> Result<()> {
+    let len = std::fs::metadata(path).map_or(0, |m| m.len());
+    let prune_before = cutoff.checked_sub_signed(slack).unwrap_or(cutoff);
+    if len >= min_bytes && oldest(path).is_some_and(|at| at < prune_before) {
+        let mut kept = prune(read(path)?, cutoff);
+        kept.extend_from_slice(samples);
+        files::write_atomic(path, to_lines(&kept)?.as_bytes())?;
+        return Ok(());
+    }
+
+    let mut lines = to_lines(samples)?;
+    // A write cut short leaves a partial last line; start on a fresh one so
+    // only that line is lost
+    if len > 0 && !ends_with_newline(path)? {
+        lines.insert(0, '\n');
+    }
+    let mut file = files::open_append(path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+fn ends_with_newline(path: &Path) -> Result<bool> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0u8];
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+/// Timestamp of the first sample, reading only the first line
+fn oldest(path: &Path) -> Option<DateTime<Utc>> {
+    let file = std::fs::File::open(path).ok()?;
+    let line = BufReader::new(file).lines().next()?.ok()?;
+    serde_json::from_str::<Sample>(&line).ok().map(|s| s.at)
+}
+
+fn to_lines(samples: &[Sample]) -> Result<String> {
+    let mut out = String::new();
+    for sample in samples {
+        out.push_str(&serde_json::to_string(sample)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Drop samples taken before `cutoff`
+pub fn prune(samples: Vec<Sample>, cutoff: DateTime<Utc>) -> Vec<Sample> {
+    samples.into_iter().filter(|s| s.at >= cutoff).collect()
+}
+
+/// Smallest fall in used percent counted as a window reset; rounding in
+/// provider responses can wobble a value by less than this
+const RESET_DROP: f64 = 1.0;
+
+/// One window's `(time, used percent)` points for `provider`, oldest first.
+/// `window` indexes the session, week and Sonnet values in that order.
+pub fn series(samples: &[Sample], provider: Provider, window: usize) -> Vec<(DateTime<Utc>, f64)> {
+    samples
+        .iter()
+        .filter(|s| s.provider == provider)
+        .filter_map(|s| Some((s.at, s.windows().get(window).copied().flatten()?)))
+        .collect()
+}
+
+/// At most `max` points: consecutive runs are merged into their highest
+/// point, so peaks survive and a reset still shows as a drop between runs
+pub fn downsample(points: &[(DateTime<Utc>, f64)], max: usize) -> Vec<(DateTime<Utc>, f64)> {
+    if points.len() <= max || max == 0 {
+        return points.to_vec();
+    }
+    points
+        .chunks(points.len().div_ceil(max))
+        .filter_map(|run| run.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1)))
+        .collect()
+}
+
+/// Indices of points whose value fell from the one before, i.e. where the
+/// window reset in between
+pub fn resets(points: &[(DateTime<Utc>, f64)]) -> Vec<usize> {
+    points
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0].1 - pair[1].1 >= RESET_DROP)
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// All samples in file order; unreadable lines are skipped with a warning
+pub fn read(path: &Path) -> Result<Vec<Sample>> {
+    let (samples, skipped) = read_counted(path)?;
+    if skipped > 0 {
+        tracing::warn!(
+            "skipped {} unreadable line(s) in {}",
+            skipped,
+            path.display()
+        );
+    }
+    Ok(samples)
+}
+
+/// All samples in file order, and how many lines weren't samples: cut
+/// short by a crash, not UTF-8, or from a format this build doesn't know
+pub fn read_counted(path: &Path) -> Result<(Vec<Sample>, usize)> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e).context("Failed to open history"),
+    };
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line.context("Failed to read history")?;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        match serde_json::from_slice(&line) {
+            Ok(sample) => samples.push(sample),
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok((samples, skipped))
+}
+
+/// `--since` accepts a duration back from now ("7d", "12h", "1w 2d") or a
+/// date ("2026-10-01") or RFC 3339 timestamp
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(duration) = humantime::parse_duration(value) {
+        return Ok(cutoff(now, duration));
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    anyhow::bail!(
+        "Invalid --since {:?}: use a duration like \"7d\" or a date like \"2026-10-01\"",
+        value
+    )
+}
+
+const SPARKLINE_WIDTH: usize = 48;
+
+/// Per-provider sparklines of each window, then a table of the latest
+/// `limit` samples
+pub fn print_report(samples: &[Sample], providers: &[Provider], limit: usize, icon_set: IconSet) {
+    if samples.is_empty() {
+        println!("No usage history in this range yet.");
+        return;
+    }
+    let pct = |v: Option<f64>| v.map_or("--".to_string(), |v| format!("{:.0}%", v));
+
+    for provider in providers {
+        let rows: Vec<&Sample> = samples.iter().filter(|s| s.provider == *provider).collect();
+        if rows.is_empty() {
+            continue;
+        }
+        println!("{} ({} samples)", provider.display_name(), rows.len());
+
+        for (i, label) in ["Session", "Week   ", "Sonnet "].iter().enumerate() {
+            let values: Vec<f64> = rows.iter().filter_map(|s| s.windows()[i]).collect();
+            if values.is_empty() {
+                continue;
+            }
+            let peak = values.iter().copied().fold(0.0, f64::max);
+            println!(
+                "  {}  {}  peak {:.0}%",
+                label,
+                textbar::sparkline(&values, SPARKLINE_WIDTH, icon_set),
+                peak
+            );
+        }
+
+        println!(
+            "  {:<16}  {:>7}  {:>5}  {:>6}  {:>8}",
+            "Time", "Session", "Week", "Sonnet", "Cost"
+        );
+        for sample in &rows[rows.len().saturating_sub(limit)..] {
+            println!(
+                "  {:<16}  {:>7}  {:>5}  {:>6}  {:>8}",
+                sample.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                pct(sample.session),
+                pct(sample.week),
+                pct(sample.sonnet),
+                sample
+                    .cost
+                    .map_or("--".to_string(), |c| format!("{:.2}", c)),
+            );
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(at: DateTime<Utc>, session: f64) -> Sample {
+        Sample {
+            at,
+            provider: Provider::Claude,
+            session: Some(session),
+            week: None,
+            sonnet: None,
+            cost: None,
+            cost_limit: None,
+            plan: None,
+        }
+    }
+
+    #[test]
+    fn test_prune_drops_expired() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let cut = cutoff(now, Duration::from_secs(30 * 86400));
+        let samples = vec![
+            sample(cut - chrono::Duration::seconds(1), 1.0),
+            sample(cut, 2.0),
+            sample(now, 3.0),
+        ];
+        let kept = prune(samples, cut);
+        assert_eq!(
+            kept.iter().map(|s| s.session).collect::<Vec<_>>(),
+            [Some(2.0), Some(3.0)]
+        );
+    }
+
+    #[test]
+    fn test_append_prunes_on_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let old = now - chrono::Duration::days(40);
+
+        let eager = |samples: &[Sample], cutoff| {
+            append(&path, samples, cutoff, 0, chrono::Duration::zero()).unwrap()
+        };
+
+        eager(&[sample(old, 1.0)], old - chrono::Duration::days(1));
+        eager(&[sample(now, 2.0)], old - chrono::Duration::days(1));
+        assert_eq!(read(&path).unwrap().len(), 2);
+
+        eager(
+            &[sample(now, 3.0)],
+            cutoff(now, Duration::from_secs(30 * 86400)),
+        );
+        let samples = read(&path).unwrap();
+        assert_eq!(samples, vec![sample(now, 2.0), sample(now, 3.0)]);
+    }
+
+    #[test]
+    fn test_pruning_waits_for_size_and_slack() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let cut = now - chrono::Duration::days(30);
+        let day = chrono::Duration::days(1);
+        let line_bytes = to_lines(&[sample(now, 1.0)]).unwrap().len() as u64;
+
+        append(&path, &[sample(cut - day * 2, 1.0)], cut, 0, day * 3).unwrap();
+        // Expired, but the file is small: appended
+        append(&path, &[sample(now, 2.0)], cut, 3 * line_bytes, day).unwrap();
+        assert_eq!(read(&path).unwrap().len(), 2);
+        // Big enough, but not yet `slack` past the cutoff: appended
+        append(&path, &[sample(now, 3.0)], cut, line_bytes, day * 3).unwrap();
+        assert_eq!(read(&path).unwrap().len(), 3);
+        // Both: rewritten without the expired sample
+        append(&path, &[sample(now, 4.0)], cut, line_bytes, day).unwrap();
+        let sessions: Vec<_> = read(&path).unwrap().iter().map(|s| s.session).collect();
+        assert_eq!(sessions, [Some(2.0), Some(3.0), Some(4.0)]);
+    }
+
+    #[test]
+    fn test_unreadable_lines_are_skipped_and_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let good = to_lines(&[sample(now, 1.0)]).unwrap();
+        let mut content = good.clone().into_bytes();
+        content.extend_from_slice(
+            b"not json\n\n\xff\xfe\n{\"at\":\"2026-10-17T12:00:00Z\",\"provider\":\"gemini\"}\n",
+        );
+        content.extend_from_slice(good.as_bytes());
+        // A write cut short
+        content.extend_from_slice(&good.as_bytes()[..20]);
+        std::fs::write(&path, &content).unwrap();
+
+        let (samples, skipped) = read_counted(&path).unwrap();
+        assert_eq!(samples, vec![sample(now, 1.0), sample(now, 1.0)]);
+        assert_eq!(skipped, 4);
+
+        // The next append starts a new line rather than extending the cut one
+        append(
+            &path,
+            &[sample(now, 2.0)],
+            now,
+            u64::MAX,
+            chrono::Duration::zero(),
+        )
+        .unwrap();
+        let (samples, skipped) = read_counted(&path).unwrap();
+        assert_eq!(samples.last(), Some(&sample(now, 2.0)));
+        assert_eq!(skipped, 4);
+    }
+
+    #[test]
+    fn test_record_and_load_in_each_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let hours = |h| now - chrono::Duration::hours(h);
+        let retention = Duration::from_secs(48 * 3600);
+        for backend in crate::cache::test_backends(dir.path()) {
+            assert!(load_from(&backend, hours(100)).unwrap().is_empty());
+            record_to(&backend, &[], retention, now).unwrap();
+            record_to(
+                &backend,
+                &[sample(hours(30), 1.0), sample(hours(10), 2.0)],
+                retention,
+                now,
+            )
+            .unwrap();
+            record_to(&backend, &[sample(now, 3.0)], retention, now).unwrap();
+
+            let sessions = |since| -> Vec<_> {
+                let samples = load_from(&backend, since).unwrap();
+                samples.iter().map(|s| s.session).collect()
+            };
+            assert_eq!(sessions(hours(100)), [Some(1.0), Some(2.0), Some(3.0)]);
+            assert_eq!(sessions(hours(10)), [Some(2.0), Some(3.0)]);
+            assert_eq!(sessions(now), [Some(3.0)]);
+        }
+    }
+
+    #[test]
+    fn test_sample_is_compact() {
+        let at = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        assert_eq!(
+            serde_json::to_string(&sample(at, 42.0)).unwrap(),
+            r#"{"at":"2026-10-17T12:00:00Z","provider":"claude","session":42.0}"#
+        );
+    }
+
+    #[test]
+    fn test_series() {
+        let at = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let mut week = sample(at, 10.0);
+        week.week = Some(40.0);
+        let mut codex = sample(at, 50.0);
+        codex.provider = Provider::Codex;
+        let samples = [sample(at, 5.0), week, codex];
+
+        assert_eq!(
+            series(&samples, Provider::Claude, 0),
+            [(at, 5.0), (at, 10.0)]
+        );
+        assert_eq!(series(&samples, Provider::Claude, 1), [(at, 40.0)]);
+        assert!(series(&samples, Provider::Claude, 2).is_empty());
+    }
+
+    #[test]
+    fn test_downsample_keeps_peaks() {
+        let start = Utc.with_ymd_and_hms(2026, 10, 17, 0, 0, 0).unwrap();
+        let points: Vec<_> = [10.0, 30.0, 20.0, 5.0, 7.0, 6.0, 40.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (start + chrono::Duration::minutes(i as i64), v))
+            .collect();
+
+        assert_eq!(downsample(&points, 10), points);
+        let merged = downsample(&points, 3);
+        assert_eq!(
+            merged.iter().map(|p| p.1).collect::<Vec<_>>(),
+            [30.0, 7.0, 40.0]
+        );
+        assert_eq!(merged[0].0, points[1].0);
+        assert!(downsample(&points, 2).len() <= 2);
+    }
+
+    #[test]
+    fn test_resets() {
+        let at = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let points: Vec<_> = [10.0, 60.0, 59.5, 2.0, 30.0, 0.0]
+            .iter()
+            .map(|&v| (at, v))
+            .collect();
+        // A half-point wobble is not a reset
+        assert_eq!(resets(&points), [3, 5]);
+        assert!(resets(&points[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse_since("7d", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 10, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("12h", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 17, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2026-10-01", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2026-10-01T08:00:00+02:00", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 1, 6, 0, 0).unwrap()
+        );
+        assert!(parse_since("last week", now).is_err());
+        assert!(parse_since("7", now).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Provider icons for the popup: the built-in SVGs or a user's file, drawn in
+//! the theme's foreground color
+
+use crate::models::Provider;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Where an icon's image comes from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IconSource {
+    Builtin(Provider),
+    /// `[providers.<name>] icon_path`
+    File(PathBuf),
+}
+
+/// Everything a rendered icon depends on; a different theme color or size
+/// is a different icon
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IconKey {
+    pub source: IconSource,
+    pub size: i32,
+    pub color: String,
+}
+
+/// The SVG shipped for a provider; None for those drawn with a glyph
+pub fn builtin_svg(provider: Provider) -> Option<&'static [u8]> {
+    match provider {
+        Provider::Claude | Provider::ClaudeOrg => Some(include_bytes!("../assets/claude.svg")),
+        Provider::Codex => Some(include_bytes!("../assets/openai.svg")),
+        Provider::OpenCode => Some(include_bytes!("../assets/opencode-logo-dark.svg")),
+        Provider::Kiro | Provider::Windsurf => None,
+    }
+}
+
+/// `svg` with every `currentColor` painted `color`; SVGs with fixed colors
+/// come back unchanged
+pub fn recolor(svg: &str, color: &str) -> String {
+    svg.replace("currentColor", color)
+}
+
+/// `#rrggbb` for a color with 0.0–1.0 channels, as GDK reports them
+pub fn hex_color(red: f32, green: f32, blue: f32) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(red),
+        channel(green),
+        channel(blue)
+    )
+}
+
+/// Whether `value` is a `#RRGGBB` color
+pub fn is_hex_color(value: &str) -> bool {
+    value
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Rendered icons by key, so re-rendering the popup every tick doesn't
+/// rasterize them again. Failures are kept too, so a broken `icon_path` is
+/// only read and reported once.
+pub struct IconCache<T> {
+    icons: HashMap<IconKey, Option<T>>,
+}
+
+impl<T> Default for IconCache<T> {
+    fn default() -> Self {
+        Self {
+            icons: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> IconCache<T> {
+    /// The icon for `key`, rendered with `render` the first time it's asked for
+    pub fn get_or_render(
+        &mut self,
+        key: IconKey,
+        render: impl FnOnce(&IconKey) -> Option<T>,
+    ) -> Option<T> {
+        if let Some(icon) = self.icons.get(&key) {
+            return icon.clone();
+        }
+        let icon = render(&key);
+        self.icons.insert(key, icon.clone());
+        icon
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn key(size: i32, color: &str) -> IconKey {
+        IconKey {
+            source: IconSource::Builtin(Provider::Claude),
+            size,
+            color: color.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_recolor() {
+        let svg = r#"<path fill="currentColor"/><path stroke="currentColor"/>"#;
+        assert_eq!(
+            recolor(svg, "#2e3440"),
+            r##"<path fill="#2e3440"/><path stroke="#2e3440"/>"##
+        );
+        let fixed = r##"<path fill="#ffffff"/>"##;
+        assert_eq!(recolor(fixed, "#2e3440"), fixed);
+        let claude = String::from_utf8_lossy(builtin_svg(Provider::Claude).unwrap());
+        assert!(!recolor(&claude, "#000000").contains("currentColor"));
+    }
+
+    #[test]
+    fn test_hex_color() {
+        assert_eq!(hex_color(1.0, 1.0, 1.0), "#ffffff");
+        assert_eq!(hex_color(0.0, 0.5, 0.2), "#008033");
+        assert_eq!(hex_color(-0.1, 1.2, 0.0), "#00ff00");
+        assert!(is_hex_color("#F8F8f2"));
+        assert!(!is_hex_color("f8f8f2"));
+        assert!(!is_hex_color("#fff"));
+        assert!(!is_hex_color("#f8f8g2"));
+    }
+
+    #[test]
+    fn test_cache_renders_each_key_once() {
+        let mut cache = IconCache::default();
+        let renders = Cell::new(0);
+        let get = |cache: &mut IconCache<String>, key: IconKey| {
+            cache.get_or_render(key, |key| {
+                renders.set(renders.get() + 1);
+                Some(format!("{}@{}", key.color, key.size))
+            })
+        };
+        assert_eq!(get(&mut cache, key(16, "#ffffff")).unwrap(), "#ffffff@16");
+        assert_eq!(get(&mut cache, key(16, "#ffffff")).unwrap(), "#ffffff@16");
+        // A theme switch or another size renders anew
+        assert_eq!(get(&mut cache, key(16, "#000000")).unwrap(), "#000000@16");
+        assert_eq!(get(&mut cache, key(32, "#000000")).unwrap(), "#000000@32");
+        assert_eq!(renders.get(), 3);
+
+        let file = IconKey {
+            source: IconSource::File(PathBuf::from("/nonexistent.svg")),
+            ..key(16, "#ffffff")
+        };
+        assert_ne!(file, key(16, "#ffffff"));
+        let mut failures = 0;
+        for _ in 0..2 {
+            let icon = cache.get_or_render(file.clone(), |_| {
+                failures += 1;
+                None
+            });
+            assert!(icon.is_none());
+        }
+        assert_eq!(failures, 1);
+    }
+}
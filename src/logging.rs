@@ -0,0 +1,270 @@
+use crate::files;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+
+/// Rotate the log file once it reaches this size, keeping one old copy
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Level for `-v` counts: off by default (info for the daemon, which logs
+/// to journald), debug with `-v`, trace with `-vv`
+pub fn level_for(verbose: u8, daemon: bool) -> LevelFilter {
+    match verbose {
+        0 if daemon => LevelFilter::INFO,
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Install the global subscriber. Logs go to stderr at `level`; a log file,
+/// when configured, gets at least info so headless runs leave a trail.
+/// Nothing is ever written to stdout, which carries the command output.
+pub fn init(level: LevelFilter, log_file: Option<PathBuf>) {
+    let file = log_file.and_then(
+        |path| match RotatingFile::open(path.clone(), MAX_LOG_BYTES) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("Failed to open log file {}: {}", path.display(), e);
+                None
+            }
+        },
+    );
+    let stderr = level != LevelFilter::OFF;
+    if !stderr && file.is_none() {
+        return;
+    }
+    let max_level = if file.is_some() {
+        level.max(LevelFilter::INFO)
+    } else {
+        level
+    };
+    let ansi = file.is_none() && io::stderr().is_terminal();
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(max_level)
+        .with_ansi(ansi)
+        .with_writer(Mutex::new(LogWriter { stderr, file }))
+        .try_init();
+}
+
+/// Sends each log line to stderr and/or the log file
+struct LogWriter {
+    stderr: bool,
+    file: Option<RotatingFile>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.stderr {
+            io::stderr().write_all(buf)?;
+        }
+        if let Some(ref mut file) = self.file {
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(ref mut file) = self.file {
+            file.flush()?;
+        }
+        io::stderr().flush()
+    }
+}
+
+/// An append-only file that moves itself to `<name>.1` once it grows past
+/// `max_bytes`
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = files::open_append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            file,
+            len,
+        })
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".1");
+        self.path.with_file_name(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        std::fs::rename(&self.path, self.rotated_path())?;
+        self.file = files::open_append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Mask anything that looks like a secret: `sk-…` keys, JWTs, and long
+/// opaque tokens keep only their first four characters
+pub fn redact(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let token = word.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+            let secret = token.len() >= 8
+                && (token.starts_with("sk-") || token.starts_with("eyJ") || token.len() >= 32)
+                && token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+            if secret {
+                word.replace(token, &format!("{}…", &token[..4]))
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A credential or personal value that must never reach the logs: its Debug
+/// output is masked, so structs holding it can still derive Debug, and the
+/// value itself is only reachable through [`Redacted::expose`]
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Redacted<T>(T);
+
+impl<T> Redacted<T> {
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// A URL for logs with secret-looking query values masked
+pub fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), redact(&v)))
+        .collect();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact("key sk-ant-admin01-abcdef rejected"),
+            "key sk-a… rejected"
+        );
+        assert_eq!(redact("token eyJhbGciOi.x.y."), "token eyJh….");
+        assert_eq!(
+            redact("opaque abcdefghijklmnopqrstuvwxyz0123456789"),
+            "opaque abcd…"
+        );
+        let path = "/home/me/.claude/.credentials.json parsed";
+        assert_eq!(redact(path), path);
+    }
+
+    #[test]
+    fn test_redacted_debug() {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct Credentials {
+            token: Redacted<String>,
+            email: Option<Redacted<String>>,
+        }
+        let creds = Credentials {
+            token: "sk-ant-oat01-secret".to_string().into(),
+            email: Some("me@example.com".to_string().into()),
+        };
+        let debug = format!("{:?} {:#?}", creds, creds);
+        assert!(!debug.contains("secret"), "{}", debug);
+        assert!(!debug.contains("me@example.com"), "{}", debug);
+        assert!(debug.contains("token: <redacted>"), "{}", debug);
+        assert_eq!(creds.token.expose(), "sk-ant-oat01-secret");
+
+        let parsed: Redacted<String> = serde_json::from_str("\"tok\"").unwrap();
+        assert_eq!(parsed.into_inner(), "tok");
+    }
+
+    #[test]
+    fn test_redact_url() {
+        let url = reqwest::Url::parse(
+            "https://example.com/usage?origin=AI_EDITOR&key=sk-abcdefghijklmnop",
+        )
+        .unwrap();
+        assert_eq!(
+            redact_url(&url),
+            "https://example.com/usage?origin=AI_EDITOR&key=sk-a%E2%80%A6"
+        );
+        let plain = reqwest::Url::parse("https://example.com/api/usage").unwrap();
+        assert_eq!(redact_url(&plain), "https://example.com/api/usage");
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotabar.log");
+        let mut file = RotatingFile::open(path.clone(), 10).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        file.write_all(b"third\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("quotabar.log.1")).unwrap(),
+            "second\n"
+        );
+    }
+
+    #[test]
+    fn test_level_for() {
+        assert_eq!(level_for(0, false), LevelFilter::OFF);
+        assert_eq!(level_for(0, true), LevelFilter::INFO);
+        assert_eq!(level_for(1, false), LevelFilter::DEBUG);
+        assert_eq!(level_for(3, true), LevelFilter::TRACE);
+    }
+}
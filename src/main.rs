@@ -1,95 +1,803 @@
 #![allow(dead_code)]
 
-use anyhow::Result;
-use cache::CacheState;
-use chrono::Utc;
+use anyhow::{Context, Result};
+use cache::{Backend, CacheState};
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
-use config::Config;
-use models::{Provider, UsageSnapshot};
-use providers::claude::ClaudeProvider;
-use providers::codex::CodexProvider;
-use providers::ProviderFetcher;
+use config::{Config, ProviderChoice, WaybarMode};
+use fetch::FetchMode;
+use models::{Provider, RateWindow, UsageSnapshot};
+use pace::UsagePace;
+use schemars::JsonSchema;
 use serde::Serialize;
 use std::collections::HashMap;
 
+mod barsignal;
+mod browser;
 mod cache;
+mod cachecli;
+mod changes;
+mod check;
+mod compositor;
 mod config;
+mod configcli;
+mod configwatch;
+mod daemon;
+mod detect;
+mod doctor;
+mod envvars;
+mod events;
+mod export;
+mod fetch;
+mod files;
+mod format;
+mod history;
+mod icons;
+mod logging;
+mod metrics;
+mod migrate;
 mod mock;
 mod models;
+mod origin;
 mod pace;
+mod paths;
 mod popup;
 mod providers;
+mod query;
+mod ratelimit;
+mod schema;
+mod settings;
+mod socket;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod status;
+mod statusline;
+mod textbar;
+mod theme;
+mod timefmt;
+mod watch;
 
 #[derive(Parser)]
 #[command(name = "quotabar")]
 #[command(about = "Monitor API quota/usage for AI coding tools")]
 struct Cli {
+    /// Log requests, cache and config decisions to stderr (-vv for trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Render canned snapshots instead of fetching; never touches credentials,
+/// the network, or the cache
+#[derive(clap::Args)]
+struct MockArgs {
+    /// Use built-in mock data instead of real providers
+    #[arg(long)]
+    mock: bool,
+    /// Use snapshots from a JSON file (an object keyed by provider id)
+    #[arg(long, value_name = "PATH", conflicts_with = "mock")]
+    mock_file: Option<std::path::PathBuf>,
+    /// Use one of the built-in scenarios, such as every provider critical
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_enum,
+        conflicts_with_all = ["mock", "mock_file"]
+    )]
+    mock_scenario: Option<mock::Scenario>,
+}
+
+impl MockArgs {
+    fn data(&self) -> Result<Option<mock::MockData>> {
+        if let Some(scenario) = self.mock_scenario {
+            return Ok(Some(scenario.data()));
+        }
+        match self.mock_file {
+            Some(ref path) => mock::load_mock_file(path).map(|s| Some(mock::MockData::new(s))),
+            None => Ok(self
+                .mock
+                .then(|| mock::MockData::new(mock::mock_snapshots()))),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Write a commented default config if none exists
+    Init {
+        /// Enable only the providers whose credential files are present,
+        /// selecting the first
+        #[arg(long)]
+        detect: bool,
+        /// Replace an existing config
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the config file path
+    Path,
+    /// Print the effective value of a key, including defaults
+    Get {
+        /// Dotted key, e.g. `general.selected_provider`
+        key: String,
+    },
+    /// Set a key in the config file, keeping comments and unknown keys
+    Set {
+        /// Dotted key, e.g. `providers.codex.enabled`
+        key: String,
+        value: String,
+    },
+    /// Open the config in $VISUAL/$EDITOR and check it afterwards
+    Edit,
+    /// Check the config file, failing on errors and on unknown keys
+    Validate,
+    /// Rewrite a config from an older quotabar in the current format
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show the cache's path, size and age, with each provider's age and
+    /// last error
+    Info {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the cache file as indented JSON
+    Show,
+    /// Delete the cache file, asking first
+    Clear {
+        /// Don't ask
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Show layer-shell popup (reads cache, refreshes in background)
     Popup {
-        /// Use mock data instead of real providers
+        /// Show only this provider, in more detail
+        #[arg(long, value_enum)]
+        provider: Option<Provider>,
+        #[command(flatten)]
+        mock: MockArgs,
+    },
+    /// Print JSON for Waybar from the cache, fetching when it is missing or stale
+    Waybar {
+        /// Only fetch and show these providers (repeatable); overrides
+        /// `selected_provider`
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// When to fetch before printing: auto fetches only if the cache is
+        /// missing or older than `refresh_interval`
+        #[arg(long = "fetch", value_name = "WHEN", value_enum, default_value_t)]
+        fetch_mode: FetchMode,
+        #[command(flatten)]
+        mock: MockArgs,
+    },
+    /// Fetch, cache, and print a text line for polybar
+    Polybar {
+        /// Only fetch and show these providers (repeatable); overrides
+        /// `selected_provider`
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// Print plain text without %{F} color tags
+        #[arg(long)]
+        no_color: bool,
+        /// When to fetch before printing: auto fetches only if the cache is
+        /// missing or older than `refresh_interval`
+        #[arg(long = "fetch", value_name = "WHEN", value_enum, default_value_t)]
+        fetch_mode: FetchMode,
+    },
+    /// Fetch, cache, and print full text, short text and color for i3blocks
+    I3blocks {
+        /// Only fetch and show these providers (repeatable); overrides
+        /// `selected_provider`
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// When to fetch before printing: auto fetches only if the cache is
+        /// missing or older than `refresh_interval`
+        #[arg(long = "fetch", value_name = "WHEN", value_enum, default_value_t)]
+        fetch_mode: FetchMode,
+    },
+    /// Print a tmux status-line segment from the cache (never blocks on network)
+    Tmux {
+        /// Only show these providers (repeatable); overrides `selected_provider`
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// Include the weekly percentage
+        #[arg(long)]
+        detail: bool,
+        /// Start a background fetch when the cached data is older than this
+        /// (e.g. "5m")
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        refresh_if_older_than: Option<std::time::Duration>,
+    },
+    /// Print a short warning for shell prompts when usage is high, else
+    /// nothing (reads only the cache)
+    Prompt {
+        /// Minimum used percentage to print anything (default from
+        /// `[prompt] threshold`)
+        #[arg(long)]
+        threshold: Option<f64>,
+        /// Only consider these providers (repeatable)
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+    },
+    /// Choose the provider shown in Waybar, then print the refreshed Waybar JSON
+    Select {
+        /// Provider to show
+        #[arg(value_enum, required_unless_present = "next", conflicts_with = "next")]
+        provider: Option<Provider>,
+        /// Cycle to the next enabled provider with cached data
+        #[arg(long)]
+        next: bool,
+        /// Send SIGRTMIN+N to waybar so the module updates immediately
+        /// [default: waybar.signal]
+        #[arg(long, value_name = "N")]
+        signal: Option<u8>,
+    },
+    /// Live terminal view of `status` that redraws every second
+    Watch {
+        /// Only show this provider (repeatable)
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// How often to fetch from the network [default: refresh_interval]
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        interval: Option<std::time::Duration>,
+        /// Draw a usage bar next to each percentage
+        #[arg(long)]
+        bars: bool,
+    },
+    /// Show recorded usage samples with a sparkline per window
+    History {
+        /// Only show this provider (repeatable)
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// How far back to look: a duration ("7d", "12h") or a date
+        #[arg(long, default_value = "7d")]
+        since: String,
+        /// Table rows per provider (most recent)
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Export recorded usage history as CSV or JSON
+    Export {
+        #[arg(long, value_enum, default_value = "csv")]
+        format: export::ExportFormat,
+        /// Only export this provider (repeatable)
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// Start of the range: a date, timestamp, or duration back ("30d")
+        #[arg(long)]
+        from: Option<String>,
+        /// End of the range; a bare date includes that whole day
+        #[arg(long)]
+        to: Option<String>,
+        /// Write here instead of stdout
+        #[arg(long, short)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Print usage as Prometheus metrics
+    Metrics {
+        /// Atomically write to this file (node_exporter textfile collector)
+        #[arg(long, value_name = "PATH", conflicts_with = "listen")]
+        textfile: Option<std::path::PathBuf>,
+        /// Serve /metrics on this address, e.g. 127.0.0.1:9464
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<String>,
+    },
+    /// Print one cached value by dotted path, e.g. `claude.primary.used_percent`;
+    /// exits 1 if the path doesn't resolve
+    Get {
+        /// `<provider>.<field>...`; windows also expose `remaining_percent`
+        /// and `resets_in_seconds`
+        path: String,
+        /// Fetch the provider first instead of reading the cache
+        #[arg(long)]
+        fresh: bool,
+    },
+    /// Exit 0 when usage is under the thresholds, 2 when over, 1 on errors
+    #[command(group(clap::ArgGroup::new("threshold").required(true).multiple(true)))]
+    Check {
+        #[arg(long, value_enum)]
+        provider: Provider,
+        /// Window to compare against `--max`
+        #[arg(long, value_enum, default_value = "any")]
+        window: check::CheckWindow,
+        /// Highest allowed used percentage
+        #[arg(long, value_name = "PERCENT", group = "threshold")]
+        max: Option<f64>,
+        /// Highest allowed spend in the provider's cost unit
+        #[arg(long, value_name = "AMOUNT", group = "threshold")]
+        cost_max: Option<f64>,
+        /// Fetch the provider first instead of reading the cache
+        #[arg(long)]
+        fresh: bool,
+    },
+    /// Read or change settings in config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Inspect or clear the cache of fetched usage
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Check config, cache, credentials and endpoints; exits 1 on any failure
+    Doctor {
+        /// Also show each usage endpoint's rate-limit headroom from the last fetch
         #[arg(long)]
-        mock: bool,
+        network: bool,
+    },
+    /// Refresh the cache every `refresh_interval` until stopped (SIGUSR1 refreshes now)
+    Daemon {
+        /// Send SIGRTMIN+N to waybar after each refresh that updates the
+        /// cache [default: waybar.signal]
+        #[arg(long, value_name = "N")]
+        waybar_signal: Option<u8>,
     },
-    /// Fetch, cache, and print JSON for Waybar
-    Waybar,
     /// Print all provider status to terminal
-    Status,
+    Status {
+        /// Only fetch and show these providers (repeatable)
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+        /// Show fetch timings and usage-endpoint rate-limit headroom
+        #[arg(long)]
+        timings: bool,
+        /// Draw a usage bar for each window (default from `[status] bars`)
+        #[arg(long)]
+        bars: bool,
+        /// Print a JSON report instead of text; always exits 0, with
+        /// per-provider error objects on failure
+        #[arg(long, conflicts_with_all = ["timings", "bars"])]
+        json: bool,
+        #[command(flatten)]
+        mock: MockArgs,
+    },
     /// Force fetch and update cache
-    Fetch,
+    Fetch {
+        /// Fetch even if the cached snapshot is newer than min_fetch_interval
+        #[arg(long)]
+        force: bool,
+        /// Only fetch these providers (repeatable); others stay cached
+        #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+        providers: Vec<Provider>,
+    },
+    /// Print the JSON Schema for one of the JSON outputs
+    Schema {
+        #[arg(value_enum)]
+        target: schema::SchemaTarget,
+    },
+    /// Print cached values as shell variable assignments (for `eval`)
+    Env {
+        /// Only emit variables for this provider
+        #[arg(long, value_enum)]
+        provider: Option<Provider>,
+        /// Prefix for every variable name
+        #[arg(long, default_value = "QB_")]
+        prefix: String,
+        /// List the variable names that may be emitted and exit
+        #[arg(long)]
+        list: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    // `get` answers from the cache without reading the config at all, so it
+    // only logs to stderr; `prompt` and `tmux` reuse this config rather than
+    // paying for a second load
+    let loaded = match cli.command {
+        Commands::Get { fresh: false, .. } => None,
+        _ => Config::load().ok(),
+    };
+    let log_file = loaded.as_ref().and_then(|c| c.log_file_path());
+    let daemon = matches!(cli.command, Commands::Daemon { .. });
+    logging::init(logging::level_for(cli.verbose, daemon), log_file);
 
     match cli.command {
-        Commands::Popup { mock } => {
-            popup::run(mock)?;
+        Commands::Popup { provider, mock } => {
+            popup::run(mock.data()?, provider)?;
         }
-        Commands::Waybar => {
-            let output = waybar_output().await;
+        Commands::Waybar {
+            providers,
+            fetch_mode,
+            mock,
+        } => {
+            let output = match mock.data()? {
+                Some(mock) => {
+                    build_waybar_output(&mock.snapshots, &mock.errors(), &load_config(&providers))
+                }
+                None => waybar_output(&providers, fetch_mode).await,
+            };
             println!("{}", serde_json::to_string(&output).unwrap());
         }
-        Commands::Status => {
-            match fetch_claude().await {
-                Ok(s) => print_status(&s),
-                Err(e) => eprintln!("Claude: {}", e),
+        Commands::Polybar {
+            providers,
+            no_color,
+            fetch_mode,
+        } => {
+            let config = load_config(&providers);
+            let (snapshots, _) =
+                bar_snapshots(&config, &shown_providers(&config), fetch_mode).await;
+            let snapshot = select_snapshot(&snapshots, &config);
+            println!("{}", statusline::polybar_line(snapshot, &config, !no_color));
+        }
+        Commands::Tmux {
+            providers,
+            detail,
+            refresh_if_older_than,
+        } => {
+            let config = restricted(loaded.unwrap_or_default(), &providers);
+            let snapshots = socket::load_cache()
+                .map(|c| c.snapshots)
+                .unwrap_or_default();
+            let snapshot = select_snapshot(&snapshots, &config);
+
+            if let Some(max_age) = refresh_if_older_than {
+                let fresh =
+                    snapshot.is_some_and(|s| fetch::is_fresh(s.updated_at, Utc::now(), max_age));
+                if !fresh {
+                    // The segment still prints; tmux will pick up new data next tick
+                    let _ = fetch::spawn_background_fetch(&providers);
+                }
             }
-            match fetch_codex().await {
-                Ok(s) => print_status(&s),
-                Err(e) => eprintln!("Codex: {}", e),
+            println!("{}", statusline::tmux_segment(snapshot, &config, detail));
+        }
+        Commands::Prompt {
+            threshold,
+            providers,
+        } => {
+            let config = restricted(loaded.unwrap_or_default(), &providers);
+            let snapshots = socket::load_cache()
+                .map(|c| c.snapshots)
+                .unwrap_or_default();
+            let threshold = threshold.unwrap_or(config.prompt.threshold);
+            if let Some(text) =
+                statusline::prompt_text(select_snapshot(&snapshots, &config), &config, threshold)
+            {
+                println!("{}", text);
             }
         }
-        Commands::Fetch => {
-            let mut snapshots = HashMap::new();
+        Commands::Select {
+            provider,
+            next,
+            signal,
+        } => {
+            let mut config = Config::load().unwrap_or_default();
+            let provider = if next {
+                let snapshots = CacheState::load()
+                    .ok()
+                    .flatten()
+                    .map(|c| c.snapshots)
+                    .unwrap_or_default();
+                next_provider(&config, &snapshots).ok_or_else(|| {
+                    anyhow::anyhow!("No enabled provider has cached data; run `quotabar fetch`")
+                })?
+            } else {
+                provider.expect("clap requires a provider without --next")
+            };
+            if !config.is_provider_enabled(provider) {
+                anyhow::bail!("{} is disabled in the config", provider.display_name());
+            }
 
-            match fetch_claude().await {
-                Ok(s) => {
-                    snapshots.insert(Provider::Claude, s);
+            config.general.selected_provider = Some(provider.into());
+            config.save()?;
+            let output = waybar_output(&[], FetchMode::Auto).await;
+            println!("{}", serde_json::to_string(&output)?);
+            if let Some(n) = signal.or(config.waybar.signal) {
+                barsignal::send(n)?;
+            }
+        }
+        Commands::Watch {
+            providers,
+            interval,
+            bars,
+        } => {
+            let config = load_config(&providers);
+            let interval = match interval {
+                Some(interval) => config::clamp_refresh_interval(interval),
+                None => config.refresh_interval(),
+            };
+            let style = status::StatusStyle::from_config(&config, bars);
+            let shown = if providers.is_empty() {
+                Provider::ALL.to_vec()
+            } else {
+                providers
+            };
+            watch::run(&config, &shown, interval, &style).await?;
+        }
+        Commands::History {
+            providers,
+            since,
+            limit,
+        } => {
+            let config = Config::load().unwrap_or_default();
+            let since = history::parse_since(&since, Utc::now())?;
+            let samples = history::load(since)?;
+            let shown = if providers.is_empty() {
+                Provider::ALL.to_vec()
+            } else {
+                providers
+            };
+            history::print_report(&samples, &shown, limit, config.general.icon_set);
+        }
+        Commands::Export {
+            format,
+            providers,
+            from,
+            to,
+            output,
+        } => {
+            let now = Utc::now();
+            let filter = export::Filter {
+                providers,
+                from: from.map(|v| history::parse_since(&v, now)).transpose()?,
+                to: to.map(|v| export::parse_to(&v, now)).transpose()?,
+            };
+            let samples = history::load(filter.from.unwrap_or(DateTime::<Utc>::MIN_UTC))?;
+            let data = export::export(&samples, &filter, format)?;
+            match output {
+                Some(path) => std::fs::write(&path, data)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{}", data),
+            }
+        }
+        Commands::Metrics { textfile, listen } => {
+            let config = Config::load().unwrap_or_default();
+            if let Some(addr) = listen {
+                let interval = config.refresh_interval();
+                metrics::serve(&addr, interval).await?;
+            } else {
+                let text = metrics::collect(&config).await;
+                match textfile {
+                    Some(path) => metrics::write_textfile(&path, &text)?,
+                    None => print!("{}", text),
                 }
-                Err(e) => eprintln!("Failed to fetch Claude: {}", e),
             }
-
-            match fetch_codex().await {
-                Ok(s) => {
-                    snapshots.insert(Provider::Codex, s);
+        }
+        Commands::Get { path, fresh } => {
+            let snapshots = match query::parse_path(&path) {
+                Ok((provider, _)) if fresh => {
+                    let config = load_config(&[provider]);
+                    fetch::refresh(&config, &[provider], true).await.snapshots
+                }
+                Ok((provider, _)) => socket::load_snapshot(provider)
+                    .map(|snapshot| HashMap::from([(provider, snapshot)]))
+                    .unwrap_or_default(),
+                // query::get says what's wrong with the path
+                Err(_) => HashMap::new(),
+            };
+            match query::get(&snapshots, &path, Utc::now()) {
+                Ok(value) => println!("{}", value),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
                 }
-                Err(e) => eprintln!("Failed to fetch Codex: {}", e),
             }
-
-            if !snapshots.is_empty() {
-                let state = CacheState {
-                    snapshots,
-                    updated_at: Utc::now(),
+        }
+        Commands::Check {
+            provider,
+            window,
+            max,
+            cost_max,
+            fresh,
+        } => {
+            let thresholds = check::Thresholds {
+                window,
+                max,
+                cost_max,
+            };
+            std::process::exit(check::run(provider, thresholds, fresh).await);
+        }
+        Commands::Config { action } => {
+            let path = Config::config_path();
+            match action {
+                ConfigAction::Init { detect, force } => {
+                    let content = if detect {
+                        let found = detect::detect_here();
+                        println!("{}", detect::summary(&found));
+                        configcli::detected_config(&found)?
+                    } else {
+                        configcli::DEFAULT_CONFIG.to_string()
+                    };
+                    configcli::init(&path, &content, force)?;
+                    println!("Wrote {}", path.display());
+                }
+                ConfigAction::Path => println!("{}", path.display()),
+                ConfigAction::Get { key } => {
+                    let config = Config::load()
+                        .with_context(|| format!("Failed to load {}", path.display()))?;
+                    if let Some(value) = configcli::get(&config, &key)? {
+                        println!("{}", value);
+                    }
+                }
+                ConfigAction::Set { key, value } => configcli::set(&path, &key, &value)?,
+                ConfigAction::Edit => configcli::edit(&path)?,
+                ConfigAction::Validate => {
+                    let problems = configcli::validate(&path)?;
+                    if !problems.is_empty() {
+                        config::report_problems(&path, &problems);
+                        std::process::exit(1);
+                    }
+                    println!("{} is valid", path.display());
+                }
+                ConfigAction::Migrate => match configcli::migrate(&path)? {
+                    Some(migrated) => {
+                        println!(
+                            "Migrated {} from version {} to {}",
+                            path.display(),
+                            migrated.from,
+                            migrate::CURRENT
+                        );
+                        for change in migrated.changes {
+                            println!("  {}", change);
+                        }
+                    }
+                    None => println!("{} is up to date", path.display()),
+                },
+            }
+        }
+        Commands::Cache { action } => {
+            let backend = Backend::current();
+            let path = backend.path();
+            match action {
+                CacheAction::Info { json } => {
+                    let now = Utc::now();
+                    let info = cachecli::info(backend, now)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&info)?);
+                    } else {
+                        println!("{}", cachecli::render(&info, now));
+                    }
+                }
+                CacheAction::Show => match cachecli::show(backend)? {
+                    Some(json) => println!("{}", json),
+                    None => println!("No cache at {}", path.display()),
+                },
+                CacheAction::Clear { yes } => {
+                    if !backend.exists()? {
+                        println!("No cache at {}", path.display());
+                    } else if yes || cachecli::confirm_clear(path)? {
+                        let _lock = CacheState::lock()?;
+                        backend.clear()?;
+                        println!("Cleared {}", path.display());
+                    }
+                }
+            }
+        }
+        Commands::Doctor { network } => {
+            if !doctor::run(network).await {
+                std::process::exit(1);
+            }
+        }
+        Commands::Daemon { waybar_signal } => {
+            daemon::run(waybar_signal).await?;
+        }
+        Commands::I3blocks {
+            providers,
+            fetch_mode,
+        } => {
+            let config = load_config(&providers);
+            let (snapshots, _) =
+                bar_snapshots(&config, &shown_providers(&config), fetch_mode).await;
+            let snapshot = select_snapshot(&snapshots, &config);
+            for line in statusline::i3blocks_lines(snapshot, &config) {
+                println!("{}", line);
+            }
+        }
+        Commands::Status {
+            providers,
+            timings,
+            bars,
+            json,
+            mock,
+        } => {
+            let mock = mock.data()?;
+            let config = load_config(&providers);
+            let style = status::StatusStyle::from_config(&config, bars);
+            let shown = if providers.is_empty() {
+                Provider::ALL.to_vec()
+            } else {
+                providers
+            };
+            if json {
+                let mut entries = Vec::new();
+                for provider in shown {
+                    let entry = if !config.is_provider_enabled(provider) {
+                        status::ProviderStatus::disabled(provider)
+                    } else {
+                        match status_snapshot(provider, &config, mock.as_ref()).await {
+                            Ok(s) => status::ProviderStatus::ok(s),
+                            Err(e) => status::ProviderStatus::failed(provider, &e),
+                        }
+                    };
+                    entries.push(entry);
+                }
+                let report = status::StatusReport {
+                    generated_at: Utc::now(),
+                    providers: entries,
                 };
-                state.save()?;
-                println!("Cache updated at {}", CacheState::cache_path().display());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            for provider in shown {
+                if !config.is_provider_enabled(provider) {
+                    println!("{}", status::disabled_line(provider, &style));
+                    continue;
+                }
+                let started = std::time::Instant::now();
+                let result = status_snapshot(provider, &config, mock.as_ref()).await;
+                let elapsed = started.elapsed();
+                match result {
+                    Ok(s) => {
+                        status::print_status(&s, &style);
+                        if timings {
+                            status::print_timings(&s, elapsed);
+                        }
+                    }
+                    Err(e) => eprintln!("{}: {}", provider.display_name(), e),
+                }
+            }
+        }
+        Commands::Fetch { force, providers } => {
+            let config = load_config(&providers);
+            let refresh = fetch::refresh(&config, &config.enabled_providers(), force).await;
+
+            for (provider, e) in &refresh.errors {
+                eprintln!("Failed to fetch {}: {}", provider.display_name(), e);
+            }
+            for (provider, until) in &refresh.backed_off {
+                let e = providers::ProviderError::RateLimited { until: *until };
+                println!("{}: {}", provider.display_name(), e);
+            }
+            for provider in &refresh.reused {
+                println!(
+                    "{}: cached snapshot is recent, skipped (use --force to refetch)",
+                    provider.display_name()
+                );
+            }
+            if let Some(e) = refresh.save_error {
+                return Err(e);
+            }
+            if let (Some(n), false) = (config.waybar.signal, refresh.fetched.is_empty()) {
+                if let Err(e) = barsignal::send(n) {
+                    eprintln!("Failed to signal waybar: {:#}", e);
+                }
+            }
+            if refresh.reused.len() + refresh.backed_off.len() < refresh.snapshots.len() {
+                println!("Cache updated at {}", Backend::current().path().display());
+            }
+        }
+        Commands::Schema { target } => {
+            let schema = schema::schema_for(target);
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+        }
+        Commands::Env {
+            provider,
+            prefix,
+            list,
+        } => {
+            if list {
+                for (name, description) in envvars::FIELDS {
+                    println!("{}{:<40} {}", prefix, name, description);
+                }
+                return Ok(());
+            }
+            let config = Config::load().unwrap_or_default();
+            let cache = CacheState::load().ok().flatten();
+            let providers = match provider {
+                Some(p) => vec![p],
+                None => Provider::ALL.to_vec(),
+            };
+            for line in envvars::env_lines(cache.as_ref(), &providers, &prefix, &config, Utc::now())
+            {
+                println!("{}", line);
             }
         }
     }
@@ -97,163 +805,937 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn fetch_claude() -> Result<models::UsageSnapshot> {
-    let provider = ClaudeProvider::new();
-    provider.fetch().await
+#[derive(Serialize, JsonSchema)]
+struct WaybarOutput {
+    text: String,
+    tooltip: String,
+    /// The provider id, plus `warning`/`critical` near the limit
+    class: Vec<String>,
+    /// Rounded max of the session and week usage, for waybar's `states`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percentage: Option<u8>,
 }
 
-async fn fetch_codex() -> Result<models::UsageSnapshot> {
-    let provider = CodexProvider::new();
-    provider.fetch().await
+/// Fetch a provider for `status`, or take it from the mock data when given
+async fn status_snapshot(
+    provider: Provider,
+    config: &Config,
+    mock: Option<&mock::MockData>,
+) -> Result<UsageSnapshot> {
+    match mock {
+        Some(mock) if mock.failures.contains_key(&provider) => {
+            anyhow::bail!("{}", mock.failures[&provider].reason)
+        }
+        Some(mock) => mock
+            .snapshots
+            .get(&provider)
+            .cloned()
+            .with_context(|| format!("No mock data for {}", provider.display_name())),
+        None => fetch::fetch_one(provider, config).await,
+    }
 }
 
-fn print_status(snapshot: &models::UsageSnapshot) {
-    println!(
-        "{} {} {}",
-        snapshot.provider.icon(),
-        snapshot.provider.display_name(),
-        snapshot
-            .identity
-            .as_ref()
-            .and_then(|i| i.plan.as_ref())
-            .map(|p| format!("({})", p))
-            .unwrap_or_default()
-    );
-
-    if let Some(ref primary) = snapshot.primary {
-        println!(
-            "  Current session:            {:.0}% used {}",
-            primary.used_percent,
-            primary.reset_description.as_deref().unwrap_or("")
-        );
-    }
-    if let Some(ref secondary) = snapshot.secondary {
-        println!(
-            "  Current week (all models):  {:.0}% used {}",
-            secondary.used_percent,
-            secondary.reset_description.as_deref().unwrap_or("")
-        );
-    }
-    if let Some(ref tertiary) = snapshot.tertiary {
-        println!(
-            "  Current week (Sonnet only): {:.0}% used {}",
-            tertiary.used_percent,
-            tertiary.reset_description.as_deref().unwrap_or("")
-        );
-    }
-    if let Some(ref cost) = snapshot.cost {
-        println!(
-            "  Cost:    ${:.2} / ${:.2} {}",
-            cost.used,
-            cost.limit,
-            cost.period.as_deref().unwrap_or("")
-        );
-    }
+/// Load the config, narrowed to `providers` when a filter was given
+fn load_config(providers: &[Provider]) -> Config {
+    restricted(Config::load().unwrap_or_default(), providers)
 }
 
-#[derive(Serialize)]
-struct WaybarOutput {
-    text: String,
-    tooltip: String,
-    class: Vec<String>,
+/// `config` limited to `providers`, or as it is when none are given
+fn restricted(config: Config, providers: &[Provider]) -> Config {
+    if providers.is_empty() {
+        config
+    } else {
+        config.restricted_to(providers)
+    }
 }
 
-async fn waybar_output() -> WaybarOutput {
-    // Fetch from all providers (currently Claude + Codex)
-    let mut snapshots = HashMap::new();
-    let config = Config::load().unwrap_or_default();
+/// How long a bar command waits on an inline fetch before printing cached data
+const BAR_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
-    if let Ok(snapshot) = fetch_claude().await {
-        snapshots.insert(Provider::Claude, snapshot);
-    }
-    if let Ok(snapshot) = fetch_codex().await {
-        snapshots.insert(Provider::Codex, snapshot);
-    }
+async fn waybar_output(providers: &[Provider], mode: FetchMode) -> WaybarOutput {
+    let config = load_config(providers);
+    let (snapshots, errors) = bar_snapshots(&config, &config.enabled_providers(), mode).await;
+    build_waybar_output(&snapshots, &errors, &config)
+}
 
-    // Save to cache
-    if !snapshots.is_empty() {
-        let state = CacheState {
-            snapshots: snapshots.clone(),
-            updated_at: Utc::now(),
-        };
-        let _ = state.save();
+/// The providers a single-provider bar shows: the selected one, or all the
+/// enabled ones when it picks among them
+fn shown_providers(config: &Config) -> Vec<Provider> {
+    match config.general.selected_provider {
+        Some(ProviderChoice::Specific(provider)) if config.is_provider_enabled(provider) => {
+            vec![provider]
+        }
+        _ => config.enabled_providers(),
     }
+}
+
+/// Cached snapshots for a bar command, fetching `providers` first when `mode`
+/// says to (for at most [`BAR_FETCH_TIMEOUT`], then in the background), with
+/// the providers whose fetch failed in this run
+async fn bar_snapshots(
+    config: &Config,
+    providers: &[Provider],
+    mode: FetchMode,
+) -> (
+    HashMap<Provider, UsageSnapshot>,
+    HashMap<Provider, anyhow::Error>,
+) {
+    let cached = match mode {
+        FetchMode::Never => socket::load_cache(),
+        _ => CacheState::load().ok().flatten(),
+    };
+    let mut snapshots = cached
+        .as_ref()
+        .map(|c| c.snapshots.clone())
+        .unwrap_or_default();
 
-    // Build output from snapshots
-    build_waybar_output(&snapshots, config.general.selected_provider)
+    let mut errors = HashMap::new();
+    let max_age = config.refresh_interval();
+    let foreign = |s: &UsageSnapshot| origin::foreign(s, config).is_some();
+    if mode.should_fetch(cached.as_ref(), providers, max_age, Utc::now(), foreign) {
+        // Never wait on another process's refresh: a bar tick that blocks on
+        // the lock would outlast the timeout below
+        let refresh = fetch::try_refresh(config, providers, false);
+        match tokio::time::timeout(BAR_FETCH_TIMEOUT, refresh).await {
+            Ok(None) => tracing::debug!("cache lock held elsewhere, showing cached data"),
+            Ok(Some(refresh)) => {
+                for (provider, e) in &refresh.errors {
+                    tracing::warn!("{}: {:#}", provider.display_name(), e);
+                }
+                if let Some(ref e) = refresh.save_error {
+                    tracing::warn!("failed to write cache: {:#}", e);
+                }
+                // Providers that failed keep their cached snapshot
+                snapshots.extend(refresh.snapshots);
+                errors = refresh.errors;
+                for (provider, until) in refresh.backed_off {
+                    if !snapshots.contains_key(&provider) {
+                        errors.insert(
+                            provider,
+                            providers::ProviderError::RateLimited { until }.into(),
+                        );
+                    }
+                }
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "fetch took longer than {:?}, showing cached data",
+                    BAR_FETCH_TIMEOUT
+                );
+                for provider in providers {
+                    if !snapshots.contains_key(provider) {
+                        errors.insert(*provider, anyhow::anyhow!("network timeout"));
+                    }
+                }
+                // Finish in the background so the next tick has fresh data
+                let _ = fetch::spawn_background_fetch(providers);
+            }
+        }
+    }
+    (snapshots, errors)
 }
 
+/// Render the module from the snapshots to show and the providers whose fetch
+/// failed in this run; failures are listed in the tooltip
 fn build_waybar_output(
     snapshots: &HashMap<Provider, UsageSnapshot>,
-    selected_provider: Option<Provider>,
+    errors: &HashMap<Provider, anyhow::Error>,
+    config: &Config,
 ) -> WaybarOutput {
-    let icon = "󰧑";
-    let snapshot = selected_provider
-        .and_then(|provider| snapshots.get(&provider))
-        .or_else(|| snapshots.get(&Provider::Claude))
-        .or_else(|| snapshots.get(&Provider::Codex))
-        .or_else(|| snapshots.get(&Provider::OpenCode));
+    let precision = config.general.precision;
+    let time = config.time_style();
+    let display = config.general.display;
+    let mode = config.waybar.mode;
+    let snapshot = match mode {
+        WaybarMode::Selected => select_snapshot(snapshots, config),
+        WaybarMode::Max | WaybarMode::All => most_constrained_snapshot(snapshots, config),
+    };
+    // "Claude: <reason>" per failed provider, in provider order
+    let failures: Vec<String> = Provider::ALL
+        .into_iter()
+        .filter_map(|p| errors.get(&p).map(|e| (p, e)))
+        .map(|(p, e)| format!("{}: {}", p.display_name(), providers::short_reason(e)))
+        .map(|line| markup_escape(line, config))
+        .collect();
     let Some(snapshot) = snapshot else {
+        // Signing in again is actionable, so it gets its own class
+        let class = if errors.values().any(providers::is_unauthorized) {
+            "auth-error"
+        } else {
+            "error"
+        };
         return WaybarOutput {
-            text: format!("{} --", icon),
-            tooltip: "No data available".to_string(),
-            class: vec!["error".to_string()],
+            text: statusline::no_data_text(),
+            tooltip: if failures.is_empty() {
+                "No data available".to_string()
+            } else {
+                failures.join("\n")
+            },
+            class: vec![class.to_string()],
+            percentage: None,
         };
     };
 
-    let session = snapshot.primary.as_ref().map(|r| r.used_percent);
-    let week = snapshot.secondary.as_ref().map(|r| r.used_percent);
+    let pace_of = |s: &UsageSnapshot| {
+        s.secondary()
+            .and_then(|week| pace::compute_pace(s.provider, week, Utc::now()))
+    };
+    // The module icon unless the provider has its own configured
+    let module_info = |s: &UsageSnapshot| config.display_info(s, statusline::ICON);
+    let tooltip_for = |s: &UsageSnapshot| match config.waybar.tooltip_format {
+        Some(ref template) => {
+            // Markup in the template itself is left alone; only values are escaped
+            let values = statusline::template_values(s, &module_info(s), precision, &time)
+                .into_iter()
+                .map(|(key, value)| (key, value.map(|v| markup_escape(v, config))))
+                .collect();
+            format::render(template, &values)
+        }
+        None => default_tooltip(s, config, pace_of(s).as_ref()),
+    };
+    // A user format applies to each provider shown
+    let text_for = |s: &UsageSnapshot| match config.waybar.format {
+        Some(ref template) => {
+            let values = statusline::template_values(s, &module_info(s), precision, &time);
+            format::render(template, &values)
+        }
+        // "󰧑 31% / 51%" (session / week)
+        None if mode == WaybarMode::Selected => {
+            statusline::bar_text(s, &module_info(s), precision, display)
+        }
+        None => {
+            let info = config.display_info(s, s.provider.icon());
+            statusline::compact_text(s, &info, precision, display)
+        }
+    };
 
-    // Build text: "󰧑 31% / 51%" (session / week)
-    let text = match (session, week) {
-        (Some(s), Some(w)) => format!("{} {:.0}% / {:.0}%", icon, s, w),
-        (Some(s), None) => format!("{} {:.0}%", icon, s),
-        (None, Some(w)) => format!("{} {:.0}%", icon, w),
-        (None, None) => format!("{} --", icon),
+    // Below `hide_below` the text is left empty so waybar hides the module
+    let in_bar = |s: &UsageSnapshot| worth_showing(s, config.waybar.hide_below);
+    let (text, tooltip) = if mode == WaybarMode::Selected {
+        let text = if in_bar(snapshot) {
+            text_for(snapshot)
+        } else {
+            String::new()
+        };
+        (text, tooltip_for(snapshot))
+    } else {
+        let shown = enabled_snapshots(snapshots, config);
+        let text = if mode == WaybarMode::All {
+            shown
+                .iter()
+                .filter(|s| in_bar(s))
+                .map(|s| text_for(s))
+                .collect::<Vec<_>>()
+                .join("  ")
+        } else if in_bar(snapshot) {
+            text_for(snapshot)
+        } else {
+            String::new()
+        };
+        let tooltips: Vec<String> = shown.iter().map(|s| tooltip_for(s)).collect();
+        (text, tooltips.join("\n\n"))
     };
+    let tooltip = if failures.is_empty() {
+        tooltip
+    } else {
+        format!("{}\n\n{}", tooltip, failures.join("\n"))
+    };
+    let weekly_pace = pace_of(snapshot);
 
-    // Build tooltip with more detail
-    let mut tooltip_parts = vec![snapshot.provider.display_name().to_string()];
-    if let Some(ref primary) = snapshot.primary {
-        tooltip_parts.push(format!(
-            "Session: {:.0}% (resets {})",
-            primary.used_percent,
-            primary.reset_description.as_deref().unwrap_or("--")
-        ));
-    }
-    if let Some(ref secondary) = snapshot.secondary {
-        let mut week_line = format!(
-            "Week: {:.0}% (resets {})",
-            secondary.used_percent,
-            secondary.reset_description.as_deref().unwrap_or("--")
-        );
-        if let Some(p) = pace::compute_pace(snapshot.provider, secondary, Utc::now()) {
-            let left = pace::format_pace_left(&p);
-            if let Some(right) = pace::format_pace_right(&p) {
-                week_line.push_str(&format!(" · {} · {}", left, right));
-            } else {
-                week_line.push_str(&format!(" · {}", left));
-            }
-        }
-        tooltip_parts.push(week_line);
+    // Provider class so per-provider modules can be styled, plus a class
+    // based on the most constrained window
+    let mut class = vec![snapshot.provider.id().to_string()];
+    match snapshot.status_class(config) {
+        "normal" => {}
+        status => class.push(status.to_string()),
+    }
+    if weekly_pace.is_some_and(|p| p.stage.is_deficit_warning()) {
+        class.push("pace-deficit".to_string());
+    }
+    if snapshot.is_stale(config.stale_after(), Utc::now()) {
+        class.push("stale".to_string());
+    }
+    if origin::foreign(snapshot, config).is_some() {
+        class.push("foreign".to_string());
     }
 
-    // Class based on highest usage
-    let max_used = [session, week]
+    let percentage = [snapshot.primary(), snapshot.secondary()]
         .into_iter()
         .flatten()
-        .fold(0.0_f64, f64::max);
-    let class = if max_used >= 90.0 {
-        vec!["critical".to_string()]
-    } else if max_used >= 75.0 {
-        vec!["warning".to_string()]
-    } else {
-        vec![]
-    };
+        .map(|w| w.used_percent)
+        .chain(snapshot.cost_percent())
+        .reduce(f64::max)
+        .map(|used| used.round().clamp(0.0, 100.0) as u8);
 
     WaybarOutput {
         text,
-        tooltip: tooltip_parts.join("\n"),
+        tooltip,
         class,
+        percentage,
+    }
+}
+
+/// Whether a snapshot belongs in the bar text: some window or the spending
+/// limit is at least `hide_below` % used, or hiding is off
+fn worth_showing(snapshot: &UsageSnapshot, hide_below: f64) -> bool {
+    hide_below <= 0.0 || snapshot.peak_used().is_some_and(|used| used >= hide_below)
+}
+
+/// Escape tooltip text when `[waybar] tooltip_markup` is on
+fn markup_escape(text: String, config: &Config) -> String {
+    if config.waybar.tooltip_markup {
+        statusline::escape_markup(&text)
+    } else {
+        text
+    }
+}
+
+/// Provider name, then session and week with reset times, cost and pace.
+/// With `tooltip_markup` each window line is colored by its own status.
+fn default_tooltip(
+    snapshot: &UsageSnapshot,
+    config: &Config,
+    weekly_pace: Option<&UsagePace>,
+) -> String {
+    let precision = config.general.precision;
+    let display = config.general.display;
+    let time = config.time_style();
+    let now = Utc::now();
+    // Escaped, and colored by status in markup mode
+    let status_line = |line: String, used_percent: f64| {
+        let line = markup_escape(line, config);
+        let class = config.status_class(snapshot.provider, used_percent);
+        match config.colors.for_class(class) {
+            Some(color) if config.waybar.tooltip_markup => format!(
+                "<span foreground='{}'>{}</span>",
+                statusline::escape_markup(color),
+                line
+            ),
+            _ => line,
+        }
+    };
+    let window_line = |window: &RateWindow| {
+        let line = format!(
+            "{}: {} (resets {})",
+            window.name(),
+            display.label(window, precision),
+            window.reset_text(now, &time).as_deref().unwrap_or("--")
+        );
+        status_line(line, window.used_percent)
+    };
+
+    let mut label = config.display_info(snapshot, statusline::ICON).label;
+    if let Some(origin) = origin::foreign(snapshot, config) {
+        label = format!("{} {}", label, origin.annotation());
+    }
+    let mut tooltip_parts = vec![markup_escape(label, config)];
+    for window in &snapshot.windows {
+        tooltip_parts.push(window_line(window));
+        if let Some(counts) = window.counts_text() {
+            tooltip_parts.push(markup_escape(format!("  {}", counts), config));
+        }
+    }
+    if let Some(ref cost) = snapshot.cost {
+        let line = format!("Cost: {}", cost.describe());
+        tooltip_parts.push(status_line(line, cost.used_percent()));
+    }
+    if let Some(pace) = weekly_pace {
+        tooltip_parts.push(markup_escape(pace::format_pace_line(pace), config));
+    }
+    if let Some(summary) = snapshot.busiest_member_summary() {
+        tooltip_parts.push(markup_escape(summary, config));
+    }
+    tooltip_parts.join("\n")
+}
+
+/// Pick the snapshot to display: the selected provider when it is enabled and
+/// has data, the most constrained one for `"auto"`, otherwise the first
+/// enabled provider with data
+fn select_snapshot<'a>(
+    snapshots: &'a HashMap<Provider, UsageSnapshot>,
+    config: &Config,
+) -> Option<&'a UsageSnapshot> {
+    match config.general.selected_provider {
+        Some(ProviderChoice::Auto) => return most_constrained_snapshot(snapshots, config),
+        Some(ProviderChoice::Specific(provider)) if config.is_provider_enabled(provider) => {
+            if let Some(snapshot) = snapshots.get(&provider) {
+                return Some(snapshot);
+            }
+        }
+        _ => {}
+    }
+    config
+        .enabled_providers()
+        .into_iter()
+        .find_map(|provider| snapshots.get(&provider))
+}
+
+/// Snapshots of the enabled providers, in provider order
+fn enabled_snapshots<'a>(
+    snapshots: &'a HashMap<Provider, UsageSnapshot>,
+    config: &Config,
+) -> Vec<&'a UsageSnapshot> {
+    config
+        .enabled_providers()
+        .into_iter()
+        .filter_map(|provider| snapshots.get(&provider))
+        .collect()
+}
+
+/// The enabled provider closest to a limit; see [`Config::most_constrained`]
+fn most_constrained_snapshot<'a>(
+    snapshots: &'a HashMap<Provider, UsageSnapshot>,
+    config: &Config,
+) -> Option<&'a UsageSnapshot> {
+    snapshots.get(&config.most_constrained(snapshots)?)
+}
+
+/// The enabled provider with cached data after the current selection,
+/// wrapping around; providers without a snapshot are skipped
+fn next_provider(
+    config: &Config,
+    snapshots: &HashMap<Provider, UsageSnapshot>,
+) -> Option<Provider> {
+    let candidates: Vec<Provider> = config
+        .enabled_providers()
+        .into_iter()
+        .filter(|p| snapshots.contains_key(p))
+        .collect();
+    let current = config
+        .general
+        .selected_provider
+        .and_then(ProviderChoice::provider)
+        .and_then(|selected| candidates.iter().position(|p| *p == selected));
+    match current {
+        Some(i) => candidates.get((i + 1) % candidates.len()).copied(),
+        None => candidates.first().copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderConfig;
+    use crate::mock::mock_snapshots;
+
+    fn config_with(selected: Option<Provider>, disabled: &[Provider]) -> Config {
+        let mut config = Config::default();
+        config.general.selected_provider = selected.map(Into::into);
+        for provider in Provider::ALL {
+            config.providers.insert(
+                provider,
+                ProviderConfig {
+                    enabled: !disabled.contains(&provider),
+                    ..Default::default()
+                },
+            );
+        }
+        config
+    }
+
+    #[test]
+    fn test_unknown_mock_scenario_lists_the_names() {
+        let err = Cli::try_parse_from(["quotabar", "popup", "--mock-scenario", "calm"])
+            .err()
+            .unwrap()
+            .to_string();
+        for name in ["healthy", "depleted-with-overage", "many-providers"] {
+            assert!(err.contains(name), "{}", err);
+        }
+        assert!(
+            Cli::try_parse_from(["quotabar", "status", "--mock", "--mock-scenario", "errors"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_select_uses_enabled_selection() {
+        let snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Codex), &[]);
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::Codex);
+    }
+
+    #[test]
+    fn test_select_falls_back_when_selection_disabled() {
+        let snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Claude), &[Provider::Claude]);
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::Codex);
+    }
+
+    #[test]
+    fn test_shown_providers() {
+        let config = config_with(Some(Provider::Codex), &[]);
+        assert_eq!(shown_providers(&config), vec![Provider::Codex]);
+
+        // A disabled or automatic selection can land on any enabled provider
+        let config = config_with(Some(Provider::Codex), &[Provider::Codex]);
+        assert_eq!(shown_providers(&config), config.enabled_providers());
+        let mut config = config_with(None, &[]);
+        config.general.selected_provider = Some(ProviderChoice::Auto);
+        assert_eq!(shown_providers(&config), config.enabled_providers());
+
+        let config = config_with(None, &[]).restricted_to(&[Provider::Claude, Provider::Codex]);
+        assert_eq!(shown_providers(&config), vec![Provider::Claude]);
+    }
+
+    #[test]
+    fn test_select_skips_disabled_stale_snapshots() {
+        let snapshots = mock_snapshots();
+        let config = config_with(None, &[Provider::Claude, Provider::Codex]);
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::OpenCode);
+
+        let config = config_with(None, &Provider::ALL);
+        assert!(select_snapshot(&snapshots, &config).is_none());
+    }
+
+    #[test]
+    fn test_provider_filter_overrides_selection() {
+        let snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Claude), &[Provider::Codex])
+            .restricted_to(&[Provider::Codex, Provider::OpenCode]);
+        assert_eq!(
+            config.enabled_providers(),
+            vec![Provider::Codex, Provider::OpenCode]
+        );
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::Codex);
+    }
+
+    #[test]
+    fn test_provider_filter_can_enable_claude_org() {
+        let config = Config::default().restricted_to(&[Provider::ClaudeOrg]);
+        assert_eq!(config.enabled_providers(), vec![Provider::ClaudeOrg]);
+    }
+
+    #[test]
+    fn test_next_provider_cycles_in_order() {
+        let snapshots = mock_snapshots();
+        let mut config = config_with(None, &[]);
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::Claude));
+
+        config.general.selected_provider = Some(Provider::Claude.into());
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::Codex));
+        config.general.selected_provider = Some(Provider::Codex.into());
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::OpenCode));
+        config.general.selected_provider = Some(Provider::OpenCode.into());
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::Claude));
+    }
+
+    #[test]
+    fn test_next_provider_skips_disabled_and_missing() {
+        let mut snapshots = mock_snapshots();
+        // Codex disabled, OpenCode has no data: Claude cycles to itself
+        snapshots.remove(&Provider::OpenCode);
+        let config = config_with(Some(Provider::Claude), &[Provider::Codex]);
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::Claude));
+
+        // A selection without data starts from the first candidate
+        let config = config_with(Some(Provider::Kiro), &[]);
+        assert_eq!(next_provider(&config, &snapshots), Some(Provider::Claude));
+
+        let config = config_with(None, &Provider::ALL);
+        assert_eq!(next_provider(&config, &snapshots), None);
+    }
+
+    #[test]
+    fn test_disabled_selection_renders_fallback() {
+        let snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Claude), &[Provider::Claude]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output.tooltip.starts_with("Codex"));
+        assert_ne!(output.text, "󰧑 --");
+    }
+
+    #[test]
+    fn test_foreign_snapshot_is_marked() {
+        let mut snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Claude), &[]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(!output.class.contains(&"foreign".to_string()));
+
+        snapshots.get_mut(&Provider::Claude).unwrap().origin =
+            Some(origin::Origin::new("desktop", Some("/elsewhere")));
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output.class.contains(&"foreign".to_string()));
+        assert!(output.tooltip.starts_with("Claude (from desktop)\n"));
+
+        config.general.track_origin = false;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(!output.class.contains(&"foreign".to_string()));
+        assert!(output.tooltip.starts_with("Claude\n"));
+    }
+
+    #[test]
+    fn test_waybar_templates() {
+        let snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Codex), &[]);
+        config.waybar.format = Some("{provider} {session}%[ / {week}%]".into());
+        config.waybar.tooltip_format =
+            Some("Session {session}%[ (resets {reset_session})][\nWeek {week}%]".into());
+
+        // Codex mock has no weekly window: its groups drop out
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "Codex 85%");
+        assert_eq!(output.tooltip, "Session 85% (resets in 1h)");
+
+        config.general.selected_provider = Some(Provider::Claude.into());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "Claude 72% / 45%");
+        assert_eq!(output.tooltip.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_waybar_pace() {
+        // Mock Claude week: 45% used four days into seven, lasting to reset
+        let mut snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Claude), &[]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output
+            .tooltip
+            .ends_with("Pace: 12% in reserve — lasts until reset"));
+        // Resets are recomputed from the clock, with the local time
+        assert!(output
+            .tooltip
+            .contains("5-hour session: 72% used (resets in 5h, "));
+        assert!(!output.class.contains(&"pace-deficit".to_string()));
+
+        let week = &mut snapshots.get_mut(&Provider::Claude).unwrap().windows[1];
+        week.used_percent = 70.0;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output
+            .tooltip
+            .contains("Pace: 13% in deficit — runs out in"));
+        assert!(output.class.contains(&"pace-deficit".to_string()));
+
+        // No weekly window, no pace line
+        let config = config_with(Some(Provider::Codex), &[]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(!output.tooltip.contains("Pace"));
+    }
+
+    #[test]
+    fn test_most_constrained_ordering() {
+        // Mock worst windows: Claude 72%, Codex 85%, OpenCode 15%
+        let mut snapshots = mock_snapshots();
+        let config = config_with(None, &[]);
+        let pick = |snapshots: &HashMap<Provider, UsageSnapshot>, config: &Config| {
+            most_constrained_snapshot(snapshots, config).map(|s| s.provider)
+        };
+        assert_eq!(pick(&snapshots, &config), Some(Provider::Codex));
+
+        // Disabled providers are never picked
+        let no_codex = config_with(None, &[Provider::Codex]);
+        assert_eq!(pick(&snapshots, &no_codex), Some(Provider::Claude));
+
+        // A tie goes to the provider listed first
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.windows[0].used_percent = 85.0;
+        assert_eq!(pick(&snapshots, &config), Some(Provider::Claude));
+
+        // Providers without windows only win when nothing else has data
+        let mut bare = mock_snapshots();
+        bare.retain(|p, _| *p == Provider::OpenCode);
+        let opencode = bare.get_mut(&Provider::OpenCode).unwrap();
+        opencode.windows.clear();
+        assert_eq!(pick(&bare, &config), Some(Provider::OpenCode));
+        bare.insert(Provider::Codex, mock_snapshots()[&Provider::Codex].clone());
+        assert_eq!(pick(&bare, &config), Some(Provider::Codex));
+
+        assert_eq!(pick(&HashMap::new(), &config), None);
+    }
+
+    #[test]
+    fn test_select_auto_follows_most_constrained() {
+        // Mock worst windows: Claude 72%, Codex 85%, OpenCode 15%
+        let snapshots = mock_snapshots();
+        let mut config = config_with(None, &[]);
+        config.general.selected_provider = Some(ProviderChoice::Auto);
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::Codex);
+
+        config = config_with(None, &[Provider::Codex]);
+        config.general.selected_provider = Some(ProviderChoice::Auto);
+        let selected = select_snapshot(&snapshots, &config).unwrap();
+        assert_eq!(selected.provider, Provider::Claude);
+
+        // The bar follows it too
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output.tooltip.contains("Claude"), "{}", output.tooltip);
+    }
+
+    #[test]
+    fn test_waybar_modes() {
+        let snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Claude), &[Provider::Windsurf]);
+
+        config.waybar.mode = WaybarMode::Max;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        // Codex has no built-in glyph, so no icon or separating space
+        assert_eq!(output.text, "85%");
+        assert_eq!(output.class, vec!["codex", "warning"]);
+        for name in ["Claude", "Codex", "OpenCode"] {
+            assert!(output.tooltip.contains(name), "{}", output.tooltip);
+        }
+
+        config.waybar.mode = WaybarMode::All;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(
+            output.text,
+            format!(
+                "{} 72%  85%  {} 15%",
+                Provider::Claude.icon(),
+                Provider::OpenCode.icon()
+            )
+        );
+        assert_eq!(output.tooltip.split("\n\n").count(), 3);
+
+        config.waybar.mode = WaybarMode::Selected;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "󰧑 72% / 45%");
+        assert!(!output.tooltip.contains("Codex"));
+
+        let output = build_waybar_output(&HashMap::new(), &Default::default(), &config);
+        assert_eq!(output.class, vec!["error"]);
+    }
+
+    #[test]
+    fn test_waybar_class_and_percentage() {
+        // Mock Claude: session 72%, week 45%; Codex: session 85%. A 92.6%
+        // week four days in is also well ahead of pace
+        let mut snapshots = mock_snapshots();
+        let output = build_waybar_output(
+            &snapshots,
+            &Default::default(),
+            &config_with(Some(Provider::Claude), &[]),
+        );
+        assert_eq!(output.class, vec!["claude"]);
+        assert_eq!(output.percentage, Some(72));
+
+        let output = build_waybar_output(
+            &snapshots,
+            &Default::default(),
+            &config_with(Some(Provider::Codex), &[]),
+        );
+        assert_eq!(output.class, vec!["codex", "warning"]);
+        assert_eq!(output.percentage, Some(85));
+
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.windows[1].used_percent = 92.6;
+        let output = build_waybar_output(
+            &snapshots,
+            &Default::default(),
+            &config_with(Some(Provider::Claude), &[]),
+        );
+        assert_eq!(output.class, vec!["claude", "critical", "pace-deficit"]);
+        assert_eq!(output.percentage, Some(93));
+
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.updated_at = Utc::now() - chrono::Duration::minutes(20);
+        let output = build_waybar_output(
+            &snapshots,
+            &Default::default(),
+            &config_with(Some(Provider::Claude), &[]),
+        );
+        assert_eq!(
+            output.class,
+            vec!["claude", "critical", "pace-deficit", "stale"]
+        );
+
+        let output = build_waybar_output(&HashMap::new(), &Default::default(), &Config::default());
+        assert_eq!(output.class, vec!["error"]);
+        assert_eq!(output.percentage, None);
+        let json = serde_json::to_value(&output).unwrap();
+        assert!(json.get("percentage").is_none());
+    }
+
+    #[test]
+    fn test_waybar_remaining_display() {
+        let snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Codex), &[]);
+        config.general.display = models::PercentDisplay::Remaining;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, format!("{} 15%", statusline::ICON));
+        assert!(output.tooltip.contains("1-hour limit: 15% left"));
+        // Still classified on usage
+        assert_eq!(output.class, vec!["codex", "warning"]);
+    }
+
+    #[test]
+    fn test_waybar_hide_below() {
+        // Mock Claude peaks at 72%, Codex at 85%, OpenCode at 15%
+        let snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Claude), &[Provider::Windsurf]);
+        config.waybar.hide_below = 80.0;
+
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "");
+        assert!(output.tooltip.starts_with("Claude"));
+
+        config.general.selected_provider = Some(Provider::Codex.into());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, format!("{} 85%", statusline::ICON));
+        assert_eq!(output.class, vec!["codex", "warning"]);
+
+        config.waybar.mode = WaybarMode::Max;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        // Codex has no built-in glyph, so no icon or separating space
+        assert_eq!(output.text, "85%");
+
+        // Only providers over the threshold appear; the tooltip keeps them all
+        config.waybar.mode = WaybarMode::All;
+        config.waybar.hide_below = 50.0;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, format!("{} 72%  85%", Provider::Claude.icon()));
+        assert_eq!(output.tooltip.split("\n\n").count(), 3);
+
+        config.waybar.hide_below = 90.0;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "");
+
+        config.waybar.hide_below = 0.0;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output.text.contains("15%"));
+    }
+
+    #[test]
+    fn test_waybar_tooltip_markup() {
+        let mut snapshots = mock_snapshots();
+        let mut config = config_with(Some(Provider::Codex), &[]);
+        config.waybar.tooltip_markup = true;
+        let codex = snapshots.get_mut(&Provider::Codex).unwrap();
+        let session = &mut codex.windows[0];
+        session.resets_at = None;
+        session.reset_description = Some("Mon & <Tue>".to_string());
+
+        // Codex session at 85% takes the warning color
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(
+            output.tooltip.contains(
+                "<span foreground='#e5c07b'>1-hour limit: 85% used (resets Mon &amp; &lt;Tue&gt;)</span>"
+            ),
+            "{}",
+            output.tooltip
+        );
+
+        // Normal windows stay plain unless a normal color is set
+        config.general.selected_provider = Some(Provider::Claude.into());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output.tooltip.contains("\n5-hour session: 72% used"));
+        config.colors.normal = Some("#98c379".to_string());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(output
+            .tooltip
+            .contains("<span foreground='#98c379'>5-hour session: 72% used"));
+
+        // Template values are escaped, the template's own markup is not
+        config.general.selected_provider = Some(Provider::Codex.into());
+        config.waybar.tooltip_format = Some("<b>{provider}</b> {reset_session}".to_string());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.tooltip, "<b>Codex</b> Mon &amp; &lt;Tue&gt;");
+
+        // Off by default: plain text
+        config.waybar.tooltip_markup = false;
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.tooltip, "<b>Codex</b> Mon & <Tue>");
+    }
+
+    #[test]
+    fn test_waybar_tooltip_counts() {
+        let mut snapshots = mock_snapshots();
+        let config = config_with(Some(Provider::Claude), &[]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert!(!output.tooltip.contains(" requests"), "{}", output.tooltip);
+
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.windows[0] = claude.windows[0]
+            .clone()
+            .with_counts(412.0, 500.0, "requests");
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        let lines: Vec<&str> = output.tooltip.lines().collect();
+        let session = lines
+            .iter()
+            .position(|l| l.starts_with("5-hour session"))
+            .unwrap();
+        assert_eq!(lines[session + 1], "  412 / 500 requests");
+        assert!(lines[session + 2].starts_with("Weekly limit"));
+    }
+
+    /// A provider that only reports spend against a budget
+    fn cost_only_snapshot(used: f64, limit: f64) -> UsageSnapshot {
+        let mut snapshot = mock_snapshots().remove(&Provider::OpenCode).unwrap();
+        snapshot.windows.clear();
+        snapshot.cost = Some(models::CostSnapshot {
+            used,
+            limit,
+            currency_code: "USD".to_string(),
+            period: Some("Monthly".to_string()),
+            resets_at: None,
+            extra_usage: false,
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_waybar_cost_only() {
+        let config = config_with(Some(Provider::OpenCode), &[]);
+        let snapshots = HashMap::from([(Provider::OpenCode, cost_only_snapshot(42.0, 100.0))]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, format!("{} $42 / $100", statusline::ICON));
+        assert_eq!(output.class, vec!["opencode"]);
+        assert_eq!(output.percentage, Some(42));
+
+        // An exhausted budget is critical
+        let snapshots = HashMap::from([(Provider::OpenCode, cost_only_snapshot(100.0, 100.0))]);
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.class, vec!["opencode", "critical"]);
+        assert_eq!(output.percentage, Some(100));
+
+        let mut config = config;
+        config.waybar.format = Some("{provider}[ {cost}]".to_string());
+        let output = build_waybar_output(&snapshots, &Default::default(), &config);
+        assert_eq!(output.text, "OpenCode $100 / $100");
+        assert!(output.tooltip.contains("\nCost: $100.00 / $100.00 Monthly"));
+    }
+
+    #[test]
+    fn test_waybar_fetch_errors() {
+        use providers::ProviderError;
+        let config = config_with(Some(Provider::Claude), &[]);
+        let unauthorized = || {
+            anyhow::Error::from(ProviderError::Unauthorized(
+                "Token expired. Run `claude login`.".to_string(),
+            ))
+        };
+
+        // Nothing cached: each failure gets a line, auth failures their own class
+        let errors = HashMap::from([
+            (Provider::Codex, anyhow::anyhow!("could not connect")),
+            (Provider::Claude, unauthorized()),
+        ]);
+        let output = build_waybar_output(&HashMap::new(), &errors, &config);
+        assert_eq!(
+            output.tooltip,
+            "Claude: Token expired. Run `claude login`.\nCodex: could not connect"
+        );
+        assert_eq!(output.class, vec!["auth-error"]);
+
+        let errors = HashMap::from([(Provider::Codex, anyhow::anyhow!("could not connect"))]);
+        let output = build_waybar_output(&HashMap::new(), &errors, &config);
+        assert_eq!(output.tooltip, "Codex: could not connect");
+        assert_eq!(output.class, vec!["error"]);
+
+        // Mixed: the working provider renders as usual, failures are appended
+        let mut snapshots = mock_snapshots();
+        snapshots.retain(|p, _| *p == Provider::Claude);
+        let errors = HashMap::from([(Provider::Codex, unauthorized())]);
+        let output = build_waybar_output(&snapshots, &errors, &config);
+        assert_eq!(output.text, "󰧑 72% / 45%");
+        assert_eq!(output.class, vec!["claude"]);
+        assert!(output
+            .tooltip
+            .ends_with("\n\nCodex: Token expired. Run `claude login`."));
     }
 }
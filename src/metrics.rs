@@ -0,0 +1,303 @@
+use crate::cache::CacheState;
+use crate::config::Config;
+use crate::fetch::{self, Refresh};
+use crate::models::Provider;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Longest request head the /metrics server reads before answering
+const MAX_REQUEST: usize = 8192;
+
+/// One metric family: all its samples are written together under a single
+/// HELP/TYPE header, as the exposition format requires
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            samples: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, labels: &[(&str, &str)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+        self.samples.push((labels, value));
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Prometheus exposition text for `providers`, using the snapshots and
+/// errors of a refresh
+pub fn render(providers: &[Provider], refresh: &Refresh, now: DateTime<Utc>) -> String {
+    let mut used = Family::new(
+        "quotabar_used_percent",
+        "Percentage of the rate-limit window used",
+    );
+    let mut reset = Family::new(
+        "quotabar_reset_seconds",
+        "Seconds until the rate-limit window resets",
+    );
+    let mut cost_used = Family::new(
+        "quotabar_cost_used_dollars",
+        "Spend in the current billing period, in US dollars",
+    );
+    let mut cost_limit = Family::new(
+        "quotabar_cost_limit_dollars",
+        "Spend limit for the current billing period, in US dollars",
+    );
+    let mut success = Family::new(
+        "quotabar_fetch_success",
+        "Whether the last fetch for the provider succeeded (1) or failed (0)",
+    );
+    let mut updated = Family::new(
+        "quotabar_snapshot_timestamp_seconds",
+        "Unix time the provider snapshot was fetched",
+    );
+
+    for &provider in providers {
+        let id = provider.id();
+        success.push(
+            &[("provider", id)],
+            if refresh.errors.contains_key(&provider) {
+                0.0
+            } else {
+                1.0
+            },
+        );
+        let Some(snapshot) = refresh.snapshots.get(&provider) else {
+            continue;
+        };
+        updated.push(&[("provider", id)], snapshot.updated_at.timestamp() as f64);
+        for rate in &snapshot.windows {
+            let labels = [("provider", id), ("window", rate.kind.id())];
+            used.push(&labels, rate.used_percent);
+            if let Some(resets_at) = rate.resets_at {
+                reset.push(&labels, (resets_at - now).num_seconds().max(0) as f64);
+            }
+        }
+        if let Some(cost) = snapshot.cost.as_ref().filter(|c| c.currency_code == "USD") {
+            cost_used.push(&[("provider", id)], cost.used);
+            if cost.limit > 0.0 {
+                cost_limit.push(&[("provider", id)], cost.limit);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for family in [used, reset, cost_used, cost_limit, success, updated] {
+        if family.samples.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(out, "# TYPE {} gauge", family.name);
+        for (labels, value) in family.samples {
+            let _ = writeln!(out, "{}{{{}}} {}", family.name, labels, value);
+        }
+    }
+    out
+}
+
+/// Refresh enabled providers (reusing fresh cache entries) and render them
+pub async fn collect(config: &Config) -> String {
+    let providers = config.enabled_providers();
+    let refresh = fetch::refresh(config, &providers, false).await;
+    render(&providers, &refresh, Utc::now())
+}
+
+/// Write for node_exporter's textfile collector: a temp file in the same
+/// directory renamed into place, so a scrape never sees a partial file
+pub fn write_textfile(path: &Path, metrics: &str) -> Result<()> {
+    let name = path
+        .file_name()
+        .context("--textfile needs a file name")?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", name));
+    std::fs::write(&temp_path, metrics)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Serve `/metrics` on `addr`. Scrapes read the last rendered text; a
+/// background task refreshes it every `interval`.
+pub async fn serve(addr: &str, interval: Duration) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    let current = Arc::new(Mutex::new(initial_metrics()));
+
+    let refreshed = Arc::clone(&current);
+    tokio::spawn(async move {
+        loop {
+            let config = Config::load().unwrap_or_default();
+            let metrics = collect(&config).await;
+            *refreshed.lock().unwrap_or_else(|e| e.into_inner()) = metrics;
+            tokio::time::sleep(interval).await;
+        }
+    });
+
+    eprintln!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let current = Arc::clone(&current);
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let body = current.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            let response = http_response(&request, &body);
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+/// Metrics from the cache alone, served until the first refresh finishes
+fn initial_metrics() -> String {
+    let Some(cached) = CacheState::load().ok().flatten() else {
+        return String::new();
+    };
+    let providers: Vec<Provider> = Provider::ALL
+        .into_iter()
+        .filter(|p| cached.snapshots.contains_key(p))
+        .collect();
+    let refresh = Refresh {
+        snapshots: cached.snapshots,
+        ..Default::default()
+    };
+    render(&providers, &refresh, Utc::now())
+}
+
+fn http_response(request: &[u8], metrics: &str) -> String {
+    let request = String::from_utf8_lossy(request);
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", CONTENT_TYPE, metrics),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found; try /metrics\n"),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n",
+        ),
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use pretty_assertions::assert_eq;
+
+    /// Locks metric names and labels; dashboards and alerts query these
+    #[test]
+    fn test_metric_names_and_labels() {
+        let snapshots = mock_snapshots();
+        let now = snapshots[&Provider::Claude].updated_at;
+        let stamp = now.timestamp();
+        let mut refresh = Refresh::default();
+        refresh
+            .snapshots
+            .insert(Provider::Claude, snapshots[&Provider::Claude].clone());
+        refresh
+            .errors
+            .insert(Provider::Codex, anyhow::anyhow!("token expired"));
+
+        let text = render(&[Provider::Claude, Provider::Codex], &refresh, now);
+        assert_eq!(
+            text,
+            format!(
+                "\
+# HELP quotabar_used_percent Percentage of the rate-limit window used
+# TYPE quotabar_used_percent gauge
+quotabar_used_percent{{provider=\"claude\",window=\"session\"}} 72
+quotabar_used_percent{{provider=\"claude\",window=\"week\"}} 45
+# HELP quotabar_reset_seconds Seconds until the rate-limit window resets
+# TYPE quotabar_reset_seconds gauge
+quotabar_reset_seconds{{provider=\"claude\",window=\"session\"}} 18000
+quotabar_reset_seconds{{provider=\"claude\",window=\"week\"}} 259200
+# HELP quotabar_cost_used_dollars Spend in the current billing period, in US dollars
+# TYPE quotabar_cost_used_dollars gauge
+quotabar_cost_used_dollars{{provider=\"claude\"}} 42.5
+# HELP quotabar_cost_limit_dollars Spend limit for the current billing period, in US dollars
+# TYPE quotabar_cost_limit_dollars gauge
+quotabar_cost_limit_dollars{{provider=\"claude\"}} 100
+# HELP quotabar_fetch_success Whether the last fetch for the provider succeeded (1) or failed (0)
+# TYPE quotabar_fetch_success gauge
+quotabar_fetch_success{{provider=\"claude\"}} 1
+quotabar_fetch_success{{provider=\"codex\"}} 0
+# HELP quotabar_snapshot_timestamp_seconds Unix time the provider snapshot was fetched
+# TYPE quotabar_snapshot_timestamp_seconds gauge
+quotabar_snapshot_timestamp_seconds{{provider=\"claude\"}} {stamp}
+"
+            )
+        );
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape("x\ny"), "x\\ny");
+    }
+
+    #[test]
+    fn test_http_routes() {
+        let ok = http_response(b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n", "m 1\n");
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(ok.contains("Content-Length: 4\r\n"));
+        assert!(ok.ends_with("\r\n\r\nm 1\n"));
+
+        let missing = http_response(b"GET / HTTP/1.1\r\n\r\n", "m 1\n");
+        assert!(missing.starts_with("HTTP/1.1 404"));
+        let post = http_response(b"POST /metrics HTTP/1.1\r\n\r\n", "m 1\n");
+        assert!(post.starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn test_textfile_written_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotabar.prom");
+        write_textfile(&path, "m 1\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "m 1\n");
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(names.len(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+//! Upgrading config files written for older versions of quotabar
+//!
+//! Each migration is a small step over the TOML document, so comments and keys
+//! quotabar doesn't know survive it. Loading applies them in memory; only
+//! `quotabar config migrate` writes the result back.
+
+use anyhow::{bail, Context, Result};
+use toml_edit::{DocumentMut, Item};
+
+/// One step, from the version before it to the next; returns whether it
+/// changed anything
+type Step = fn(&mut DocumentMut) -> bool;
+
+/// Steps in order, each described for the user; the first upgrades version 1,
+/// which is also any file without `version`
+const STEPS: &[(&str, Step)] = &[("[popup] height is now max_height", popup_max_height)];
+
+/// The version this build writes and understands
+pub const CURRENT: i64 = STEPS.len() as i64 + 1;
+
+/// A config file brought up to [`CURRENT`]
+#[derive(Debug)]
+pub struct Migrated {
+    /// The version the file was written for
+    pub from: i64,
+    pub document: DocumentMut,
+    /// What the steps changed; empty when the file only needs its version
+    pub changes: Vec<&'static str>,
+}
+
+/// `content` upgraded to the current version, or None when it already is;
+/// fails when it's from a newer quotabar
+pub fn upgrade(content: &str) -> Result<Option<Migrated>> {
+    let mut document: DocumentMut = content.parse().context("Config file is not valid TOML")?;
+    let from = version(&document)?;
+    if from == CURRENT {
+        return Ok(None);
+    }
+    let mut changes = Vec::new();
+    for (description, step) in &STEPS[(from - 1) as usize..] {
+        if step(&mut document) {
+            changes.push(*description);
+        }
+    }
+    document.insert("version", toml_edit::value(CURRENT));
+    Ok(Some(Migrated {
+        from,
+        document,
+        changes,
+    }))
+}
+
+/// The file's `version`, checked against [`CURRENT`]
+fn version(document: &DocumentMut) -> Result<i64> {
+    let version = match document.get("version") {
+        None => return Ok(1),
+        Some(item) => match item.as_integer() {
+            Some(version) if version >= 1 => version,
+            _ => bail!("version must be a whole number from 1, got {}", item),
+        },
+    };
+    if version > CURRENT {
+        bail!(
+            "config is for version {} but this quotabar only understands up to version {}; \
+             upgrade quotabar to use it",
+            version,
+            CURRENT
+        );
+    }
+    Ok(version)
+}
+
+/// Version 1 → 2: the popup's fixed `height` became `max_height`, which it
+/// grows to before scrolling. An existing `max_height` wins.
+fn popup_max_height(document: &mut DocumentMut) -> bool {
+    let Some(popup) = document.get_mut("popup").and_then(Item::as_table_like_mut) else {
+        return false;
+    };
+    let Some(decor) = popup
+        .get_key_value("height")
+        .map(|(key, _)| key.leaf_decor().clone())
+    else {
+        return false;
+    };
+    let height = popup.remove("height").expect("just found");
+    if popup.get("max_height").is_none() {
+        popup.insert("max_height", height);
+        if let Some(mut renamed) = popup.key_mut("max_height") {
+            *renamed.leaf_decor_mut() = decor;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn migrate(content: &str) -> Migrated {
+        upgrade(content).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_current_needs_nothing() {
+        assert!(upgrade(&format!("version = {}\n", CURRENT))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_unversioned_is_version_one() {
+        let migrated = migrate("[general]\nrefresh_interval = \"1m\"\n");
+        assert_eq!(migrated.from, 1);
+        assert!(migrated.changes.is_empty());
+        assert_eq!(
+            migrated.document.to_string(),
+            format!(
+                "version = {}\n[general]\nrefresh_interval = \"1m\"\n",
+                CURRENT
+            )
+        );
+    }
+
+    #[test]
+    fn test_popup_height_becomes_max_height() {
+        let migrated = migrate(
+            "# mine\n[popup]\nwidth = 300\n# tall enough for three\nheight = 600 # px\nfoo = 1\n",
+        );
+        assert_eq!(migrated.changes, ["[popup] height is now max_height"]);
+        assert_eq!(
+            migrated.document.to_string(),
+            "version = 2\n# mine\n[popup]\nwidth = 300\nfoo = 1\n\
+             # tall enough for three\nmax_height = 600 # px\n"
+        );
+
+        // An explicit max_height is kept
+        let migrated = migrate("[popup]\nheight = 600\nmax_height = 800\n");
+        assert_eq!(
+            migrated.document.to_string(),
+            "version = 2\n[popup]\nmax_height = 800\n"
+        );
+
+        let mut document: DocumentMut = "[popup]\nwidth = 300\n".parse().unwrap();
+        assert!(!popup_max_height(&mut document));
+    }
+
+    #[test]
+    fn test_rejects_newer_and_invalid_versions() {
+        let err = upgrade(&format!("version = {}\n", CURRENT + 1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "config is for version {} but this quotabar only understands up to \
+                 version {}; upgrade quotabar to use it",
+                CURRENT + 1,
+                CURRENT
+            )
+        );
+        for bad in ["version = 0", "version = \"2\""] {
+            assert!(upgrade(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,6 +1,229 @@
-use crate::models::{CostSnapshot, IdentitySnapshot, Provider, RateWindow, UsageSnapshot};
-use chrono::{Duration, Utc};
+use crate::cache::{self, FetchFailure};
+use crate::models::{
+    CostSnapshot, Granularity, IdentitySnapshot, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
+use std::path::Path;
+
+/// What `--mock`, `--mock-scenario` and `--mock-file` render: snapshots, plus
+/// failures as the cache would record them
+#[derive(Debug, Clone, Default)]
+pub struct MockData {
+    pub snapshots: HashMap<Provider, UsageSnapshot>,
+    pub failures: HashMap<Provider, FetchFailure>,
+}
+
+impl MockData {
+    pub fn new(snapshots: HashMap<Provider, UsageSnapshot>) -> Self {
+        Self {
+            snapshots,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// The failures as fetch errors, for the waybar output and `status`
+    pub fn errors(&self) -> HashMap<Provider, anyhow::Error> {
+        self.failures
+            .iter()
+            .map(|(provider, failure)| (*provider, anyhow::anyhow!(failure.reason.clone())))
+            .collect()
+    }
+}
+
+/// Built-in data sets for working on how each state looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scenario {
+    /// Everything well below the warning threshold
+    Healthy,
+    /// Claude and Codex past warning, OpenCode fine
+    Warning,
+    /// Claude and Codex past critical, OpenCode at warning
+    Critical,
+    /// Claude's session used up, running on extra usage
+    DepletedWithOverage,
+    /// Spending limits and credits without rate windows
+    CostOnly,
+    /// A stale snapshot and a provider with none, both failing to refresh
+    Errors,
+    /// Every provider at once, across all three states
+    ManyProviders,
+}
+
+impl Scenario {
+    pub fn data(self) -> MockData {
+        let now = Utc::now();
+        // Claude signed in, as the popup's account line shows it
+        let claude = |session: f64, week: f64| UsageSnapshot {
+            identity: Some(IdentitySnapshot {
+                email: Some("user@example.com".to_string()),
+                plan: Some("Max".to_string()),
+                organization: None,
+                account_id: None,
+            }),
+            ..windowed(Provider::Claude, now, session, Some(week))
+        };
+        let snapshots = match self {
+            Scenario::Healthy => vec![
+                claude(20.0, 10.0),
+                windowed(Provider::Codex, now, 12.0, None),
+                windowed(Provider::OpenCode, now, 15.0, Some(8.0)),
+            ],
+            Scenario::Warning => vec![
+                claude(80.0, 52.0),
+                windowed(Provider::Codex, now, 78.0, None),
+                windowed(Provider::OpenCode, now, 30.0, Some(20.0)),
+            ],
+            Scenario::Critical => vec![
+                claude(96.0, 71.0),
+                windowed(Provider::Codex, now, 92.0, None),
+                windowed(Provider::OpenCode, now, 80.0, Some(64.0)),
+            ],
+            Scenario::DepletedWithOverage => vec![
+                UsageSnapshot {
+                    cost: Some(CostSnapshot {
+                        extra_usage: true,
+                        ..cost(12.40, 50.0, "USD", now)
+                    }),
+                    ..claude(100.0, 64.0)
+                },
+                windowed(Provider::Codex, now, 40.0, None),
+            ],
+            Scenario::CostOnly => vec![
+                costed(Provider::ClaudeOrg, now, cost(420.0, 1000.0, "USD", now)),
+                costed(Provider::Kiro, now, cost(380.0, 500.0, "credits", now)),
+            ],
+            Scenario::Errors => vec![
+                UsageSnapshot {
+                    updated_at: now - Duration::hours(3),
+                    ..claude(55.0, 30.0)
+                },
+                windowed(Provider::OpenCode, now, 15.0, Some(8.0)),
+            ],
+            Scenario::ManyProviders => vec![
+                claude(62.0, 41.0),
+                costed(Provider::ClaudeOrg, now, cost(780.0, 1000.0, "USD", now)),
+                windowed(Provider::Codex, now, 85.0, None),
+                windowed(Provider::OpenCode, now, 15.0, Some(8.0)),
+                costed(Provider::Kiro, now, cost(460.0, 500.0, "credits", now)),
+                counted(
+                    windowed(Provider::Windsurf, now, 97.0, None),
+                    500.0,
+                    "credits",
+                ),
+            ],
+        };
+        let failures = match self {
+            Scenario::Errors => HashMap::from([
+                (
+                    Provider::Claude,
+                    failure(
+                        "Unauthorized: token expired, run `claude` to sign in again",
+                        now,
+                    ),
+                ),
+                (Provider::Codex, failure("Request timed out after 30s", now)),
+            ]),
+            _ => HashMap::new(),
+        };
+        MockData {
+            snapshots: snapshots.into_iter().map(|s| (s.provider, s)).collect(),
+            failures,
+        }
+    }
+}
+
+/// A five-hour session at `session` % and optionally a week at `week` %
+fn windowed(
+    provider: Provider,
+    now: DateTime<Utc>,
+    session: f64,
+    week: Option<f64>,
+) -> UsageSnapshot {
+    let session = RateWindow {
+        kind: WindowKind::Session,
+        used_percent: session,
+        window_minutes: Some(300),
+        resets_at: Some(now + Duration::hours(3)),
+        reset_description: Some("in 3 hours".to_string()),
+        granularity: Granularity::Percent,
+        label: None,
+        used: None,
+        limit: None,
+        unit: None,
+    };
+    let week = week.map(|used_percent| RateWindow {
+        kind: WindowKind::Weekly,
+        used_percent,
+        window_minutes: Some(10080),
+        resets_at: Some(now + Duration::days(4)),
+        reset_description: Some("in 4 days".to_string()),
+        granularity: Granularity::Percent,
+        label: None,
+        used: None,
+        limit: None,
+        unit: None,
+    });
+    UsageSnapshot {
+        windows: std::iter::once(session).chain(week).collect(),
+        ..empty(provider, now)
+    }
+}
+
+/// `snapshot` with counts out of `limit` behind each window's percentage
+fn counted(mut snapshot: UsageSnapshot, limit: f64, unit: &str) -> UsageSnapshot {
+    snapshot.windows = snapshot
+        .windows
+        .into_iter()
+        .map(|w| {
+            let used = w.used_percent / 100.0 * limit;
+            w.with_counts(used, limit, unit)
+        })
+        .collect();
+    snapshot
+}
+
+/// Only a spending limit or credit balance, no rate windows
+fn costed(provider: Provider, now: DateTime<Utc>, cost: CostSnapshot) -> UsageSnapshot {
+    UsageSnapshot {
+        cost: Some(cost),
+        ..empty(provider, now)
+    }
+}
+
+fn empty(provider: Provider, updated_at: DateTime<Utc>) -> UsageSnapshot {
+    UsageSnapshot {
+        provider,
+        windows: Vec::new(),
+        cost: None,
+        identity: None,
+        rate_limit: None,
+        busiest_member: None,
+        origin: None,
+        updated_at,
+    }
+}
+
+/// A month's spend against `limit`
+fn cost(used: f64, limit: f64, currency: &str, now: DateTime<Utc>) -> CostSnapshot {
+    CostSnapshot {
+        used,
+        limit,
+        currency_code: currency.to_string(),
+        period: Some("Monthly".to_string()),
+        resets_at: Some(now + Duration::days(12)),
+        extra_usage: false,
+    }
+}
+fn failure(reason: &str, at: DateTime<Utc>) -> FetchFailure {
+    FetchFailure {
+        reason: reason.to_string(),
+        at,
+    }
+}
 
 pub fn mock_snapshots() -> HashMap<Provider, UsageSnapshot> {
     let now = Utc::now();
@@ -11,31 +234,49 @@ pub fn mock_snapshots() -> HashMap<Provider, UsageSnapshot> {
         Provider::Claude,
         UsageSnapshot {
             provider: Provider::Claude,
-            primary: Some(RateWindow {
-                used_percent: 72.0,
-                window_minutes: Some(300),
-                resets_at: Some(now + Duration::hours(5)),
-                reset_description: Some("in 5 hours".to_string()),
-            }),
-            secondary: Some(RateWindow {
-                used_percent: 45.0,
-                window_minutes: Some(10080),
-                resets_at: Some(now + Duration::days(3)),
-                reset_description: Some("in 3 days".to_string()),
-            }),
-            tertiary: None,
+            windows: vec![
+                RateWindow {
+                    kind: WindowKind::Session,
+                    used_percent: 72.0,
+                    window_minutes: Some(300),
+                    resets_at: Some(now + Duration::hours(5)),
+                    reset_description: Some("in 5 hours".to_string()),
+                    granularity: Granularity::Unknown,
+                    label: None,
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+                RateWindow {
+                    kind: WindowKind::Weekly,
+                    used_percent: 45.0,
+                    window_minutes: Some(10080),
+                    resets_at: Some(now + Duration::days(3)),
+                    reset_description: Some("in 3 days".to_string()),
+                    granularity: Granularity::Unknown,
+                    label: None,
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+            ],
             cost: Some(CostSnapshot {
                 used: 42.50,
                 limit: 100.0,
                 currency_code: "USD".to_string(),
                 period: Some("Monthly".to_string()),
                 resets_at: Some(now + Duration::days(7)),
+                extra_usage: true,
             }),
             identity: Some(IdentitySnapshot {
                 email: Some("user@example.com".to_string()),
                 plan: Some("Max".to_string()),
                 organization: None,
+                account_id: None,
             }),
+            rate_limit: None,
+            busiest_member: None,
+            origin: None,
             updated_at: now,
         },
     );
@@ -45,20 +286,28 @@ pub fn mock_snapshots() -> HashMap<Provider, UsageSnapshot> {
         Provider::Codex,
         UsageSnapshot {
             provider: Provider::Codex,
-            primary: Some(RateWindow {
+            windows: vec![RateWindow {
+                kind: WindowKind::Session,
                 used_percent: 85.0,
                 window_minutes: Some(60),
                 resets_at: Some(now + Duration::hours(1)),
                 reset_description: Some("in 1 hour".to_string()),
-            }),
-            secondary: None,
-            tertiary: None,
+                granularity: Granularity::Percent,
+                label: None,
+                used: None,
+                limit: None,
+                unit: None,
+            }],
             cost: None,
             identity: Some(IdentitySnapshot {
                 email: Some("user@example.com".to_string()),
                 plan: Some("Pro".to_string()),
                 organization: Some("Personal".to_string()),
+                account_id: None,
             }),
+            rate_limit: None,
+            busiest_member: None,
+            origin: None,
             updated_at: now,
         },
     );
@@ -68,28 +317,203 @@ pub fn mock_snapshots() -> HashMap<Provider, UsageSnapshot> {
         Provider::OpenCode,
         UsageSnapshot {
             provider: Provider::OpenCode,
-            primary: Some(RateWindow {
-                used_percent: 15.0,
-                window_minutes: Some(300),
-                resets_at: Some(now + Duration::hours(5)),
-                reset_description: Some("in 5 hours".to_string()),
-            }),
-            secondary: Some(RateWindow {
-                used_percent: 8.0,
-                window_minutes: None,
-                resets_at: Some(now + Duration::days(5)),
-                reset_description: Some("in 5 days".to_string()),
-            }),
-            tertiary: None,
+            windows: vec![
+                RateWindow {
+                    kind: WindowKind::Session,
+                    used_percent: 15.0,
+                    window_minutes: Some(300),
+                    resets_at: Some(now + Duration::hours(5)),
+                    reset_description: Some("in 5 hours".to_string()),
+                    granularity: Granularity::Tenth,
+                    label: None,
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+                RateWindow {
+                    kind: WindowKind::Weekly,
+                    used_percent: 8.0,
+                    window_minutes: None,
+                    resets_at: Some(now + Duration::days(5)),
+                    reset_description: Some("in 5 days".to_string()),
+                    granularity: Granularity::Tenth,
+                    label: Some("Weekly limit".to_string()),
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+            ],
             cost: None,
             identity: Some(IdentitySnapshot {
                 email: Some("user@example.com".to_string()),
                 plan: Some("Free".to_string()),
                 organization: None,
+                account_id: None,
             }),
+            rate_limit: None,
+            busiest_member: None,
+            origin: None,
             updated_at: now,
         },
     );
 
     snapshots
 }
+
+/// Load snapshots from a JSON object keyed by provider id, the same shape as
+/// the cache's `snapshots` field, in either layout of their windows
+pub fn load_mock_file(path: &Path) -> Result<HashMap<Provider, UsageSnapshot>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read mock file {}", path.display()))?;
+    parse_mock(&content).with_context(|| format!("Invalid mock file {}", path.display()))
+}
+
+fn parse_mock(content: &str) -> Result<HashMap<Provider, UsageSnapshot>> {
+    let objects: HashMap<Provider, Map<String, Value>> = serde_json::from_str(content).context(
+        "expected a JSON object mapping provider ids (\"claude\", \"codex\", ...) to snapshots",
+    )?;
+    if objects.is_empty() {
+        bail!("no snapshots in the file");
+    }
+    let mut snapshots = HashMap::new();
+    for (provider, mut object) in objects {
+        cache::slots_to_windows(&mut object);
+        let snapshot: UsageSnapshot = serde_json::from_value(Value::Object(object))
+            .with_context(|| format!("snapshot under {:?}", provider.id()))?;
+        if snapshot.provider != provider {
+            bail!(
+                "snapshot under {:?} has provider {:?}",
+                provider.id(),
+                snapshot.provider.id()
+            );
+        }
+        snapshots.insert(provider, snapshot);
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_scenarios_keep_their_classes() {
+        use Provider::*;
+        let config = Config::default();
+        for scenario in Scenario::value_variants() {
+            let expected: &[(Provider, &str)] = match scenario {
+                Scenario::Healthy => &[(Claude, "normal"), (Codex, "normal"), (OpenCode, "normal")],
+                Scenario::Warning => &[
+                    (Claude, "warning"),
+                    (Codex, "warning"),
+                    (OpenCode, "normal"),
+                ],
+                Scenario::Critical => &[
+                    (Claude, "critical"),
+                    (Codex, "critical"),
+                    (OpenCode, "warning"),
+                ],
+                Scenario::DepletedWithOverage => &[(Claude, "critical"), (Codex, "normal")],
+                Scenario::CostOnly => &[(ClaudeOrg, "normal"), (Kiro, "warning")],
+                Scenario::Errors => &[(Claude, "normal"), (OpenCode, "normal")],
+                Scenario::ManyProviders => &[
+                    (Claude, "normal"),
+                    (ClaudeOrg, "warning"),
+                    (Codex, "warning"),
+                    (OpenCode, "normal"),
+                    (Kiro, "critical"),
+                    (Windsurf, "critical"),
+                ],
+            };
+            let data = scenario.data();
+            let mut classes: Vec<_> = data
+                .snapshots
+                .values()
+                .map(|s| (s.provider, s.status_class(&config)))
+                .collect();
+            classes.sort_by_key(|(p, _)| Provider::ALL.iter().position(|q| q == p));
+            assert_eq!(classes, expected, "{:?}", scenario);
+        }
+    }
+
+    #[test]
+    fn test_scenario_details() {
+        let depleted = Scenario::DepletedWithOverage.data();
+        let claude = &depleted.snapshots[&Provider::Claude];
+        assert!(claude.cost.as_ref().is_some_and(|c| c.extra_usage));
+
+        let cost_only = Scenario::CostOnly.data();
+        assert!(cost_only.snapshots.values().all(|s| s.max_used().is_none()));
+
+        let errors = Scenario::Errors.data();
+        assert_eq!(errors.errors().len(), 2);
+        // Codex failed with nothing to fall back on
+        assert!(!errors.snapshots.contains_key(&Provider::Codex));
+
+        assert!(Scenario::Healthy.data().failures.is_empty());
+        assert_eq!(
+            Scenario::ManyProviders.data().snapshots.len(),
+            Provider::ALL.len()
+        );
+    }
+
+    #[test]
+    fn test_mock_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshots.json");
+        let snapshots = mock_snapshots();
+        std::fs::write(&path, serde_json::to_string(&snapshots).unwrap()).unwrap();
+
+        let loaded = load_mock_file(&path).unwrap();
+        assert_eq!(loaded.len(), snapshots.len());
+        assert_eq!(
+            loaded[&Provider::Claude].primary().unwrap().used_percent,
+            72.0
+        );
+    }
+
+    #[test]
+    fn test_mock_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = |content: &str| {
+            let path = dir.path().join("snapshots.json");
+            std::fs::write(&path, content).unwrap();
+            format!("{:#}", load_mock_file(&path).unwrap_err())
+        };
+
+        assert!(format!(
+            "{:#}",
+            load_mock_file(&dir.path().join("missing.json")).unwrap_err()
+        )
+        .starts_with("Failed to read mock file"));
+        assert!(error("{").contains("line 1 column 1"));
+        assert!(error(r#"{"nope": {}}"#).contains("provider ids"));
+        assert!(error("{}").contains("no snapshots"));
+
+        let claude = serde_json::to_value(&mock_snapshots()[&Provider::Claude]).unwrap();
+        let mismatched = serde_json::json!({ "codex": claude }).to_string();
+        assert!(error(&mismatched).contains(r#"under "codex" has provider "claude""#));
+    }
+
+    #[test]
+    fn test_mock_file_with_window_slots() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshots.json");
+        let old = serde_json::json!({"codex": {
+            "provider": "codex",
+            "primary": {"used_percent": 40.0, "window_minutes": 300, "resets_at": null, "reset_description": null},
+            "secondary": {"used_percent": 10.0, "window_minutes": 10080, "resets_at": null, "reset_description": null},
+            "tertiary": null,
+            "cost": null,
+            "identity": null,
+            "updated_at": "2025-11-02T15:00:00Z"
+        }});
+        std::fs::write(&path, old.to_string()).unwrap();
+
+        let codex = &load_mock_file(&path).unwrap()[&Provider::Codex];
+        assert_eq!(codex.primary().unwrap().used_percent, 40.0);
+        assert_eq!(codex.secondary().unwrap().used_percent, 10.0);
+    }
+}
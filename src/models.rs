@@ -1,44 +1,163 @@
-use chrono::{DateTime, Utc};
+use crate::config::Config;
+use crate::origin::Origin;
+use crate::ratelimit::RateLimitInfo;
+use crate::timefmt::TimeStyle;
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 /// Supported providers
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, ValueEnum,
+)]
 #[serde(rename_all = "lowercase")]
+#[value(rename_all = "lowercase")]
 pub enum Provider {
     Claude,
+    /// Organization-wide Claude usage via the Admin API
+    #[serde(rename = "claude-org")]
+    #[value(name = "claude-org")]
+    ClaudeOrg,
     Codex,
     OpenCode,
+    Kiro,
+    Windsurf,
 }
 
 impl Provider {
+    /// Every provider, in default display order
+    pub const ALL: [Provider; 6] = [
+        Provider::Claude,
+        Provider::ClaudeOrg,
+        Provider::Codex,
+        Provider::OpenCode,
+        Provider::Kiro,
+        Provider::Windsurf,
+    ];
+
+    /// Stable lowercase identifier (matches the serde/CLI name)
+    pub fn id(&self) -> &'static str {
+        match self {
+            Provider::Claude => "claude",
+            Provider::ClaudeOrg => "claude-org",
+            Provider::Codex => "codex",
+            Provider::OpenCode => "opencode",
+            Provider::Kiro => "kiro",
+            Provider::Windsurf => "windsurf",
+        }
+    }
+
     pub fn display_name(&self) -> &'static str {
         match self {
             Provider::Claude => "Claude",
+            Provider::ClaudeOrg => "Claude Org",
             Provider::Codex => "Codex",
             Provider::OpenCode => "OpenCode",
+            Provider::Kiro => "Kiro",
+            Provider::Windsurf => "Windsurf",
         }
     }
 
     pub fn icon(&self) -> &'static str {
         match self {
-            Provider::Claude => "󰧑",
+            Provider::Claude | Provider::ClaudeOrg => "󰧑",
             Provider::Codex => "",
             Provider::OpenCode => "󰘦",
+            Provider::Kiro => "",
+            Provider::Windsurf => "󰖝",
         }
     }
 
     pub fn usage_url(&self) -> Option<&'static str> {
         match self {
             Provider::Claude => Some("https://claude.ai/settings/usage"),
+            Provider::ClaudeOrg => Some("https://console.anthropic.com/cost"),
             Provider::Codex => Some("https://chatgpt.com/codex/settings/usage"),
             Provider::OpenCode => Some("https://opencode.ai"),
+            Provider::Kiro => Some("https://app.kiro.dev/account/usage"),
+            Provider::Windsurf => Some("https://windsurf.com/subscription/usage"),
+        }
+    }
+}
+
+/// Resolution of the percentage a provider reports for a window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// Whole percent steps (e.g. Codex reports integers)
+    Percent,
+    /// Tenth-of-a-percent steps
+    Tenth,
+    /// Source precision is not known
+    #[default]
+    Unknown,
+}
+
+impl Granularity {
+    /// Maximum number of decimals worth displaying for this source
+    pub fn max_decimals(&self) -> Option<usize> {
+        match self {
+            Granularity::Percent => Some(0),
+            Granularity::Tenth => Some(1),
+            Granularity::Unknown => None,
+        }
+    }
+
+    /// Smallest change in percent that reflects real movement at the source
+    pub fn tolerance(&self) -> f64 {
+        match self {
+            Granularity::Percent => 1.0,
+            Granularity::Tenth => 0.1,
+            Granularity::Unknown => 0.0,
+        }
+    }
+}
+
+/// What a rate window limits: a span of use, one model, or something else
+/// the provider meters separately
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    /// A few hours of use, e.g. the 5-hour window of Claude and Codex
+    Session,
+    Daily,
+    Weekly,
+    /// Usage of one model, e.g. "sonnet"
+    Model(String),
+    /// Anything else, e.g. "oauth_apps" or "monthly"
+    Other(String),
+}
+
+impl WindowKind {
+    /// The kind of a window `minutes` long: up to a day is a session
+    pub fn from_minutes(minutes: i32) -> Self {
+        match minutes {
+            1440 => WindowKind::Daily,
+            10080 => WindowKind::Weekly,
+            m if m < 1440 => WindowKind::Session,
+            m => WindowKind::Other(format!("{}_minutes", m)),
+        }
+    }
+
+    /// Stable lowercase identifier: "session", "daily", "week", or the
+    /// model's or other window's name
+    pub fn id(&self) -> &str {
+        match self {
+            WindowKind::Session => "session",
+            WindowKind::Daily => "daily",
+            WindowKind::Weekly => "week",
+            WindowKind::Model(name) | WindowKind::Other(name) => name,
         }
     }
 }
 
 /// A single rate window representing quota usage
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RateWindow {
+    /// What the window limits
+    pub kind: WindowKind,
     /// Usage percentage (0-100)
     pub used_percent: f64,
     /// Minutes until reset (if known)
@@ -47,37 +166,171 @@ pub struct RateWindow {
     pub resets_at: Option<DateTime<Utc>>,
     /// Human-readable reset description (e.g., "in 2 hours")
     pub reset_description: Option<String>,
+    /// Resolution of `used_percent` as reported by the provider
+    #[serde(default)]
+    pub granularity: Granularity,
+    /// Name set by the provider, e.g. "Weekly — Sonnet"; derived from
+    /// `window_minutes` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Amount used, in `unit`, when the provider reports counts. Only shown;
+    /// `used_percent` drives the bars and thresholds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used: Option<f64>,
+    /// The window's allowance, in `unit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<f64>,
+    /// What is counted, e.g. "requests", "tokens" or "messages"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
 }
 
 impl RateWindow {
+    /// This window with a provider-specific name
+    pub fn with_label(self, label: &str) -> Self {
+        Self {
+            label: Some(label.to_string()),
+            ..self
+        }
+    }
+
+    /// What to call the window: its label, or one derived from its length
+    /// ("5-hour session", "Weekly limit") or else its kind
+    pub fn name(&self) -> String {
+        match (&self.label, self.window_minutes, &self.kind) {
+            (Some(label), _, _) => label.clone(),
+            (None, Some(minutes), _) => window_name(minutes),
+            (None, None, WindowKind::Daily) => window_name(1440),
+            (None, None, WindowKind::Weekly) => window_name(10080),
+            (None, None, _) => "Usage limit".to_string(),
+        }
+    }
+
     pub fn remaining_percent(&self) -> f64 {
         100.0 - self.used_percent
     }
 
-    pub fn status_class(&self) -> &'static str {
-        if self.used_percent >= 90.0 {
-            "critical"
-        } else if self.used_percent >= 75.0 {
-            "warning"
-        } else {
-            "normal"
+    /// This window with the counts behind its percentage
+    pub fn with_counts(self, used: f64, limit: f64, unit: &str) -> Self {
+        Self {
+            used: Some(used),
+            limit: Some(limit),
+            unit: Some(unit.to_string()),
+            ..self
+        }
+    }
+
+    /// The raw counts, e.g. "412 / 500 requests", or just what was used
+    /// when there is no limit; None unless the provider reported them
+    pub fn counts_text(&self) -> Option<String> {
+        let mut text = format_count(self.used?);
+        if let Some(limit) = self.limit {
+            text.push_str(&format!(" / {}", format_count(limit)));
+        }
+        if let Some(ref unit) = self.unit {
+            text.push_str(&format!(" {}", unit));
         }
+        Some(text)
+    }
+
+    /// Number of decimals to display, never exceeding the source granularity
+    pub fn display_decimals(&self, precision: u8) -> usize {
+        let requested = usize::from(precision);
+        self.granularity
+            .max_decimals()
+            .map_or(requested, |max| requested.min(max))
+    }
+
+    /// Format a percentage of this window (e.g. used or remaining) for display
+    pub fn format_percent(&self, value: f64, precision: u8) -> String {
+        format!("{:.*}", self.display_decimals(precision), value)
+    }
+
+    /// When the window resets, recomputed against `now` with the local clock
+    /// time ("in 3h 12m, 18:45"). Falls back to the description stored at
+    /// fetch time when the provider gave no timestamp.
+    pub fn reset_text(&self, now: DateTime<Utc>, time: &TimeStyle) -> Option<String> {
+        match self.resets_at {
+            Some(resets_at) => Some(time.reset(resets_at, now, &Local)),
+            None => self.reset_description.clone(),
+        }
+    }
+}
+
+/// Whether percentages are shown as used or as remaining quota. Status
+/// classes are always computed on the used percentage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PercentDisplay {
+    #[default]
+    Used,
+    Remaining,
+}
+
+impl PercentDisplay {
+    /// The percentage of `window` to show (and to fill bars with)
+    pub fn percent(self, window: &RateWindow) -> f64 {
+        match self {
+            PercentDisplay::Used => window.used_percent,
+            PercentDisplay::Remaining => window.remaining_percent(),
+        }
+    }
+
+    /// Just the number, e.g. "28"
+    pub fn format(self, window: &RateWindow, precision: u8) -> String {
+        window.format_percent(self.percent(window), precision)
+    }
+
+    /// Word after the percentage: "used" or "left"
+    pub fn suffix(self) -> &'static str {
+        match self {
+            PercentDisplay::Used => "used",
+            PercentDisplay::Remaining => "left",
+        }
+    }
+
+    /// Percentage with its suffix, e.g. "72% used" or "28% left"
+    pub fn label(self, window: &RateWindow, precision: u8) -> String {
+        format!("{}% {}", self.format(window, precision), self.suffix())
+    }
+}
+
+/// A count to at most two decimals, without trailing zeros ("412", "12.5")
+fn format_count(value: f64) -> String {
+    // f64 Display prints 412.0 as "412"; rounding first drops float noise
+    ((value * 100.0).round() / 100.0).to_string()
+}
+
+/// A name for a window of `minutes`: the common lengths by name, others by
+/// their largest whole unit ("3-hour limit", "30-day limit")
+pub fn window_name(minutes: i32) -> String {
+    match minutes {
+        300 => "5-hour session".to_string(),
+        1440 => "Daily limit".to_string(),
+        10080 => "Weekly limit".to_string(),
+        m if m > 0 && m % 1440 == 0 => format!("{}-day limit", m / 1440),
+        m if m > 0 && m % 60 == 0 => format!("{}-hour limit", m / 60),
+        m => format!("{}-minute limit", m),
     }
 }
 
 /// Spend/budget snapshot for providers with cost limits
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CostSnapshot {
     /// Amount spent
     pub used: f64,
     /// Spending limit
     pub limit: f64,
-    /// Currency code (e.g., "USD")
+    /// Currency code (e.g., "USD"), or "credits" for non-monetary balances
     pub currency_code: String,
     /// Period description (e.g., "Monthly")
     pub period: Option<String>,
     /// When period resets
     pub resets_at: Option<DateTime<Utc>>,
+    /// Pay-as-you-go usage billed once the plan's included quota runs out
+    /// (Claude's extra usage), rather than a budget for all spend
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extra_usage: bool,
 }
 
 impl CostSnapshot {
@@ -88,48 +341,567 @@ impl CostSnapshot {
             0.0
         }
     }
+
+    /// How far spend is past the limit, if it is
+    pub fn overage(&self) -> Option<f64> {
+        (self.limit > 0.0 && self.used > self.limit).then_some(self.used - self.limit)
+    }
+
+    /// When the period resets, as for rate windows ("in 2d 4h, Fri 09:00")
+    pub fn reset_text(&self, now: DateTime<Utc>, time: &TimeStyle) -> Option<String> {
+        self.resets_at
+            .map(|resets_at| time.reset(resets_at, now, &Local))
+    }
+
+    /// Format an amount in this snapshot's unit, e.g. "$12.50" or "12.50 credits"
+    pub fn format_amount(&self, value: f64) -> String {
+        self.format_with_decimals(value, 2)
+    }
+
+    fn format_with_decimals(&self, value: f64, decimals: usize) -> String {
+        format_money(value, &self.currency_code, decimals)
+    }
+
+    /// Whole amounts for bar text: "$42 / $100", or "$42" without a limit
+    pub fn compact(&self) -> String {
+        let used = self.format_with_decimals(self.used, 0);
+        if self.limit > 0.0 {
+            format!("{} / {}", used, self.format_with_decimals(self.limit, 0))
+        } else {
+            used
+        }
+    }
+
+    /// "used / limit period", dropping the limit when none is configured
+    pub fn describe(&self) -> String {
+        let mut text = self.format_amount(self.used);
+        if self.limit > 0.0 {
+            text.push_str(&format!(" / {}", self.format_amount(self.limit)));
+        }
+        if let Some(ref period) = self.period {
+            text.push_str(&format!(" {}", period));
+        }
+        text
+    }
+}
+
+/// An amount in `currency_code`: the symbol first for common currencies
+/// ("$12.50", "€12.50"), otherwise the code after it ("12.50 CHF", "12.50
+/// credits")
+pub fn format_money(value: f64, currency_code: &str, decimals: usize) -> String {
+    let symbol = match currency_code.to_ascii_uppercase().as_str() {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" => Some("¥"),
+        "INR" => Some("₹"),
+        _ => None,
+    };
+    match symbol {
+        Some(symbol) => format!("{}{:.*}", symbol, decimals, value),
+        None => format!("{:.*} {}", decimals, value, currency_code),
+    }
+}
+
+/// The heaviest individual user in an organization-wide snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MemberUsage {
+    /// Email address or API key name
+    pub member: String,
+    /// Estimated spend in the snapshot's cost currency
+    pub cost: f64,
+    /// Share of the organization's spend (0-100)
+    pub share_percent: f64,
 }
 
 /// Identity information for a provider
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IdentitySnapshot {
     /// Email address
     pub email: Option<String>,
     /// Plan type (e.g., "Pro", "Max")
     pub plan: Option<String>,
-    /// Organization name
+    /// Organization or workspace name
     pub organization: Option<String>,
+    /// Provider account identifier
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+impl IdentitySnapshot {
+    /// Drop the email, organization and account id, keeping the plan
+    pub fn redact(&mut self) {
+        self.email = None;
+        self.organization = None;
+        self.account_id = None;
+    }
+
+    /// Email and organization as one line, e.g. "me@example.com · Acme";
+    /// the email is left out unless `show_email`. None when nothing is left.
+    pub fn account_line(&self, show_email: bool) -> Option<String> {
+        let parts: Vec<&str> = [
+            self.email.as_deref().filter(|_| show_email),
+            self.organization.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect();
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
 }
 
 /// Complete usage snapshot for a provider
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct UsageSnapshot {
     pub provider: Provider,
-    /// Primary/session quota
-    pub primary: Option<RateWindow>,
-    /// Secondary quota (e.g., weekly)
-    pub secondary: Option<RateWindow>,
-    /// Tertiary quota (e.g., Opus limit)
-    pub tertiary: Option<RateWindow>,
+    /// Quota windows in display order, the main one first
+    pub windows: Vec<RateWindow>,
     /// Cost/budget information
     pub cost: Option<CostSnapshot>,
     /// Identity information
     pub identity: Option<IdentitySnapshot>,
+    /// Rate-limit headroom of the usage endpoint itself
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitInfo>,
+    /// Busiest member, for organization-wide snapshots
+    #[serde(default)]
+    pub busiest_member: Option<MemberUsage>,
+    /// The machine and credentials it was fetched under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
     /// When this snapshot was captured
     pub updated_at: DateTime<Utc>,
 }
 
 impl UsageSnapshot {
-    /// Get the most constrained (highest used) rate window
-    pub fn primary_rate(&self) -> Option<&RateWindow> {
-        self.primary.as_ref()
+    /// Heading for this snapshot: the organization name for org-wide views,
+    /// otherwise the provider name
+    pub fn label(&self) -> &str {
+        match self.provider {
+            Provider::ClaudeOrg => self
+                .identity
+                .as_ref()
+                .and_then(|i| i.organization.as_deref())
+                .unwrap_or(self.provider.display_name()),
+            _ => self.provider.display_name(),
+        }
+    }
+
+    /// e.g. "Top member: a@example.com ($6.00, 60% of spend)"
+    pub fn busiest_member_summary(&self) -> Option<String> {
+        let member = self.busiest_member.as_ref()?;
+        let amount = match self.cost {
+            Some(ref cost) => cost.format_amount(member.cost),
+            None => format!("{:.2}", member.cost),
+        };
+        Some(format!(
+            "Top member: {} ({}, {:.0}% of spend)",
+            member.member, amount, member.share_percent
+        ))
+    }
+
+    /// The main window, e.g. the session, unless the first window is the
+    /// weekly one
+    pub fn primary(&self) -> Option<&RateWindow> {
+        self.windows
+            .first()
+            .filter(|w| w.kind != WindowKind::Weekly)
+    }
+
+    /// The weekly window
+    pub fn secondary(&self) -> Option<&RateWindow> {
+        self.window(&WindowKind::Weekly)
+    }
+
+    /// The first model-specific window, e.g. Claude's weekly Sonnet limit
+    pub fn model_window(&self) -> Option<&RateWindow> {
+        self.windows
+            .iter()
+            .find(|w| matches!(w.kind, WindowKind::Model(_)))
+    }
+
+    /// The first window of `kind`
+    pub fn window(&self, kind: &WindowKind) -> Option<&RateWindow> {
+        self.windows.iter().find(|w| w.kind == *kind)
+    }
+
+    /// The first quota window with nothing left, e.g. once extra usage is
+    /// being billed
+    pub fn exhausted_window(&self) -> Option<&RateWindow> {
+        self.windows.iter().find(|w| w.used_percent >= 100.0)
+    }
+
+    /// The window with the least remaining; the earlier window wins a tie
+    pub fn most_constrained(&self) -> Option<&RateWindow> {
+        self.windows
+            .iter()
+            .min_by(|a, b| a.remaining_percent().total_cmp(&b.remaining_percent()))
     }
 
     /// Get the lowest remaining percentage across all windows
     pub fn min_remaining(&self) -> Option<f64> {
-        [&self.primary, &self.secondary, &self.tertiary]
+        self.windows
             .iter()
-            .filter_map(|w| w.as_ref().map(|r| r.remaining_percent()))
+            .map(|w| w.remaining_percent())
             .min_by(|a, b| a.partial_cmp(b).unwrap())
     }
+
+    /// Used percentage of the most constrained window
+    pub fn max_used(&self) -> Option<f64> {
+        self.min_remaining().map(|r| 100.0 - r)
+    }
+
+    /// Share of the spending limit used, when there is a limit
+    pub fn cost_percent(&self) -> Option<f64> {
+        self.cost
+            .as_ref()
+            .filter(|c| c.limit > 0.0)
+            .map(|c| c.used_percent())
+    }
+
+    /// Highest used percentage across the windows and the spending limit
+    pub fn peak_used(&self) -> Option<f64> {
+        self.max_used()
+            .into_iter()
+            .chain(self.cost_percent())
+            .reduce(f64::max)
+    }
+
+    /// Whether this snapshot is older than `max_age`, see [`crate::cache::is_stale`]
+    pub fn is_stale(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        crate::cache::is_stale(self.updated_at, max_age, now)
+    }
+
+    /// Status class of the most constrained window or the spending limit
+    /// under the configured thresholds
+    pub fn status_class(&self, config: &Config) -> &'static str {
+        config.status_class(self.provider, self.peak_used().unwrap_or(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_account_line() {
+        let identity = IdentitySnapshot {
+            email: Some("me@example.com".to_string()),
+            plan: Some("Team".to_string()),
+            organization: Some("Acme".to_string()),
+            account_id: None,
+        };
+        assert_eq!(
+            identity.account_line(true).as_deref(),
+            Some("me@example.com · Acme")
+        );
+        assert_eq!(identity.account_line(false).as_deref(), Some("Acme"));
+
+        let email_only = IdentitySnapshot {
+            organization: Some(String::new()),
+            ..identity
+        };
+        assert_eq!(
+            email_only.account_line(true).as_deref(),
+            Some("me@example.com")
+        );
+        assert_eq!(email_only.account_line(false), None);
+    }
+
+    fn window(used_percent: f64, granularity: Granularity) -> RateWindow {
+        RateWindow {
+            kind: WindowKind::Session,
+            used_percent,
+            window_minutes: None,
+            resets_at: None,
+            reset_description: None,
+            granularity,
+            label: None,
+            used: None,
+            limit: None,
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn test_cost_compact_and_peak() {
+        let mut cost = CostSnapshot {
+            used: 42.5,
+            limit: 100.0,
+            currency_code: "USD".to_string(),
+            period: None,
+            resets_at: None,
+            extra_usage: false,
+        };
+        assert_eq!(cost.compact(), "$42 / $100");
+        cost.currency_code = "credits".to_string();
+        cost.limit = 0.0;
+        assert_eq!(cost.compact(), "42 credits");
+
+        let mut snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::OpenCode)
+            .unwrap();
+        snapshot.windows.clear();
+        snapshot.cost = Some(cost.clone());
+        assert_eq!(snapshot.peak_used(), None);
+        cost.limit = 50.0;
+        snapshot.cost = Some(cost);
+        assert_eq!(snapshot.peak_used(), Some(85.0));
+        snapshot.windows.push(window(90.0, Granularity::Percent));
+        assert_eq!(snapshot.peak_used(), Some(90.0));
+    }
+
+    #[test]
+    fn test_percent_display() {
+        let w = window(72.4, Granularity::Tenth);
+        assert_eq!(PercentDisplay::Used.label(&w, 1), "72.4% used");
+        assert_eq!(PercentDisplay::Remaining.label(&w, 1), "27.6% left");
+        assert_eq!(PercentDisplay::Remaining.format(&w, 0), "28");
+        assert_eq!(PercentDisplay::Used.percent(&w), 72.4);
+    }
+
+    #[test]
+    fn test_integer_source_never_shows_decimals() {
+        let w = window(31.0, Granularity::Percent);
+        assert_eq!(w.format_percent(w.used_percent, 0), "31");
+        assert_eq!(w.format_percent(w.used_percent, 2), "31");
+    }
+
+    #[test]
+    fn test_tenth_source_caps_at_one_decimal() {
+        let w = window(31.25, Granularity::Tenth);
+        assert_eq!(w.format_percent(w.used_percent, 0), "31");
+        assert_eq!(w.format_percent(w.used_percent, 1), "31.2");
+        assert_eq!(w.format_percent(w.used_percent, 3), "31.2");
+    }
+
+    #[test]
+    fn test_unknown_source_honors_precision() {
+        let w = window(31.25, Granularity::Unknown);
+        assert_eq!(w.format_percent(w.used_percent, 2), "31.25");
+        assert_eq!(w.format_percent(w.remaining_percent(), 1), "68.8");
+    }
+
+    #[test]
+    fn test_min_remaining_covers_every_window() {
+        let json = r#"{
+            "provider": "claude",
+            "windows": [
+                {"kind": "session", "used_percent": 20.0, "window_minutes": 300, "resets_at": null, "reset_description": null}
+            ],
+            "cost": null,
+            "identity": null,
+            "updated_at": "2025-01-01T00:00:00Z"
+        }"#;
+        let mut snapshot: UsageSnapshot = serde_json::from_str(json).unwrap();
+        assert_eq!(snapshot.min_remaining(), Some(80.0));
+
+        snapshot.windows.push(RateWindow {
+            kind: WindowKind::Other("oauth_apps".to_string()),
+            ..window(95.0, Granularity::Unknown)
+        });
+        assert_eq!(snapshot.min_remaining(), Some(5.0));
+    }
+
+    #[test]
+    fn test_windows_by_kind() {
+        let kinded = |kind: WindowKind| RateWindow {
+            kind,
+            ..window(10.0, Granularity::Unknown)
+        };
+        let mut snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        snapshot.windows = vec![
+            kinded(WindowKind::Session),
+            kinded(WindowKind::Weekly),
+            kinded(WindowKind::Model("sonnet".to_string())),
+            kinded(WindowKind::Model("opus".to_string())),
+        ];
+        assert_eq!(snapshot.primary().unwrap().kind, WindowKind::Session);
+        assert_eq!(snapshot.secondary().unwrap().kind, WindowKind::Weekly);
+        assert_eq!(snapshot.model_window().unwrap().kind.id(), "sonnet");
+
+        // Without a session the weekly window isn't taken for one
+        snapshot.windows.remove(0);
+        assert!(snapshot.primary().is_none());
+        assert_eq!(snapshot.secondary().unwrap().kind, WindowKind::Weekly);
+
+        // Monthly credits are the main window of their provider
+        snapshot.windows = vec![kinded(WindowKind::Other("monthly".to_string()))];
+        assert_eq!(snapshot.primary().unwrap().kind.id(), "monthly");
+        assert!(snapshot.secondary().is_none());
+    }
+
+    #[test]
+    fn test_window_kinds() {
+        assert_eq!(WindowKind::from_minutes(300), WindowKind::Session);
+        assert_eq!(WindowKind::from_minutes(60), WindowKind::Session);
+        assert_eq!(WindowKind::from_minutes(1440), WindowKind::Daily);
+        assert_eq!(WindowKind::from_minutes(10080), WindowKind::Weekly);
+        assert_eq!(WindowKind::from_minutes(43200).id(), "43200_minutes");
+
+        let json = |kind: &WindowKind| serde_json::to_string(kind).unwrap();
+        assert_eq!(json(&WindowKind::Weekly), r#""weekly""#);
+        assert_eq!(
+            json(&WindowKind::Model("sonnet".to_string())),
+            r#"{"model":"sonnet"}"#
+        );
+        let other: WindowKind = serde_json::from_str(r#"{"other": "oauth_apps"}"#).unwrap();
+        assert_eq!(other.id(), "oauth_apps");
+    }
+
+    #[test]
+    fn test_granularity_defaults_when_missing() {
+        let json = r#"{"kind": "session", "used_percent": 12.0, "window_minutes": 300, "resets_at": null, "reset_description": null}"#;
+        let w: RateWindow = serde_json::from_str(json).unwrap();
+        assert_eq!(w.granularity, Granularity::Unknown);
+        // Caches from before labels name the window from its length
+        assert_eq!(w.label, None);
+        assert_eq!(w.name(), "5-hour session");
+    }
+
+    #[test]
+    fn test_window_names() {
+        assert_eq!(window_name(300), "5-hour session");
+        assert_eq!(window_name(1440), "Daily limit");
+        assert_eq!(window_name(10080), "Weekly limit");
+        assert_eq!(window_name(60), "1-hour limit");
+        assert_eq!(window_name(43200), "30-day limit");
+        assert_eq!(window_name(90), "90-minute limit");
+
+        let mut w = window(10.0, Granularity::Unknown);
+        assert_eq!(w.name(), "Usage limit");
+        w.kind = WindowKind::Weekly;
+        assert_eq!(w.name(), "Weekly limit");
+        w.kind = WindowKind::Session;
+        w.window_minutes = Some(10080);
+        assert_eq!(w.name(), "Weekly limit");
+        let w = w.with_label("Weekly — Sonnet");
+        assert_eq!(w.name(), "Weekly — Sonnet");
+    }
+
+    #[test]
+    fn test_counts_text() {
+        let w = window(82.4, Granularity::Unknown);
+        assert_eq!(w.counts_text(), None);
+
+        let w = w.with_counts(412.0, 500.0, "requests");
+        assert_eq!(w.counts_text().as_deref(), Some("412 / 500 requests"));
+        // The percentage is left alone
+        assert_eq!(w.used_percent, 82.4);
+
+        let w = RateWindow {
+            used: Some(12.345),
+            limit: None,
+            unit: None,
+            ..w
+        };
+        assert_eq!(w.counts_text().as_deref(), Some("12.35"));
+
+        // Caches from before counts load without them
+        let w: RateWindow =
+            serde_json::from_str(r#"{"kind":"session","used_percent":10.0}"#).unwrap();
+        assert_eq!(w.counts_text(), None);
+    }
+
+    #[test]
+    fn test_cost_format_amount() {
+        let mut cost = CostSnapshot {
+            used: 12.5,
+            limit: 100.0,
+            currency_code: "USD".to_string(),
+            period: None,
+            resets_at: None,
+            extra_usage: false,
+        };
+        assert_eq!(cost.format_amount(cost.used), "$12.50");
+        cost.currency_code = "credits".to_string();
+        assert_eq!(cost.format_amount(cost.limit), "100.00 credits");
+    }
+
+    #[test]
+    fn test_format_money() {
+        assert_eq!(format_money(12.5, "USD", 2), "$12.50");
+        assert_eq!(format_money(12.5, "usd", 0), "$12");
+        assert_eq!(format_money(12.5, "EUR", 2), "€12.50");
+        assert_eq!(format_money(7.0, "GBP", 2), "£7.00");
+        assert_eq!(format_money(12.5, "CHF", 2), "12.50 CHF");
+        assert_eq!(format_money(300.0, "credits", 0), "300 credits");
+    }
+
+    #[test]
+    fn test_exhausted_window() {
+        let mut snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        assert!(snapshot.exhausted_window().is_none());
+        snapshot.windows[1].used_percent = 100.0;
+        assert_eq!(
+            snapshot.exhausted_window().map(RateWindow::name).as_deref(),
+            Some("Weekly limit")
+        );
+    }
+
+    #[test]
+    fn test_cost_overage() {
+        let mut cost = CostSnapshot {
+            used: 112.4,
+            limit: 100.0,
+            currency_code: "USD".to_string(),
+            period: Some("Monthly".to_string()),
+            resets_at: None,
+            extra_usage: false,
+        };
+        let over = cost.overage().unwrap();
+        assert_eq!(cost.format_amount(over), "$12.40");
+        cost.used = 100.0;
+        assert_eq!(cost.overage(), None);
+        // Without a limit there is nothing to be over
+        cost.used = 500.0;
+        cost.limit = 0.0;
+        assert_eq!(cost.overage(), None);
+    }
+
+    #[test]
+    fn test_cost_describe_without_limit() {
+        let mut cost = CostSnapshot {
+            used: 12.5,
+            limit: 100.0,
+            currency_code: "USD".to_string(),
+            period: Some("Monthly".to_string()),
+            resets_at: None,
+            extra_usage: false,
+        };
+        assert_eq!(cost.describe(), "$12.50 / $100.00 Monthly");
+        cost.limit = 0.0;
+        assert_eq!(cost.describe(), "$12.50 Monthly");
+    }
+
+    #[test]
+    fn test_reset_text_prefers_timestamp() {
+        let now = Utc::now();
+        let mut window = RateWindow {
+            kind: WindowKind::Session,
+            used_percent: 10.0,
+            window_minutes: Some(300),
+            resets_at: Some(now + Duration::minutes(90)),
+            reset_description: Some("in 5h".to_string()),
+            granularity: Granularity::Percent,
+            label: None,
+            used: None,
+            limit: None,
+            unit: None,
+        };
+        let time = TimeStyle::default();
+        assert!(window
+            .reset_text(now, &time)
+            .unwrap()
+            .starts_with("in 1h 30m, "));
+
+        window.resets_at = None;
+        assert_eq!(window.reset_text(now, &time).as_deref(), Some("in 5h"));
+    }
 }
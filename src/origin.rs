@@ -0,0 +1,152 @@
+//! Which machine and credentials a snapshot was fetched under, so a cache
+//! synced from another machine isn't mistaken for this one's
+
+use crate::config::Config;
+use crate::models::{Provider, UsageSnapshot};
+use crate::providers;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Origin {
+    /// Hostname of the machine that fetched the snapshot, without its domain
+    pub host: String,
+    /// Hash of the host and the credentials used; compared, never shown
+    pub fingerprint: String,
+}
+
+impl Origin {
+    pub fn new(host: &str, credentials: Option<&str>) -> Self {
+        Self {
+            host: host.to_string(),
+            fingerprint: fingerprint(host, credentials),
+        }
+    }
+
+    /// Where this machine fetches `provider` from
+    pub fn local(provider: Provider, config: &Config) -> Self {
+        Self::new(
+            hostname(),
+            providers::credentials_id(provider, config).as_deref(),
+        )
+    }
+
+    /// e.g. "(from desktop)"
+    pub fn annotation(&self) -> String {
+        format!("(from {})", self.host)
+    }
+}
+
+/// The snapshot's origin when it came from another machine or login than
+/// this one would fetch it with. Never with `track_origin` off, nor for
+/// snapshots from before origins were recorded.
+pub fn foreign<'a>(snapshot: &'a UsageSnapshot, config: &Config) -> Option<&'a Origin> {
+    if !config.general.track_origin {
+        return None;
+    }
+    differs(snapshot, &Origin::local(snapshot.provider, config))
+}
+
+/// `snapshot`'s origin if its fingerprint isn't `local`'s
+fn differs<'a>(snapshot: &'a UsageSnapshot, local: &Origin) -> Option<&'a Origin> {
+    snapshot
+        .origin
+        .as_ref()
+        .filter(|o| o.fingerprint != local.fingerprint)
+}
+
+/// This machine's hostname, read once
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        let name = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+        short_host(&name).unwrap_or("localhost").to_string()
+    })
+}
+
+/// "desktop.lan\n" -> "desktop"
+fn short_host(name: &str) -> Option<&str> {
+    name.trim().split('.').next().filter(|h| !h.is_empty())
+}
+
+/// 64-bit FNV-1a of the host and credentials, as hex. Stable across builds
+/// and machines, unlike std's hasher; it only has to tell origins apart.
+fn fingerprint(host: &str, credentials: Option<&str>) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let input = [host, "\0", credentials.unwrap_or_default()];
+    for byte in input.iter().flat_map(|part| part.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+
+    fn fetched_on(host: &str, credentials: &str) -> UsageSnapshot {
+        UsageSnapshot {
+            origin: Some(Origin::new(host, Some(credentials))),
+            ..mock_snapshots()[&Provider::Claude].clone()
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let laptop = "/home/me/.claude/.credentials.json";
+        assert_eq!(
+            fingerprint("laptop", Some(laptop)),
+            fingerprint("laptop", Some(laptop))
+        );
+        assert_eq!(fingerprint("laptop", None).len(), 16);
+        // Either half changing changes it, and the halves don't run together
+        assert_ne!(
+            fingerprint("laptop", Some(laptop)),
+            fingerprint("desktop", Some(laptop))
+        );
+        assert_ne!(
+            fingerprint("laptop", Some(laptop)),
+            fingerprint("laptop", Some("$CLAUDE_TOKEN"))
+        );
+        assert_ne!(fingerprint("ab", Some("c")), fingerprint("a", Some("bc")));
+    }
+
+    #[test]
+    fn test_differs() {
+        let local = Origin::new("laptop", Some("/home/me/.codex/auth.json"));
+        let same = fetched_on("laptop", "/home/me/.codex/auth.json");
+        assert_eq!(differs(&same, &local), None);
+
+        let desktop = fetched_on("desktop", "/home/me/.codex/auth.json");
+        let origin = differs(&desktop, &local).unwrap();
+        assert_eq!(origin.host, "desktop");
+        assert_eq!(origin.annotation(), "(from desktop)");
+
+        // Same machine, other login
+        let other = fetched_on("laptop", "$CODEX_TOKEN");
+        assert!(differs(&other, &local).is_some());
+
+        // Cached before origins were recorded
+        let unknown = mock_snapshots()[&Provider::Claude].clone();
+        assert_eq!(differs(&unknown, &local), None);
+    }
+
+    #[test]
+    fn test_tracking_off_is_never_foreign() {
+        let mut config = Config::default();
+        let desktop = fetched_on("some-other-host", "/nowhere");
+        assert!(foreign(&desktop, &config).is_some());
+        config.general.track_origin = false;
+        assert_eq!(foreign(&desktop, &config), None);
+    }
+
+    #[test]
+    fn test_short_host() {
+        assert_eq!(short_host("desktop\n"), Some("desktop"));
+        assert_eq!(short_host("laptop.example.lan"), Some("laptop"));
+        assert_eq!(short_host(" \n"), None);
+    }
+}
@@ -15,6 +15,26 @@ pub enum PaceStage {
     FarBehind,
 }
 
+impl PaceStage {
+    /// Far enough ahead of pace to warn before the usage thresholds do
+    pub fn is_deficit_warning(self) -> bool {
+        matches!(self, PaceStage::Ahead | PaceStage::FarAhead)
+    }
+
+    /// CSS class naming this stage, e.g. "pace-far-ahead"
+    pub fn css_class(self) -> &'static str {
+        match self {
+            PaceStage::OnTrack => "pace-on-track",
+            PaceStage::SlightlyAhead => "pace-slightly-ahead",
+            PaceStage::Ahead => "pace-ahead",
+            PaceStage::FarAhead => "pace-far-ahead",
+            PaceStage::SlightlyBehind => "pace-slightly-behind",
+            PaceStage::Behind => "pace-behind",
+            PaceStage::FarBehind => "pace-far-behind",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UsagePace {
     pub stage: PaceStage,
@@ -145,6 +165,24 @@ pub fn format_pace_right(pace: &UsagePace) -> Option<String> {
     }
 }
 
+/// A full line such as "Pace: 8% in reserve — lasts until reset"
+pub fn format_pace_line(pace: &UsagePace) -> String {
+    let left = format_pace_left(pace);
+    match format_pace_right(pace) {
+        Some(right) => {
+            let mut chars = right.chars();
+            let first = chars.next().map(|c| c.to_lowercase().to_string());
+            format!(
+                "Pace: {} — {}{}",
+                left,
+                first.unwrap_or_default(),
+                chars.as_str()
+            )
+        }
+        None => format!("Pace: {}", left),
+    }
+}
+
 pub fn format_duration(seconds: f64) -> String {
     if seconds < 1.0 {
         return "now".to_string();
@@ -170,14 +208,21 @@ pub fn format_duration(seconds: f64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{Granularity, WindowKind};
     use chrono::Duration;
 
     fn make_window(used_percent: f64, window_minutes: i32, resets_in: Duration) -> RateWindow {
         RateWindow {
+            kind: WindowKind::from_minutes(window_minutes),
             used_percent,
             window_minutes: Some(window_minutes),
             resets_at: Some(Utc::now() + resets_in),
             reset_description: None,
+            granularity: Granularity::Unknown,
+            label: None,
+            used: None,
+            limit: None,
+            unit: None,
         }
     }
 
@@ -213,10 +258,16 @@ mod tests {
     #[test]
     fn test_no_reset_returns_none() {
         let window = RateWindow {
+            kind: WindowKind::Weekly,
             used_percent: 50.0,
             window_minutes: Some(10080),
             resets_at: None,
             reset_description: None,
+            granularity: Granularity::Unknown,
+            label: None,
+            used: None,
+            limit: None,
+            unit: None,
         };
         assert!(UsagePace::weekly(&window, Utc::now()).is_none());
     }
@@ -235,6 +286,43 @@ mod tests {
         assert!(pace.will_last_to_reset);
     }
 
+    fn pace(stage: PaceStage, delta: f64, eta_seconds: Option<f64>, lasts: bool) -> UsagePace {
+        UsagePace {
+            stage,
+            delta_percent: delta,
+            expected_used_percent: 50.0,
+            actual_used_percent: 50.0 + delta,
+            eta_seconds,
+            will_last_to_reset: lasts,
+        }
+    }
+
+    #[test]
+    fn test_format_pace_line() {
+        let reserve = pace(PaceStage::Behind, -8.2, None, true);
+        assert_eq!(
+            format_pace_line(&reserve),
+            "Pace: 8% in reserve — lasts until reset"
+        );
+
+        let deficit = pace(PaceStage::Ahead, 12.0, Some(86400.0 + 4.0 * 3600.0), false);
+        assert_eq!(
+            format_pace_line(&deficit),
+            "Pace: 12% in deficit — runs out in 1d 4h"
+        );
+
+        let on_track = pace(PaceStage::OnTrack, 1.0, None, false);
+        assert_eq!(format_pace_line(&on_track), "Pace: On pace");
+    }
+
+    #[test]
+    fn test_deficit_warning_stages() {
+        assert!(PaceStage::Ahead.is_deficit_warning());
+        assert!(PaceStage::FarAhead.is_deficit_warning());
+        assert!(!PaceStage::SlightlyAhead.is_deficit_warning());
+        assert!(!PaceStage::FarBehind.is_deficit_warning());
+    }
+
     #[test]
     fn test_format_duration_days_hours() {
         assert_eq!(format_duration(3.0 * 86400.0 + 5.0 * 3600.0), "3d 5h");
@@ -0,0 +1,183 @@
+//! Where quotabar keeps its config and cache, with environment overrides for
+//! sandboxed services and tests
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// Full path to config.toml
+pub const CONFIG_VAR: &str = "QUOTABAR_CONFIG";
+/// Directory for style.css, and config.toml unless [`CONFIG_VAR`] is set
+pub const CONFIG_DIR_VAR: &str = "QUOTABAR_CONFIG_DIR";
+/// Directory for the cache, history, popup state and logs
+pub const CACHE_DIR_VAR: &str = "QUOTABAR_CACHE_DIR";
+
+/// Why a path is what it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Set by this environment variable
+    Env(&'static str),
+    /// Under the config directory from [`CONFIG_DIR_VAR`]
+    ConfigDir,
+    /// The XDG location
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "from {}", var),
+            Source::ConfigDir => write!(f, "from {}", CONFIG_DIR_VAR),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// An effective path and where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub path: PathBuf,
+    pub source: Source,
+}
+
+/// `$QUOTABAR_CONFIG_DIR`, or `~/.config/quotabar`
+pub fn config_dir() -> Resolved {
+    from_env(CONFIG_DIR_VAR).unwrap_or_else(|| Resolved {
+        path: xdg(dirs::config_dir()),
+        source: Source::Default,
+    })
+}
+
+/// `$QUOTABAR_CONFIG`, or config.toml in [`config_dir`]
+pub fn config_file() -> Resolved {
+    from_env(CONFIG_VAR).unwrap_or_else(|| {
+        let dir = config_dir();
+        Resolved {
+            path: dir.path.join("config.toml"),
+            source: match dir.source {
+                Source::Default => Source::Default,
+                _ => Source::ConfigDir,
+            },
+        }
+    })
+}
+
+/// `$QUOTABAR_CACHE_DIR`, or `~/.cache/quotabar`
+pub fn cache_dir() -> Resolved {
+    from_env(CACHE_DIR_VAR).unwrap_or_else(|| Resolved {
+        path: xdg(dirs::cache_dir()),
+        source: Source::Default,
+    })
+}
+
+/// Where the daemon serves snapshots: `$XDG_RUNTIME_DIR/quotabar.sock`;
+/// None without a runtime directory
+pub fn socket_path() -> Option<PathBuf> {
+    Some(dirs::runtime_dir()?.join("quotabar.sock"))
+}
+
+/// The variable's value as a path, unless it's unset or blank
+fn from_env(var: &'static str) -> Option<Resolved> {
+    let value = std::env::var(var).ok()?;
+    Some(Resolved {
+        path: expand_home(&value)?,
+        source: Source::Env(var),
+    })
+}
+
+fn xdg(base: Option<PathBuf>) -> PathBuf {
+    base.unwrap_or_else(|| PathBuf::from(".")).join("quotabar")
+}
+
+/// `value` as a path, `~/` expanding to the home directory; None when blank
+pub fn expand_home(value: &str) -> Option<PathBuf> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    Some(match value.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()?.join(rest),
+        None => PathBuf::from(value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Tests setting the variables take turns; the process environment is
+    /// shared between test threads
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn with_env(vars: &[(&str, &str)], test: impl FnOnce()) {
+        let _guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        for var in [CONFIG_VAR, CONFIG_DIR_VAR, CACHE_DIR_VAR] {
+            std::env::remove_var(var);
+        }
+        for (var, value) in vars {
+            std::env::set_var(var, value);
+        }
+        test();
+        for (var, _) in vars {
+            std::env::remove_var(var);
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        with_env(&[], || {
+            let config = config_file();
+            assert_eq!(config.source, Source::Default);
+            assert!(config.path.ends_with("quotabar/config.toml"));
+            assert_eq!(config_dir().path, config.path.parent().unwrap());
+            assert_eq!(cache_dir().source, Source::Default);
+            assert!(cache_dir().path.ends_with("quotabar"));
+        });
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let home = dirs::home_dir().unwrap();
+        with_env(
+            &[
+                (CONFIG_VAR, "~/sandbox/qb.toml"),
+                (CACHE_DIR_VAR, "/run/quotabar"),
+            ],
+            || {
+                assert_eq!(
+                    config_file(),
+                    Resolved {
+                        path: home.join("sandbox/qb.toml"),
+                        source: Source::Env(CONFIG_VAR),
+                    }
+                );
+                assert_eq!(config_dir().source, Source::Default);
+                assert_eq!(cache_dir().path, PathBuf::from("/run/quotabar"));
+                assert_eq!(cache_dir().source.to_string(), "from QUOTABAR_CACHE_DIR");
+            },
+        );
+
+        // The config directory moves config.toml too, unless it's set itself
+        with_env(&[(CONFIG_DIR_VAR, "/etc/qb")], || {
+            assert_eq!(config_dir().path, PathBuf::from("/etc/qb"));
+            assert_eq!(
+                config_file(),
+                Resolved {
+                    path: PathBuf::from("/etc/qb/config.toml"),
+                    source: Source::ConfigDir,
+                }
+            );
+        });
+        with_env(
+            &[(CONFIG_DIR_VAR, "/etc/qb"), (CONFIG_VAR, "/x.toml")],
+            || {
+                assert_eq!(config_file().path, PathBuf::from("/x.toml"));
+            },
+        );
+
+        // Blank is the same as unset
+        with_env(&[(CACHE_DIR_VAR, "  ")], || {
+            assert_eq!(cache_dir().source, Source::Default);
+        });
+    }
+}
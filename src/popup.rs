@@ -1,28 +1,73 @@
-use crate::cache::CacheState;
-use crate::config::Config;
-use crate::mock::mock_snapshots;
-use crate::models::{Provider, UsageSnapshot};
+use crate::browser;
+use crate::cache::{CacheState, FetchFailure};
+use crate::changes::{self, Change, Seen};
+use crate::config::{Config, PopupAnchor, PopupConfig, PopupTheme, ProviderChoice, Thresholds};
+use crate::configwatch;
+use crate::doctor::{self, Check};
+use crate::fetch::{self, FetchMode};
+use crate::history::{self, Sample};
+use crate::icons::{self, IconCache, IconKey, IconSource};
+use crate::logging::redact;
+use crate::mock::MockData;
+use crate::models::{
+    CostSnapshot, PercentDisplay, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::origin;
 use crate::pace::{self, UsagePace};
+use crate::providers::{self, ProviderError};
+use crate::settings::Settings;
+use crate::status::{self, StatusStyle};
+use crate::theme;
+use crate::timefmt::{self, ClockFormat, TimeStyle};
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use gtk4::gdk::Display;
 use gtk4::gdk_pixbuf::{Colorspace, Pixbuf};
 use gtk4::prelude::*;
 use gtk4::{
-    Align, Application, ApplicationWindow, Box as GtkBox, CssProvider, Image, Label, LinkButton,
-    Orientation, ProgressBar,
+    Align, Application, ApplicationWindow, Box as GtkBox, CheckButton, CssProvider, Label,
+    LinkButton, Orientation, ProgressBar, Revealer, SpinButton, Stack, Switch,
 };
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::Duration;
 
 const APP_ID: &str = "com.quotabar.popup";
 
-pub fn run(use_mock: bool) -> Result<()> {
+/// How long after opening a focus loss is ignored by `close_on_focus_loss`
+const FOCUS_LOSS_GRACE: Duration = Duration::from_millis(500);
+
+/// How often reset times, pace and data ages are recomputed while open
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a footer notice like "Copied" stays up
+const NOTICE_DURATION: Duration = Duration::from_secs(2);
+
+/// How long refresh failures stay up in the toast
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+
+/// How far back a window's sparkline reaches
+const SPARKLINE_SPAN: chrono::Duration = chrono::Duration::hours(24);
+
+/// Points drawn per sparkline; longer histories are downsampled
+const SPARKLINE_POINTS: usize = 100;
+
+/// Fewer samples than this don't make a trend, so no sparkline is drawn
+const SPARKLINE_MIN_SAMPLES: usize = 3;
+
+/// Show the popup; `mock` replaces the cache as the data source and `only`
+/// narrows it to a single provider. Cached data is shown straight away and
+/// refreshed in the background unless mocked.
+pub fn run(mock: Option<MockData>, only: Option<Provider>) -> Result<()> {
+    let use_mock = mock.is_some();
+    // Fetches run on the caller's runtime while GTK owns this thread
+    let runtime = tokio::runtime::Handle::try_current()
+        .ok()
+        .filter(|_| !use_mock);
     let app = Application::builder().application_id(APP_ID).build();
     let window_state: Rc<RefCell<Option<ApplicationWindow>>> = Rc::new(RefCell::new(None));
 
@@ -35,17 +80,58 @@ pub fn run(use_mock: bool) -> Result<()> {
             }
         }
 
-        let snapshots = if use_mock {
-            mock_snapshots()
+        let cache = if mock.is_some() {
+            None
         } else {
-            CacheState::load()
-                .ok()
-                .flatten()
-                .map(|c| c.snapshots)
-                .unwrap_or_default()
+            CacheState::load().ok().flatten()
+        };
+        let mut snapshots = match (&mock, &cache) {
+            (Some(mock), _) => mock.snapshots.clone(),
+            (None, Some(cache)) => cache.snapshots.clone(),
+            (None, None) => HashMap::new(),
+        };
+        if let Some(provider) = only {
+            snapshots.retain(|p, _| *p == provider);
+        }
+        let failures = match (&mock, &cache) {
+            (Some(mock), _) => mock.failures.clone(),
+            (None, Some(cache)) => cache.last_errors.clone(),
+            (None, None) => HashMap::new(),
         };
 
-        let window = build_ui(app, snapshots, use_mock);
+        tracing::info!(
+            providers = snapshots.len(),
+            mock = use_mock,
+            only = only.map(|p| p.id()),
+            "opening popup"
+        );
+        let config = Config::load().unwrap_or_default();
+        let view = match only {
+            Some(provider) => View::Single {
+                provider,
+                missing: missing_message(provider, cache.as_ref(), config.general.time_format),
+            },
+            None => View::All,
+        };
+        // Fetched on opening only when the shown data is older than
+        // `refresh_interval`, as the bar decides
+        let shown = only.map_or_else(|| config.popup_providers(), |p| vec![p]);
+        let stale = FetchMode::Auto.should_fetch(
+            cache.as_ref(),
+            &shown,
+            config.refresh_interval(),
+            Utc::now(),
+            |s| origin::foreign(s, &config).is_some(),
+        );
+        let window = build_ui(
+            app,
+            snapshots,
+            failures,
+            use_mock,
+            view,
+            runtime.clone(),
+            stale,
+        );
         *window_state.borrow_mut() = Some(window);
     });
 
@@ -53,15 +139,43 @@ pub fn run(use_mock: bool) -> Result<()> {
     Ok(())
 }
 
+/// Which providers the popup lays out
+enum View {
+    /// Every enabled provider, clickable to pick the bar's provider
+    All,
+    /// One provider in detail; `missing` explains an absent snapshot
+    Single { provider: Provider, missing: String },
+}
+
+/// Why a provider has no snapshot to show: its backoff if one is pending,
+/// then its last fetch error, otherwise a hint to fetch
+fn missing_message(provider: Provider, cache: Option<&CacheState>, clock: ClockFormat) -> String {
+    if let Some(until) = cache.and_then(|c| c.backoff_for(provider, Utc::now())) {
+        return format!(
+            "{} is rate limited; retrying after {}",
+            provider.display_name(),
+            timefmt::clock(&until.with_timezone(&chrono::Local), clock)
+        );
+    }
+    match cache.and_then(|c| c.last_errors.get(&provider)) {
+        Some(failure) => format!("Last fetch failed: {}", failure.reason),
+        None => "No data yet — run `quotabar fetch`".to_string(),
+    }
+}
+
 fn build_ui(
     app: &Application,
     snapshots: HashMap<Provider, UsageSnapshot>,
+    failures: HashMap<Provider, FetchFailure>,
     use_mock: bool,
+    view: View,
+    runtime: Option<tokio::runtime::Handle>,
+    refresh_now: bool,
 ) -> ApplicationWindow {
+    let config = Config::load().unwrap_or_default();
     let window = ApplicationWindow::builder()
         .application(app)
-        .default_width(320)
-        .default_height(400)
+        .default_width(config.popup.width)
         .build();
     let app_clone = app.clone();
     window.connect_close_request(move |_| {
@@ -69,118 +183,1530 @@ fn build_ui(
         gtk4::glib::Propagation::Proceed
     });
 
-    // Layer shell setup
-    window.init_layer_shell();
-    window.set_layer(Layer::Overlay);
-    window.set_anchor(Edge::Top, true);
-    window.set_anchor(Edge::Right, true);
-    window.set_margin(Edge::Top, 40);
-    window.set_margin(Edge::Right, 10);
-    window.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::OnDemand);
+    match choose_surface(config.popup.force_window, gtk4_layer_shell::is_supported()) {
+        Surface::LayerShell => setup_layer_shell(&window, &config.popup),
+        Surface::Window => setup_plain_window(&window, config.popup.force_window),
+    }
+
+    // Load CSS
+    let css_watcher = load_css(use_mock, &config);
+
+    // Main container: the sections scroll once they outgrow
+    // `[popup] max_height`, with the footer kept below them
+    let main_box = GtkBox::new(Orientation::Vertical, 0);
+    main_box.add_css_class("popup-container");
+    let content = GtkBox::new(Orientation::Vertical, 0);
+    let scroller = gtk4::ScrolledWindow::new();
+    scroller.set_hscrollbar_policy(gtk4::PolicyType::Never);
+    scroller.set_propagate_natural_height(true);
+    scroller.set_vexpand(true);
+    scroller.set_child(Some(&content));
+    main_box.append(&scroller);
+
+    let (order, detailed) = match view {
+        View::All => (config.popup_providers(), false),
+        View::Single { provider, .. } => {
+            main_box.add_css_class("single-provider");
+            (vec![provider], true)
+        }
+    };
+    // What crossed a threshold since the last time the popup opened
+    let banner = if use_mock {
+        None
+    } else {
+        changes_banner(&changes_since_last_open(&snapshots, &config))
+    };
+    if let Some(ref banner) = banner {
+        content.append(banner);
+    }
+
+    let history = if config.popup.sparklines && !use_mock {
+        load_history(Utc::now())
+    } else {
+        Vec::new()
+    };
+    let footer = Rc::new(Footer::new());
+    footer.auto.set_visible(!detailed);
+    let entries = Rc::new(RefCell::new(Vec::new()));
+    let pinned = Rc::new(Cell::new(false));
+    let sections = Rc::new(Sections {
+        container: content,
+        selection: Rc::new(Selection {
+            scroller: scroller.clone(),
+            window: window.clone(),
+            entries: Rc::clone(&entries),
+            current: RefCell::new(config.general.selected_provider),
+            resolved: RefCell::new(None),
+            focused: RefCell::new(None),
+            auto: footer.auto.clone(),
+            buttons: RefCell::new(HashMap::new()),
+            pinned: Rc::clone(&pinned),
+        }),
+        entries,
+        placeholder: RefCell::new(None),
+        empty: RefCell::new(None),
+        failures: RefCell::new(failures),
+        collapsed: Rc::new(RefCell::new(config.popup.collapsed.clone())),
+        snapshots: RefCell::new(HashMap::new()),
+        history,
+        banner,
+        refreshing: Cell::new(false),
+        clickable: !detailed,
+        detailed,
+        order: RefCell::new(order.clone()),
+        config: RefCell::new(config),
+    });
+    for provider in order {
+        if let Some(snapshot) = snapshots.get(&provider) {
+            sections.show(snapshot);
+        }
+    }
+    if let View::Single { ref missing, .. } = view {
+        if sections.entries.borrow().is_empty() {
+            sections.show_placeholder(missing);
+        }
+    }
+
+    {
+        let selection = Rc::clone(&sections.selection);
+        footer
+            .auto
+            .connect_clicked(move |_| selection.choose(ProviderChoice::Auto));
+    }
+    // Footer with last update time
+    footer.set_updated(
+        &sections.snapshots.borrow(),
+        sections.config.borrow().general.time_format,
+    );
+    main_box.append(&footer.toast.root);
+    main_box.append(&footer.root);
+    if let Some(ref runtime) = runtime {
+        let runtime = runtime.clone();
+        let sections = Rc::clone(&sections);
+        let footer_clone = Rc::clone(&footer);
+        footer.toast.retry.connect_clicked(move |_| {
+            footer_clone.toast.hide();
+            start_refresh(
+                runtime.clone(),
+                Rc::clone(&sections),
+                Rc::clone(&footer_clone),
+            )
+        });
+    }
+
+    // Nothing cached at all, e.g. on first run
+    if matches!(view, View::All) && sections.entries.borrow().is_empty() {
+        let empty = EmptyState::new(
+            &sections.order.borrow(),
+            runtime.is_some(),
+            &sections.config.borrow(),
+        );
+        if let (Some(button), Some(runtime)) = (&empty.button, &runtime) {
+            let runtime = runtime.clone();
+            let sections = Rc::clone(&sections);
+            let footer = Rc::clone(&footer);
+            button.connect_clicked(move |_| {
+                start_refresh(runtime.clone(), Rc::clone(&sections), Rc::clone(&footer))
+            });
+        }
+        sections.container.prepend(&empty.root);
+        *sections.empty.borrow_mut() = Some(empty);
+    }
+
+    // The settings pane is swapped in over the provider sections
+    let stack = Stack::new();
+    // Each page is as tall as its own content
+    stack.set_vhomogeneous(false);
+    stack.add_named(&main_box, Some("main"));
+    window.set_child(Some(&stack));
+    let max_height = sections.config.borrow().popup.max_height;
+    let chrome = main_box.clone();
+    window.connect_realize(move |window| {
+        let Some(monitor_height) = monitor_height(window) else {
+            return;
+        };
+        // Everything around the sections: footer, padding, borders
+        let around = chrome.measure(Orientation::Vertical, -1).1
+            - scroller.measure(Orientation::Vertical, -1).1;
+        scroller.set_max_content_height(scroll_height(max_height, monitor_height, around));
+    });
+    let auto_close_secs = Rc::new(Cell::new(sections.config.borrow().popup.auto_close_secs));
+    {
+        let sections = Rc::clone(&sections);
+        let footer_clone = Rc::clone(&footer);
+        let stack = stack.clone();
+        let auto_close_secs = Rc::clone(&auto_close_secs);
+        let runtime = runtime.clone();
+        footer.settings.connect_clicked(move |_| {
+            open_settings(
+                &stack,
+                Rc::clone(&sections),
+                Rc::clone(&footer_clone),
+                Rc::clone(&auto_close_secs),
+                runtime.clone(),
+            )
+        });
+    }
+    {
+        let sections = Rc::clone(&sections);
+        let footer_clone = Rc::clone(&footer);
+        footer
+            .copy
+            .connect_clicked(move |_| copy_summary(&sections, &footer_clone));
+    }
+
+    // Escape closes; Up/Down (or j/k) and 1-3 move between sections and
+    // Enter picks the focused one, or closes when none is focused; p pins
+    // the popup open and Ctrl+C copies the summary. In the settings pane
+    // Escape cancels and other keys go to its fields.
+    let window_clone = window.clone();
+    let pin = footer.pin.clone();
+    let key_pinned = Rc::clone(&pinned);
+    let stack_clone = stack.clone();
+    let copy_sections = Rc::clone(&sections);
+    let copy_footer = Rc::clone(&footer);
+    let selection = sections.clickable.then(|| Rc::clone(&sections.selection));
+    let key_controller = gtk4::EventControllerKey::new();
+    key_controller.connect_key_pressed(move |_, key, _, modifiers| {
+        use gtk4::gdk::Key;
+        if stack_clone.visible_child_name().as_deref() == Some("settings") {
+            if key != Key::Escape {
+                return gtk4::glib::Propagation::Proceed;
+            }
+            stack_clone.set_visible_child_name("main");
+            return gtk4::glib::Propagation::Stop;
+        }
+        if modifiers.contains(gtk4::gdk::ModifierType::CONTROL_MASK) {
+            if key != Key::c {
+                return gtk4::glib::Propagation::Proceed;
+            }
+            copy_summary(&copy_sections, &copy_footer);
+            return gtk4::glib::Propagation::Stop;
+        }
+        let selection = selection.as_deref();
+        match key {
+            Key::Escape => {
+                if !key_pinned.get() {
+                    window_clone.close();
+                }
+            }
+            Key::Return | Key::KP_Enter => {
+                if !selection.is_some_and(Selection::choose_focused) && !key_pinned.get() {
+                    window_clone.close();
+                }
+            }
+            Key::p => pin.set_active(!pin.is_active()),
+            _ => {
+                let Some(selection) = selection else {
+                    return gtk4::glib::Propagation::Proceed;
+                };
+                match key {
+                    Key::Down | Key::j => selection.move_focus(1),
+                    Key::Up | Key::k => selection.move_focus(-1),
+                    _ => match key.to_unicode().and_then(|c| c.to_digit(10)) {
+                        Some(n @ 1..=3) => selection.focus_nth(n as usize),
+                        _ => return gtk4::glib::Propagation::Proceed,
+                    },
+                }
+            }
+        }
+        gtk4::glib::Propagation::Stop
+    });
+    window.add_controller(key_controller);
+
+    // Track active state for visual feedback, closing on focus loss if set
+    let main_box_clone = main_box.clone();
+    let close_on_focus_loss = sections.config.borrow().popup.close_on_focus_loss;
+    let presented_at = std::time::Instant::now();
+    let focus_pinned = Rc::clone(&pinned);
+    window.connect_is_active_notify(move |win| {
+        if win.is_active() {
+            main_box_clone.add_css_class("focused");
+        } else {
+            main_box_clone.remove_css_class("focused");
+            // Some compositors bounce focus while mapping the surface
+            if close_on_focus_loss
+                && !focus_pinned.get()
+                && presented_at.elapsed() > FOCUS_LOSS_GRACE
+            {
+                win.close();
+            }
+        }
+    });
+
+    window.present();
+    auto_close(&window, Rc::clone(&auto_close_secs), Rc::clone(&pinned));
+    if let Some(watcher) = css_watcher {
+        std::mem::forget(watcher);
+    }
+    tick_while_open(&window, Rc::clone(&sections), Rc::clone(&footer));
+    if !use_mock {
+        reload_config_while_open(
+            &window,
+            Rc::clone(&sections),
+            Rc::clone(&footer),
+            auto_close_secs,
+            runtime.clone(),
+        );
+    }
+    refresh_while_pinned(
+        &window,
+        &main_box,
+        pinned,
+        runtime.clone(),
+        Rc::clone(&sections),
+        Rc::clone(&footer),
+    );
+    if let Some(runtime) = runtime.filter(|_| refresh_now) {
+        start_refresh(runtime, sections, footer);
+    }
+    window
+}
+
+/// Threshold crossings and resets between the snapshots shown at the last
+/// opening and `snapshots`, then remember `snapshots` for next time. The
+/// text says when that last opening was.
+fn changes_since_last_open(
+    snapshots: &HashMap<Provider, UsageSnapshot>,
+    config: &Config,
+) -> Vec<(Change, String)> {
+    if snapshots.is_empty() {
+        return Vec::new();
+    }
+    let now = Utc::now();
+    let mut seen = Seen::load().unwrap_or_else(|| Seen {
+        at: now,
+        snapshots: HashMap::new(),
+    });
+    let since = changes::since(
+        seen.at.with_timezone(&chrono::Local),
+        chrono::Local::now(),
+        config.general.time_format,
+    );
+    let found = changes::changes(&seen.snapshots, snapshots, |p| config.thresholds_for(p))
+        .into_iter()
+        .map(|change| {
+            let text = change.describe(&since);
+            (change, text)
+        })
+        .collect();
+
+    // Providers not shown this time keep what was last seen of them
+    seen.at = now;
+    seen.snapshots
+        .extend(snapshots.iter().map(|(p, s)| (*p, s.clone())));
+    if let Err(e) = seen.save() {
+        tracing::warn!("failed to save popup state: {:#}", e);
+    }
+    found
+}
+
+/// A banner listing `changes`, hidden by clicking it; None when there are
+/// none
+fn changes_banner(changes: &[(Change, String)]) -> Option<GtkBox> {
+    if changes.is_empty() {
+        return None;
+    }
+    let banner = GtkBox::new(Orientation::Vertical, 2);
+    banner.add_css_class("changes-banner");
+    banner.set_tooltip_text(Some("Click to dismiss"));
+    for (change, text) in changes {
+        let label = Label::new(Some(text));
+        label.add_css_class(match change {
+            Change::Crossed { .. } => "change-crossed",
+            Change::Reset { .. } => "change-reset",
+        });
+        label.set_halign(Align::Start);
+        label.set_wrap(true);
+        banner.append(&label);
+    }
+    let dismiss = gtk4::GestureClick::new();
+    let hide = banner.clone();
+    dismiss.connect_released(move |_, _, _, _| hide.set_visible(false));
+    banner.add_controller(dismiss);
+    Some(banner)
+}
+
+/// Re-render every section and the footer each [`TICK_INTERVAL`] so
+/// relative times follow the clock, until the window closes
+fn tick_while_open(window: &ApplicationWindow, sections: Rc<Sections>, footer: Rc<Footer>) {
+    let source = gtk4::glib::timeout_add_local(TICK_INTERVAL, move || {
+        sections.rerender();
+        footer.set_updated(
+            &sections.snapshots.borrow(),
+            sections.config.borrow().general.time_format,
+        );
+        gtk4::glib::ControlFlow::Continue
+    });
+    let source = RefCell::new(Some(source));
+    window.connect_close_request(move |_| {
+        if let Some(source) = source.take() {
+            source.remove();
+        }
+        gtk4::glib::Propagation::Proceed
+    });
+}
+
+/// Follow the footer's pin button: while pinned the popup is marked
+/// `pinned` and, with a runtime, refetches every `[general]
+/// refresh_interval` through [`start_refresh`]
+fn refresh_while_pinned(
+    window: &ApplicationWindow,
+    root: &GtkBox,
+    pinned: Rc<Cell<bool>>,
+    runtime: Option<tokio::runtime::Handle>,
+    sections: Rc<Sections>,
+    footer: Rc<Footer>,
+) {
+    let source: Rc<RefCell<Option<gtk4::glib::SourceId>>> = Rc::new(RefCell::new(None));
+    let timer = Rc::clone(&source);
+    let refresh_footer = Rc::clone(&footer);
+    let root = root.clone();
+    footer.pin.connect_toggled(move |pin| {
+        pinned.set(pin.is_active());
+        if pin.is_active() {
+            root.add_css_class("pinned");
+        } else {
+            root.remove_css_class("pinned");
+        }
+        if let Some(source) = timer.borrow_mut().take() {
+            source.remove();
+        }
+        let Some(runtime) = runtime.clone().filter(|_| pin.is_active()) else {
+            return;
+        };
+        let interval = sections.config.borrow().refresh_interval();
+        let sections = Rc::clone(&sections);
+        let footer = Rc::clone(&refresh_footer);
+        let id = gtk4::glib::timeout_add_local(interval, move || {
+            start_refresh(runtime.clone(), Rc::clone(&sections), Rc::clone(&footer));
+            gtk4::glib::ControlFlow::Continue
+        });
+        *timer.borrow_mut() = Some(id);
+    });
+    window.connect_close_request(move |_| {
+        if let Some(source) = source.take() {
+            source.remove();
+        }
+        gtk4::glib::Propagation::Proceed
+    });
+}
+
+/// Height of the monitor `window` is on; the first monitor until the
+/// compositor has said
+fn monitor_height(window: &ApplicationWindow) -> Option<i32> {
+    let display = WidgetExt::display(window);
+    let monitor = window
+        .is_layer_window()
+        .then(|| window.monitor())
+        .flatten()
+        .or_else(|| {
+            let surface = window.surface()?;
+            display.monitor_at_surface(&surface)
+        })
+        .or_else(|| display.monitors().item(0).and_downcast())?;
+    Some(monitor.geometry().height())
+}
+
+/// How tall the sections may grow before scrolling: `[popup] max_height`,
+/// or 80% of the monitor, less the `around` taken by the footer and padding
+fn scroll_height(max_height: Option<i32>, monitor_height: i32, around: i32) -> i32 {
+    let total = max_height.unwrap_or(monitor_height * 4 / 5);
+    // Room for at least one section header
+    (total - around).max(60)
+}
+
+/// The monitor named by `[popup] output`: a connector name or "focused".
+/// None (the compositor's choice) when it can't be found.
+fn find_monitor(output: &str) -> Option<gtk4::gdk::Monitor> {
+    let connector = if output == "focused" {
+        let Some(focused) = crate::compositor::focused_output() else {
+            tracing::info!("could not ask the compositor for the focused output");
+            return None;
+        };
+        focused
+    } else {
+        output.to_string()
+    };
+    let monitors = Display::default()?.monitors();
+    let found = (0..monitors.n_items())
+        .filter_map(|i| monitors.item(i).and_downcast::<gtk4::gdk::Monitor>())
+        .find(|m| m.connector().as_deref() == Some(connector.as_str()));
+    match found {
+        Some(_) => tracing::info!(output = %connector, "opening popup on output"),
+        None => tracing::warn!(
+            "output {:?} not found, letting the compositor choose",
+            connector
+        ),
+    }
+    found
+}
+
+/// What the popup is drawn on
+#[derive(Debug, PartialEq)]
+enum Surface {
+    /// A layer-shell overlay, anchored and placed per `[popup]`
+    LayerShell,
+    /// An ordinary undecorated window, placed by the window manager
+    Window,
+}
+
+/// The layer shell unless it is unsupported (X11, GNOME, nested
+/// compositors) or `[popup] force_window` asks for a window
+fn choose_surface(force_window: bool, layer_shell_supported: bool) -> Surface {
+    if layer_shell_supported && !force_window {
+        Surface::LayerShell
+    } else {
+        Surface::Window
+    }
+}
+
+fn setup_layer_shell(window: &ApplicationWindow, popup: &PopupConfig) {
+    window.init_layer_shell();
+    window.set_layer(Layer::Overlay);
+    if let Some(ref output) = popup.output {
+        // Looked up on every run so monitors plugged in since last time count
+        window.set_monitor(find_monitor(output).as_ref());
+    }
+    place_window(window, popup);
+    window.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::OnDemand);
+}
+
+/// Fallback without the layer shell. GTK 4 can't position its own windows,
+/// so `anchor`, the margins and `output` don't apply.
+fn setup_plain_window(window: &ApplicationWindow, forced: bool) {
+    if forced {
+        tracing::info!("[popup] force_window is set, opening a regular window");
+    } else {
+        tracing::warn!(
+            "layer shell not supported here, opening a regular window; \
+             [popup] anchor, margins and output are ignored"
+        );
+    }
+    window.set_title(Some("quotabar"));
+    window.set_decorated(false);
+    window.set_resizable(false);
+}
+
+/// Anchor the layer surface per `[popup]`; with no edges anchored the
+/// compositor centers it
+fn place_window(window: &ApplicationWindow, popup: &PopupConfig) {
+    let anchored = anchor_edges(popup.anchor);
+    for edge in [Edge::Top, Edge::Right, Edge::Bottom, Edge::Left] {
+        window.set_anchor(edge, anchored.contains(&edge));
+    }
+    window.set_margin(Edge::Top, popup.margin_top);
+    window.set_margin(Edge::Right, popup.margin_right);
+    window.set_margin(Edge::Bottom, popup.margin_bottom);
+    window.set_margin(Edge::Left, popup.margin_left);
+    // Centered on the whole output rather than the space bars leave free
+    let exclusive_zone = if popup.anchor == PopupAnchor::Center {
+        -1
+    } else {
+        0
+    };
+    window.set_exclusive_zone(exclusive_zone);
+}
+
+/// Which layer-shell edges `anchor` pins the popup to
+fn anchor_edges(anchor: PopupAnchor) -> &'static [Edge] {
+    match anchor {
+        PopupAnchor::TopRight => &[Edge::Top, Edge::Right],
+        PopupAnchor::TopLeft => &[Edge::Top, Edge::Left],
+        PopupAnchor::BottomRight => &[Edge::Bottom, Edge::Right],
+        PopupAnchor::BottomLeft => &[Edge::Bottom, Edge::Left],
+        PopupAnchor::Center => &[],
+    }
+}
+
+/// Close `window` once it has been left alone for `after`; pointer motion
+/// and key presses inside it start the wait over. It stays while `pinned`.
+fn auto_close(window: &ApplicationWindow, secs: Rc<Cell<u64>>, pinned: Rc<Cell<bool>>) {
+    let timer: Rc<RefCell<Option<gtk4::glib::SourceId>>> = Rc::new(RefCell::new(None));
+    let arm = {
+        let window = window.clone();
+        let timer = Rc::clone(&timer);
+        move || {
+            if let Some(source) = timer.borrow_mut().take() {
+                source.remove();
+            }
+            if secs.get() == 0 {
+                return;
+            }
+            let after = Duration::from_secs(secs.get());
+            let window = window.clone();
+            let fired = Rc::clone(&timer);
+            let pinned = Rc::clone(&pinned);
+            let source = gtk4::glib::timeout_add_local_once(after, move || {
+                // Already gone once it has fired; nothing to remove later
+                fired.borrow_mut().take();
+                // Re-armed by the next motion or key once unpinned
+                if pinned.get() {
+                    return;
+                }
+                tracing::debug!("popup idle for {:?}, closing", after);
+                window.close();
+            });
+            *timer.borrow_mut() = Some(source);
+        }
+    };
+    arm();
+
+    let motion = gtk4::EventControllerMotion::new();
+    let rearm = arm.clone();
+    motion.connect_motion(move |_, _, _| rearm());
+    window.add_controller(motion);
+
+    let keys = gtk4::EventControllerKey::new();
+    keys.connect_key_pressed(move |_, _, _, _| {
+        arm();
+        gtk4::glib::Propagation::Proceed
+    });
+    window.add_controller(keys);
+}
+
+/// Fetch the shown providers on `runtime` and update the open popup in
+/// place when the results come back. The cache is written as usual.
+fn start_refresh(runtime: tokio::runtime::Handle, sections: Rc<Sections>, footer: Rc<Footer>) {
+    if sections.refreshing.get() {
+        return;
+    }
+    footer.set_refreshing(true);
+    sections.set_refreshing(true);
+    let config = sections.config.borrow().clone();
+    let providers = sections.order.borrow().clone();
+    let fetch = runtime.spawn(async move { fetch::refresh(&config, &providers, false).await });
+
+    // The join handle is awaited on the GTK main loop, so widgets are only
+    // touched from this thread
+    gtk4::glib::spawn_future_local(async move {
+        let refresh = match fetch.await {
+            Ok(refresh) => refresh,
+            Err(e) => {
+                tracing::warn!("popup refresh did not finish: {}", e);
+                footer.set_refreshing(false);
+                sections.set_refreshing(false);
+                footer.set_status("Refresh failed");
+                return;
+            }
+        };
+        if let Some(ref e) = refresh.save_error {
+            tracing::warn!("failed to write cache: {:#}", e);
+        }
+
+        // Providers still backing off from a 429 count as failed too
+        let mut errors = refresh.errors;
+        for (provider, until) in refresh.backed_off {
+            errors
+                .entry(provider)
+                .or_insert_with(|| ProviderError::RateLimited { until }.into());
+        }
+        let order = sections.order.borrow().clone();
+        for &provider in &order {
+            let reason = errors.get(&provider).map(providers::short_reason);
+            if let (None, Some(snapshot)) = (&reason, refresh.snapshots.get(&provider)) {
+                sections.failures.borrow_mut().remove(&provider);
+                sections.show(snapshot);
+            }
+            if let Some(reason) = reason {
+                tracing::warn!("{}: {}", provider.display_name(), reason);
+                // Kept so the next re-render still shows it
+                sections.failures.borrow_mut().insert(
+                    provider,
+                    FetchFailure {
+                        reason: reason.clone(),
+                        at: Utc::now(),
+                    },
+                );
+                // Cached values stay; the section says why they weren't updated
+                sections.show_error(provider, &reason);
+            }
+        }
+
+        footer.set_refreshing(false);
+        sections.set_refreshing(false);
+        footer.set_updated(
+            &sections.snapshots.borrow(),
+            sections.config.borrow().general.time_format,
+        );
+        let lines = failure_lines(&errors, &order);
+        if !lines.is_empty() {
+            footer.toast.show(&lines.join("\n"));
+        }
+    });
+}
+
+/// One "Provider: reason" line per failed provider, in `order`
+fn failure_lines(errors: &HashMap<Provider, anyhow::Error>, order: &[Provider]) -> Vec<String> {
+    order
+        .iter()
+        .filter_map(|provider| {
+            let error = errors.get(provider)?;
+            Some(format!(
+                "{}: {}",
+                provider.display_name(),
+                providers::short_reason(error)
+            ))
+        })
+        .collect()
+}
+
+/// Put the `quotabar status` text for the shown providers on the clipboard
+fn copy_summary(sections: &Sections, footer: &Footer) {
+    let style = StatusStyle {
+        color: false,
+        ..StatusStyle::from_config(&sections.config.borrow(), false)
+    };
+    let snapshots = sections.snapshots.borrow();
+    let shown: Vec<&UsageSnapshot> = sections
+        .order
+        .borrow()
+        .iter()
+        .filter_map(|p| snapshots.get(p))
+        .collect();
+    if shown.is_empty() {
+        footer.notify("Nothing to copy");
+        return;
+    }
+    let Some(display) = Display::default() else {
+        tracing::warn!("no display to copy the summary to");
+        return;
+    };
+    display
+        .clipboard()
+        .set_text(&status::status_text(shown, &style));
+    footer.notify("Copied");
+}
+
+/// Swap the settings pane in over the sections. Apply saves the config and
+/// brings the open popup in line with it, fetching newly enabled providers
+/// when it can; Cancel just swaps back.
+fn open_settings(
+    stack: &Stack,
+    sections: Rc<Sections>,
+    footer: Rc<Footer>,
+    auto_close_secs: Rc<Cell<u64>>,
+    runtime: Option<tokio::runtime::Handle>,
+) {
+    if let Some(old) = stack.child_by_name("settings") {
+        stack.remove(&old);
+    }
+    let settings = Settings::from_config(&sections.config.borrow());
+    let apply = {
+        let stack = stack.clone();
+        move |settings: &Settings| -> Result<()> {
+            let mut config = Config::load()?;
+            settings.apply(&mut config)?;
+            config.save()?;
+            apply_config(config, &sections, &footer, &auto_close_secs, &runtime);
+            stack.set_visible_child_name("main");
+            Ok(())
+        }
+    };
+    let cancel = {
+        let stack = stack.clone();
+        move || stack.set_visible_child_name("main")
+    };
+    stack.add_named(&settings_pane(settings, apply, cancel), Some("settings"));
+    stack.set_visible_child_name("settings");
+}
+
+/// Switch the open popup to `config`: sections are added, dropped and
+/// re-rendered to match, and newly enabled providers are filled from the
+/// cache and fetched
+fn apply_config(
+    config: Config,
+    sections: &Rc<Sections>,
+    footer: &Rc<Footer>,
+    auto_close_secs: &Cell<u64>,
+    runtime: &Option<tokio::runtime::Handle>,
+) {
+    auto_close_secs.set(config.popup.auto_close_secs);
+    let missing = sections.reconfigure(config);
+    if !missing.is_empty() {
+        if let Some(cache) = CacheState::load().ok().flatten() {
+            for snapshot in missing.iter().filter_map(|p| cache.snapshots.get(p)) {
+                sections.show(snapshot);
+            }
+        }
+        if let Some(runtime) = runtime {
+            start_refresh(runtime.clone(), Rc::clone(sections), Rc::clone(footer));
+        }
+    }
+    footer.set_updated(
+        &sections.snapshots.borrow(),
+        sections.config.borrow().general.time_format,
+    );
+}
+
+/// Apply edits to config.toml while the window is open, once the editor is
+/// done writing. A file that doesn't parse is logged and the current config
+/// stays.
+fn reload_config_while_open(
+    window: &ApplicationWindow,
+    sections: Rc<Sections>,
+    footer: Rc<Footer>,
+    auto_close_secs: Rc<Cell<u64>>,
+    runtime: Option<tokio::runtime::Handle>,
+) {
+    let (tx, rx) = std::sync::mpsc::channel::<()>();
+    let watcher = match configwatch::watch(&Config::config_path(), move || {
+        let _ = tx.send(());
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            tracing::warn!("config changes won't apply until reopened: {:#}", e);
+            return;
+        }
+    };
+    let mut debouncer = configwatch::Debouncer::default();
+    let source = gtk4::glib::timeout_add_local(Duration::from_millis(100), move || {
+        // Owned here so it watches for as long as the timer runs
+        let _ = &watcher;
+        while rx.try_recv().is_ok() {
+            debouncer.changed(std::time::Instant::now());
+        }
+        if debouncer.settled(std::time::Instant::now()) {
+            if let Some(config) = configwatch::reload() {
+                apply_config(config, &sections, &footer, &auto_close_secs, &runtime);
+            }
+        }
+        gtk4::glib::ControlFlow::Continue
+    });
+    let source = RefCell::new(Some(source));
+    window.connect_close_request(move |_| {
+        if let Some(source) = source.take() {
+            source.remove();
+        }
+        gtk4::glib::Propagation::Proceed
+    });
+}
+
+/// The settings form, editing a copy of `settings`. Apply passes it to
+/// `apply` and shows what went wrong under the form if that fails.
+fn settings_pane(
+    settings: Settings,
+    apply: impl Fn(&Settings) -> Result<()> + 'static,
+    cancel: impl Fn() + 'static,
+) -> gtk4::ScrolledWindow {
+    let providers = settings.providers.clone();
+    let settings = Rc::new(RefCell::new(settings));
+    let pane = GtkBox::new(Orientation::Vertical, 6);
+    pane.add_css_class("settings-pane");
+    let heading = |text: &str| {
+        let label = Label::new(Some(text));
+        label.add_css_class("settings-heading");
+        label.set_halign(Align::Start);
+        label
+    };
+
+    pane.append(&heading("Providers"));
+    for &(provider, enabled) in &providers {
+        let toggle = Switch::new();
+        toggle.set_active(enabled);
+        let settings = Rc::clone(&settings);
+        toggle.connect_active_notify(move |toggle| {
+            settings
+                .borrow_mut()
+                .set_enabled(provider, toggle.is_active())
+        });
+        pane.append(&setting_row(provider.display_name(), &toggle));
+    }
+
+    pane.append(&heading("Shown in the bar"));
+    let selected = settings.borrow().selected;
+    let choices = std::iter::once((ProviderChoice::Auto, "Most constrained")).chain(
+        providers
+            .iter()
+            .map(|(p, _)| (ProviderChoice::Specific(*p), p.display_name())),
+    );
+    let mut group: Option<CheckButton> = None;
+    for (choice, label) in choices {
+        let radio = CheckButton::with_label(label);
+        radio.set_group(group.as_ref());
+        radio.set_active(selected.unwrap_or(ProviderChoice::Auto) == choice);
+        let settings = Rc::clone(&settings);
+        radio.connect_toggled(move |radio| {
+            if radio.is_active() {
+                settings.borrow_mut().selected = Some(choice);
+            }
+        });
+        pane.append(&radio);
+        group.get_or_insert(radio);
+    }
+
+    pane.append(&heading("Display"));
+    let spinner = |value: f64, max: f64, step: f64, set: fn(&mut Settings, f64)| {
+        let spin = SpinButton::with_range(0.0, max, step);
+        spin.set_value(value);
+        let settings = Rc::clone(&settings);
+        spin.connect_value_changed(move |spin| set(&mut settings.borrow_mut(), spin.value()));
+        spin
+    };
+    let current = settings.borrow().clone();
+    pane.append(&setting_row(
+        "Warning at %",
+        &spinner(current.warning, 100.0, 1.0, |s, v| s.warning = v),
+    ));
+    pane.append(&setting_row(
+        "Critical at %",
+        &spinner(current.critical, 100.0, 1.0, |s, v| s.critical = v),
+    ));
+    let remaining = Switch::new();
+    remaining.set_active(current.display == PercentDisplay::Remaining);
+    {
+        let settings = Rc::clone(&settings);
+        remaining.connect_active_notify(move |toggle| {
+            settings.borrow_mut().display = if toggle.is_active() {
+                PercentDisplay::Remaining
+            } else {
+                PercentDisplay::Used
+            };
+        });
+    }
+    pane.append(&setting_row("Show % left instead of used", &remaining));
+    pane.append(&setting_row(
+        "Auto-close after (s, 0 = never)",
+        &spinner(current.auto_close_secs as f64, 3600.0, 5.0, |s, v| {
+            s.auto_close_secs = v as u64
+        }),
+    ));
+
+    let error = error_label("");
+    error.set_visible(false);
+    pane.append(&error);
+
+    let buttons = GtkBox::new(Orientation::Horizontal, 8);
+    buttons.set_halign(Align::End);
+    let cancel_button = gtk4::Button::with_label("Cancel");
+    cancel_button.connect_clicked(move |_| cancel());
+    buttons.append(&cancel_button);
+    let apply_button = gtk4::Button::with_label("Apply");
+    apply_button.add_css_class("suggested-action");
+    apply_button.connect_clicked(move |_| {
+        let result = apply(&settings.borrow());
+        match result {
+            Ok(()) => error.set_visible(false),
+            Err(e) => {
+                error.set_text(&format!("{:#}", e));
+                error.set_visible(true);
+            }
+        }
+    });
+    buttons.append(&apply_button);
+    pane.append(&buttons);
+
+    let scrolled = gtk4::ScrolledWindow::new();
+    scrolled.set_hscrollbar_policy(gtk4::PolicyType::Never);
+    scrolled.set_propagate_natural_height(true);
+    scrolled.set_child(Some(&pane));
+    scrolled
+}
+
+/// A label on the left and its control on the right
+fn setting_row(label: &str, control: &impl IsA<gtk4::Widget>) -> GtkBox {
+    let row = GtkBox::new(Orientation::Horizontal, 8);
+    let label = Label::new(Some(label));
+    label.set_hexpand(true);
+    label.set_halign(Align::Start);
+    row.append(&label);
+    control.set_valign(Align::Center);
+    row.append(control);
+    row
+}
+
+/// The provider sections on screen, kept so a refresh can update them in
+/// place. Each section's outer box (with its click handler and `selected`
+/// class) lives for the whole popup; only its contents are rebuilt.
+struct Sections {
+    container: GtkBox,
+    selection: Rc<Selection>,
+    /// Replaced when the settings pane applies changes
+    config: RefCell<Config>,
+    /// Provider order; new sections are inserted to keep it
+    order: RefCell<Vec<Provider>>,
+    /// Single-provider view: email and pace on every window, no selection
+    detailed: bool,
+    clickable: bool,
+    entries: Rc<RefCell<Vec<(Provider, GtkBox)>>>,
+    /// Shown in place of the only section until it has data
+    placeholder: RefCell<Option<Label>>,
+    /// Shown in place of every section while none has data
+    empty: RefCell<Option<EmptyState>>,
+    /// Last fetch errors from the cache, dropped as providers refresh
+    failures: RefCell<HashMap<Provider, FetchFailure>>,
+    /// Providers whose sections show only their header
+    collapsed: Rc<RefCell<Vec<Provider>>>,
+    /// What each section last showed, for re-rendering as time passes
+    snapshots: RefCell<HashMap<Provider, UsageSnapshot>>,
+    /// The last [`SPARKLINE_SPAN`] of history.jsonl, read once on opening;
+    /// empty with sparklines off
+    history: Vec<Sample>,
+    /// Changes since the last opening, kept above the sections
+    banner: Option<GtkBox>,
+    /// A fetch is under way; another isn't started until it's back
+    refreshing: Cell<bool>,
+}
+
+impl Sections {
+    /// Render `snapshot` into its section, creating the section if needed
+    fn show(&self, snapshot: &UsageSnapshot) {
+        self.snapshots
+            .borrow_mut()
+            .insert(snapshot.provider, snapshot.clone());
+        let section = self
+            .section(snapshot.provider)
+            .unwrap_or_else(|| self.add(snapshot.provider));
+        let failures = self.failures.borrow();
+        let (header, revealer) = fill_provider_section(
+            &section,
+            snapshot,
+            failures.get(&snapshot.provider),
+            &self.history,
+            &self.config.borrow(),
+            self.detailed,
+        );
+        if self.clickable {
+            self.make_collapsible(snapshot.provider, &header, &revealer);
+            header.append(&self.selection.button(snapshot.provider));
+        }
+        drop(failures);
+        self.resolve_auto();
+    }
+
+    /// Tell the selection which provider `"auto"` picks from what's shown
+    fn resolve_auto(&self) {
+        let resolved = self
+            .config
+            .borrow()
+            .most_constrained(&self.snapshots.borrow());
+        self.selection.resolve(resolved);
+    }
+
+    /// Render every section again from its last snapshot
+    fn rerender(&self) {
+        let snapshots: Vec<UsageSnapshot> = self.snapshots.borrow().values().cloned().collect();
+        for snapshot in &snapshots {
+            self.show(snapshot);
+        }
+    }
+
+    /// Switch to `config` once the settings pane has saved it: sections of
+    /// providers no longer enabled go and the rest re-render. Returns the
+    /// enabled providers with nothing to show yet.
+    fn reconfigure(&self, config: Config) -> Vec<Provider> {
+        let order = if self.detailed {
+            self.order.borrow().clone()
+        } else {
+            config.popup_providers()
+        };
+        let current = config.general.selected_provider;
+        *self.config.borrow_mut() = config;
+        *self.selection.current.borrow_mut() = current;
+
+        self.entries.borrow_mut().retain(|(provider, section)| {
+            let keep = order.contains(provider);
+            if !keep {
+                self.container.remove(section);
+                self.snapshots.borrow_mut().remove(provider);
+                self.selection.buttons.borrow_mut().remove(provider);
+            }
+            keep
+        });
+        if self
+            .selection
+            .focused
+            .borrow()
+            .is_some_and(|p| !order.contains(&p))
+        {
+            self.selection.set_focus(None);
+        }
+        *self.order.borrow_mut() = order.clone();
+
+        self.rerender();
+        self.resolve_auto();
+        self.selection.mark("selected", self.selection.shown());
+        self.selection.sync_buttons();
+        let snapshots = self.snapshots.borrow();
+        order
+            .into_iter()
+            .filter(|p| !snapshots.contains_key(p))
+            .collect()
+    }
+
+    /// Clicking the header hides or shows the rest of the section, and the
+    /// choice is saved to `[popup] collapsed`
+    fn make_collapsible(&self, provider: Provider, header: &GtkBox, revealer: &Revealer) {
+        let chevron = Label::new(None);
+        chevron.add_css_class("chevron");
+        header.prepend(&chevron);
+        let show = {
+            let revealer = revealer.clone();
+            move |collapsed: bool| {
+                revealer.set_reveal_child(!collapsed);
+                chevron.set_text(if collapsed { "▸" } else { "▾" });
+            }
+        };
+        show(self.collapsed.borrow().contains(&provider));
+
+        let collapsed = Rc::clone(&self.collapsed);
+        let click_controller = gtk4::GestureClick::new();
+        click_controller.connect_released(move |_, _, _, _| {
+            let mut collapsed = collapsed.borrow_mut();
+            show(toggle_collapsed(&mut collapsed, provider));
+            if let Ok(mut config) = Config::load() {
+                config.popup.collapsed = collapsed.clone();
+                let _ = config.save();
+            }
+        });
+        header.add_controller(click_controller);
+    }
+
+    /// Note a failed refresh on a provider's section, if it has one; the
+    /// toast lists every failure either way
+    fn show_error(&self, provider: Provider, reason: &str) {
+        if let Some(label) = self.placeholder.borrow().as_ref() {
+            label.set_text(&format!("Refresh failed: {}", reason));
+            return;
+        }
+        if let Some(empty) = self.empty.borrow().as_ref() {
+            empty.show_error(provider, reason);
+            return;
+        }
+        let Some(body) = self.section(provider).as_ref().and_then(section_body) else {
+            return;
+        };
+        let text = format!("Refresh failed: {}", reason);
+        // Replaces the cached error's row rather than adding a second one
+        match error_row(&body) {
+            Some(label) => label.set_text(&text),
+            None => body.append(&error_label(&text)),
+        }
+    }
+
+    /// Disable the empty state's fetch button while a fetch runs
+    fn set_refreshing(&self, refreshing: bool) {
+        self.refreshing.set(refreshing);
+        if let Some(button) = self.empty.borrow().as_ref().and_then(|e| e.button.as_ref()) {
+            button.set_sensitive(!refreshing);
+            button.set_label(if refreshing {
+                "Fetching…"
+            } else {
+                "Fetch now"
+            });
+        }
+    }
+
+    fn show_placeholder(&self, message: &str) {
+        let label = Label::new(Some(message));
+        label.add_css_class("missing-text");
+        label.set_halign(Align::Start);
+        label.set_wrap(true);
+        self.container.prepend(&label);
+        *self.placeholder.borrow_mut() = Some(label);
+    }
+
+    fn section(&self, provider: Provider) -> Option<GtkBox> {
+        self.entries
+            .borrow()
+            .iter()
+            .find(|(p, _)| *p == provider)
+            .map(|(_, section)| section.clone())
+    }
+
+    /// A new empty section in `order` position
+    fn add(&self, provider: Provider) -> GtkBox {
+        if let Some(label) = self.placeholder.borrow_mut().take() {
+            self.container.remove(&label);
+        }
+        if let Some(empty) = self.empty.borrow_mut().take() {
+            self.container.remove(&empty.root);
+        }
+        let section = GtkBox::new(Orientation::Vertical, 8);
+        section.add_css_class("provider-section");
+        if self.clickable && self.selection.shown() == Some(provider) {
+            section.add_css_class("selected");
+        }
+
+        let order = self.order.borrow();
+        let rank = |p: &Provider| order.iter().position(|o| o == p);
+        let mut entries = self.entries.borrow_mut();
+        let index = entries
+            .iter()
+            .position(|(p, _)| rank(p) > rank(&provider))
+            .unwrap_or(entries.len());
+        match index.checked_sub(1).map(|i| &entries[i].1) {
+            Some(previous) => self.container.insert_child_after(&section, Some(previous)),
+            None => self
+                .container
+                .insert_child_after(&section, self.banner.as_ref()),
+        }
+        entries.insert(index, (provider, section.clone()));
+        section
+    }
+}
+
+/// What the popup shows before anything has been fetched: each enabled
+/// provider with the outcome of its local `doctor` checks, and a button to
+/// fetch when the popup can
+struct EmptyState {
+    root: GtkBox,
+    /// Each provider's mark and detail line, rewritten when a fetch fails
+    rows: HashMap<Provider, (Label, Label)>,
+    button: Option<gtk4::Button>,
+}
+
+impl EmptyState {
+    fn new(providers: &[Provider], can_fetch: bool, config: &Config) -> Self {
+        let root = GtkBox::new(Orientation::Vertical, 8);
+        root.add_css_class("empty-state");
+
+        let title = Label::new(Some("No usage data yet"));
+        title.add_css_class("empty-title");
+        title.set_halign(Align::Start);
+        root.append(&title);
+
+        let text = if can_fetch {
+            "Nothing has been fetched so far. Sections appear here as soon as a fetch finishes."
+        } else {
+            "Nothing has been fetched so far. Run `quotabar fetch` to fetch usage."
+        };
+        let intro = Label::new(Some(text));
+        intro.add_css_class("missing-text");
+        intro.set_halign(Align::Start);
+        intro.set_wrap(true);
+        root.append(&intro);
+
+        if providers.is_empty() {
+            let none = Label::new(Some("No providers are enabled in config.toml"));
+            none.add_css_class("section-error");
+            none.set_halign(Align::Start);
+            root.append(&none);
+        }
+
+        let now = Utc::now();
+        let mut rows = HashMap::new();
+        for &provider in providers {
+            let readiness = Readiness::new(&doctor::local_checks(provider, config, now));
+            let row = GtkBox::new(Orientation::Horizontal, 8);
+            row.add_css_class("empty-provider");
+
+            let mark = Label::new(None);
+            mark.set_valign(Align::Start);
+            row.append(&mark);
+
+            let text = GtkBox::new(Orientation::Vertical, 2);
+            text.set_hexpand(true);
+            let name = Label::new(Some(provider.display_name()));
+            name.add_css_class("provider-name");
+            name.set_halign(Align::Start);
+            text.append(&name);
+            let detail = Label::new(None);
+            detail.add_css_class("empty-detail");
+            detail.set_halign(Align::Start);
+            detail.set_wrap(true);
+            text.append(&detail);
+            if let Some(hint) = readiness.hint {
+                let hint = Label::new(Some(&hint));
+                hint.add_css_class("empty-hint");
+                hint.set_halign(Align::Start);
+                hint.set_wrap(true);
+                text.append(&hint);
+            }
+            row.append(&text);
+            root.append(&row);
+
+            set_mark(&mark, readiness.ok);
+            detail.set_text(&readiness.detail);
+            rows.insert(provider, (mark, detail));
+        }
+
+        let button = can_fetch.then(|| {
+            let button = gtk4::Button::with_label("Fetch now");
+            button.add_css_class("fetch-button");
+            button.set_halign(Align::Start);
+            root.append(&button);
+            button
+        });
+
+        Self { root, rows, button }
+    }
+
+    /// Mark a provider's row, if it has one, as failed with `reason`
+    fn show_error(&self, provider: Provider, reason: &str) {
+        if let Some((mark, detail)) = self.rows.get(&provider) {
+            set_mark(mark, false);
+            detail.set_text(&format!("Fetch failed: {}", reason));
+        }
+    }
+}
+
+fn set_mark(mark: &Label, ok: bool) {
+    mark.set_text(if ok { "✓" } else { "✗" });
+    mark.set_css_classes(&[if ok { "check-ok" } else { "check-fail" }]);
+}
+
+/// A provider's line in the empty state: its first failed check with the
+/// fix, or that its credentials were found
+#[derive(Debug, PartialEq)]
+struct Readiness {
+    ok: bool,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Readiness {
+    fn new(checks: &[Check]) -> Self {
+        match checks.iter().find(|c| !c.ok) {
+            Some(failed) => Self {
+                ok: false,
+                detail: redact(&format!("{}: {}", failed.name, failed.detail)),
+                hint: failed.hint.clone(),
+            },
+            None => Self {
+                ok: true,
+                detail: if checks.is_empty() {
+                    "Ready to fetch".to_string()
+                } else {
+                    "Credentials found".to_string()
+                },
+                hint: None,
+            },
+        }
+    }
+}
+
+/// The bar's provider as picked in the popup, by each header's select
+/// button or the keyboard, and the section the keyboard is on. Only
+/// sections with data exist, so providers without a snapshot are never
+/// focused.
+struct Selection {
+    window: ApplicationWindow,
+    /// Scrolls the sections; keyboard focus keeps its section in view
+    scroller: gtk4::ScrolledWindow,
+    entries: Rc<RefCell<Vec<(Provider, GtkBox)>>>,
+    current: RefCell<Option<ProviderChoice>>,
+    /// What `"auto"` picks right now, whether or not it's chosen
+    resolved: RefCell<Option<Provider>>,
+    focused: RefCell<Option<Provider>>,
+    /// The footer's button for choosing `"auto"`, which names its pick
+    auto: gtk4::Button,
+    /// The radio-style button in each header, replaced as sections refill
+    buttons: RefCell<HashMap<Provider, gtk4::Button>>,
+    /// Pinned open; nothing but the window manager closes it
+    pinned: Rc<Cell<bool>>,
+}
+
+impl Selection {
+    /// A new select button for `provider`'s header
+    fn button(self: &Rc<Self>, provider: Provider) -> gtk4::Button {
+        let button = gtk4::Button::new();
+        button.add_css_class("select-button");
+        button.set_tooltip_text(Some("Show in the bar"));
+        button.set_valign(Align::Center);
+        let selection = Rc::clone(self);
+        button.connect_clicked(move |_| selection.choose(provider.into()));
+        self.buttons.borrow_mut().insert(provider, button.clone());
+        self.sync_buttons();
+        button
+    }
 
-    // Load CSS
-    let css_watcher = load_css(use_mock);
+    fn sync_buttons(&self) {
+        let current = *self.current.borrow();
+        for (provider, button) in self.buttons.borrow().iter() {
+            button.set_label(if current == Some((*provider).into()) {
+                "●"
+            } else {
+                "○"
+            });
+        }
+        let auto = current == Some(ProviderChoice::Auto);
+        self.auto
+            .set_label(&auto_label(auto, *self.resolved.borrow()));
+        if auto {
+            self.auto.add_css_class("active");
+        } else {
+            self.auto.remove_css_class("active");
+        }
+    }
 
-    // Main container
-    let main_box = GtkBox::new(Orientation::Vertical, 0);
-    main_box.add_css_class("popup-container");
+    /// The provider the bar shows, as far as the popup can tell
+    fn shown(&self) -> Option<Provider> {
+        match *self.current.borrow() {
+            Some(ProviderChoice::Auto) => *self.resolved.borrow(),
+            choice => choice.and_then(ProviderChoice::provider),
+        }
+    }
 
-    let selected_provider = Config::load()
-        .ok()
-        .and_then(|config| config.general.selected_provider);
-    let selected_state: Rc<RefCell<Option<Provider>>> = Rc::new(RefCell::new(selected_provider));
-    let sections: Rc<RefCell<Vec<(Provider, GtkBox)>>> = Rc::new(RefCell::new(Vec::new()));
+    /// Record what `"auto"` picks now that the sections changed
+    fn resolve(&self, provider: Option<Provider>) {
+        if self.resolved.replace(provider) == provider {
+            return;
+        }
+        self.mark("selected", self.shown());
+        self.sync_buttons();
+    }
 
-    // Provider sections
-    let providers = [Provider::Claude, Provider::Codex, Provider::OpenCode];
-    for provider in providers {
-        if let Some(snapshot) = snapshots.get(&provider) {
-            let section = create_provider_section(snapshot);
-            if Some(snapshot.provider) == selected_provider {
-                section.add_css_class("selected");
+    /// Make `choice` the bar's provider, saving it to the config;
+    /// choosing the one already selected closes the popup unless pinned
+    fn choose(&self, choice: ProviderChoice) {
+        if !select(&mut self.current.borrow_mut(), choice) {
+            if !self.pinned.get() {
+                self.window.close();
             }
-            sections
-                .borrow_mut()
-                .push((snapshot.provider, section.clone()));
-
-            let section_provider = snapshot.provider;
-            let sections_clone = Rc::clone(&sections);
-            let selected_state = Rc::clone(&selected_state);
-            let window_clone = window.clone();
-            let click_controller = gtk4::GestureClick::new();
-            click_controller.connect_released(move |_, _, _, _| {
-                let mut current = selected_state.borrow_mut();
-                if *current == Some(section_provider) {
-                    window_clone.close();
-                    return;
-                }
-                if let Ok(mut config) = Config::load() {
-                    config.general.selected_provider = Some(section_provider);
-                    let _ = config.save();
-                }
-                *current = Some(section_provider);
-                for (provider, section) in sections_clone.borrow().iter() {
-                    if *provider == section_provider {
-                        section.add_css_class("selected");
-                    } else {
-                        section.remove_css_class("selected");
-                    }
-                }
-            });
-            section.add_controller(click_controller);
-            main_box.append(&section);
+            return;
         }
+        if let Ok(mut config) = Config::load() {
+            config.general.selected_provider = Some(choice);
+            let _ = config.save();
+        }
+        self.mark("selected", self.shown());
+        self.sync_buttons();
     }
 
-    // Footer with last update time
-    let footer = create_footer(&snapshots);
-    main_box.append(&footer);
+    /// Choose the keyboard-focused section; false when there is none
+    fn choose_focused(&self) -> bool {
+        let focused = *self.focused.borrow();
+        focused
+            .inspect(|&provider| self.choose(provider.into()))
+            .is_some()
+    }
 
-    window.set_child(Some(&main_box));
+    fn move_focus(&self, delta: isize) {
+        let order = self.order();
+        let focused = step_focus(&order, *self.focused.borrow(), delta);
+        self.set_focus(focused);
+    }
 
-    // Close on Escape or click outside
-    let window_clone = window.clone();
-    let key_controller = gtk4::EventControllerKey::new();
-    key_controller.connect_key_pressed(move |_, key, _, _| {
-        if key == gtk4::gdk::Key::Escape
-            || key == gtk4::gdk::Key::Return
-            || key == gtk4::gdk::Key::KP_Enter
-        {
-            window_clone.close();
-            gtk4::glib::Propagation::Stop
-        } else {
-            gtk4::glib::Propagation::Proceed
+    /// Focus the `n`th section, counting from 1
+    fn focus_nth(&self, n: usize) {
+        if let Some(&provider) = self.order().get(n - 1) {
+            self.set_focus(Some(provider));
         }
-    });
-    window.add_controller(key_controller);
+    }
 
-    // Track active state for visual feedback
-    let main_box_clone = main_box.clone();
-    window.connect_is_active_notify(move |win| {
-        if win.is_active() {
-            main_box_clone.add_css_class("focused");
-        } else {
-            main_box_clone.remove_css_class("focused");
+    fn set_focus(&self, provider: Option<Provider>) {
+        *self.focused.borrow_mut() = provider;
+        self.mark("keyboard-focus", provider);
+        let entries = self.entries.borrow();
+        let Some((_, section)) = entries.iter().find(|(p, _)| Some(*p) == provider) else {
+            return;
+        };
+        let Some(content) = self.scroller.child() else {
+            return;
+        };
+        if let Some(bounds) = section.compute_bounds(&content) {
+            let adjustment = self.scroller.vadjustment();
+            adjustment.set_value(scroll_into_view(
+                adjustment.value(),
+                adjustment.page_size(),
+                f64::from(bounds.y()),
+                f64::from(bounds.y() + bounds.height()),
+            ));
         }
-    });
+    }
 
-    window.present();
-    if let Some(watcher) = css_watcher {
-        std::mem::forget(watcher);
+    fn order(&self) -> Vec<Provider> {
+        self.entries.borrow().iter().map(|(p, _)| *p).collect()
     }
-    window
-}
 
-fn load_css(use_mock: bool) -> Option<RecommendedWatcher> {
-    let provider = CssProvider::new();
-    let css_path = resolve_css_path(use_mock);
+    /// Put `class` on `provider`'s section and take it off the others
+    fn mark(&self, class: &str, provider: Option<Provider>) {
+        for (p, section) in self.entries.borrow().iter() {
+            if Some(*p) == provider {
+                section.add_css_class(class);
+            } else {
+                section.remove_css_class(class);
+            }
+        }
+    }
+}
 
-    // Try user CSS first, fall back to built-in
-    if let Some(path) = css_path.as_ref().filter(|p| p.exists()) {
-        provider.load_from_path(path);
+/// Scroll position that shows `top..bottom` with as little movement as
+/// possible from `value`; the top wins when it doesn't fit
+fn scroll_into_view(value: f64, page: f64, top: f64, bottom: f64) -> f64 {
+    if top < value || bottom - top > page {
+        top
+    } else if bottom > value + page {
+        bottom - page
     } else {
-        provider.load_from_data(include_str!("popup.css"));
+        value
+    }
+}
+
+/// Collapse `provider` or expand it again; true when now collapsed
+fn toggle_collapsed(collapsed: &mut Vec<Provider>, provider: Provider) -> bool {
+    match collapsed.iter().position(|&p| p == provider) {
+        Some(index) => {
+            collapsed.remove(index);
+            false
+        }
+        None => {
+            collapsed.push(provider);
+            true
+        }
+    }
+}
+
+/// Record `choice` as selected; false when it already was
+fn select(current: &mut Option<ProviderChoice>, choice: ProviderChoice) -> bool {
+    if *current == Some(choice) {
+        return false;
+    }
+    *current = Some(choice);
+    true
+}
+
+/// The footer's auto button: naming the provider it picks while chosen
+fn auto_label(chosen: bool, resolved: Option<Provider>) -> String {
+    match resolved.filter(|_| chosen) {
+        Some(provider) => format!("Auto: {}", provider.display_name()),
+        None => "Auto".to_string(),
     }
+}
+
+/// The section `delta` steps from `focused` in `order`, stopping at either
+/// end. With nothing focused yet, Down starts at the top and Up at the bottom.
+fn step_focus(order: &[Provider], focused: Option<Provider>, delta: isize) -> Option<Provider> {
+    let last = order.len().checked_sub(1)?;
+    let index = match focused.and_then(|f| order.iter().position(|&p| p == f)) {
+        Some(index) => index.saturating_add_signed(delta).min(last),
+        None if delta < 0 => last,
+        None => 0,
+    };
+    order.get(index).copied()
+}
+
+/// Load the theme picked by `[popup] theme`, with `[colors]` defined ahead
+/// of it, or the user's style.css in its place. style.css is watched and
+/// reloaded on change.
+fn load_css(use_mock: bool, config: &Config) -> Option<RecommendedWatcher> {
+    let provider = CssProvider::new();
+    // Only asked when needed; the portal can take a moment to answer
+    let auto = config.popup.theme == PopupTheme::Auto;
+    let shade = theme::resolve(
+        config.popup.theme,
+        auto.then(portal_color_scheme).flatten(),
+        auto && prefers_dark(),
+    );
+    let colors = theme::color_definitions(shade, &config.colors);
+    let css_path = resolve_css_path(use_mock, shade);
+    let load = {
+        let provider = provider.clone();
+        move |path: Option<&Path>| {
+            // The user's file replaces the built-in stylesheet entirely
+            let css = match path.map(std::fs::read_to_string) {
+                Some(Ok(css)) => css,
+                Some(Err(e)) => {
+                    tracing::warn!("using the built-in stylesheet: {}", e);
+                    shade.stylesheet().to_string()
+                }
+                None => shade.stylesheet().to_string(),
+            };
+            provider.load_from_data(&format!("{}{}", colors, css));
+        }
+    };
+    load(css_path.as_deref().filter(|p| p.exists()));
 
     gtk4::style_context_add_provider_for_display(
         &Display::default().expect("Could not get default display"),
@@ -194,7 +1720,6 @@ fn load_css(use_mock: bool) -> Option<RecommendedWatcher> {
         return None;
     }
 
-    let provider_for_reload = provider.clone();
     let reload_path = path.clone();
     let (tx, rx) = std::sync::mpsc::channel::<()>();
     gtk4::glib::timeout_add_local(Duration::from_millis(200), move || {
@@ -203,7 +1728,7 @@ fn load_css(use_mock: bool) -> Option<RecommendedWatcher> {
             changed = true;
         }
         if changed {
-            provider_for_reload.load_from_path(&reload_path);
+            load(Some(&reload_path));
             println!("CSS reloaded");
         }
         gtk4::glib::ControlFlow::Continue
@@ -229,26 +1754,107 @@ fn load_css(use_mock: bool) -> Option<RecommendedWatcher> {
     Some(watcher)
 }
 
-fn resolve_css_path(use_mock: bool) -> Option<PathBuf> {
+fn resolve_css_path(use_mock: bool, shade: theme::Shade) -> Option<PathBuf> {
     if use_mock {
-        return Some(PathBuf::from("src").join("popup.css"));
+        return Some(PathBuf::from("src").join(shade.file_name()));
+    }
+
+    Some(crate::paths::config_dir().path.join("style.css"))
+}
+
+/// The desktop portal's color-scheme: 0 no preference, 1 dark, 2 light.
+/// None without a portal, after half a second at most.
+fn portal_color_scheme() -> Option<u32> {
+    use gtk4::gio;
+    use gtk4::glib::prelude::*;
+    let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).ok()?;
+    let reply = bus
+        .call_sync(
+            Some("org.freedesktop.portal.Desktop"),
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings",
+            "ReadOne",
+            Some(&("org.freedesktop.appearance", "color-scheme").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            500,
+            gio::Cancellable::NONE,
+        )
+        .ok()?;
+    reply.child_value(0).as_variant()?.get::<u32>()
+}
+
+/// GTK's own `gtk-application-prefer-dark-theme`, or a theme named dark
+fn prefers_dark() -> bool {
+    gtk4::Settings::default().is_some_and(|settings| {
+        settings.is_gtk_application_prefer_dark_theme()
+            || settings
+                .gtk_theme_name()
+                .is_some_and(|name| name.to_lowercase().ends_with("-dark"))
+    })
+}
+
+/// The box under a section's header, inside its revealer
+fn section_body(section: &GtkBox) -> Option<GtkBox> {
+    section
+        .last_child()?
+        .downcast::<Revealer>()
+        .ok()?
+        .child()?
+        .downcast()
+        .ok()
+}
+
+/// A section's error row, if it has one
+fn error_row(body: &GtkBox) -> Option<Label> {
+    let mut child = body.first_child();
+    while let Some(widget) = child {
+        if widget.has_css_class("section-error") {
+            return widget.downcast().ok();
+        }
+        child = widget.next_sibling();
     }
+    None
+}
 
-    dirs::config_dir().map(|p| p.join("quotabar").join("style.css"))
+fn error_label(text: &str) -> Label {
+    let label = Label::new(Some(text));
+    label.add_css_class("section-error");
+    label.set_halign(Align::Start);
+    label.set_wrap(true);
+    label
 }
 
-fn create_provider_section(snapshot: &UsageSnapshot) -> GtkBox {
-    let section = GtkBox::new(Orientation::Vertical, 8);
-    section.add_css_class("provider-section");
+/// Replace a provider section's contents with `snapshot`, noting `failure`
+/// when its last fetch went wrong and drawing sparklines from `history`;
+/// `detailed` adds pace for every window rather than just the weekly one.
+/// Returns the header and the revealer holding everything below it.
+fn fill_provider_section(
+    section: &GtkBox,
+    snapshot: &UsageSnapshot,
+    failure: Option<&FetchFailure>,
+    history: &[Sample],
+    config: &Config,
+    detailed: bool,
+) -> (GtkBox, Revealer) {
+    let precision = config.general.precision;
+    let display = config.general.display;
+    let thresholds = config.thresholds_for(snapshot.provider);
+    let time = config.time_style();
+    let rows = config.display_for(snapshot.provider).rows(snapshot);
+    while let Some(child) = section.first_child() {
+        section.remove(&child);
+    }
 
     // Provider header with icon and name
     let header = GtkBox::new(Orientation::Horizontal, 8);
     header.add_css_class("provider-header");
 
-    let icon: gtk4::Widget = if let Some(image) = provider_icon(&snapshot.provider) {
+    let info = config.display_info(snapshot, snapshot.provider.icon());
+    let icon: gtk4::Widget = if let Some(image) = provider_icon(snapshot.provider, config) {
         image.upcast()
     } else {
-        let label = Label::new(Some(snapshot.provider.icon()));
+        let label = Label::new(Some(&info.icon));
         label.add_css_class("provider-icon");
         label.set_halign(Align::Center);
         label.set_valign(Align::Center);
@@ -262,94 +1868,355 @@ fn create_provider_section(snapshot: &UsageSnapshot) -> GtkBox {
     icon_box.append(&icon);
     header.append(&icon_box);
 
-    let name = Label::new(Some(snapshot.provider.display_name()));
+    // The name, with the account email and organization underneath
+    let names = GtkBox::new(Orientation::Vertical, 0);
+    names.set_valign(Align::Center);
+    let name = Label::new(Some(&info.label));
     name.add_css_class("provider-name");
-    name.set_valign(Align::Center);
-    name.set_yalign(0.5);
-    header.append(&name);
+    name.set_halign(Align::Start);
+    names.append(&name);
+    let account = rows
+        .identity
+        .and_then(|i| i.account_line(config.popup.show_email));
+    if let Some(account) = account {
+        let label = Label::new(Some(&account));
+        label.add_css_class("account-label");
+        label.set_halign(Align::Start);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        label.set_tooltip_text(Some(&account));
+        names.append(&label);
+    }
+    header.append(&names);
 
     let right_side = GtkBox::new(Orientation::Horizontal, 6);
     right_side.set_hexpand(true);
     right_side.set_halign(Align::End);
     right_side.set_valign(Align::Center);
 
+    let now = Utc::now();
+    let mut age = timefmt::age(now - snapshot.updated_at);
+    // Another machine's data stays dimmed until a fetch here replaces it
+    match origin::foreign(snapshot, config) {
+        Some(origin) => {
+            age = format!("{} {}", age, origin.annotation());
+            section.add_css_class("foreign");
+        }
+        None => section.remove_css_class("foreign"),
+    }
+    let age_label = Label::new(Some(&age));
+    age_label.add_css_class("age-label");
+    if snapshot.is_stale(config.popup_stale_after(), now) {
+        age_label.add_css_class("stale");
+    }
+    right_side.append(&age_label);
+
     if let Some(url) = snapshot.provider.usage_url() {
         let link = LinkButton::new(url);
         link.set_label("Usage");
         link.add_css_class("usage-link");
+        // Only taken over when a command is set; GTK's handler otherwise
+        let command = config.general.open_url_command.clone();
+        link.connect_activate_link(move |_| match command {
+            Some(ref command) => {
+                open_url(url, Some(command));
+                gtk4::glib::Propagation::Stop
+            }
+            None => gtk4::glib::Propagation::Proceed,
+        });
         right_side.append(&link);
+
+        // Middle-clicking anywhere on the header opens it too
+        let command = config.general.open_url_command.clone();
+        let middle_click = gtk4::GestureClick::new();
+        middle_click.set_button(2);
+        middle_click.connect_released(move |_, _, _, _| open_url(url, command.as_deref()));
+        header.add_controller(middle_click);
     }
 
-    // Plan badge if available
-    if let Some(ref identity) = snapshot.identity {
-        if let Some(ref plan) = identity.plan {
-            let badge = Label::new(Some(plan));
-            badge.add_css_class("plan-badge");
-            right_side.append(&badge);
-        }
+    if let Some(plan) = rows.identity.and_then(|i| i.plan.as_ref()) {
+        let badge = Label::new(Some(plan));
+        badge.add_css_class("plan-badge");
+        right_side.append(&badge);
     }
 
     header.append(&right_side);
     section.append(&header);
 
-    // Primary quota bar (5-hour session)
-    if let Some(ref primary) = snapshot.primary {
-        let bar = create_quota_bar("Current session", primary, None);
-        section.append(&bar);
+    let body = GtkBox::new(Orientation::Vertical, 8);
+    body.add_css_class("provider-body");
+    let revealer = Revealer::new();
+    revealer.set_child(Some(&body));
+    revealer.set_reveal_child(true);
+    section.append(&revealer);
+
+    if let Some(failure) = failure {
+        body.append(&error_label(&format!(
+            "Last fetch failed {}: {}",
+            timefmt::age(now - failure.at),
+            failure.reason
+        )));
     }
 
-    // Secondary quota bar (7-day all models)
-    if let Some(ref secondary) = snapshot.secondary {
-        let pace = pace::compute_pace(snapshot.provider, secondary, Utc::now());
-        let bar = create_quota_bar("Current week (all models)", secondary, pace.as_ref());
-        section.append(&bar);
+    for window in rows.windows {
+        // Session-length windows run out too quickly for pace to say much
+        // outside the detailed view
+        let short = matches!(window.kind, WindowKind::Session | WindowKind::Daily);
+        let pace = (detailed || !short)
+            .then(|| pace::compute_pace(snapshot.provider, window, now))
+            .flatten();
+        let bar = create_quota_bar(
+            &window.name(),
+            window,
+            pace.as_ref(),
+            precision,
+            display,
+            thresholds,
+            &time,
+        );
+        if let Some(index) = history::window_index(snapshot, window) {
+            add_sparkline(&bar, history, snapshot.provider, index, now);
+        }
+        body.append(&bar);
     }
 
-    // Tertiary quota bar (7-day model-specific)
-    if let Some(ref tertiary) = snapshot.tertiary {
-        let bar = create_quota_bar("Current week (Sonnet only)", tertiary, None);
-        section.append(&bar);
+    // Spend against the budget, or just the spend when there is no limit.
+    // Extra usage is titled, under a line saying whether it is being billed.
+    if let Some(cost) = rows.cost {
+        let title = cost.extra_usage.then_some("Extra usage");
+        if cost.extra_usage {
+            let (text, class) = plan_state(snapshot);
+            let state = Label::new(Some(&text));
+            state.add_css_class("plan-state");
+            state.add_css_class(class);
+            state.set_halign(Align::Start);
+            state.set_wrap(true);
+            body.append(&state);
+        }
+        if cost.limit > 0.0 {
+            body.append(&create_cost_bar(
+                cost, title, precision, display, thresholds, &time,
+            ));
+        } else {
+            let cost_box = GtkBox::new(Orientation::Horizontal, 4);
+            cost_box.add_css_class("cost-info");
+
+            let cost_label = Label::new(Some(&cost_text(cost, title)));
+            cost_label.add_css_class("cost-text");
+            cost_box.append(&cost_label);
+
+            body.append(&cost_box);
+        }
     }
 
-    // Cost info
-    if let Some(ref cost) = snapshot.cost {
-        let cost_box = GtkBox::new(Orientation::Horizontal, 4);
-        cost_box.add_css_class("cost-info");
+    if let Some(summary) = rows.busiest_member {
+        let member_label = Label::new(Some(&summary));
+        member_label.add_css_class("cost-text");
+        member_label.set_halign(Align::Start);
+        body.append(&member_label);
+    }
+    (header, revealer)
+}
 
-        let cost_label = Label::new(Some(&format!(
-            "${:.2} / ${:.2} {}",
-            cost.used,
-            cost.limit,
-            cost.period.as_deref().unwrap_or("")
-        )));
-        cost_label.add_css_class("cost-text");
-        cost_box.append(&cost_label);
+/// Samples from the last [`SPARKLINE_SPAN`], or none when the history can't
+/// be read
+fn load_history(now: DateTime<Utc>) -> Vec<Sample> {
+    match history::load(now - SPARKLINE_SPAN) {
+        Ok(samples) => samples,
+        Err(e) => {
+            tracing::warn!("no sparklines: {:#}", e);
+            Vec::new()
+        }
+    }
+}
 
-        section.append(&cost_box);
+/// Append a sparkline of one window's history to its quota bar, `window`
+/// picking it as in [`history::series`]. Falls at window resets get a faint
+/// vertical marker. Nothing is added with too few samples.
+fn add_sparkline(
+    bar: &GtkBox,
+    samples: &[Sample],
+    provider: Provider,
+    window: usize,
+    now: DateTime<Utc>,
+) {
+    let points = history::series(samples, provider, window);
+    if points.len() < SPARKLINE_MIN_SAMPLES {
+        return;
     }
+    let points = history::downsample(&points, SPARKLINE_POINTS);
+    let resets = history::resets(&points);
+    let start = now - SPARKLINE_SPAN;
+    let span = SPARKLINE_SPAN.num_seconds() as f64;
+
+    let area = gtk4::DrawingArea::new();
+    area.set_content_width(120);
+    area.set_content_height(20);
+    area.set_halign(Align::Start);
+    area.add_css_class("sparkline");
+    area.set_draw_func(move |area, cr, width, height| {
+        let (width, height) = (f64::from(width), f64::from(height));
+        let x =
+            |at: DateTime<Utc>| ((at - start).num_seconds() as f64 / span).clamp(0.0, 1.0) * width;
+        let y = |used: f64| 1.0 + (1.0 - used.clamp(0.0, 100.0) / 100.0) * (height - 2.0);
+        // Drawn in the CSS `color` of `.sparkline`
+        let color = area.style_context().color();
+        let (r, g, b) = (
+            f64::from(color.red()),
+            f64::from(color.green()),
+            f64::from(color.blue()),
+        );
+
+        cr.set_line_width(1.0);
+        cr.set_source_rgba(r, g, b, 0.35);
+        for &i in &resets {
+            let at = x(points[i].0).round() + 0.5;
+            cr.move_to(at, 0.0);
+            cr.line_to(at, height);
+        }
+        let _ = cr.stroke();
+
+        cr.set_line_width(1.5);
+        cr.set_source_rgba(r, g, b, f64::from(color.alpha()));
+        for (i, &(at, used)) in points.iter().enumerate() {
+            if i == 0 {
+                cr.move_to(x(at), y(used));
+            } else {
+                cr.line_to(x(at), y(used));
+            }
+        }
+        let _ = cr.stroke();
+    });
+    bar.append(&area);
+}
 
-    section
+/// Open `url` with `[general] open_url_command` if set, otherwise the
+/// desktop's default handler
+fn open_url(url: &str, command: Option<&str>) {
+    let result = match command {
+        Some(command) => browser::open(command, url),
+        None => {
+            gtk4::gio::AppInfo::launch_default_for_uri(url, None::<&gtk4::gio::AppLaunchContext>)
+                .map_err(anyhow::Error::from)
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!("could not open {}: {:#}", url, e);
+    }
+}
+
+/// Whether the plan's included quota is used up, so extra usage is being
+/// billed, and the class for it
+fn plan_state(snapshot: &UsageSnapshot) -> (String, &'static str) {
+    match snapshot.exhausted_window() {
+        Some(window) => (
+            format!("{} exhausted — extra usage active", window.name()),
+            "extra-usage-active",
+        ),
+        None => (
+            "Included usage left — extra usage not in use".to_string(),
+            "extra-usage-idle",
+        ),
+    }
+}
+
+/// "used / limit period", after `title` when there is one
+fn cost_text(cost: &CostSnapshot, title: Option<&str>) -> String {
+    match title {
+        Some(title) => format!("{}: {}", title, cost.describe()),
+        None => cost.describe(),
+    }
+}
+
+/// Like [`create_quota_bar`] for a spending limit, labelled with `title`
+/// when set. Spend past the limit fills the bar, adds `over-budget` and
+/// shows the overage on the right.
+fn create_cost_bar(
+    cost: &CostSnapshot,
+    title: Option<&str>,
+    precision: u8,
+    display: PercentDisplay,
+    thresholds: Thresholds,
+    time: &TimeStyle,
+) -> GtkBox {
+    let container = GtkBox::new(Orientation::Vertical, 4);
+    container.add_css_class("quota-bar-container");
+    container.add_css_class("cost-bar-container");
+
+    let used = cost.used_percent();
+    let shown = match display {
+        PercentDisplay::Used => used.min(100.0),
+        PercentDisplay::Remaining => (100.0 - used).max(0.0),
+    };
+    let bar = ProgressBar::new();
+    bar.set_fraction(shown / 100.0);
+    bar.add_css_class("quota-bar");
+    match thresholds.classify(used) {
+        "normal" => {}
+        class => bar.add_css_class(class),
+    }
+    container.append(&bar);
+
+    let label_row = GtkBox::new(Orientation::Horizontal, 0);
+    let label_widget = Label::new(Some(&cost_text(cost, title)));
+    label_widget.add_css_class("quota-label");
+    label_row.append(&label_widget);
+
+    let right = match cost.overage() {
+        Some(over) => {
+            // Full even when showing what's left, so the overage stands out
+            bar.set_fraction(1.0);
+            bar.add_css_class("over-budget");
+            let label = Label::new(Some(&format!("+{} over", cost.format_amount(over))));
+            label.add_css_class("cost-overage");
+            label
+        }
+        None => {
+            let text = format!("{:.*}% {}", precision as usize, shown, display.suffix());
+            let label = Label::new(Some(&text));
+            label.add_css_class("quota-percent");
+            label
+        }
+    };
+    right.set_hexpand(true);
+    right.set_halign(Align::End);
+    label_row.append(&right);
+    container.append(&label_row);
+
+    if let Some(reset_text) = cost.reset_text(Utc::now(), time) {
+        let reset_label = Label::new(Some(&format!("Resets {}", reset_text)));
+        reset_label.add_css_class("reset-time");
+        reset_label.set_halign(Align::Start);
+        container.append(&reset_label);
+    }
+
+    container
 }
 
 fn create_quota_bar(
     label: &str,
-    window: &crate::models::RateWindow,
+    window: &RateWindow,
     pace: Option<&UsagePace>,
+    precision: u8,
+    display: PercentDisplay,
+    thresholds: Thresholds,
+    time: &TimeStyle,
 ) -> GtkBox {
     let container = GtkBox::new(Orientation::Vertical, 4);
     container.add_css_class("quota-bar-container");
 
-    let used_percent = window.used_percent;
+    let now = Utc::now();
+    let (window, awaiting_refresh) = as_of(window, now);
+    let window = &window;
 
-    // Progress bar (shows used percentage)
+    // Progress bar (filled with used or remaining, per the display setting)
     let bar = ProgressBar::new();
-    bar.set_fraction(used_percent / 100.0);
+    bar.set_fraction(display.percent(window) / 100.0);
     bar.add_css_class("quota-bar");
 
-    if used_percent >= 90.0 {
-        bar.add_css_class("critical");
-    } else if used_percent >= 75.0 {
-        bar.add_css_class("warning");
+    // Colors always follow the used percentage
+    match thresholds.classify(window.used_percent) {
+        "normal" => {}
+        class => bar.add_css_class(class),
     }
 
     container.append(&bar);
@@ -361,7 +2228,7 @@ fn create_quota_bar(
     label_widget.add_css_class("quota-label");
     label_row.append(&label_widget);
 
-    let percent_label = Label::new(Some(&format!("{:.0}% used", used_percent)));
+    let percent_label = Label::new(Some(&display.label(window, precision)));
     percent_label.add_css_class("quota-percent");
     percent_label.set_hexpand(true);
     percent_label.set_halign(Align::End);
@@ -369,81 +2236,382 @@ fn create_quota_bar(
 
     container.append(&label_row);
 
+    // Raw counts behind the percentage, when the provider reports them
+    if let Some(counts) = window.counts_text() {
+        let counts_label = Label::new(Some(&counts));
+        counts_label.add_css_class("quota-counts");
+        counts_label.set_halign(Align::End);
+        container.append(&counts_label);
+    }
+
     // Reset time
-    if let Some(reset_text) = window.reset_description.as_deref() {
-        let reset_label = Label::new(Some(&format!("Resets {}", reset_text)));
+    let reset_text = if awaiting_refresh {
+        Some("Reset, awaiting refresh".to_string())
+    } else {
+        window
+            .reset_text(now, time)
+            .map(|text| format!("Resets {}", text))
+    };
+    if let Some(reset_text) = reset_text {
+        let reset_label = Label::new(Some(&reset_text));
         reset_label.add_css_class("reset-time");
+        if awaiting_refresh {
+            reset_label.add_css_class("awaiting-refresh");
+        }
         reset_label.set_halign(Align::Start);
         container.append(&reset_label);
     }
 
-    // Pace info row
+    // Pace row: reserve/deficit on the left, run-out estimate on the right
     if let Some(pace) = pace {
-        let left = pace::format_pace_left(pace);
-        let right = pace::format_pace_right(pace);
+        let content = PaceRow::new(pace);
+        let row = GtkBox::new(Orientation::Horizontal, 8);
+        for class in &content.classes {
+            row.add_css_class(class);
+        }
+
+        let left = Label::new(Some(&content.left));
+        left.add_css_class("pace-left");
+        left.set_halign(Align::Start);
+        row.append(&left);
+
+        if let Some(ref text) = content.right {
+            let right = Label::new(Some(text));
+            right.add_css_class("pace-right");
+            right.set_hexpand(true);
+            right.set_halign(Align::End);
+            row.append(&right);
+        }
+
+        container.append(&row);
+    }
 
-        let pace_text = match right {
-            Some(ref r) => format!("{} · {}", left, r),
-            None => left,
+    container
+}
+
+/// `window` as it stands at `now`: once its reset has passed it is shown
+/// empty (and flagged) until the next fetch confirms the new usage
+fn as_of(window: &RateWindow, now: DateTime<Utc>) -> (RateWindow, bool) {
+    if window.resets_at.is_some_and(|reset| reset <= now) {
+        let emptied = RateWindow {
+            used_percent: 0.0,
+            used: window.used.map(|_| 0.0),
+            ..window.clone()
         };
+        (emptied, true)
+    } else {
+        (window.clone(), false)
+    }
+}
 
-        let pace_label = Label::new(Some(&pace_text));
-        pace_label.add_css_class("pace-info");
-        pace_label.set_halign(Align::Start);
+/// Text and CSS classes for a window's pace row, kept apart from the
+/// widgets so it can be tested without a display
+#[derive(Debug, PartialEq)]
+struct PaceRow {
+    left: String,
+    right: Option<String>,
+    classes: Vec<&'static str>,
+}
 
-        match pace.stage {
-            pace::PaceStage::SlightlyAhead | pace::PaceStage::Ahead | pace::PaceStage::FarAhead => {
-                pace_label.add_css_class("pace-deficit");
-            }
-            pace::PaceStage::SlightlyBehind
-            | pace::PaceStage::Behind
-            | pace::PaceStage::FarBehind => {
-                pace_label.add_css_class("pace-reserve");
+impl PaceRow {
+    fn new(pace: &UsagePace) -> Self {
+        use pace::PaceStage::*;
+        let tone = match pace.stage {
+            SlightlyAhead | Ahead | FarAhead => "pace-deficit",
+            SlightlyBehind | Behind | FarBehind => "pace-reserve",
+            OnTrack => "pace-ontrack",
+        };
+        PaceRow {
+            left: pace::format_pace_left(pace),
+            right: pace::format_pace_right(pace),
+            classes: vec!["pace-info", tone, pace.stage.css_class()],
+        }
+    }
+}
+
+/// Last update time, plus a spinner and status text for the background
+/// refresh
+struct Footer {
+    root: GtkBox,
+    updated: Label,
+    spinner: gtk4::Spinner,
+    status: Label,
+    /// Copies the summary to the clipboard
+    copy: gtk4::Button,
+    /// Opens the settings pane
+    settings: gtk4::Button,
+    /// Shows the most constrained provider in the bar
+    auto: gtk4::Button,
+    /// Keeps the popup open and refreshing
+    pin: gtk4::ToggleButton,
+    /// Background refresh failures, shown above the footer
+    toast: Toast,
+}
+
+impl Footer {
+    fn new() -> Self {
+        let root = GtkBox::new(Orientation::Horizontal, 8);
+        root.add_css_class("footer");
+
+        let updated = Label::new(None);
+        updated.add_css_class("footer-text");
+        root.append(&updated);
+
+        let spinner = gtk4::Spinner::new();
+        spinner.set_visible(false);
+        spinner.set_hexpand(true);
+        spinner.set_halign(Align::End);
+        root.append(&spinner);
+
+        let status = Label::new(None);
+        status.add_css_class("footer-text");
+        status.add_css_class("footer-status");
+        status.set_halign(Align::End);
+        root.append(&status);
+
+        let auto = gtk4::Button::with_label("Auto");
+        auto.add_css_class("footer-button");
+        auto.add_css_class("auto-button");
+        auto.set_tooltip_text(Some("Show the provider closest to a limit in the bar"));
+        root.append(&auto);
+
+        let copy = gtk4::Button::with_label("⧉");
+        copy.add_css_class("footer-button");
+        copy.set_tooltip_text(Some("Copy summary (Ctrl+C)"));
+        root.append(&copy);
+
+        let pin = gtk4::ToggleButton::with_label("📌");
+        pin.add_css_class("footer-button");
+        pin.set_tooltip_text(Some("Keep open and refreshing (P)"));
+        root.append(&pin);
+
+        let settings = gtk4::Button::with_label("⚙");
+        settings.add_css_class("footer-button");
+        settings.set_tooltip_text(Some("Settings"));
+        root.append(&settings);
+
+        Self {
+            root,
+            updated,
+            spinner,
+            status,
+            copy,
+            settings,
+            auto,
+            pin,
+            toast: Toast::new(),
+        }
+    }
+
+    /// Show the oldest snapshot's time, so one stale provider isn't hidden
+    /// behind fresher ones
+    fn set_updated(&self, snapshots: &HashMap<Provider, UsageSnapshot>, clock: ClockFormat) {
+        let text = match snapshots.values().map(|s| s.updated_at).min() {
+            Some(oldest) => format!(
+                "Oldest data {} ({})",
+                timefmt::clock(&oldest.with_timezone(&chrono::Local), clock),
+                timefmt::age(Utc::now() - oldest)
+            ),
+            None => "No data".to_string(),
+        };
+        self.updated.set_text(&text);
+    }
+
+    fn set_refreshing(&self, refreshing: bool) {
+        self.spinner.set_visible(refreshing);
+        self.spinner.set_spinning(refreshing);
+        self.set_status(if refreshing { "Refreshing…" } else { "" });
+    }
+
+    fn set_status(&self, text: &str) {
+        self.status.set_text(text);
+    }
+
+    /// Show `text` in the status slot for [`NOTICE_DURATION`], unless
+    /// something else replaces it first
+    fn notify(&self, text: &str) {
+        self.set_status(text);
+        let status = self.status.clone();
+        let text = text.to_string();
+        gtk4::glib::timeout_add_local_once(NOTICE_DURATION, move || {
+            if status.text() == text {
+                status.set_text("");
             }
-            pace::PaceStage::OnTrack => {
-                pace_label.add_css_class("pace-ontrack");
+        });
+    }
+}
+
+/// A bar that slides in above the footer listing what failed in a
+/// background refresh, with a retry button. It hides after
+/// [`TOAST_DURATION`] unless the pointer is over it.
+struct Toast {
+    root: Revealer,
+    text: Label,
+    retry: gtk4::Button,
+    hovered: Rc<Cell<bool>>,
+    timer: Rc<RefCell<Option<gtk4::glib::SourceId>>>,
+}
+
+impl Toast {
+    fn new() -> Self {
+        let root = Revealer::new();
+        root.set_transition_type(gtk4::RevealerTransitionType::SlideUp);
+
+        let bar = GtkBox::new(Orientation::Horizontal, 8);
+        bar.add_css_class("error-toast");
+        let text = Label::new(None);
+        text.add_css_class("error-toast-text");
+        text.set_halign(Align::Start);
+        text.set_hexpand(true);
+        text.set_wrap(true);
+        text.set_xalign(0.0);
+        bar.append(&text);
+        let retry = gtk4::Button::with_label("Retry");
+        retry.add_css_class("toast-button");
+        retry.set_valign(Align::Center);
+        bar.append(&retry);
+        root.set_child(Some(&bar));
+
+        let toast = Self {
+            root,
+            text,
+            retry,
+            hovered: Rc::new(Cell::new(false)),
+            timer: Rc::new(RefCell::new(None)),
+        };
+        let hover = gtk4::EventControllerMotion::new();
+        let (hovered, timer) = (Rc::clone(&toast.hovered), Rc::clone(&toast.timer));
+        hover.connect_enter(move |_, _, _| {
+            hovered.set(true);
+            if let Some(source) = timer.borrow_mut().take() {
+                source.remove();
             }
+        });
+        let hovered = Rc::clone(&toast.hovered);
+        let (root, timer) = (toast.root.clone(), Rc::clone(&toast.timer));
+        hover.connect_leave(move |_| {
+            hovered.set(false);
+            hide_later(&root, &timer);
+        });
+        toast.root.add_controller(hover);
+        toast
+    }
+
+    /// Show `text`; the same text again while it is up only restarts the
+    /// wait, so repeated failures don't pile up
+    fn show(&self, text: &str) {
+        if !(self.root.reveals_child() && self.text.text() == text) {
+            self.text.set_text(text);
+            self.root.set_reveal_child(true);
+        }
+        if !self.hovered.get() {
+            hide_later(&self.root, &self.timer);
         }
+    }
 
-        container.append(&pace_label);
+    fn hide(&self) {
+        if let Some(source) = self.timer.borrow_mut().take() {
+            source.remove();
+        }
+        self.root.set_reveal_child(false);
     }
+}
 
-    container
+/// Hide `root` after [`TOAST_DURATION`], replacing any wait under way
+fn hide_later(root: &Revealer, timer: &Rc<RefCell<Option<gtk4::glib::SourceId>>>) {
+    if let Some(source) = timer.borrow_mut().take() {
+        source.remove();
+    }
+    let root = root.clone();
+    let fired = Rc::clone(timer);
+    let source = gtk4::glib::timeout_add_local_once(TOAST_DURATION, move || {
+        // Already gone once it has fired; nothing to remove later
+        fired.borrow_mut().take();
+        root.set_reveal_child(false);
+    });
+    *timer.borrow_mut() = Some(source);
 }
 
-fn create_footer(snapshots: &HashMap<Provider, UsageSnapshot>) -> GtkBox {
-    let footer = GtkBox::new(Orientation::Horizontal, 8);
-    footer.add_css_class("footer");
+/// Side of a provider icon, in pixels
+const ICON_SIZE: i32 = 16;
 
-    // Find most recent update time (convert to local)
-    let last_update = snapshots
-        .values()
-        .map(|s| s.updated_at)
-        .max()
-        .map(|t| t.with_timezone(&chrono::Local).format("%H:%M").to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
+thread_local! {
+    static ICONS: RefCell<IconCache<Pixbuf>> = RefCell::new(IconCache::default());
+}
 
-    let update_label = Label::new(Some(&format!("Updated at {}", last_update)));
-    update_label.add_css_class("footer-text");
-    footer.append(&update_label);
+/// The provider's icon from `[providers.<name>] icon_path` or the built-in
+/// SVG, painted at draw time in the CSS `color` of `.provider-icon` (or
+/// `[popup] icon_color`) so it follows theme and selection changes. None
+/// when there is no image, or the file can't be read or parsed, to fall
+/// back to the glyph.
+fn provider_icon(provider: Provider, config: &Config) -> Option<gtk4::DrawingArea> {
+    let source = match config.icon_path(provider) {
+        Some(path) => IconSource::File(path),
+        None => {
+            icons::builtin_svg(provider)?;
+            IconSource::Builtin(provider)
+        }
+    };
+    let area = gtk4::DrawingArea::new();
+    area.add_css_class("provider-icon");
+    area.set_content_width(ICON_SIZE);
+    area.set_content_height(ICON_SIZE);
+
+    let fixed = config.popup.icon_color.clone();
+    let key = move |area: &gtk4::DrawingArea| {
+        let color = fixed.clone().unwrap_or_else(|| {
+            let color = area.style_context().color();
+            icons::hex_color(color.red(), color.green(), color.blue())
+        });
+        IconKey {
+            source: source.clone(),
+            size: ICON_SIZE,
+            color,
+        }
+    };
+    // Whether the image loads doesn't depend on the color
+    cached_icon(key(&area))?;
+    area.set_draw_func(move |area, cr, width, height| {
+        let Some(pixbuf) = cached_icon(key(area)) else {
+            return;
+        };
+        let x = f64::from(width - pixbuf.width()) / 2.0;
+        let y = f64::from(height - pixbuf.height()) / 2.0;
+        cr.set_source_pixbuf(&pixbuf, x, y);
+        let _ = cr.paint();
+    });
+    Some(area)
+}
 
-    footer
+fn cached_icon(key: IconKey) -> Option<Pixbuf> {
+    ICONS.with(|icons| icons.borrow_mut().get_or_render(key, render_icon))
 }
 
-fn provider_icon(provider: &Provider) -> Option<Image> {
-    let svg_bytes = match provider {
-        Provider::Claude => include_bytes!("../assets/claude.svg").as_slice(),
-        Provider::Codex => include_bytes!("../assets/openai.svg").as_slice(),
-        Provider::OpenCode => include_bytes!("../assets/opencode-logo-dark.svg").as_slice(),
+fn render_icon(key: &IconKey) -> Option<Pixbuf> {
+    let path = match &key.source {
+        IconSource::Builtin(provider) => {
+            let svg = String::from_utf8_lossy(icons::builtin_svg(*provider)?);
+            return render_svg_icon(icons::recolor(&svg, &key.color).as_bytes(), key.size);
+        }
+        IconSource::File(path) => path,
     };
-
-    let svg_string = String::from_utf8_lossy(svg_bytes).replace("currentColor", "white");
-    let size = 16;
-    let pixbuf = render_svg_icon(svg_string.as_bytes(), size)?;
-    let image = Image::from_pixbuf(Some(&pixbuf));
-    image.add_css_class("provider-icon");
-    image.set_pixel_size(size);
-    Some(image)
+    let is_svg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    let icon = if is_svg {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|svg| render_svg_icon(icons::recolor(&svg, &key.color).as_bytes(), key.size))
+    } else {
+        Pixbuf::from_file_at_scale(path, key.size, key.size, true).ok()
+    };
+    if icon.is_none() {
+        tracing::warn!(
+            "could not load icon {}, showing the glyph instead",
+            path.display()
+        );
+    }
+    icon
 }
 
 fn render_svg_icon(svg_bytes: &[u8], size: i32) -> Option<Pixbuf> {
@@ -459,7 +2627,15 @@ fn render_svg_icon(svg_bytes: &[u8], size: i32) -> Option<Pixbuf> {
     let transform = resvg::tiny_skia::Transform::from_scale(scale, scale).post_translate(tx, ty);
     let mut pixmap_mut = pixmap.as_mut();
     resvg::render(&tree, transform, &mut pixmap_mut);
-    let data = pixmap.take();
+    // Pixbufs want straight alpha; tiny-skia premultiplies
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let c = pixel.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
     let row_stride = size * 4;
     Some(Pixbuf::from_mut_slice(
         data,
@@ -471,3 +2647,250 @@ fn render_svg_icon(svg_bytes: &[u8], size: i32) -> Option<Pixbuf> {
         row_stride,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use crate::pace::PaceStage;
+
+    fn make_pace(stage: PaceStage, delta_percent: f64, eta_seconds: Option<f64>) -> UsagePace {
+        UsagePace {
+            stage,
+            delta_percent,
+            expected_used_percent: 50.0,
+            actual_used_percent: 50.0 + delta_percent,
+            eta_seconds,
+            will_last_to_reset: eta_seconds.is_none(),
+        }
+    }
+
+    #[test]
+    fn test_as_of_empties_windows_past_reset() {
+        let now = Utc::now();
+        let mut window = mock_snapshots()[&Provider::Claude].windows[0].clone();
+        window.resets_at = Some(now + chrono::Duration::minutes(1));
+        let (shown, awaiting) = as_of(&window, now);
+        assert!(!awaiting);
+        assert_eq!(shown.used_percent, 72.0);
+
+        let (shown, awaiting) = as_of(&window, now + chrono::Duration::minutes(2));
+        assert!(awaiting);
+        assert_eq!(shown.used_percent, 0.0);
+
+        // Counts reset along with the percentage
+        let counted = window.clone().with_counts(412.0, 500.0, "requests");
+        let (shown, _) = as_of(&counted, now + chrono::Duration::minutes(2));
+        assert_eq!(shown.counts_text().as_deref(), Some("0 / 500 requests"));
+
+        // Without a timestamp there is nothing to count down to
+        window.resets_at = None;
+        assert!(!as_of(&window, now + chrono::Duration::days(30)).1);
+    }
+
+    #[test]
+    fn test_readiness() {
+        let found = Readiness::new(&[
+            Check::pass("Credentials", "/home/me/.claude/.credentials.json parsed"),
+            Check::pass("Token", "valid for 3h"),
+        ]);
+        assert!(found.ok);
+        assert_eq!(found.detail, "Credentials found");
+        assert_eq!(found.hint, None);
+
+        let expired = Readiness::new(&[
+            Check::pass("Credentials", "parsed"),
+            Check::fail("Token", "expired 2d ago", "Run `claude login`"),
+        ]);
+        assert!(!expired.ok);
+        assert_eq!(expired.detail, "Token: expired 2d ago");
+        assert_eq!(expired.hint.as_deref(), Some("Run `claude login`"));
+
+        // Providers without local checks can only be tried
+        let unchecked = Readiness::new(&[]);
+        assert!(unchecked.ok);
+        assert_eq!(unchecked.detail, "Ready to fetch");
+    }
+
+    #[test]
+    fn test_select() {
+        let mut current = None;
+        assert!(select(&mut current, Provider::Codex.into()));
+        assert_eq!(current, Some(Provider::Codex.into()));
+        assert!(select(&mut current, Provider::Claude.into()));
+        assert_eq!(current, Some(Provider::Claude.into()));
+        // Choosing the selected provider again closes instead
+        assert!(!select(&mut current, Provider::Claude.into()));
+        assert_eq!(current, Some(Provider::Claude.into()));
+        assert!(select(&mut current, ProviderChoice::Auto));
+        assert!(!select(&mut current, ProviderChoice::Auto));
+    }
+
+    #[test]
+    fn test_auto_label() {
+        assert_eq!(auto_label(true, Some(Provider::Codex)), "Auto: Codex");
+        // Only named while chosen, and only once something has data
+        assert_eq!(auto_label(false, Some(Provider::Codex)), "Auto");
+        assert_eq!(auto_label(true, None), "Auto");
+    }
+
+    #[test]
+    fn test_toggle_collapsed() {
+        let mut collapsed = vec![Provider::OpenCode];
+        assert!(toggle_collapsed(&mut collapsed, Provider::Codex));
+        assert_eq!(collapsed, [Provider::OpenCode, Provider::Codex]);
+        assert!(!toggle_collapsed(&mut collapsed, Provider::OpenCode));
+        assert_eq!(collapsed, [Provider::Codex]);
+    }
+
+    #[test]
+    fn test_step_focus() {
+        let order = [Provider::Claude, Provider::Codex, Provider::Kiro];
+        assert_eq!(step_focus(&order, None, 1), Some(Provider::Claude));
+        assert_eq!(step_focus(&order, None, -1), Some(Provider::Kiro));
+        assert_eq!(
+            step_focus(&order, Some(Provider::Claude), 1),
+            Some(Provider::Codex)
+        );
+        assert_eq!(
+            step_focus(&order, Some(Provider::Codex), -1),
+            Some(Provider::Claude)
+        );
+        // Stops at the ends
+        assert_eq!(
+            step_focus(&order, Some(Provider::Kiro), 1),
+            Some(Provider::Kiro)
+        );
+        assert_eq!(
+            step_focus(&order, Some(Provider::Claude), -1),
+            Some(Provider::Claude)
+        );
+        // A focused provider that lost its section starts over
+        assert_eq!(
+            step_focus(&order, Some(Provider::Windsurf), 1),
+            Some(Provider::Claude)
+        );
+        assert_eq!(step_focus(&[], None, 1), None);
+    }
+
+    #[test]
+    fn test_scroll_into_view() {
+        // Already visible: stays put
+        assert_eq!(scroll_into_view(100.0, 300.0, 150.0, 250.0), 100.0);
+        // Above or below the page
+        assert_eq!(scroll_into_view(100.0, 300.0, 40.0, 140.0), 40.0);
+        assert_eq!(scroll_into_view(100.0, 300.0, 350.0, 450.0), 150.0);
+        // Taller than the page: its top
+        assert_eq!(scroll_into_view(0.0, 300.0, 350.0, 800.0), 350.0);
+    }
+
+    #[test]
+    fn test_scroll_height() {
+        assert_eq!(scroll_height(None, 1080, 40), 824);
+        assert_eq!(scroll_height(Some(600), 1080, 40), 560);
+        assert_eq!(scroll_height(Some(50), 1080, 40), 60);
+    }
+
+    #[test]
+    fn test_plan_state() {
+        let mut snapshot = mock_snapshots().remove(&Provider::Claude).unwrap();
+        assert_eq!(
+            plan_state(&snapshot),
+            (
+                "Included usage left — extra usage not in use".to_string(),
+                "extra-usage-idle"
+            )
+        );
+        snapshot.windows[1].used_percent = 100.0;
+        assert_eq!(
+            plan_state(&snapshot),
+            (
+                "Weekly limit exhausted — extra usage active".to_string(),
+                "extra-usage-active"
+            )
+        );
+        let cost = snapshot.cost.as_ref().unwrap();
+        assert!(cost_text(cost, Some("Extra usage")).starts_with("Extra usage: $42.50 / $100.00"));
+    }
+
+    #[test]
+    fn test_failure_lines() {
+        let mut errors = HashMap::new();
+        errors.insert(
+            Provider::Codex,
+            anyhow::Error::new(ProviderError::Unauthorized("token expired".to_string())),
+        );
+        errors.insert(
+            Provider::Claude,
+            anyhow::anyhow!("HTTP 500").context("Claude usage request failed"),
+        );
+        errors.insert(
+            Provider::Kiro,
+            anyhow::Error::new(ProviderError::Unsupported("not built".to_string())),
+        );
+        let order = [Provider::Claude, Provider::OpenCode, Provider::Codex];
+        assert_eq!(
+            failure_lines(&errors, &order),
+            [
+                "Claude: Claude usage request failed",
+                "Codex: token expired"
+            ]
+        );
+        assert!(failure_lines(&HashMap::new(), &order).is_empty());
+    }
+
+    #[test]
+    fn test_choose_surface() {
+        assert_eq!(choose_surface(false, true), Surface::LayerShell);
+        assert_eq!(choose_surface(true, true), Surface::Window);
+        assert_eq!(choose_surface(false, false), Surface::Window);
+    }
+
+    #[test]
+    fn test_anchor_edges() {
+        assert_eq!(
+            anchor_edges(PopupAnchor::TopRight),
+            [Edge::Top, Edge::Right]
+        );
+        assert_eq!(
+            anchor_edges(PopupAnchor::BottomLeft),
+            [Edge::Bottom, Edge::Left]
+        );
+        assert!(anchor_edges(PopupAnchor::Center).is_empty());
+    }
+
+    #[test]
+    fn test_pace_row_reserve() {
+        let row = PaceRow::new(&make_pace(PaceStage::SlightlyBehind, -8.0, None));
+        assert_eq!(
+            row,
+            PaceRow {
+                left: "8% in reserve".to_string(),
+                right: Some("Lasts until reset".to_string()),
+                classes: vec!["pace-info", "pace-reserve", "pace-slightly-behind"],
+            }
+        );
+    }
+
+    #[test]
+    fn test_pace_row_far_ahead() {
+        let eta = (28 * 3600) as f64;
+        let row = PaceRow::new(&make_pace(PaceStage::FarAhead, 30.0, Some(eta)));
+        assert_eq!(row.left, "30% in deficit");
+        assert_eq!(row.right.as_deref(), Some("Runs out in 1d 4h"));
+        assert_eq!(
+            row.classes,
+            vec!["pace-info", "pace-deficit", "pace-far-ahead"]
+        );
+    }
+
+    #[test]
+    fn test_pace_row_on_track() {
+        let row = PaceRow::new(&make_pace(PaceStage::OnTrack, 0.5, None));
+        assert_eq!(row.left, "On pace");
+        assert_eq!(
+            row.classes,
+            vec!["pace-info", "pace-ontrack", "pace-on-track"]
+        );
+    }
+}
@@ -1,12 +1,26 @@
-use crate::models::{CostSnapshot, IdentitySnapshot, Provider, RateWindow, UsageSnapshot};
-use crate::providers::ProviderFetcher;
+use crate::config::{Config, ProviderConfig};
+use crate::doctor::{self, Check};
+use crate::logging::Redacted;
+use crate::models::{
+    CostSnapshot, Granularity, IdentitySnapshot, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::paths::expand_home;
+use crate::providers::{
+    http_client, normalize_plan_name, CredentialSource, JsonRecorded, ProviderError,
+    ProviderFetcher, SendLogged,
+};
+use crate::ratelimit::RateLimitInfo;
+use crate::timefmt::{self, RelativeStyle};
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const API_URL: &str = "https://api.anthropic.com/api/oauth/usage";
+const PROFILE_URL: &str = "https://api.anthropic.com/api/oauth/profile";
+/// Plan label when neither credentials, usage nor profile name one
+const UNKNOWN_PLAN: &str = "Unknown";
 const USER_AGENT: &str = "quotabar";
 
 /// Claude Code credentials from ~/.claude/.credentials.json
@@ -19,9 +33,9 @@ struct CredentialsFile {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct OAuthCredentials {
-    access_token: String,
+    access_token: Redacted<String>,
     #[allow(dead_code)]
-    refresh_token: Option<String>,
+    refresh_token: Option<Redacted<String>>,
     /// Unix timestamp in milliseconds
     expires_at: Option<i64>,
     #[allow(dead_code)]
@@ -41,23 +55,20 @@ impl OAuthCredentials {
     }
 
     fn plan_name(&self) -> Option<String> {
-        self.rate_limit_tier.as_ref().map(|tier| {
-            let lower = tier.to_lowercase();
-            if lower.contains("enterprise") {
-                "Enterprise"
-            } else if lower.contains("team") {
-                "Team"
-            } else if lower.contains("max") {
-                "Max"
-            } else if lower.contains("pro") {
-                "Pro"
-            } else if lower.contains("free") {
-                "Free"
-            } else {
-                return tier.clone();
-            }
-            .to_string()
-        })
+        self.rate_limit_tier
+            .as_deref()
+            .and_then(normalize_plan_name)
+    }
+
+    /// A bare token from `token_env`, with no expiry or tier to go on
+    fn from_token(token: &str) -> Self {
+        Self {
+            access_token: token.to_string().into(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: None,
+            rate_limit_tier: None,
+        }
     }
 }
 
@@ -66,11 +77,70 @@ impl OAuthCredentials {
 struct UsageResponse {
     five_hour: Option<RateWindowResponse>,
     seven_day: Option<RateWindowResponse>,
-    #[allow(dead_code)]
     seven_day_oauth_apps: Option<RateWindowResponse>,
     seven_day_opus: Option<RateWindowResponse>,
     seven_day_sonnet: Option<RateWindowResponse>,
     extra_usage: Option<ExtraUsageResponse>,
+    /// Not always present; used when credentials lack `rateLimitTier`
+    rate_limit_tier: Option<String>,
+    subscription_type: Option<String>,
+}
+
+impl UsageResponse {
+    fn plan_name(&self) -> Option<String> {
+        self.rate_limit_tier
+            .as_deref()
+            .and_then(normalize_plan_name)
+            .or_else(|| {
+                self.subscription_type
+                    .as_deref()
+                    .and_then(normalize_plan_name)
+            })
+    }
+}
+
+/// API response from /api/oauth/profile
+#[derive(Debug, Default, Deserialize)]
+struct ProfileResponse {
+    account: Option<ProfileAccount>,
+    organization: Option<ProfileOrganization>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileAccount {
+    #[serde(default)]
+    has_claude_max: bool,
+    #[serde(default)]
+    has_claude_pro: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileOrganization {
+    rate_limit_tier: Option<String>,
+    organization_type: Option<String>,
+}
+
+impl ProfileResponse {
+    fn plan_name(&self) -> Option<String> {
+        let org = self.organization.as_ref();
+        org.and_then(|o| o.rate_limit_tier.as_deref())
+            .and_then(normalize_plan_name)
+            .or_else(|| {
+                org.and_then(|o| o.organization_type.as_deref())
+                    .and_then(normalize_plan_name)
+            })
+            .or_else(|| {
+                self.account.as_ref().and_then(|a| {
+                    if a.has_claude_max {
+                        Some("Max".to_string())
+                    } else if a.has_claude_pro {
+                        Some("Pro".to_string())
+                    } else {
+                        None
+                    }
+                })
+            })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -91,43 +161,28 @@ struct ExtraUsageResponse {
 
 pub struct ClaudeProvider {
     client: reqwest::Client,
+    credentials: CredentialSource,
+    /// Where raw responses are kept, with `debug_responses` on
+    raw_dir: Option<PathBuf>,
 }
 
 impl ClaudeProvider {
-    pub fn new() -> Self {
+    pub fn from_config(config: &Config) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: http_client(),
+            credentials: credential_source(config.providers.get(&Provider::Claude), |var| {
+                std::env::var(var).ok()
+            }),
+            raw_dir: config.debug_responses_dir(),
         }
     }
 
-    fn credentials_path() -> PathBuf {
-        dirs::home_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(".claude")
-            .join(".credentials.json")
+    fn load_credentials(&self) -> Result<OAuthCredentials> {
+        self.credentials
+            .load(OAuthCredentials::from_token, load_credentials_from)
     }
 
-    fn load_credentials() -> Result<OAuthCredentials> {
-        let path = Self::credentials_path();
-        if !path.exists() {
-            return Err(anyhow!(
-                "Claude credentials not found at {}. Run `claude login` first.",
-                path.display()
-            ));
-        }
-
-        let content = std::fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-
-        let creds: CredentialsFile =
-            serde_json::from_str(&content).with_context(|| "Failed to parse credentials JSON")?;
-
-        creds
-            .claude_ai_oauth
-            .ok_or_else(|| anyhow!("No OAuth credentials found. Run `claude login` first."))
-    }
-
-    async fn fetch_usage(&self, token: &str) -> Result<UsageResponse> {
+    async fn fetch_usage(&self, token: &str) -> Result<(UsageResponse, Option<RateLimitInfo>)> {
         let response = self
             .client
             .get(API_URL)
@@ -136,43 +191,68 @@ impl ClaudeProvider {
             .header("Content-Type", "application/json")
             .header("anthropic-beta", "oauth-2025-04-20")
             .header("User-Agent", USER_AGENT)
-            .send()
+            .send_logged()
             .await
             .context("Failed to connect to Anthropic API")?;
 
+        let rate_limit = RateLimitInfo::from_headers(response.headers(), Utc::now());
         let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = crate::ratelimit::retry_after(response.headers(), Utc::now());
+            return Err(ProviderError::RateLimited { until }.into());
+        }
         if status == reqwest::StatusCode::UNAUTHORIZED {
-            return Err(anyhow!(
-                "Claude OAuth token expired or invalid. Run `claude login` to refresh."
-            ));
+            return Err(ProviderError::Unauthorized(
+                "Claude OAuth token expired or invalid. Run `claude login` to refresh.".to_string(),
+            )
+            .into());
         }
         if status == reqwest::StatusCode::FORBIDDEN {
-            return Err(anyhow!(
+            return Err(ProviderError::Unauthorized(
                 "Claude OAuth token missing required scope. Run `claude login` to refresh."
-            ));
+                    .to_string(),
+            )
+            .into());
         }
         if !status.is_success() {
             let body = response.text().await.unwrap_or_default();
             return Err(anyhow!("Anthropic API error ({}): {}", status, body));
         }
 
-        response
-            .json()
+        let usage = response
+            .json_recorded("claude", self.raw_dir.as_deref())
             .await
-            .context("Failed to parse usage response")
+            .context("Failed to parse usage response")?;
+        Ok((usage, rate_limit))
     }
-}
 
-impl Default for ClaudeProvider {
-    fn default() -> Self {
-        Self::new()
+    async fn fetch_profile(&self, token: &str) -> Result<ProfileResponse> {
+        let response = self
+            .client
+            .get(PROFILE_URL)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Accept", "application/json")
+            .header("anthropic-beta", "oauth-2025-04-20")
+            .header("User-Agent", USER_AGENT)
+            .send_logged()
+            .await
+            .context("Failed to connect to Anthropic API")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Anthropic profile API error ({})", status));
+        }
+        response
+            .json_recorded("claude-profile", self.raw_dir.as_deref())
+            .await
+            .context("Failed to parse profile response")
     }
 }
 
 #[async_trait]
 impl ProviderFetcher for ClaudeProvider {
     async fn fetch(&self) -> Result<UsageSnapshot> {
-        let creds = Self::load_credentials()?;
+        let creds = self.load_credentials()?;
 
         if creds.is_expired() {
             return Err(anyhow!(
@@ -180,45 +260,43 @@ impl ProviderFetcher for ClaudeProvider {
             ));
         }
 
-        let usage = self.fetch_usage(&creds.access_token).await?;
+        let (usage, rate_limit) = self.fetch_usage(creds.access_token.expose()).await?;
+        let plan = resolve_plan(creds.plan_name(), usage.plan_name(), || async {
+            self.fetch_profile(creds.access_token.expose())
+                .await
+                .ok()
+                .and_then(|p| p.plan_name())
+        })
+        .await;
         let now = Utc::now();
 
-        // Primary: 5-hour session window
-        let primary = usage.five_hour.map(|w| RateWindow {
-            used_percent: w.utilization,
-            window_minutes: Some(300),
-            resets_at: w.resets_at.as_ref().and_then(|s| parse_iso8601(s)),
-            reset_description: w
-                .resets_at
-                .as_ref()
-                .and_then(|s| parse_iso8601(s))
-                .map(|dt| format_reset_time(dt, now)),
-        });
-
-        // Secondary: 7-day window
-        let secondary = usage.seven_day.map(|w| RateWindow {
-            used_percent: w.utilization,
-            window_minutes: Some(10080),
-            resets_at: w.resets_at.as_ref().and_then(|s| parse_iso8601(s)),
-            reset_description: w
-                .resets_at
-                .as_ref()
-                .and_then(|s| parse_iso8601(s))
-                .map(|dt| format_reset_time(dt, now)),
-        });
-
-        // Tertiary: Model-specific (prefer Sonnet, fallback to Opus)
-        let model_window = usage.seven_day_sonnet.or(usage.seven_day_opus);
-        let tertiary = model_window.map(|w| RateWindow {
-            used_percent: w.utilization,
-            window_minutes: Some(10080),
-            resets_at: w.resets_at.as_ref().and_then(|s| parse_iso8601(s)),
-            reset_description: w
-                .resets_at
-                .as_ref()
-                .and_then(|s| parse_iso8601(s))
-                .map(|dt| format_reset_time(dt, now)),
-        });
+        // The 5-hour session, the 7-day window, the model-specific weeks,
+        // and the separate 7-day pool for third-party OAuth apps
+        let model = |name: &str| WindowKind::Model(name.to_string());
+        let windows = [
+            (usage.five_hour, WindowKind::Session, None),
+            (usage.seven_day, WindowKind::Weekly, None),
+            (
+                usage.seven_day_sonnet,
+                model("sonnet"),
+                Some("Weekly — Sonnet"),
+            ),
+            (usage.seven_day_opus, model("opus"), Some("Weekly — Opus")),
+            (
+                usage.seven_day_oauth_apps,
+                WindowKind::Other("oauth_apps".to_string()),
+                Some("Weekly — OAuth apps"),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(window, kind, label)| {
+            let window = make_window(window?, kind, now);
+            Some(match label {
+                Some(label) => window.with_label(label),
+                None => window,
+            })
+        })
+        .collect();
 
         // Cost: Extra usage (credits in cents)
         let cost = usage.extra_usage.and_then(|e| {
@@ -245,20 +323,23 @@ impl ProviderFetcher for ClaudeProvider {
                 currency_code: e.currency.unwrap_or_else(|| "USD".to_string()),
                 period: Some("Monthly".to_string()),
                 resets_at: None,
+                extra_usage: true,
             })
         });
 
         Ok(UsageSnapshot {
             provider: Provider::Claude,
-            primary,
-            secondary,
-            tertiary,
+            windows,
             cost,
             identity: Some(IdentitySnapshot {
                 email: None,
-                plan: creds.plan_name(),
+                plan: Some(plan),
                 organization: None,
+                account_id: None,
             }),
+            rate_limit,
+            busiest_member: None,
+            origin: None,
             updated_at: now,
         })
     }
@@ -266,6 +347,131 @@ impl ProviderFetcher for ClaudeProvider {
     fn name(&self) -> &'static str {
         "Claude"
     }
+
+    fn diagnose(&self, now: DateTime<Utc>) -> Vec<Check> {
+        match self.credentials {
+            CredentialSource::Env { ref var, .. } => CredentialSource::env_checks(var),
+            CredentialSource::File { ref path, .. } => credential_checks(path, now),
+        }
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(API_URL.to_string())
+    }
+}
+
+/// [`super::credentials_id`] for Claude
+pub fn credentials_id(config: &Config) -> String {
+    credential_source(config.providers.get(&Provider::Claude), |var| {
+        std::env::var(var).ok()
+    })
+    .id()
+}
+
+/// `token_env`, then `credentials_path`, then ~/.claude/.credentials.json
+fn credential_source(
+    config: Option<&ProviderConfig>,
+    env: impl Fn(&str) -> Option<String>,
+) -> CredentialSource {
+    if let Some(source) = CredentialSource::from_token_env(config, env) {
+        return source;
+    }
+    if let Some(path) = config
+        .and_then(|c| c.credentials_path.as_deref())
+        .and_then(expand_home)
+    {
+        return CredentialSource::File {
+            path,
+            from: "[providers.claude] credentials_path",
+        };
+    }
+    CredentialSource::File {
+        path: dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".claude")
+            .join(".credentials.json"),
+        from: "default",
+    }
+}
+
+fn load_credentials_from(path: &Path) -> Result<OAuthCredentials> {
+    if !path.exists() {
+        return Err(anyhow!(
+            "Claude credentials not found at {}. Run `claude login` first.",
+            path.display()
+        ));
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let creds: CredentialsFile =
+        serde_json::from_str(&content).with_context(|| "Failed to parse credentials JSON")?;
+
+    creds
+        .claude_ai_oauth
+        .ok_or_else(|| anyhow!("No OAuth credentials found. Run `claude login` first."))
+}
+
+fn credential_checks(path: &Path, now: DateTime<Utc>) -> Vec<Check> {
+    const HINT: &str = "Run `claude login`";
+    let creds = match load_credentials_from(path) {
+        Ok(creds) => creds,
+        Err(e) => return vec![Check::fail("Credentials", format!("{:#}", e), HINT)],
+    };
+    let token = match creds.expires_at.and_then(DateTime::from_timestamp_millis) {
+        Some(expires_at) if expires_at <= now => Check::fail(
+            "Token",
+            format!("expired {} ago", doctor::approx(now - expires_at)),
+            "Run `claude` once to refresh the token, or `claude login`",
+        ),
+        Some(expires_at) => Check::pass(
+            "Token",
+            format!("valid for {}", doctor::approx(expires_at - now)),
+        ),
+        None => Check::pass("Token", "no expiry recorded"),
+    };
+    vec![
+        Check::pass("Credentials", format!("{} parsed", path.display())),
+        token,
+    ]
+}
+
+/// Plan precedence: credentials tier, then the usage response, then the
+/// profile endpoint (only queried when both are missing), then "Unknown"
+async fn resolve_plan<F, Fut>(
+    credentials: Option<String>,
+    usage: Option<String>,
+    profile: F,
+) -> String
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Option<String>>,
+{
+    if let Some(plan) = credentials.or(usage) {
+        return plan;
+    }
+    profile().await.unwrap_or_else(|| UNKNOWN_PLAN.to_string())
+}
+
+fn make_window(window: RateWindowResponse, kind: WindowKind, now: DateTime<Utc>) -> RateWindow {
+    let resets_at = window.resets_at.as_deref().and_then(parse_iso8601);
+    let minutes = match kind {
+        WindowKind::Session => 300,
+        _ => 10080,
+    };
+    RateWindow {
+        kind,
+        used_percent: window.utilization,
+        window_minutes: Some(minutes),
+        resets_at,
+        reset_description: resets_at.map(|dt| timefmt::relative(dt, now, RelativeStyle::Long)),
+        granularity: Granularity::Unknown,
+        label: None,
+        used: None,
+        limit: None,
+        unit: None,
+    }
 }
 
 fn parse_iso8601(s: &str) -> Option<DateTime<Utc>> {
@@ -280,31 +486,94 @@ fn parse_iso8601(s: &str) -> Option<DateTime<Utc>> {
         })
 }
 
-fn format_reset_time(reset: DateTime<Utc>, now: DateTime<Utc>) -> String {
-    let duration = reset.signed_duration_since(now);
-    let hours = duration.num_hours();
-    let minutes = duration.num_minutes() % 60;
-
-    if hours <= 0 && minutes <= 0 {
-        "now".to_string()
-    } else if hours < 1 {
-        format!("in {} min", minutes.max(1))
-    } else if hours < 24 {
-        format!("in {}h", hours)
-    } else {
-        let days = hours / 24;
-        if days == 1 {
-            "in 1 day".to_string()
-        } else {
-            format!("in {} days", days)
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_credential_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".credentials.json");
+        let now = DateTime::from_timestamp_millis(1_800_000_000_000).unwrap();
+
+        let checks = credential_checks(&path, now);
+        assert_eq!(checks.len(), 1);
+        assert!(!checks[0].ok);
+
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(!credential_checks(&path, now)[0].ok);
+
+        let write = |expires_at: i64| {
+            let json = format!(
+                r#"{{"claudeAiOauth": {{"accessToken": "sk-ant-oat01-secret", "expiresAt": {}}}}}"#,
+                expires_at
+            );
+            std::fs::write(&path, json).unwrap();
+        };
+        write((now + chrono::Duration::hours(3)).timestamp_millis());
+        let checks = credential_checks(&path, now);
+        assert!(checks.iter().all(|c| c.ok), "{:?}", checks);
+        assert_eq!(checks[1].detail, "valid for 3h");
+
+        write((now - chrono::Duration::minutes(90)).timestamp_millis());
+        let checks = credential_checks(&path, now);
+        assert!(checks[0].ok);
+        assert!(!checks[1].ok);
+        assert_eq!(checks[1].detail, "expired 1h 30m ago");
+        assert!(checks.iter().all(|c| !c.detail.contains("secret")));
+    }
+
+    #[test]
+    fn test_credential_source_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        std::fs::write(&path, r#"{"claudeAiOauth": {"accessToken": "from-file"}}"#).unwrap();
+        let config = ProviderConfig {
+            credentials_path: Some(path.display().to_string()),
+            token_env: Some("MY_TOKEN".to_string()),
+            ..Default::default()
+        };
+        let load = |source: CredentialSource| {
+            source.load(OAuthCredentials::from_token, load_credentials_from)
+        };
+
+        let source = credential_source(Some(&config), |var| {
+            (var == "MY_TOKEN").then(|| "from-env".to_string())
+        });
+        let creds = load(source).unwrap();
+        assert_eq!(creds.access_token.expose(), "from-env");
+        assert!(!creds.is_expired());
+
+        let source = credential_source(Some(&config), |_| Some(String::new()));
+        assert_eq!(
+            source,
+            CredentialSource::File {
+                path: path.clone(),
+                from: "[providers.claude] credentials_path",
+            }
+        );
+        assert_eq!(load(source).unwrap().access_token.expose(), "from-file");
+
+        let source = credential_source(None, |_| Some("unused".to_string()));
+        assert!(matches!(
+            source,
+            CredentialSource::File { ref path, from: "default" }
+                if path.ends_with(".claude/.credentials.json")
+        ));
+
+        // Errors say which setting pointed at the file
+        let missing = ProviderConfig {
+            credentials_path: Some(dir.path().join("missing.json").display().to_string()),
+            ..Default::default()
+        };
+        let error = format!(
+            "{:#}",
+            load(credential_source(Some(&missing), |_| None)).unwrap_err()
+        );
+        assert!(error.contains("missing.json"), "{}", error);
+        assert!(error.contains("credentials_path"), "{}", error);
+    }
+
     #[test]
     fn test_parse_iso8601() {
         let dt = parse_iso8601("2024-01-15T10:30:00.000Z");
@@ -314,16 +583,55 @@ mod tests {
         assert!(dt.is_some());
     }
 
-    #[test]
-    fn test_format_reset_time() {
-        let now = Utc::now();
-        let reset = now + chrono::Duration::hours(5);
-        assert_eq!(format_reset_time(reset, now), "in 5h");
+    fn profile(json: &str) -> Option<String> {
+        serde_json::from_str::<ProfileResponse>(json)
+            .unwrap()
+            .plan_name()
+    }
 
-        let reset = now + chrono::Duration::minutes(30);
-        assert_eq!(format_reset_time(reset, now), "in 30 min");
+    #[tokio::test]
+    async fn test_plan_prefers_credentials_tier() {
+        let plan = resolve_plan(Some("Max".into()), Some("Pro".into()), || async {
+            panic!("profile should not be queried")
+        })
+        .await;
+        assert_eq!(plan, "Max");
+    }
 
-        let reset = now + chrono::Duration::days(3);
-        assert_eq!(format_reset_time(reset, now), "in 3 days");
+    #[tokio::test]
+    async fn test_plan_falls_back_to_usage_response() {
+        let usage: UsageResponse =
+            serde_json::from_str(r#"{"rate_limit_tier": "default_claude_max_5x"}"#).unwrap();
+        let plan = resolve_plan(None, usage.plan_name(), || async {
+            panic!("profile should not be queried")
+        })
+        .await;
+        assert_eq!(plan, "Max");
+    }
+
+    #[tokio::test]
+    async fn test_plan_falls_back_to_profile() {
+        let from_profile = profile(r#"{"organization": {"organization_type": "claude_team"}}"#);
+        let plan = resolve_plan(None, None, || async { from_profile }).await;
+        assert_eq!(plan, "Team");
+    }
+
+    #[tokio::test]
+    async fn test_plan_unknown_as_last_resort() {
+        let plan = resolve_plan(None, None, || async { None }).await;
+        assert_eq!(plan, UNKNOWN_PLAN);
+    }
+
+    #[test]
+    fn test_profile_plan_sources() {
+        assert_eq!(
+            profile(r#"{"organization": {"rate_limit_tier": "default_claude_max_20x", "organization_type": "claude_pro"}}"#).as_deref(),
+            Some("Max")
+        );
+        assert_eq!(
+            profile(r#"{"account": {"has_claude_pro": true}}"#).as_deref(),
+            Some("Pro")
+        );
+        assert_eq!(profile(r#"{"account": {}}"#), None);
     }
 }
@@ -0,0 +1,374 @@
+use crate::config::Config;
+use crate::doctor::Check;
+use crate::models::{CostSnapshot, IdentitySnapshot, MemberUsage, Provider, UsageSnapshot};
+use crate::providers::{http_client, JsonRecorded, ProviderError, ProviderFetcher, SendLogged};
+use crate::ratelimit::RateLimitInfo;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const API_BASE: &str = "https://api.anthropic.com/v1/organizations";
+const API_VERSION: &str = "2023-06-01";
+const USER_AGENT: &str = "quotabar";
+const ADMIN_KEY_ENV: &str = "ANTHROPIC_ADMIN_KEY";
+/// Stop following `next_page` after this many requests
+const MAX_PAGES: usize = 20;
+
+/// GET /v1/organizations/me
+#[derive(Debug, Deserialize)]
+struct OrganizationInfo {
+    name: Option<String>,
+}
+
+/// One page of a paginated Admin API report
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    #[serde(default = "Vec::new")]
+    data: Vec<T>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
+}
+
+/// A time bucket from the cost report
+#[derive(Debug, Deserialize)]
+struct CostBucket {
+    #[serde(default)]
+    results: Vec<CostResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CostResult {
+    /// Decimal string in cents
+    amount: String,
+}
+
+/// A per-actor, per-day record from the Claude Code usage report
+#[derive(Debug, Deserialize)]
+struct ClaudeCodeRecord {
+    actor: Actor,
+    #[serde(default)]
+    model_breakdown: Vec<ModelBreakdown>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Actor {
+    #[serde(rename = "user_actor")]
+    User { email_address: String },
+    #[serde(rename = "api_actor")]
+    Api { api_key_name: String },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelBreakdown {
+    estimated_cost: Option<EstimatedCost>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EstimatedCost {
+    /// Cents
+    amount: f64,
+}
+
+pub struct ClaudeOrgProvider {
+    client: reqwest::Client,
+    api_key: Option<String>,
+    budget: Option<f64>,
+    /// Where raw responses are kept, with `debug_responses` on
+    raw_dir: Option<PathBuf>,
+}
+
+impl ClaudeOrgProvider {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client(),
+            api_key: api_key(config),
+            budget: config
+                .providers
+                .get(&Provider::Claude)
+                .and_then(|c| c.org_budget),
+            raw_dir: config.debug_responses_dir(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        api_key: &str,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<(T, Option<RateLimitInfo>)> {
+        let response = self
+            .client
+            .get(format!("{}{}", API_BASE, path))
+            .query(query)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", API_VERSION)
+            .header("Accept", "application/json")
+            .header("User-Agent", USER_AGENT)
+            .send_logged()
+            .await
+            .context("Failed to connect to Anthropic Admin API")?;
+
+        let rate_limit = RateLimitInfo::from_headers(response.headers(), Utc::now());
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = crate::ratelimit::retry_after(response.headers(), Utc::now());
+            return Err(ProviderError::RateLimited { until }.into());
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(ProviderError::Unauthorized(
+                "Anthropic Admin API key invalid or lacks access. Org usage needs an sk-ant-admin key.".to_string(),
+            )
+            .into());
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Anthropic Admin API error ({}): {}", status, body));
+        }
+
+        let name = format!("claude-org-{}", path.rsplit('/').next().unwrap_or_default());
+        let parsed = response
+            .json_recorded(&name, self.raw_dir.as_deref())
+            .await
+            .with_context(|| format!("Failed to parse Admin API response from {}", path))?;
+        Ok((parsed, rate_limit))
+    }
+
+    /// Follow `next_page` until the report is exhausted
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        api_key: &str,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<(Vec<T>, Option<RateLimitInfo>)> {
+        let mut items = Vec::new();
+        let mut rate_limit = None;
+        let mut page_token: Option<String> = None;
+
+        for _ in 0..MAX_PAGES {
+            let mut params = query.to_vec();
+            if let Some(token) = page_token.take() {
+                params.push(("page", token));
+            }
+            let (page, info): (Page<T>, _) = self.get(api_key, path, &params).await?;
+            items.extend(page.data);
+            rate_limit = info.or(rate_limit);
+            match page.next_page {
+                Some(next) if page.has_more => page_token = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok((items, rate_limit))
+    }
+}
+
+/// `[providers.claude] admin_api_key`, then the admin key variable
+fn api_key(config: &Config) -> Option<String> {
+    config
+        .providers
+        .get(&Provider::Claude)
+        .and_then(|c| c.admin_api_key.clone())
+        .or_else(|| std::env::var(ADMIN_KEY_ENV).ok())
+        .filter(|k| !k.trim().is_empty())
+}
+
+/// [`super::credentials_id`] for the organization: the admin key itself,
+/// as it's what names the organization
+pub fn credentials_id(config: &Config) -> Option<String> {
+    api_key(config)
+}
+
+#[async_trait]
+impl ProviderFetcher for ClaudeOrgProvider {
+    async fn fetch(&self) -> Result<UsageSnapshot> {
+        let api_key = self.api_key.as_deref().ok_or_else(|| {
+            anyhow!(
+                "Claude org usage needs an Admin API key: set `admin_api_key` under [providers.claude] or {}",
+                ADMIN_KEY_ENV
+            )
+        })?;
+        let now = Utc::now();
+        let period_start = month_start(now);
+
+        let (org, _): (OrganizationInfo, _) = self.get(api_key, "/me", &[]).await?;
+        let (buckets, _): (Vec<CostBucket>, _) = self
+            .get_all(
+                api_key,
+                "/cost_report",
+                &[("starting_at", period_start.to_rfc3339())],
+            )
+            .await?;
+        let (records, rate_limit): (Vec<ClaudeCodeRecord>, _) = self
+            .get_all(
+                api_key,
+                "/usage_report/claude_code",
+                &[("starting_at", now.format("%Y-%m-%d").to_string())],
+            )
+            .await?;
+
+        let resets_at = month_start(period_start + Duration::days(32));
+        Ok(UsageSnapshot {
+            provider: Provider::ClaudeOrg,
+            windows: Vec::new(),
+            cost: Some(CostSnapshot {
+                used: total_cost(&buckets),
+                limit: self.budget.unwrap_or(0.0),
+                currency_code: "USD".to_string(),
+                period: Some("Monthly".to_string()),
+                resets_at: Some(resets_at),
+                extra_usage: false,
+            }),
+            identity: Some(IdentitySnapshot {
+                email: None,
+                plan: None,
+                organization: org.name.filter(|n| !n.trim().is_empty()),
+                account_id: None,
+            }),
+            rate_limit,
+            busiest_member: busiest_member(&records),
+            origin: None,
+            updated_at: now,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "Claude Org"
+    }
+
+    fn diagnose(&self, _now: DateTime<Utc>) -> Vec<Check> {
+        vec![match self.api_key {
+            Some(_) => Check::pass("Admin key", "configured"),
+            None => Check::fail(
+                "Admin key",
+                "no Admin API key",
+                format!(
+                    "Set `admin_api_key` under [providers.claude] or {}",
+                    ADMIN_KEY_ENV
+                ),
+            ),
+        }]
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(format!("{}/me", API_BASE))
+    }
+}
+
+/// Total spend in dollars across all cost buckets
+fn total_cost(buckets: &[CostBucket]) -> f64 {
+    let cents: f64 = buckets
+        .iter()
+        .flat_map(|b| &b.results)
+        .filter_map(|r| r.amount.trim().parse::<f64>().ok())
+        .sum();
+    cents / 100.0
+}
+
+/// The member with the highest estimated Claude Code spend, with their share
+/// of everyone's spend in the same report
+fn busiest_member(records: &[ClaudeCodeRecord]) -> Option<MemberUsage> {
+    let mut per_member: HashMap<&str, f64> = HashMap::new();
+    for record in records {
+        let name = match &record.actor {
+            Actor::User { email_address } => email_address.as_str(),
+            Actor::Api { api_key_name } => api_key_name.as_str(),
+            Actor::Unknown => continue,
+        };
+        let cents: f64 = record
+            .model_breakdown
+            .iter()
+            .filter_map(|m| m.estimated_cost.as_ref())
+            .map(|c| c.amount)
+            .sum();
+        *per_member.entry(name).or_default() += cents;
+    }
+
+    let total: f64 = per_member.values().sum();
+    let (member, cents) = per_member
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(a.0)))?;
+    Some(MemberUsage {
+        member: member.to_string(),
+        cost: cents / 100.0,
+        share_percent: if total > 0.0 {
+            cents / total * 100.0
+        } else {
+            0.0
+        },
+    })
+}
+
+fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total_cost_sums_cents() {
+        let page: Page<CostBucket> = serde_json::from_str(
+            r#"{"data": [
+                {"starting_at": "2026-10-01T00:00:00Z", "results": [
+                    {"currency": "USD", "amount": "1250.5"},
+                    {"currency": "USD", "amount": "49.5"}
+                ]},
+                {"starting_at": "2026-10-02T00:00:00Z", "results": []}
+            ], "has_more": false, "next_page": null}"#,
+        )
+        .unwrap();
+        assert_eq!(total_cost(&page.data), 13.0);
+    }
+
+    #[test]
+    fn test_busiest_member() {
+        let page: Page<ClaudeCodeRecord> = serde_json::from_str(
+            r#"{"data": [
+                {"actor": {"type": "user_actor", "email_address": "a@example.com"},
+                 "model_breakdown": [{"estimated_cost": {"currency": "USD", "amount": 300}}]},
+                {"actor": {"type": "user_actor", "email_address": "b@example.com"},
+                 "model_breakdown": [
+                    {"estimated_cost": {"currency": "USD", "amount": 400}},
+                    {"estimated_cost": {"currency": "USD", "amount": 200}}
+                 ]},
+                {"actor": {"type": "api_actor", "api_key_name": "ci"},
+                 "model_breakdown": [{"estimated_cost": {"currency": "USD", "amount": 100}}]},
+                {"actor": {"type": "service_actor"}, "model_breakdown": []}
+            ], "has_more": false}"#,
+        )
+        .unwrap();
+        let top = busiest_member(&page.data).unwrap();
+        assert_eq!(top.member, "b@example.com");
+        assert_eq!(top.cost, 6.0);
+        assert_eq!(top.share_percent, 60.0);
+    }
+
+    #[test]
+    fn test_busiest_member_empty() {
+        assert!(busiest_member(&[]).is_none());
+    }
+
+    #[test]
+    fn test_month_start() {
+        let now = Utc.with_ymd_and_hms(2026, 12, 17, 8, 30, 0).unwrap();
+        assert_eq!(
+            month_start(now),
+            Utc.with_ymd_and_hms(2026, 12, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            month_start(month_start(now) + Duration::days(32)),
+            Utc.with_ymd_and_hms(2027, 1, 1, 0, 0, 0).unwrap()
+        );
+    }
+}
@@ -1,5 +1,16 @@
-use crate::models::{IdentitySnapshot, Provider, RateWindow, UsageSnapshot};
-use crate::providers::ProviderFetcher;
+use crate::config::{Config, ProviderConfig};
+use crate::doctor::{self, Check};
+use crate::logging::Redacted;
+use crate::models::{
+    Granularity, IdentitySnapshot, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::paths::expand_home;
+use crate::providers::{
+    http_client, normalize_plan_name, CredentialSource, JsonRecorded, ProviderError,
+    ProviderFetcher, SendLogged,
+};
+use crate::ratelimit::RateLimitInfo;
+use crate::timefmt::{self, RelativeStyle};
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
@@ -8,7 +19,7 @@ use chrono::{DateTime, TimeZone, Utc};
 use serde::Deserialize;
 use serde_json::Value;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const DEFAULT_CHATGPT_BASE_URL: &str = "https://chatgpt.com/backend-api";
 const CHATGPT_USAGE_PATH: &str = "/wham/usage";
@@ -18,26 +29,37 @@ const USER_AGENT: &str = "quotabar";
 #[derive(Debug, Deserialize)]
 struct AuthFile {
     #[serde(rename = "OPENAI_API_KEY")]
-    openai_api_key: Option<String>,
+    openai_api_key: Option<Redacted<String>>,
     tokens: Option<AuthTokens>,
 }
 
 #[derive(Debug, Deserialize)]
 struct AuthTokens {
-    access_token: String,
+    access_token: Redacted<String>,
     #[allow(dead_code)]
-    refresh_token: Option<String>,
-    id_token: Option<String>,
+    refresh_token: Option<Redacted<String>>,
+    id_token: Option<Redacted<String>>,
     account_id: Option<String>,
 }
 
 #[derive(Debug)]
 struct Credentials {
-    access_token: String,
-    id_token: Option<String>,
+    access_token: Redacted<String>,
+    id_token: Option<Redacted<String>>,
     account_id: Option<String>,
 }
 
+impl Credentials {
+    /// A bare token from `token_env` or an API key, with no identity claims
+    fn from_token(token: &str) -> Self {
+        Self {
+            access_token: token.to_string().into(),
+            id_token: None,
+            account_id: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct UsageResponse {
     plan_type: Option<String>,
@@ -72,90 +94,33 @@ struct CreditDetails {
 
 pub struct CodexProvider {
     client: reqwest::Client,
+    credentials: CredentialSource,
+    /// The Codex directory, for its config.toml
+    home: PathBuf,
+    /// Where raw responses are kept, with `debug_responses` on
+    raw_dir: Option<PathBuf>,
 }
 
 impl CodexProvider {
-    pub fn new() -> Self {
+    pub fn from_config(config: &Config) -> Self {
+        let raw_dir = config.debug_responses_dir();
+        let config = config.providers.get(&Provider::Codex);
+        let env = |var: &str| env::var(var).ok();
         Self {
-            client: reqwest::Client::new(),
-        }
-    }
-
-    fn credentials_path() -> PathBuf {
-        if let Ok(codex_home) = env::var("CODEX_HOME") {
-            let trimmed = codex_home.trim();
-            if !trimmed.is_empty() {
-                return PathBuf::from(trimmed).join("auth.json");
-            }
+            client: http_client(),
+            credentials: credential_source(config, env),
+            home: codex_home(config, env).0,
+            raw_dir,
         }
-
-        dirs::home_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(".codex")
-            .join("auth.json")
     }
 
-    fn config_path() -> PathBuf {
-        if let Ok(codex_home) = env::var("CODEX_HOME") {
-            let trimmed = codex_home.trim();
-            if !trimmed.is_empty() {
-                return PathBuf::from(trimmed).join("config.toml");
-            }
-        }
-
-        dirs::home_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(".codex")
-            .join("config.toml")
-    }
-
-    fn load_credentials() -> Result<Credentials> {
-        let path = Self::credentials_path();
-        if !path.exists() {
-            return Err(anyhow!(
-                "Codex credentials not found at {}. Run `codex` first.",
-                path.display()
-            ));
-        }
-
-        let content = std::fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-
-        let auth: AuthFile =
-            serde_json::from_str(&content).with_context(|| "Failed to parse auth.json")?;
-
-        if let Some(api_key) = auth
-            .openai_api_key
-            .as_ref()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-        {
-            return Ok(Credentials {
-                access_token: api_key,
-                id_token: None,
-                account_id: None,
-            });
-        }
-
-        let tokens = auth
-            .tokens
-            .ok_or_else(|| anyhow!("Codex auth.json missing tokens. Run `codex` to log in."))?;
-
-        if tokens.access_token.trim().is_empty() {
-            return Err(anyhow!(
-                "Codex auth.json missing access token. Run `codex` to log in."
-            ));
-        }
-
-        Ok(Credentials {
-            access_token: tokens.access_token,
-            id_token: tokens.id_token,
-            account_id: tokens.account_id,
-        })
+    fn load_credentials(&self) -> Result<Credentials> {
+        self.credentials
+            .load(Credentials::from_token, load_credentials_from)
     }
 
-    fn resolve_usage_url() -> reqwest::Url {
-        let base = Self::resolve_chatgpt_base_url();
+    fn resolve_usage_url(&self) -> reqwest::Url {
+        let base = self.resolve_chatgpt_base_url();
         let normalized = Self::normalize_chatgpt_base_url(&base);
         let path = if normalized.contains("/backend-api") {
             CHATGPT_USAGE_PATH
@@ -172,8 +137,8 @@ impl CodexProvider {
         })
     }
 
-    fn resolve_chatgpt_base_url() -> String {
-        if let Ok(contents) = std::fs::read_to_string(Self::config_path()) {
+    fn resolve_chatgpt_base_url(&self) -> String {
+        if let Ok(contents) = std::fs::read_to_string(self.home.join("config.toml")) {
             if let Some(parsed) = Self::parse_chatgpt_base_url(&contents) {
                 return parsed;
             }
@@ -207,12 +172,18 @@ impl CodexProvider {
         trimmed
     }
 
-    async fn fetch_usage(&self, creds: &Credentials) -> Result<UsageResponse> {
-        let url = Self::resolve_usage_url();
+    async fn fetch_usage(
+        &self,
+        creds: &Credentials,
+    ) -> Result<(UsageResponse, Option<RateLimitInfo>)> {
+        let url = self.resolve_usage_url();
         let mut request = self
             .client
             .get(url)
-            .header("Authorization", format!("Bearer {}", creds.access_token))
+            .header(
+                "Authorization",
+                format!("Bearer {}", creds.access_token.expose()),
+            )
             .header("Accept", "application/json")
             .header("User-Agent", USER_AGENT);
 
@@ -226,42 +197,62 @@ impl CodexProvider {
         }
 
         let response = request
-            .send()
+            .send_logged()
             .await
             .context("Failed to connect to Codex usage API")?;
 
+        let rate_limit = RateLimitInfo::from_headers(response.headers(), Utc::now());
         let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = crate::ratelimit::retry_after(response.headers(), Utc::now());
+            return Err(ProviderError::RateLimited { until }.into());
+        }
         if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
-            return Err(anyhow!(
-                "Codex OAuth token expired or invalid. Run `codex` to re-authenticate."
-            ));
+            return Err(ProviderError::Unauthorized(
+                "Codex OAuth token expired or invalid. Run `codex` to re-authenticate.".to_string(),
+            )
+            .into());
         }
         if !status.is_success() {
             let body = response.text().await.unwrap_or_default();
             return Err(anyhow!("Codex API error ({}): {}", status, body));
         }
 
-        response
-            .json()
+        let usage = response
+            .json_recorded("codex", self.raw_dir.as_deref())
             .await
-            .context("Failed to parse Codex usage response")
+            .context("Failed to parse Codex usage response")?;
+        Ok((usage, rate_limit))
     }
 
     fn make_window(window: Option<&WindowSnapshot>, now: DateTime<Utc>) -> Option<RateWindow> {
         let window = window?;
         let reset = Utc.timestamp_opt(window.reset_at, 0).single();
-        let reset_description = reset.map(|dt| format_reset_time(dt, now));
+        let reset_description = reset.map(|dt| timefmt::relative(dt, now, RelativeStyle::Long));
         let minutes = (window.limit_window_seconds / 60) as i32;
         Some(RateWindow {
+            kind: WindowKind::from_minutes(minutes),
             used_percent: window.used_percent as f64,
             window_minutes: Some(minutes),
             resets_at: reset,
             reset_description,
+            granularity: Granularity::Percent,
+            label: None,
+            used: None,
+            limit: None,
+            unit: None,
         })
     }
 
     fn resolve_identity(creds: &Credentials, response: &UsageResponse) -> Option<IdentitySnapshot> {
-        let payload = creds.id_token.as_deref().and_then(parse_jwt_payload);
+        let payload = creds
+            .id_token
+            .as_ref()
+            .and_then(|t| parse_jwt_payload(t.expose()));
+        let auth_claim = payload
+            .as_ref()
+            .and_then(|p| p.get("https://api.openai.com/auth"))
+            .and_then(Value::as_object);
 
         let email = payload
             .as_ref()
@@ -283,10 +274,7 @@ impl CodexProvider {
             .as_deref()
             .and_then(normalize_plan_name)
             .or_else(|| {
-                payload
-                    .as_ref()
-                    .and_then(|p| p.get("https://api.openai.com/auth"))
-                    .and_then(Value::as_object)
+                auth_claim
                     .and_then(|obj| obj.get("chatgpt_plan_type"))
                     .and_then(Value::as_str)
                     .and_then(normalize_plan_name)
@@ -299,47 +287,54 @@ impl CodexProvider {
                     .and_then(normalize_plan_name)
             });
 
-        if email.is_none() && plan.is_none() {
+        let organization = auth_claim.and_then(organization_from_claim);
+
+        let account_id = creds
+            .account_id
+            .clone()
+            .or_else(|| {
+                auth_claim
+                    .and_then(|obj| obj.get("chatgpt_account_id"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            })
+            .filter(|s| !s.trim().is_empty());
+
+        if email.is_none() && plan.is_none() && organization.is_none() && account_id.is_none() {
             return None;
         }
 
         Some(IdentitySnapshot {
             email,
             plan,
-            organization: None,
+            organization,
+            account_id,
         })
     }
 }
 
-impl Default for CodexProvider {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 #[async_trait]
 impl ProviderFetcher for CodexProvider {
     async fn fetch(&self) -> Result<UsageSnapshot> {
-        let creds = Self::load_credentials()?;
-        let usage = self.fetch_usage(&creds).await?;
+        let creds = self.load_credentials()?;
+        let (usage, rate_limit) = self.fetch_usage(&creds).await?;
         let now = Utc::now();
 
-        let primary = usage
+        let windows = usage
             .rate_limit
-            .as_ref()
-            .and_then(|r| Self::make_window(r.primary_window.as_ref(), now));
-        let secondary = usage
-            .rate_limit
-            .as_ref()
-            .and_then(|r| Self::make_window(r.secondary_window.as_ref(), now));
+            .iter()
+            .flat_map(|r| [&r.primary_window, &r.secondary_window])
+            .filter_map(|w| Self::make_window(w.as_ref(), now))
+            .collect();
 
         Ok(UsageSnapshot {
             provider: Provider::Codex,
-            primary,
-            secondary,
-            tertiary: None,
+            windows,
             cost: None,
             identity: Self::resolve_identity(&creds, &usage),
+            rate_limit,
+            busiest_member: None,
+            origin: None,
             updated_at: now,
         })
     }
@@ -347,27 +342,142 @@ impl ProviderFetcher for CodexProvider {
     fn name(&self) -> &'static str {
         "Codex"
     }
-}
 
-fn format_reset_time(reset: DateTime<Utc>, now: DateTime<Utc>) -> String {
-    let duration = reset.signed_duration_since(now);
-    let hours = duration.num_hours();
-    let minutes = duration.num_minutes() % 60;
-
-    if hours <= 0 && minutes <= 0 {
-        "now".to_string()
-    } else if hours < 1 {
-        format!("in {} min", minutes.max(1))
-    } else if hours < 24 {
-        format!("in {}h", hours)
-    } else {
-        let days = hours / 24;
-        if days == 1 {
-            "in 1 day".to_string()
-        } else {
-            format!("in {} days", days)
+    fn diagnose(&self, now: DateTime<Utc>) -> Vec<Check> {
+        match self.credentials {
+            CredentialSource::Env { ref var, .. } => CredentialSource::env_checks(var),
+            CredentialSource::File { ref path, .. } => credential_checks(path, now),
         }
     }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(self.resolve_usage_url().to_string())
+    }
+}
+
+/// The Codex directory: `codex_home`, then `CODEX_HOME`, then ~/.codex;
+/// with what chose it
+fn codex_home(
+    config: Option<&ProviderConfig>,
+    env: impl Fn(&str) -> Option<String>,
+) -> (PathBuf, &'static str) {
+    if let Some(home) = config
+        .and_then(|c| c.codex_home.as_deref())
+        .and_then(expand_home)
+    {
+        return (home, "[providers.codex] codex_home");
+    }
+    if let Some(home) = env("CODEX_HOME").as_deref().and_then(expand_home) {
+        return (home, "CODEX_HOME");
+    }
+    let home = dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".codex");
+    (home, "default")
+}
+
+/// [`super::credentials_id`] for Codex
+pub fn credentials_id(config: &Config) -> String {
+    credential_source(config.providers.get(&Provider::Codex), |var| {
+        env::var(var).ok()
+    })
+    .id()
+}
+
+/// `token_env`, then `auth_path`, then auth.json in the Codex directory
+fn credential_source(
+    config: Option<&ProviderConfig>,
+    env: impl Fn(&str) -> Option<String>,
+) -> CredentialSource {
+    if let Some(source) = CredentialSource::from_token_env(config, &env) {
+        return source;
+    }
+    if let Some(path) = config
+        .and_then(|c| c.auth_path.as_deref())
+        .and_then(expand_home)
+    {
+        return CredentialSource::File {
+            path,
+            from: "[providers.codex] auth_path",
+        };
+    }
+    let (home, from) = codex_home(config, env);
+    CredentialSource::File {
+        path: home.join("auth.json"),
+        from,
+    }
+}
+
+fn load_credentials_from(path: &Path) -> Result<Credentials> {
+    if !path.exists() {
+        return Err(anyhow!(
+            "Codex credentials not found at {}. Run `codex` first.",
+            path.display()
+        ));
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let auth: AuthFile =
+        serde_json::from_str(&content).with_context(|| "Failed to parse auth.json")?;
+
+    if let Some(api_key) = auth
+        .openai_api_key
+        .as_ref()
+        .map(|s| s.expose().trim().to_string())
+        .filter(|s| !s.is_empty())
+    {
+        return Ok(Credentials::from_token(&api_key));
+    }
+
+    let tokens = auth
+        .tokens
+        .ok_or_else(|| anyhow!("Codex auth.json missing tokens. Run `codex` to log in."))?;
+
+    if tokens.access_token.expose().trim().is_empty() {
+        return Err(anyhow!(
+            "Codex auth.json missing access token. Run `codex` to log in."
+        ));
+    }
+
+    Ok(Credentials {
+        access_token: tokens.access_token,
+        id_token: tokens.id_token,
+        account_id: tokens.account_id,
+    })
+}
+
+fn credential_checks(path: &Path, now: DateTime<Utc>) -> Vec<Check> {
+    let creds = match load_credentials_from(path) {
+        Ok(creds) => creds,
+        Err(e) => {
+            return vec![Check::fail(
+                "Credentials",
+                format!("{:#}", e),
+                "Run `codex` and sign in",
+            )]
+        }
+    };
+    let expires_at = parse_jwt_payload(creds.access_token.expose())
+        .and_then(|claims| claims.get("exp")?.as_i64())
+        .and_then(|exp| Utc.timestamp_opt(exp, 0).single());
+    let token = match expires_at {
+        Some(expires_at) if expires_at <= now => Check::fail(
+            "Token",
+            format!("expired {} ago", doctor::approx(now - expires_at)),
+            "Run `codex` once to refresh the token",
+        ),
+        Some(expires_at) => Check::pass(
+            "Token",
+            format!("valid for {}", doctor::approx(expires_at - now)),
+        ),
+        None => Check::pass("Token", "no expiry recorded"),
+    };
+    vec![
+        Check::pass("Credentials", format!("{} parsed", path.display())),
+        token,
+    ]
 }
 
 fn parse_jwt_payload(token: &str) -> Option<Value> {
@@ -378,29 +488,28 @@ fn parse_jwt_payload(token: &str) -> Option<Value> {
     serde_json::from_slice(&decoded).ok()
 }
 
-fn normalize_plan_name(plan: &str) -> Option<String> {
-    let trimmed = plan.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-    let lower = trimmed.to_lowercase();
-    let label = match lower.as_str() {
-        "free" => "Free",
-        "plus" => "Plus",
-        "pro" => "Pro",
-        "team" => "Team",
-        "enterprise" => "Enterprise",
-        "business" => "Business",
-        "education" => "Education",
-        "go" => "Go",
-        "guest" => "Guest",
-        "free_workspace" => "Free Workspace",
-        "k12" => "K-12",
-        "quorum" => "Quorum",
-        "edu" => "Edu",
-        _ => trimmed,
+/// Workspace or organization title from the `https://api.openai.com/auth`
+/// claim. The implicit "Personal" organization is not worth showing.
+fn organization_from_claim(auth: &serde_json::Map<String, Value>) -> Option<String> {
+    let workspace = auth
+        .get("chatgpt_workspace_name")
+        .or_else(|| auth.get("workspace_name"))
+        .and_then(Value::as_str);
+
+    let org_title = || {
+        let orgs = auth.get("organizations")?.as_array()?;
+        orgs.iter()
+            .find(|o| o.get("is_default").and_then(Value::as_bool) == Some(true))
+            .or_else(|| orgs.first())
+            .and_then(|o| o.get("title"))
+            .and_then(Value::as_str)
     };
-    Some(label.to_string())
+
+    workspace
+        .or_else(org_title)
+        .map(str::trim)
+        .filter(|t| !t.is_empty() && !t.eq_ignore_ascii_case("personal"))
+        .map(str::to_string)
 }
 
 fn deserialize_balance_opt<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
@@ -424,3 +533,186 @@ where
     }
     .map(Some)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn jwt(payload: Value) -> String {
+        let encode = |v: &Value| URL_SAFE_NO_PAD.encode(v.to_string());
+        format!(
+            "{}.{}.sig",
+            encode(&json!({"alg": "RS256", "typ": "JWT"})),
+            encode(&payload)
+        )
+    }
+
+    fn identity(payload: Value, account_id: Option<&str>) -> Option<IdentitySnapshot> {
+        let creds = Credentials {
+            access_token: "token".to_string().into(),
+            id_token: Some(jwt(payload).into()),
+            account_id: account_id.map(str::to_string),
+        };
+        let response = UsageResponse {
+            plan_type: None,
+            rate_limit: None,
+            credits: None,
+        };
+        CodexProvider::resolve_identity(&creds, &response)
+    }
+
+    #[test]
+    fn test_credential_source_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = |name: &str, token: &str| {
+            let home = dir.path().join(name);
+            std::fs::create_dir_all(&home).unwrap();
+            let json = json!({"tokens": {"access_token": token}});
+            std::fs::write(home.join("auth.json"), json.to_string()).unwrap();
+            home
+        };
+        let env_home = auth("env-home", "from-codex-home-var");
+        let config_home = auth("config-home", "from-codex-home");
+        let auth_path = auth("explicit", "from-auth-path").join("auth.json");
+        let token = |config: &ProviderConfig, env: &[(&str, &str)]| {
+            let lookup = |var: &str| {
+                env.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, value)| value.to_string())
+            };
+            credential_source(Some(config), lookup)
+                .load(Credentials::from_token, load_credentials_from)
+                .map(|creds| creds.access_token.into_inner())
+        };
+        let env_home = env_home.display().to_string();
+        let env = [("CODEX_HOME", env_home.as_str()), ("MY_TOKEN", "from-env")];
+
+        let mut config = ProviderConfig {
+            token_env: Some("MY_TOKEN".to_string()),
+            auth_path: Some(auth_path.display().to_string()),
+            codex_home: Some(config_home.display().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(token(&config, &env).unwrap(), "from-env");
+        // An empty variable falls through to the files
+        assert_eq!(
+            token(&config, &[("MY_TOKEN", " ")]).unwrap(),
+            "from-auth-path"
+        );
+        assert_eq!(token(&config, &env[..1]).unwrap(), "from-auth-path");
+        config.auth_path = None;
+        assert_eq!(token(&config, &env[..1]).unwrap(), "from-codex-home");
+        assert_eq!(codex_home(Some(&config), |_| None).0, config_home);
+        config.codex_home = None;
+        assert_eq!(token(&config, &env[..1]).unwrap(), "from-codex-home-var");
+        assert_eq!(
+            codex_home(Some(&config), |_| None),
+            (dirs::home_dir().unwrap().join(".codex"), "default")
+        );
+
+        // Errors say which setting pointed at the file
+        config.codex_home = Some(dir.path().join("missing").display().to_string());
+        let error = format!("{:#}", token(&config, &env[..1]).unwrap_err());
+        assert!(error.contains("missing/auth.json"), "{}", error);
+        assert!(error.contains("[providers.codex] codex_home"), "{}", error);
+    }
+
+    #[test]
+    fn test_credential_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        let now = Utc.timestamp_opt(1_800_000_000, 0).unwrap();
+        assert!(!credential_checks(&path, now)[0].ok);
+
+        std::fs::write(&path, r#"{"tokens": null}"#).unwrap();
+        let checks = credential_checks(&path, now);
+        assert!(!checks[0].ok);
+        assert!(checks[0].detail.contains("missing tokens"));
+
+        let token = jwt(json!({"exp": 1_800_000_000 - 3600}));
+        let auth = json!({"tokens": {"access_token": token, "id_token": null}});
+        std::fs::write(&path, auth.to_string()).unwrap();
+        let checks = credential_checks(&path, now);
+        assert!(checks[0].ok);
+        assert!(!checks[1].ok);
+        assert_eq!(checks[1].detail, "expired 1h ago");
+
+        std::fs::write(&path, r#"{"OPENAI_API_KEY": "sk-proj-abc"}"#).unwrap();
+        assert!(credential_checks(&path, now).iter().all(|c| c.ok));
+    }
+
+    #[test]
+    fn test_personal_account_has_no_organization() {
+        let identity = identity(
+            json!({
+                "email": "me@example.com",
+                "https://api.openai.com/auth": {
+                    "chatgpt_account_id": "acct-personal",
+                    "chatgpt_plan_type": "plus",
+                    "organizations": [
+                        {"id": "org-1", "is_default": true, "title": "Personal"}
+                    ]
+                }
+            }),
+            None,
+        )
+        .unwrap();
+        assert_eq!(identity.email.as_deref(), Some("me@example.com"));
+        assert_eq!(identity.plan.as_deref(), Some("Plus"));
+        assert_eq!(identity.organization, None);
+        assert_eq!(identity.account_id.as_deref(), Some("acct-personal"));
+    }
+
+    #[test]
+    fn test_workspace_account_organization() {
+        let identity = identity(
+            json!({
+                "https://api.openai.com/auth": {
+                    "chatgpt_account_id": "acct-claim",
+                    "chatgpt_plan_type": "team",
+                    "organizations": [
+                        {"id": "org-1", "is_default": false, "title": "Personal"},
+                        {"id": "org-2", "is_default": true, "title": "Acme Corp"}
+                    ]
+                }
+            }),
+            Some("acct-auth-file"),
+        )
+        .unwrap();
+        assert_eq!(identity.plan.as_deref(), Some("Team"));
+        assert_eq!(identity.organization.as_deref(), Some("Acme Corp"));
+        // auth.json's account id wins over the claim
+        assert_eq!(identity.account_id.as_deref(), Some("acct-auth-file"));
+    }
+
+    #[test]
+    fn test_workspace_name_preferred_over_org_title() {
+        let identity = identity(
+            json!({
+                "https://api.openai.com/auth": {
+                    "chatgpt_workspace_name": "Platform Team",
+                    "organizations": [{"id": "org-2", "title": "Acme Corp"}]
+                }
+            }),
+            None,
+        )
+        .unwrap();
+        assert_eq!(identity.organization.as_deref(), Some("Platform Team"));
+    }
+
+    #[test]
+    fn test_malformed_token_yields_no_identity() {
+        let creds = Credentials {
+            access_token: "token".to_string().into(),
+            id_token: Some("not-a-jwt".to_string().into()),
+            account_id: None,
+        };
+        let response = UsageResponse {
+            plan_type: None,
+            rate_limit: None,
+            credits: None,
+        };
+        assert!(CodexProvider::resolve_identity(&creds, &response).is_none());
+    }
+}
@@ -0,0 +1,387 @@
+use crate::config::Config;
+use crate::doctor::{self, Check};
+use crate::logging::Redacted;
+use crate::models::{
+    Granularity, IdentitySnapshot, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::providers::{
+    http_client, normalize_plan_name, JsonRecorded, ProviderError, ProviderFetcher, SendLogged,
+};
+use crate::ratelimit::RateLimitInfo;
+use crate::timefmt::{self, RelativeStyle};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const USAGE_PATH: &str = "/getUsageLimits";
+const USER_AGENT: &str = "quotabar";
+
+/// A cached SSO token from ~/.aws/sso/cache/*.json
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoToken {
+    access_token: Option<Redacted<String>>,
+    expires_at: Option<String>,
+    region: Option<String>,
+    profile_arn: Option<String>,
+}
+
+impl SsoToken {
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+            .as_deref()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+}
+
+/// API response from getUsageLimits
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageResponse {
+    #[serde(default)]
+    usage_breakdown_list: Vec<UsageBreakdown>,
+    /// Unix timestamp in seconds
+    next_date_reset: Option<f64>,
+    subscription_info: Option<SubscriptionInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageBreakdown {
+    #[allow(dead_code)]
+    resource_type: Option<String>,
+    current_usage: f64,
+    usage_limit: f64,
+    /// Unix timestamp in seconds
+    next_date_reset: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubscriptionInfo {
+    subscription_title: Option<String>,
+}
+
+pub struct KiroProvider {
+    client: reqwest::Client,
+    /// Where raw responses are kept, with `debug_responses` on
+    raw_dir: Option<PathBuf>,
+}
+
+impl KiroProvider {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client(),
+            raw_dir: config.debug_responses_dir(),
+        }
+    }
+
+    fn sso_cache_dir() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".aws")
+            .join("sso")
+            .join("cache")
+    }
+
+    fn load_token() -> Result<SsoToken> {
+        let dir = Self::sso_cache_dir();
+        if !dir.exists() {
+            return Err(anyhow!(
+                "AWS SSO cache not found at {}. Sign in to Kiro first.",
+                dir.display()
+            ));
+        }
+        select_token(&dir, Utc::now())
+    }
+
+    async fn fetch_usage(
+        &self,
+        token: &SsoToken,
+        access_token: &str,
+    ) -> Result<(UsageResponse, Option<RateLimitInfo>)> {
+        let region = token.region.as_deref().unwrap_or(DEFAULT_REGION);
+        let url = format!(
+            "https://codewhisperer.{}.amazonaws.com{}",
+            region, USAGE_PATH
+        );
+        let mut request = self
+            .client
+            .get(url)
+            .query(&[("origin", "AI_EDITOR")])
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Accept", "application/json")
+            .header("User-Agent", USER_AGENT);
+        if let Some(ref arn) = token.profile_arn {
+            request = request.query(&[("profileArn", arn)]);
+        }
+
+        let response = request
+            .send_logged()
+            .await
+            .context("Failed to connect to Kiro usage API")?;
+
+        let rate_limit = RateLimitInfo::from_headers(response.headers(), Utc::now());
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = crate::ratelimit::retry_after(response.headers(), Utc::now());
+            return Err(ProviderError::RateLimited { until }.into());
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(ProviderError::Unauthorized(
+                "Kiro SSO token expired or invalid. Sign in to Kiro again.".to_string(),
+            )
+            .into());
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Kiro API error ({}): {}", status, body));
+        }
+
+        let usage = response
+            .json_recorded("kiro", self.raw_dir.as_deref())
+            .await
+            .context("Failed to parse Kiro usage response")?;
+        Ok((usage, rate_limit))
+    }
+}
+
+/// [`super::credentials_id`] for Kiro
+pub fn credentials_id() -> String {
+    KiroProvider::sso_cache_dir().display().to_string()
+}
+
+#[async_trait]
+impl ProviderFetcher for KiroProvider {
+    async fn fetch(&self) -> Result<UsageSnapshot> {
+        let token = Self::load_token()?;
+        let access_token = token
+            .access_token
+            .as_ref()
+            .map(|t| t.expose().clone())
+            .ok_or_else(|| anyhow!("Kiro SSO token has no access token"))?;
+        let (usage, rate_limit) = self.fetch_usage(&token, &access_token).await?;
+        let now = Utc::now();
+
+        // Monthly interactions; resets at the month boundary
+        let interactions = usage
+            .usage_breakdown_list
+            .first()
+            .filter(|b| b.usage_limit > 0.0)
+            .map(|b| {
+                let resets_at = b
+                    .next_date_reset
+                    .or(usage.next_date_reset)
+                    .and_then(|ts| Utc.timestamp_opt(ts as i64, 0).single())
+                    .unwrap_or_else(|| next_month_start(now));
+                RateWindow {
+                    kind: WindowKind::Other("monthly".to_string()),
+                    used_percent: (b.current_usage / b.usage_limit * 100.0).clamp(0.0, 100.0),
+                    window_minutes: None,
+                    resets_at: Some(resets_at),
+                    reset_description: Some(timefmt::relative(resets_at, now, RelativeStyle::Long)),
+                    granularity: Granularity::Unknown,
+                    label: Some("Monthly interactions".to_string()),
+                    used: Some(b.current_usage),
+                    limit: Some(b.usage_limit),
+                    unit: Some("interactions".to_string()),
+                }
+            });
+
+        let plan = usage
+            .subscription_info
+            .and_then(|s| s.subscription_title)
+            .and_then(|t| normalize_plan_name(&t));
+
+        Ok(UsageSnapshot {
+            provider: Provider::Kiro,
+            windows: interactions.into_iter().collect(),
+            cost: None,
+            identity: Some(IdentitySnapshot {
+                email: None,
+                plan,
+                organization: None,
+                account_id: None,
+            }),
+            rate_limit,
+            busiest_member: None,
+            origin: None,
+            updated_at: now,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "Kiro"
+    }
+
+    fn diagnose(&self, now: DateTime<Utc>) -> Vec<Check> {
+        vec![token_check(&Self::sso_cache_dir(), now)]
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        let region = Self::load_token()
+            .ok()
+            .and_then(|t| t.region)
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        Some(format!(
+            "https://codewhisperer.{}.amazonaws.com{}",
+            region, USAGE_PATH
+        ))
+    }
+}
+
+fn token_check(dir: &Path, now: DateTime<Utc>) -> Check {
+    const HINT: &str = "Sign in to Kiro again to refresh the SSO token";
+    if !dir.exists() {
+        return Check::fail("Token", format!("{} not found", dir.display()), HINT);
+    }
+    match select_token(dir, now) {
+        Ok(token) => Check::pass(
+            "Token",
+            match token.expires_at() {
+                Some(expires_at) => format!("valid for {}", doctor::approx(expires_at - now)),
+                None => "valid".to_string(),
+            },
+        ),
+        Err(e) => Check::fail("Token", format!("{:#}", e), HINT),
+    }
+}
+
+/// Pick the newest non-expired token in the SSO cache directory
+fn select_token(dir: &Path, now: DateTime<Utc>) -> Result<SsoToken> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| std::fs::read_to_string(&path).ok())
+        .filter_map(|content| serde_json::from_str::<SsoToken>(&content).ok())
+        .filter(|token| {
+            token
+                .access_token
+                .as_ref()
+                .is_some_and(|t| !t.expose().is_empty())
+        })
+        .filter_map(|token| token.expires_at().map(|exp| (exp, token)))
+        .filter(|(exp, _)| *exp > now)
+        .max_by_key(|(exp, _)| *exp)
+        .map(|(_, token)| token)
+        .ok_or_else(|| anyhow!("No valid Kiro SSO token found. Sign in to Kiro again."))
+}
+
+fn next_month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn write_token(dir: &Path, name: &str, access_token: Option<&str>, expires: DateTime<Utc>) {
+        let mut value = serde_json::json!({
+            "expiresAt": expires.to_rfc3339(),
+            "region": "us-east-1",
+        });
+        if let Some(token) = access_token {
+            value["accessToken"] = serde_json::json!(token);
+        }
+        std::fs::write(dir.join(name), value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_token_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        assert!(!token_check(&dir.path().join("missing"), now).ok);
+        assert!(!token_check(dir.path(), now).ok);
+
+        write_token(dir.path(), "a.json", Some("tok"), now - Duration::hours(1));
+        assert!(!token_check(dir.path(), now).ok);
+        write_token(dir.path(), "b.json", Some("tok"), now + Duration::hours(2));
+        let check = token_check(dir.path(), now);
+        assert!(check.ok);
+        assert_eq!(check.detail, "valid for 2h");
+    }
+
+    #[test]
+    fn test_select_newest_valid_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        write_token(
+            dir.path(),
+            "old.json",
+            Some("old"),
+            now + Duration::hours(1),
+        );
+        write_token(
+            dir.path(),
+            "new.json",
+            Some("new"),
+            now + Duration::hours(8),
+        );
+        write_token(
+            dir.path(),
+            "expired.json",
+            Some("gone"),
+            now - Duration::hours(1),
+        );
+
+        let token = select_token(dir.path(), now).unwrap();
+        assert_eq!(token.access_token.unwrap().expose(), "new");
+    }
+
+    #[test]
+    fn test_select_skips_client_registrations_and_junk() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        // Client registration files carry an expiry but no access token
+        write_token(dir.path(), "client.json", None, now + Duration::days(90));
+        write_token(
+            dir.path(),
+            "token.json",
+            Some("tok"),
+            now + Duration::hours(1),
+        );
+        std::fs::write(dir.path().join("broken.json"), "{not json").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let token = select_token(dir.path(), now).unwrap();
+        assert_eq!(token.access_token.unwrap().expose(), "tok");
+    }
+
+    #[test]
+    fn test_select_errors_when_all_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        write_token(dir.path(), "a.json", Some("a"), now - Duration::minutes(5));
+        assert!(select_token(dir.path(), now).is_err());
+    }
+
+    #[test]
+    fn test_next_month_start() {
+        let dec = Utc.with_ymd_and_hms(2025, 12, 15, 10, 0, 0).unwrap();
+        assert_eq!(
+            next_month_start(dec),
+            Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+        );
+        let mar = Utc.with_ymd_and_hms(2026, 3, 31, 23, 59, 0).unwrap();
+        assert_eq!(
+            next_month_start(mar),
+            Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap()
+        );
+    }
+}
@@ -1,12 +1,466 @@
 pub mod claude;
+pub mod claude_org;
 pub mod codex;
+#[cfg(feature = "aws")]
+pub mod kiro;
+pub mod windsurf;
 
-use crate::models::UsageSnapshot;
+use crate::config::{Config, ProviderConfig};
+use crate::doctor::Check;
+use crate::logging::Redacted;
+use crate::models::{Provider, UsageSnapshot};
+use crate::origin::Origin;
+use crate::timefmt::{self, ClockFormat};
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[async_trait]
 pub trait ProviderFetcher: Send + Sync {
     async fn fetch(&self) -> Result<UsageSnapshot>;
     fn name(&self) -> &'static str;
+
+    /// Local checks for `quotabar doctor`: credentials present, parseable,
+    /// and not expired
+    fn diagnose(&self, _now: DateTime<Utc>) -> Vec<Check> {
+        Vec::new()
+    }
+
+    /// URL `quotabar doctor` probes for reachability
+    fn endpoint(&self) -> Option<String> {
+        None
+    }
+}
+
+/// `RequestBuilder::send` that logs the method, redacted URL, status and
+/// timing of every provider request at debug level
+pub trait SendLogged {
+    fn send_logged(self) -> impl Future<Output = reqwest::Result<reqwest::Response>> + Send;
+}
+
+impl SendLogged for reqwest::RequestBuilder {
+    async fn send_logged(self) -> reqwest::Result<reqwest::Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let method = request.method().clone();
+        let url = crate::logging::redact_url(request.url());
+        let started = Instant::now();
+        let result = client.execute(request).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(ref response) => tracing::debug!(
+                %method,
+                %url,
+                status = response.status().as_u16(),
+                elapsed_ms,
+                "HTTP response"
+            ),
+            Err(ref e) => {
+                tracing::debug!(%method, %url, elapsed_ms, error = %e, "HTTP request failed")
+            }
+        }
+        result
+    }
+}
+
+/// Limits on provider requests. A refresh holds the cache lock while it
+/// fetches, so a stalled endpoint must give up well before other processes
+/// stop waiting for that lock.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const _: () = assert!(REQUEST_TIMEOUT.as_secs() < crate::cache::LOCK_TIMEOUT.as_secs());
+
+/// The HTTP client every provider fetches with
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to initialize the HTTP client")
+}
+
+/// Saved raw responses are cut short past this size
+const RAW_MAX_BYTES: usize = 256 * 1024;
+
+/// `Response::json` that, given the `debug_responses` directory, first keeps
+/// the body in `<raw_dir>/<name>-last.json` and names that file in parse
+/// errors. Only the
+/// body is saved, never the request or its headers.
+pub trait JsonRecorded {
+    fn json_recorded<T: DeserializeOwned>(
+        self,
+        name: &str,
+        raw_dir: Option<&Path>,
+    ) -> impl Future<Output = Result<T>> + Send;
+}
+
+impl JsonRecorded for reqwest::Response {
+    async fn json_recorded<T: DeserializeOwned>(
+        self,
+        name: &str,
+        raw_dir: Option<&Path>,
+    ) -> Result<T> {
+        let body = self.bytes().await?;
+        let saved = raw_dir.and_then(|dir| match save_raw(dir, name, &body) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                tracing::warn!("could not save the raw {} response: {:#}", name, e);
+                None
+            }
+        });
+        parse_raw(&body, saved.as_deref())
+    }
+}
+
+/// Overwrite `<dir>/<name>-last.json` with `body`, or its first
+/// [`RAW_MAX_BYTES`]
+fn save_raw(dir: &Path, name: &str, body: &[u8]) -> Result<PathBuf> {
+    let path = dir.join(format!("{}-last.json", name));
+    crate::files::write_atomic(&path, &body[..body.len().min(RAW_MAX_BYTES)])?;
+    tracing::debug!(path = %path.display(), bytes = body.len(), "saved raw response");
+    Ok(path)
+}
+
+/// Parse a response body; errors point at the saved copy, if there is one
+fn parse_raw<T: DeserializeOwned>(body: &[u8], saved: Option<&Path>) -> Result<T> {
+    serde_json::from_slice(body).map_err(|e| match saved {
+        Some(path) => anyhow::anyhow!("{} (response saved in {})", e, path.display()),
+        None => e.into(),
+    })
+}
+
+/// Provider failures that callers handle specially. Returned wrapped in
+/// `anyhow::Error`; recover with `downcast_ref`.
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error(
+        "rate limited, retrying after {}",
+        timefmt::clock(&.until.with_timezone(&Local), ClockFormat::default())
+    )]
+    RateLimited { until: DateTime<Utc> },
+    /// No fetcher in this build
+    #[error("{0}")]
+    Unsupported(String),
+    /// Credentials were rejected (HTTP 401/403); retrying won't help until
+    /// the user signs in again
+    #[error("{0}")]
+    Unauthorized(String),
+}
+
+/// When a rate-limited fetch may be retried, if `error` is a 429
+pub fn rate_limited_until(error: &anyhow::Error) -> Option<DateTime<Utc>> {
+    match error.downcast_ref::<ProviderError>() {
+        Some(ProviderError::RateLimited { until }) => Some(*until),
+        _ => None,
+    }
+}
+
+/// Whether `error` means the provider rejected our credentials
+pub fn is_unauthorized(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ProviderError>(),
+        Some(ProviderError::Unauthorized(_))
+    )
+}
+
+/// A one-line reason for a failed fetch, for places with no room for the
+/// whole error chain (bar tooltips)
+pub fn short_reason(error: &anyhow::Error) -> String {
+    if let Some(e) = error.downcast_ref::<ProviderError>() {
+        return e.to_string();
+    }
+    let network = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>());
+    match network {
+        Some(e) if e.is_timeout() => "network timeout".to_string(),
+        Some(e) if e.is_connect() => "could not connect".to_string(),
+        _ => crate::logging::redact(&error.to_string()),
+    }
+}
+
+/// Where a provider's credentials come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum CredentialSource {
+    /// `[providers.<name>] token_env`: the variable holds the bearer token
+    Env {
+        var: String,
+        token: Redacted<String>,
+    },
+    /// A credentials file, and what chose it ("default" for the usual place)
+    File { path: PathBuf, from: &'static str },
+}
+
+impl CredentialSource {
+    /// The token in the provider's `token_env` variable, when that is
+    /// configured, set and non-empty
+    pub fn from_token_env(
+        config: Option<&ProviderConfig>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Option<Self> {
+        let var = config?.token_env.as_deref()?.trim();
+        let token = env(var).map(|t| t.trim().to_string());
+        match token.filter(|t| !t.is_empty()) {
+            Some(token) => Some(CredentialSource::Env {
+                var: var.to_string(),
+                token: token.into(),
+            }),
+            None => {
+                tracing::debug!(var, "token_env is unset or empty, reading credentials file");
+                None
+            }
+        }
+    }
+
+    /// Credentials from the token, or read from the file with `read`;
+    /// errors name the setting that chose a non-default file
+    pub fn load<T>(
+        &self,
+        from_token: impl FnOnce(&str) -> T,
+        read: impl FnOnce(&Path) -> Result<T>,
+    ) -> Result<T> {
+        match self {
+            CredentialSource::Env { token, .. } => Ok(from_token(token.expose())),
+            CredentialSource::File {
+                path,
+                from: "default",
+            } => read(path),
+            CredentialSource::File { path, from } => {
+                read(path).map_err(|e| anyhow::anyhow!("{:#} (path from {})", e, from))
+            }
+        }
+    }
+
+    /// The variable or file, for [`credentials_id`]
+    pub fn id(&self) -> String {
+        match self {
+            CredentialSource::Env { var, .. } => format!("${}", var),
+            CredentialSource::File { path, .. } => path.display().to_string(),
+        }
+    }
+
+    /// Doctor checks for a token from the environment, which has no file to
+    /// parse or expiry to read
+    pub fn env_checks(var: &str) -> Vec<Check> {
+        vec![
+            Check::pass("Credentials", format!("token from ${}", var)),
+            Check::pass("Token", "expiry not checked"),
+        ]
+    }
+}
+
+/// Build the fetcher for a provider, if one is implemented
+pub fn fetcher_for(provider: Provider, config: &Config) -> Option<Box<dyn ProviderFetcher>> {
+    match provider {
+        Provider::Claude => Some(Box::new(claude::ClaudeProvider::from_config(config))),
+        Provider::ClaudeOrg => Some(Box::new(claude_org::ClaudeOrgProvider::from_config(config))),
+        Provider::Codex => Some(Box::new(codex::CodexProvider::from_config(config))),
+        Provider::Windsurf => Some(Box::new(windsurf::WindsurfProvider::from_config(config))),
+        #[cfg(feature = "aws")]
+        Provider::Kiro => Some(Box::new(kiro::KiroProvider::from_config(config))),
+        #[cfg(not(feature = "aws"))]
+        Provider::Kiro => None,
+        Provider::OpenCode => None,
+    }
+}
+
+/// Which credentials a provider's fetches use, e.g. the file they're read
+/// from, without building its fetcher; only ever hashed into an [`Origin`]
+pub fn credentials_id(provider: Provider, config: &Config) -> Option<String> {
+    match provider {
+        Provider::Claude => Some(claude::credentials_id(config)),
+        Provider::ClaudeOrg => claude_org::credentials_id(config),
+        Provider::Codex => Some(codex::credentials_id(config)),
+        Provider::Windsurf => Some(windsurf::credentials_id()),
+        #[cfg(feature = "aws")]
+        Provider::Kiro => Some(kiro::credentials_id()),
+        #[cfg(not(feature = "aws"))]
+        Provider::Kiro => None,
+        Provider::OpenCode => None,
+    }
+}
+
+/// Fetch a fresh snapshot for a single provider, stamped with its [`Origin`]
+/// unless `track_origin` is off
+pub async fn fetch(provider: Provider, config: &Config) -> Result<UsageSnapshot> {
+    let fetcher = fetcher_for(provider, config).ok_or_else(|| {
+        ProviderError::Unsupported(match provider {
+            Provider::Kiro => "Kiro support requires building with `--features aws`".to_string(),
+            _ => format!("{} is not supported yet", provider.display_name()),
+        })
+    })?;
+    let started = Instant::now();
+    let mut result = fetcher.fetch().await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(_) => tracing::debug!(provider = provider.id(), elapsed_ms, "fetched"),
+        Err(ref e) => tracing::debug!(
+            provider = provider.id(),
+            elapsed_ms,
+            "fetch failed: {:#}",
+            e
+        ),
+    }
+    if let Ok(ref mut snapshot) = result {
+        if config.general.track_origin {
+            snapshot.origin = Some(Origin::local(provider, config));
+        }
+    }
+    result
+}
+
+/// Map a raw plan/tier string from any provider to a display label, e.g.
+/// "plus" -> "Plus", "default_claude_max_20x" -> "Max", "KIRO PRO+" -> "Pro+".
+/// Unrecognised values are passed through trimmed; blank ones give None.
+pub fn normalize_plan_name(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let lower = trimmed.to_lowercase();
+
+    // Exact identifiers first
+    let exact = match lower.as_str() {
+        "free" => Some("Free"),
+        "plus" => Some("Plus"),
+        "pro" => Some("Pro"),
+        "team" => Some("Team"),
+        "enterprise" => Some("Enterprise"),
+        "business" => Some("Business"),
+        "education" => Some("Education"),
+        "go" => Some("Go"),
+        "guest" => Some("Guest"),
+        "free_workspace" => Some("Free Workspace"),
+        "k12" => Some("K-12"),
+        "quorum" => Some("Quorum"),
+        "edu" => Some("Edu"),
+        _ => None,
+    };
+    if let Some(label) = exact {
+        return Some(label.to_string());
+    }
+
+    // Then tier strings that embed the plan name
+    let label = if lower.contains("enterprise") {
+        "Enterprise"
+    } else if lower.contains("team") {
+        "Team"
+    } else if lower.contains("power") {
+        "Power"
+    } else if lower.contains("max") {
+        "Max"
+    } else if lower.contains("pro+") || lower.contains("pro plus") {
+        "Pro+"
+    } else if lower.contains("pro") {
+        "Pro"
+    } else if lower.contains("free") {
+        "Free"
+    } else {
+        trimmed
+    };
+    Some(label.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_reason() {
+        let auth = anyhow::Error::from(ProviderError::Unauthorized(
+            "Token expired. Run `claude login`.".to_string(),
+        ))
+        .context("Claude fetch failed");
+        assert_eq!(short_reason(&auth), "Token expired. Run `claude login`.");
+
+        // Only the outermost message, with secrets masked
+        let other = anyhow::anyhow!("bad key sk-abcdefghijklmnop").context("Failed to parse");
+        assert_eq!(short_reason(&other), "Failed to parse");
+        let other = anyhow::anyhow!("bad key sk-abcdefghijklmnop");
+        assert_eq!(short_reason(&other), "bad key sk-a…");
+    }
+
+    #[test]
+    fn test_save_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = dir.path().join("raw");
+        let path = save_raw(&raw, "claude", br#"{"five_hour": null}"#).unwrap();
+        assert_eq!(path, raw.join("claude-last.json"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"five_hour": null}"#
+        );
+
+        // Each fetch replaces the last, and a huge body is cut short
+        save_raw(&raw, "claude", &vec![b' '; RAW_MAX_BYTES * 2]).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            RAW_MAX_BYTES as u64
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_saved_response() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Usage {
+            #[allow(dead_code)]
+            five_hour: f64,
+        }
+        let body = br#"{"five_hour_v2": 12}"#;
+        let saved = Path::new("/c/quotabar/raw/claude-last.json");
+
+        let error = parse_raw::<Usage>(body, Some(saved))
+            .unwrap_err()
+            .context("Failed to parse usage response");
+        assert_eq!(
+            format!("{:#}", error),
+            "Failed to parse usage response: missing field `five_hour` at line 1 column 20 \
+             (response saved in /c/quotabar/raw/claude-last.json)"
+        );
+
+        let error = parse_raw::<Usage>(body, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "missing field `five_hour` at line 1 column 20"
+        );
+        assert!(parse_raw::<Usage>(br#"{"five_hour": 1.5}"#, Some(saved)).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_exact_identifiers() {
+        assert_eq!(normalize_plan_name("plus").as_deref(), Some("Plus"));
+        assert_eq!(normalize_plan_name(" K12 ").as_deref(), Some("K-12"));
+        assert_eq!(
+            normalize_plan_name("free_workspace").as_deref(),
+            Some("Free Workspace")
+        );
+    }
+
+    #[test]
+    fn test_normalize_tier_strings() {
+        assert_eq!(
+            normalize_plan_name("default_claude_max_20x").as_deref(),
+            Some("Max")
+        );
+        assert_eq!(
+            normalize_plan_name("claude_enterprise").as_deref(),
+            Some("Enterprise")
+        );
+        assert_eq!(normalize_plan_name("KIRO PRO+").as_deref(), Some("Pro+"));
+        assert_eq!(normalize_plan_name("Kiro Power").as_deref(), Some("Power"));
+        assert_eq!(
+            normalize_plan_name("Kiro Free Tier").as_deref(),
+            Some("Free")
+        );
+    }
+
+    #[test]
+    fn test_normalize_passthrough_and_blank() {
+        assert_eq!(normalize_plan_name(" Custom ").as_deref(), Some("Custom"));
+        assert_eq!(normalize_plan_name("   "), None);
+    }
 }
@@ -0,0 +1,341 @@
+use crate::config::Config;
+use crate::doctor::Check;
+use crate::logging::Redacted;
+use crate::models::{
+    CostSnapshot, Granularity, IdentitySnapshot, Provider, RateWindow, UsageSnapshot, WindowKind,
+};
+use crate::providers::{
+    http_client, normalize_plan_name, JsonRecorded, ProviderError, ProviderFetcher, SendLogged,
+};
+use crate::ratelimit::RateLimitInfo;
+use crate::timefmt::{self, RelativeStyle};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+const API_URL: &str =
+    "https://server.codeium.com/exa.seat_management_pb.SeatManagementService/GetUserStatus";
+const USER_AGENT: &str = "quotabar";
+/// The API reports credits in hundredths
+const CREDIT_SCALE: f64 = 100.0;
+
+/// Codeium/Windsurf config from ~/.codeium/config.json
+#[derive(Debug, Deserialize)]
+struct ConfigFile {
+    #[serde(alias = "apiKey")]
+    api_key: Option<Redacted<String>>,
+}
+
+/// API response from GetUserStatus
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusResponse {
+    user_status: Option<UserStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserStatus {
+    email: Option<String>,
+    plan_status: Option<PlanStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanStatus {
+    plan_info: Option<PlanInfo>,
+    /// Hundredths of a credit; negative means unlimited
+    available_prompt_credits: Option<i64>,
+    used_prompt_credits: Option<i64>,
+    available_flex_credits: Option<i64>,
+    used_flex_credits: Option<i64>,
+    plan_end: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanInfo {
+    plan_name: Option<String>,
+    #[serde(default)]
+    has_unlimited_prompt_credits: bool,
+}
+
+pub struct WindsurfProvider {
+    client: reqwest::Client,
+    /// Where raw responses are kept, with `debug_responses` on
+    raw_dir: Option<PathBuf>,
+}
+
+impl WindsurfProvider {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            client: http_client(),
+            raw_dir: config.debug_responses_dir(),
+        }
+    }
+
+    fn credentials_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".codeium")
+            .join("config.json")
+    }
+
+    fn load_api_key() -> Result<String> {
+        load_api_key_from(&Self::credentials_path())
+    }
+
+    async fn fetch_status(&self, api_key: &str) -> Result<(StatusResponse, Option<RateLimitInfo>)> {
+        let body = json!({
+            "metadata": {
+                "apiKey": api_key,
+                "ideName": "windsurf",
+                "extensionName": "quotabar",
+                "extensionVersion": env!("CARGO_PKG_VERSION"),
+                "locale": "en",
+            }
+        });
+        let response = self
+            .client
+            .post(API_URL)
+            .header("Accept", "application/json")
+            .header("User-Agent", USER_AGENT)
+            .json(&body)
+            .send_logged()
+            .await
+            .context("Failed to connect to Windsurf API")?;
+
+        let rate_limit = RateLimitInfo::from_headers(response.headers(), Utc::now());
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = crate::ratelimit::retry_after(response.headers(), Utc::now());
+            return Err(ProviderError::RateLimited { until }.into());
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(ProviderError::Unauthorized(
+                "Windsurf API key expired or invalid. Sign in to Windsurf again.".to_string(),
+            )
+            .into());
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Windsurf API error ({}): {}", status, body));
+        }
+
+        let parsed = response
+            .json_recorded("windsurf", self.raw_dir.as_deref())
+            .await
+            .context("Failed to parse Windsurf status response")?;
+        Ok((parsed, rate_limit))
+    }
+}
+
+/// [`super::credentials_id`] for Windsurf
+pub fn credentials_id() -> String {
+    WindsurfProvider::credentials_path().display().to_string()
+}
+
+#[async_trait]
+impl ProviderFetcher for WindsurfProvider {
+    async fn fetch(&self) -> Result<UsageSnapshot> {
+        let api_key = Self::load_api_key()?;
+        let (response, rate_limit) = self.fetch_status(&api_key).await?;
+        let mut snapshot = snapshot_from_status(response, Utc::now())?;
+        snapshot.rate_limit = rate_limit;
+        Ok(snapshot)
+    }
+
+    fn name(&self) -> &'static str {
+        "Windsurf"
+    }
+
+    fn diagnose(&self, _now: DateTime<Utc>) -> Vec<Check> {
+        vec![credential_check(&Self::credentials_path())]
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        Some(API_URL.to_string())
+    }
+}
+
+fn load_api_key_from(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Err(anyhow!(
+            "Windsurf credentials not found at {}. Sign in to Windsurf first.",
+            path.display()
+        ));
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: ConfigFile =
+        serde_json::from_str(&content).with_context(|| "Failed to parse Windsurf config JSON")?;
+
+    config
+        .api_key
+        .map(|k| k.expose().trim().to_string())
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow!("No Windsurf API key found. Sign in to Windsurf first."))
+}
+
+fn credential_check(path: &Path) -> Check {
+    match load_api_key_from(path) {
+        Ok(_) => Check::pass("Credentials", format!("{} has an API key", path.display())),
+        Err(e) => Check::fail(
+            "Credentials",
+            format!("{:#}", e),
+            "Sign in to Windsurf so it writes ~/.codeium/config.json",
+        ),
+    }
+}
+
+fn snapshot_from_status(response: StatusResponse, now: DateTime<Utc>) -> Result<UsageSnapshot> {
+    let user = response
+        .user_status
+        .ok_or_else(|| anyhow!("Windsurf response missing user status"))?;
+    let plan = user
+        .plan_status
+        .ok_or_else(|| anyhow!("Windsurf response missing plan status"))?;
+
+    let resets_at = plan
+        .plan_end
+        .as_deref()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+
+    let unlimited = plan
+        .plan_info
+        .as_ref()
+        .is_some_and(|p| p.has_unlimited_prompt_credits)
+        || plan.available_prompt_credits.is_some_and(|c| c < 0);
+
+    // Monthly prompt credits; unlimited tiers have no meaningful window
+    let credits = match (plan.available_prompt_credits, unlimited) {
+        (Some(available), false) if available > 0 => {
+            let used = plan.used_prompt_credits.unwrap_or(0) as f64;
+            Some(RateWindow {
+                kind: WindowKind::Other("monthly".to_string()),
+                used_percent: (used / available as f64 * 100.0).clamp(0.0, 100.0),
+                window_minutes: None,
+                resets_at,
+                reset_description: resets_at
+                    .map(|dt| timefmt::relative(dt, now, RelativeStyle::Long)),
+                granularity: Granularity::Unknown,
+                label: Some("Monthly credits".to_string()),
+                used: Some(used / CREDIT_SCALE),
+                limit: Some(available as f64 / CREDIT_SCALE),
+                unit: Some("credits".to_string()),
+            })
+        }
+        _ => None,
+    };
+
+    // Add-on (flex) credits bought on top of the plan
+    let cost = plan
+        .available_flex_credits
+        .filter(|available| *available > 0)
+        .map(|available| CostSnapshot {
+            used: plan.used_flex_credits.unwrap_or(0) as f64 / CREDIT_SCALE,
+            limit: available as f64 / CREDIT_SCALE,
+            currency_code: "credits".to_string(),
+            period: Some("Add-on".to_string()),
+            resets_at: None,
+            extra_usage: false,
+        });
+
+    Ok(UsageSnapshot {
+        provider: Provider::Windsurf,
+        windows: credits.into_iter().collect(),
+        cost,
+        identity: Some(IdentitySnapshot {
+            email: user.email.filter(|e| !e.is_empty()),
+            plan: plan
+                .plan_info
+                .and_then(|p| p.plan_name)
+                .and_then(|p| normalize_plan_name(&p)),
+            organization: None,
+            account_id: None,
+        }),
+        rate_limit: None,
+        busiest_member: None,
+        origin: None,
+        updated_at: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> UsageSnapshot {
+        let response: StatusResponse = serde_json::from_str(json).unwrap();
+        snapshot_from_status(response, Utc::now()).unwrap()
+    }
+
+    #[test]
+    fn test_credential_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        assert!(!credential_check(&path).ok);
+
+        std::fs::write(&path, r#"{"apiKey": "  "}"#).unwrap();
+        assert!(!credential_check(&path).ok);
+
+        std::fs::write(&path, r#"{"apiKey": "sk-ws-01-secret"}"#).unwrap();
+        let check = credential_check(&path);
+        assert!(check.ok);
+        assert!(!check.detail.contains("secret"));
+    }
+
+    #[test]
+    fn test_prompt_credits_window() {
+        let snapshot = parse(
+            r#"{"userStatus": {"email": "a@b.c", "planStatus": {
+                "planInfo": {"planName": "Pro"},
+                "availablePromptCredits": 50000,
+                "usedPromptCredits": 12500,
+                "planEnd": "2030-01-01T00:00:00Z"
+            }}}"#,
+        );
+        let window = snapshot.primary().unwrap();
+        assert_eq!(window.used_percent, 25.0);
+        assert_eq!(window.counts_text().as_deref(), Some("125 / 500 credits"));
+        assert!(window.resets_at.is_some());
+        assert!(snapshot.cost.is_none());
+        assert_eq!(snapshot.identity.unwrap().plan.as_deref(), Some("Pro"));
+    }
+
+    #[test]
+    fn test_unlimited_omits_window() {
+        let snapshot = parse(
+            r#"{"userStatus": {"planStatus": {
+                "planInfo": {"planName": "Enterprise", "hasUnlimitedPromptCredits": true},
+                "availablePromptCredits": 0,
+                "usedPromptCredits": 0
+            }}}"#,
+        );
+        assert!(snapshot.windows.is_empty());
+
+        let snapshot = parse(r#"{"userStatus": {"planStatus": {"availablePromptCredits": -1}}}"#);
+        assert!(snapshot.windows.is_empty());
+    }
+
+    #[test]
+    fn test_flex_credits_become_cost() {
+        let snapshot = parse(
+            r#"{"userStatus": {"planStatus": {
+                "availablePromptCredits": 100,
+                "usedPromptCredits": 100,
+                "availableFlexCredits": 25000,
+                "usedFlexCredits": 5000
+            }}}"#,
+        );
+        let cost = snapshot.cost.unwrap();
+        assert_eq!(cost.used, 50.0);
+        assert_eq!(cost.limit, 250.0);
+        assert_eq!(snapshot.windows[0].used_percent, 100.0);
+    }
+}
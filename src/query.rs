@@ -0,0 +1,186 @@
+use crate::models::{Provider, RateWindow, UsageSnapshot};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Split `claude.primary.used_percent` into the provider and the field path
+pub fn parse_path(path: &str) -> Result<(Provider, Vec<&str>)> {
+    let mut parts = path.trim().split('.');
+    let id = parts.next().unwrap_or_default();
+    let provider = Provider::ALL
+        .into_iter()
+        .find(|p| p.id() == id)
+        .with_context(|| format!("Unknown provider {:?} in {:?}", id, path))?;
+    let fields: Vec<&str> = parts.collect();
+    if fields.iter().any(|f| f.is_empty()) {
+        bail!("Empty path segment in {:?}", path);
+    }
+    Ok((provider, fields))
+}
+
+/// Resolve a dotted path against the snapshots. Leaves print bare (numbers
+/// as-is, strings unquoted); objects print as compact JSON.
+pub fn get(
+    snapshots: &HashMap<Provider, UsageSnapshot>,
+    path: &str,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    let (provider, fields) = parse_path(path)?;
+    let snapshot = snapshots
+        .get(&provider)
+        .with_context(|| format!("No cached data for {}", provider.display_name()))?;
+    let mut value = snapshot_value(snapshot, now)?;
+    for field in &fields {
+        value = match value {
+            Value::Object(mut map) => map.remove(*field),
+            _ => None,
+        }
+        .filter(|v| !v.is_null())
+        .with_context(|| format!("{:?} does not resolve", path))?;
+    }
+    Ok(render(&value))
+}
+
+/// The snapshot as JSON, with computed fields added to each window and the
+/// cost. Each window is also under its kind (`session`, `week`, `sonnet`,
+/// `oauth_apps`, ...), and the `primary`/`secondary`/`tertiary`/`model`
+/// aliases name the main, weekly and first model window.
+fn snapshot_value(snapshot: &UsageSnapshot, now: DateTime<Utc>) -> Result<Value> {
+    let mut value = serde_json::to_value(snapshot)?;
+    let Value::Object(ref mut map) = value else {
+        return Ok(value);
+    };
+    let windows = snapshot
+        .windows
+        .iter()
+        .map(|w| window_value(w, now))
+        .collect::<Result<Vec<_>>>()?;
+    // The first window of a kind takes its name, never a snapshot field
+    for (window, value) in snapshot.windows.iter().zip(&windows) {
+        map.entry(window.kind.id()).or_insert_with(|| value.clone());
+    }
+    let aliases = [
+        ("primary", snapshot.primary()),
+        ("secondary", snapshot.secondary()),
+        ("tertiary", snapshot.model_window()),
+        ("model", snapshot.model_window()),
+    ];
+    for (alias, window) in aliases {
+        if let Some(window) = window {
+            map.insert(alias.to_string(), window_value(window, now)?);
+        }
+    }
+    map.insert("windows".to_string(), Value::Array(windows));
+    if let (Some(cost), Some(Value::Object(cost_map))) = (&snapshot.cost, map.get_mut("cost")) {
+        cost_map.insert("used_percent".to_string(), cost.used_percent().into());
+        cost_map.insert(
+            "remaining".to_string(),
+            (cost.limit > 0.0)
+                .then(|| (cost.limit - cost.used).max(0.0))
+                .into(),
+        );
+    }
+    Ok(value)
+}
+
+fn window_value(window: &RateWindow, now: DateTime<Utc>) -> Result<Value> {
+    let mut value = serde_json::to_value(window)?;
+    if let Value::Object(ref mut map) = value {
+        map.insert(
+            "remaining_percent".to_string(),
+            window.remaining_percent().into(),
+        );
+        map.insert(
+            "resets_in_seconds".to_string(),
+            window
+                .resets_at
+                .map(|at| (at - now).num_seconds().max(0))
+                .into(),
+        );
+    }
+    Ok(value)
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        // f64 Display prints 72.0 as "72" and keeps full precision otherwise
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.to_string(),
+            None => n.as_f64().map_or_else(|| n.to_string(), |f| f.to_string()),
+        },
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use crate::models::WindowKind;
+
+    fn get_at_update(path: &str) -> Result<String> {
+        let snapshots = mock_snapshots();
+        let now = snapshots[&Provider::Claude].updated_at;
+        get(&snapshots, path, now)
+    }
+
+    #[test]
+    fn test_window_paths() {
+        assert_eq!(get_at_update("claude.primary.used_percent").unwrap(), "72");
+        assert_eq!(
+            get_at_update("claude.primary.remaining_percent").unwrap(),
+            "28"
+        );
+        assert_eq!(
+            get_at_update("claude.session.resets_in_seconds").unwrap(),
+            "18000"
+        );
+        assert_eq!(
+            get_at_update("claude.week.used_percent").unwrap(),
+            get_at_update("claude.secondary.used_percent").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_windows_by_kind() {
+        let mut snapshots = mock_snapshots();
+        let claude = snapshots.get_mut(&Provider::Claude).unwrap();
+        claude.windows.push(RateWindow {
+            kind: WindowKind::Model("opus".to_string()),
+            used_percent: 12.0,
+            ..claude.windows[1].clone()
+        });
+        let now = Utc::now();
+        let get = |path| get(&snapshots, path, now).unwrap();
+        assert_eq!(get("claude.opus.used_percent"), "12");
+        assert_eq!(get("claude.model.used_percent"), "12");
+        assert_eq!(get("claude.tertiary.used_percent"), "12");
+        assert!(get("claude.windows").contains(r#""kind":{"model":"opus"}"#));
+    }
+
+    #[test]
+    fn test_cost_and_identity_paths() {
+        assert_eq!(get_at_update("claude.cost.used").unwrap(), "42.5");
+        assert_eq!(get_at_update("claude.cost.remaining").unwrap(), "57.5");
+        assert_eq!(get_at_update("claude.cost.currency_code").unwrap(), "USD");
+        assert_eq!(get_at_update("claude.identity.plan").unwrap(), "Max");
+        let primary = get_at_update("claude.primary").unwrap();
+        assert!(primary.starts_with('{'));
+    }
+
+    #[test]
+    fn test_unresolved_paths() {
+        // Missing provider data, unknown provider, missing window and field
+        let mut snapshots = mock_snapshots();
+        snapshots.remove(&Provider::Codex);
+        let now = Utc::now();
+        assert!(get(&snapshots, "codex.primary.used_percent", now).is_err());
+        assert!(get(&snapshots, "nope.primary", now).is_err());
+        assert!(get(&snapshots, "claude.tertiary.used_percent", now).is_err());
+        assert!(get(&snapshots, "claude.primary.bogus", now).is_err());
+        assert!(get(&snapshots, "claude.primary.used_percent.x", now).is_err());
+        assert!(get(&snapshots, "claude..used_percent", now).is_err());
+    }
+}
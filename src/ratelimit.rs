@@ -0,0 +1,313 @@
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::HeaderMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Below this many remaining requests the poll interval is stretched
+const REMAINING_FLOOR: u64 = 5;
+/// Backoff after a 429 that carries no usable Retry-After header
+const DEFAULT_RETRY_AFTER_SECS: i64 = 60;
+
+/// Rate-limit headroom reported by a provider's usage endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimitInfo {
+    /// Requests allowed in the current window
+    pub limit: Option<u64>,
+    /// Requests left in the current window
+    pub remaining: Option<u64>,
+    /// When the window resets
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+impl RateLimitInfo {
+    /// Parse `anthropic-ratelimit-requests-*` or `x-ratelimit-*` headers
+    pub fn from_headers(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Self> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let number = |name: &str| get(name).and_then(|v| v.parse::<u64>().ok());
+
+        let info = if get("anthropic-ratelimit-requests-remaining").is_some() {
+            RateLimitInfo {
+                limit: number("anthropic-ratelimit-requests-limit"),
+                remaining: number("anthropic-ratelimit-requests-remaining"),
+                resets_at: get("anthropic-ratelimit-requests-reset").and_then(|v| {
+                    DateTime::parse_from_rfc3339(v)
+                        .ok()
+                        .map(|dt| dt.with_timezone(&Utc))
+                }),
+            }
+        } else if get("x-ratelimit-remaining-requests").is_some() {
+            RateLimitInfo {
+                limit: number("x-ratelimit-limit-requests"),
+                remaining: number("x-ratelimit-remaining-requests"),
+                resets_at: get("x-ratelimit-reset-requests")
+                    .and_then(parse_reset_duration)
+                    .map(|d| now + d),
+            }
+        } else if get("x-ratelimit-remaining").is_some() {
+            RateLimitInfo {
+                limit: number("x-ratelimit-limit"),
+                remaining: number("x-ratelimit-remaining"),
+                resets_at: get("x-ratelimit-reset").and_then(|v| parse_reset_value(v, now)),
+            }
+        } else {
+            return None;
+        };
+
+        Some(info)
+    }
+
+    /// Human-readable summary, e.g. "58/60 requests remaining, resets in 40s"
+    pub fn describe(&self, now: DateTime<Utc>) -> String {
+        let mut text = match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) => format!("{}/{} requests remaining", remaining, limit),
+            (Some(remaining), None) => format!("{} requests remaining", remaining),
+            _ => "remaining requests unknown".to_string(),
+        };
+        if let Some(resets_at) = self.resets_at {
+            let seconds = (resets_at - now).num_seconds().max(0);
+            text.push_str(&format!(", resets in {}", format_seconds(seconds)));
+        }
+        text
+    }
+
+    /// Whether the remaining headroom is at or below the floor
+    pub fn is_low(&self) -> bool {
+        self.remaining.is_some_and(|r| r <= REMAINING_FLOOR)
+    }
+}
+
+/// Stretch a poll interval when the usage endpoint is close to its limit:
+/// wait at least until the reported reset, and never less than twice `base`
+pub fn effective_interval(
+    base: std::time::Duration,
+    info: Option<&RateLimitInfo>,
+    now: DateTime<Utc>,
+) -> std::time::Duration {
+    let Some(info) = info.filter(|i| i.is_low()) else {
+        return base;
+    };
+    let until_reset = info
+        .resets_at
+        .and_then(|r| (r - now).to_std().ok())
+        .unwrap_or_default();
+    until_reset.max(base * 2)
+}
+
+/// When to retry after a 429, from the `Retry-After` header or a default
+pub fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> DateTime<Utc> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_retry_after(v, now))
+        .unwrap_or_else(|| now + Duration::seconds(DEFAULT_RETRY_AFTER_SECS))
+}
+
+/// Parse a `Retry-After` value: delay in seconds or an HTTP date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(now + Duration::seconds(seconds as i64));
+    }
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Parse OpenAI-style reset durations such as "1s", "6m0s", "40ms", "1h2m3.5s"
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total_ms = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    let mut matched = false;
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let factor = match c {
+            'h' => 3_600_000.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                1.0
+            }
+            'm' => 60_000.0,
+            's' => 1_000.0,
+            _ => return None,
+        };
+        total_ms += amount * factor;
+        matched = true;
+    }
+
+    if !number.is_empty() || !matched {
+        return None;
+    }
+    Some(Duration::milliseconds(total_ms.round() as i64))
+}
+
+/// Parse a generic reset header: seconds from now, or a unix timestamp
+fn parse_reset_value(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let seconds: f64 = value.parse().ok()?;
+    // Values this large can only be epoch timestamps
+    if seconds > 1_000_000_000.0 {
+        DateTime::from_timestamp(seconds as i64, 0)
+    } else {
+        Some(now + Duration::milliseconds((seconds * 1000.0) as i64))
+    }
+}
+
+fn format_seconds(seconds: i64) -> String {
+    if seconds >= 3600 {
+        format!("{}h {}m", seconds / 3600, (seconds % 3600) / 60)
+    } else if seconds >= 60 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_anthropic_headers() {
+        let now = Utc::now();
+        let reset = (now + Duration::seconds(40)).to_rfc3339();
+        let map = headers(&[
+            ("anthropic-ratelimit-requests-limit", "60"),
+            ("anthropic-ratelimit-requests-remaining", "58"),
+            ("anthropic-ratelimit-requests-reset", &reset),
+        ]);
+        let info = RateLimitInfo::from_headers(&map, now).unwrap();
+        assert_eq!(info.limit, Some(60));
+        assert_eq!(info.remaining, Some(58));
+        assert!(info
+            .describe(now)
+            .starts_with("58/60 requests remaining, resets in"));
+    }
+
+    #[test]
+    fn test_openai_headers() {
+        let now = Utc::now();
+        let map = headers(&[
+            ("x-ratelimit-limit-requests", "500"),
+            ("x-ratelimit-remaining-requests", "3"),
+            ("x-ratelimit-reset-requests", "6m0s"),
+        ]);
+        let info = RateLimitInfo::from_headers(&map, now).unwrap();
+        assert_eq!(info.remaining, Some(3));
+        assert_eq!(info.resets_at, Some(now + Duration::minutes(6)));
+        assert!(info.is_low());
+    }
+
+    #[test]
+    fn test_generic_headers() {
+        let now = Utc::now();
+        let map = headers(&[
+            ("x-ratelimit-limit", "100"),
+            ("x-ratelimit-remaining", "99"),
+            ("x-ratelimit-reset", "30"),
+        ]);
+        let info = RateLimitInfo::from_headers(&map, now).unwrap();
+        assert_eq!(info.resets_at, Some(now + Duration::seconds(30)));
+        assert_eq!(
+            info.describe(now),
+            "99/100 requests remaining, resets in 30s"
+        );
+    }
+
+    #[test]
+    fn test_missing_headers() {
+        assert!(RateLimitInfo::from_headers(&HeaderMap::new(), Utc::now()).is_none());
+    }
+
+    #[test]
+    fn test_parse_reset_duration() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::seconds(1)));
+        assert_eq!(
+            parse_reset_duration("40ms"),
+            Some(Duration::milliseconds(40))
+        );
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::milliseconds(3_723_500))
+        );
+        assert_eq!(parse_reset_duration("12"), None);
+        assert_eq!(parse_reset_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_retry_after_seconds() {
+        let now = Utc::now();
+        let map = headers(&[("retry-after", "120")]);
+        assert_eq!(retry_after(&map, now), now + Duration::seconds(120));
+    }
+
+    #[test]
+    fn test_retry_after_http_date() {
+        let now = Utc::now();
+        let map = headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        assert_eq!(
+            retry_after(&map, now),
+            DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_retry_after_default() {
+        let now = Utc::now();
+        let expected = now + Duration::seconds(DEFAULT_RETRY_AFTER_SECS);
+        assert_eq!(retry_after(&HeaderMap::new(), now), expected);
+        let map = headers(&[("retry-after", "soon")]);
+        assert_eq!(retry_after(&map, now), expected);
+    }
+
+    #[test]
+    fn test_effective_interval() {
+        let now = Utc::now();
+        let base = std::time::Duration::from_secs(60);
+        let plenty = RateLimitInfo {
+            limit: Some(60),
+            remaining: Some(40),
+            resets_at: Some(now + Duration::minutes(10)),
+        };
+        assert_eq!(effective_interval(base, Some(&plenty), now), base);
+        assert_eq!(effective_interval(base, None, now), base);
+
+        let low = RateLimitInfo {
+            remaining: Some(2),
+            ..plenty.clone()
+        };
+        assert_eq!(
+            effective_interval(base, Some(&low), now),
+            std::time::Duration::from_secs(600)
+        );
+
+        let low_soon = RateLimitInfo {
+            resets_at: Some(now + Duration::seconds(5)),
+            ..low
+        };
+        assert_eq!(effective_interval(base, Some(&low_soon), now), base * 2);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cache::CacheState;
+use crate::cachecli::CacheInfo;
+use crate::models::UsageSnapshot;
+use crate::status::StatusReport;
+use crate::WaybarOutput;
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// Bump whenever the shape of any JSON output changes
+pub const SCHEMA_VERSION: u32 = 15;
+
+/// JSON outputs with a published schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaTarget {
+    /// Output of `quotabar waybar`
+    Waybar,
+    /// Output of `quotabar status --json`
+    Status,
+    /// A single provider snapshot
+    Snapshot,
+    /// The cache file (state.json)
+    Cache,
+    /// Output of `quotabar cache info --json`
+    CacheInfo,
+}
+
+pub fn schema_for(target: SchemaTarget) -> Value {
+    let schema = match target {
+        SchemaTarget::Waybar => schemars::schema_for!(WaybarOutput),
+        SchemaTarget::Status => schemars::schema_for!(StatusReport),
+        SchemaTarget::Snapshot => schemars::schema_for!(UsageSnapshot),
+        SchemaTarget::Cache => schemars::schema_for!(CacheState),
+        SchemaTarget::CacheInfo => schemars::schema_for!(CacheInfo),
+    };
+    let mut value = serde_json::to_value(schema).expect("schema serializes to JSON");
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("x-schema-version".to_string(), SCHEMA_VERSION.into());
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_waybar_output;
+    use crate::config::Config;
+    use crate::mock::mock_snapshots;
+    use chrono::Utc;
+
+    fn assert_valid(target: SchemaTarget, instance: &Value) {
+        let schema = schema_for(target);
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{:?}: {:?}", target, errors);
+    }
+
+    #[test]
+    fn test_waybar_output_matches_schema() {
+        let output =
+            build_waybar_output(&mock_snapshots(), &Default::default(), &Config::default());
+        assert_valid(SchemaTarget::Waybar, &serde_json::to_value(output).unwrap());
+
+        let empty =
+            build_waybar_output(&Default::default(), &Default::default(), &Config::default());
+        assert_valid(SchemaTarget::Waybar, &serde_json::to_value(empty).unwrap());
+    }
+
+    #[test]
+    fn test_snapshots_match_schema() {
+        for snapshot in mock_snapshots().values() {
+            assert_valid(
+                SchemaTarget::Snapshot,
+                &serde_json::to_value(snapshot).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_status_report_matches_schema() {
+        let mut providers: Vec<_> = mock_snapshots()
+            .into_values()
+            .map(crate::status::ProviderStatus::ok)
+            .collect();
+        providers.push(crate::status::ProviderStatus::disabled(
+            crate::models::Provider::Kiro,
+        ));
+        let report = StatusReport {
+            generated_at: Utc::now(),
+            providers,
+        };
+        assert_valid(SchemaTarget::Status, &serde_json::to_value(report).unwrap());
+    }
+
+    #[test]
+    fn test_cache_matches_schema() {
+        let state = CacheState {
+            snapshots: mock_snapshots(),
+            last_errors: std::collections::HashMap::from([(
+                crate::models::Provider::Codex,
+                crate::cache::FetchFailure {
+                    reason: "network timeout".to_string(),
+                    at: Utc::now(),
+                },
+            )]),
+            ..CacheState::empty(Utc::now())
+        };
+        assert_valid(SchemaTarget::Cache, &serde_json::to_value(state).unwrap());
+    }
+
+    #[test]
+    fn test_cache_info_matches_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        for backend in crate::cache::test_backends(dir.path()) {
+            let missing = crate::cachecli::info(&backend, Utc::now()).unwrap();
+            assert_valid(
+                SchemaTarget::CacheInfo,
+                &serde_json::to_value(missing).unwrap(),
+            );
+
+            let state = CacheState {
+                snapshots: mock_snapshots(),
+                ..CacheState::empty(Utc::now())
+            };
+            backend.save(&state).unwrap();
+            let info = crate::cachecli::info(&backend, Utc::now()).unwrap();
+            assert!(info.exists);
+            assert_eq!(info.providers.len(), state.snapshots.len());
+            assert_valid(
+                SchemaTarget::CacheInfo,
+                &serde_json::to_value(info).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_is_versioned() {
+        for target in SchemaTarget::value_variants() {
+            assert_eq!(schema_for(*target)["x-schema-version"], SCHEMA_VERSION);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! The popup's settings pane as plain data, read from and written to a
+//! [`Config`]
+
+use crate::config::{Config, ProviderChoice, ProviderConfig};
+use crate::models::{PercentDisplay, Provider};
+use anyhow::{bail, Result};
+
+/// What the settings pane edits. Built from the config when the pane opens
+/// and written back by Apply; everything else in the config is untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Every provider that can be switched on here, in display order, and
+    /// whether it is
+    pub providers: Vec<(Provider, bool)>,
+    /// The bar's provider; None leaves the choice to quotabar
+    pub selected: Option<ProviderChoice>,
+    pub warning: f64,
+    pub critical: f64,
+    pub display: PercentDisplay,
+    pub auto_close_secs: u64,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            providers: Provider::ALL
+                .into_iter()
+                // Switched on through `[providers.claude] org_usage`
+                .filter(|p| *p != Provider::ClaudeOrg)
+                .map(|p| (p, config.is_provider_enabled(p)))
+                .collect(),
+            selected: config.general.selected_provider,
+            warning: config.thresholds.warning,
+            critical: config.thresholds.critical,
+            display: config.general.display,
+            auto_close_secs: config.popup.auto_close_secs,
+        }
+    }
+
+    pub fn set_enabled(&mut self, provider: Provider, enabled: bool) {
+        if let Some(entry) = self.providers.iter_mut().find(|(p, _)| *p == provider) {
+            entry.1 = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, provider: Provider) -> bool {
+        self.providers.iter().any(|&(p, on)| p == provider && on)
+    }
+
+    /// Why these settings can't be applied, worded for showing next to the
+    /// fields
+    pub fn validate(&self) -> Result<()> {
+        if self.warning >= self.critical {
+            bail!(
+                "Warning ({}%) must be below critical ({}%)",
+                self.warning,
+                self.critical
+            );
+        }
+        if let Some(provider) = self
+            .selected
+            .and_then(ProviderChoice::provider)
+            .filter(|p| !self.is_enabled(*p))
+        {
+            bail!(
+                "{} is shown in the bar but disabled",
+                provider.display_name()
+            );
+        }
+        Ok(())
+    }
+
+    /// Validate, then write these settings into `config`
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        self.validate()?;
+        for &(provider, enabled) in &self.providers {
+            config
+                .providers
+                .entry(provider)
+                .or_insert_with(|| ProviderConfig::default_for(provider))
+                .enabled = enabled;
+        }
+        config.general.selected_provider = self.selected;
+        config.thresholds.warning = self.warning;
+        config.thresholds.critical = self.critical;
+        config.general.display = self.display;
+        config.popup.auto_close_secs = self.auto_close_secs;
+        config.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let config = Config::parse(
+            "[general]\nselected_provider = \"codex\"\ndisplay = \"remaining\"\n\n\
+             [thresholds]\nwarning = 60.0\n\n[popup]\nauto_close_secs = 15\n",
+        )
+        .unwrap();
+        let settings = Settings::from_config(&config);
+        assert_eq!(settings.selected, Some(Provider::Codex.into()));
+        assert_eq!(settings.warning, 60.0);
+        assert_eq!(settings.critical, config.thresholds.critical);
+        assert_eq!(settings.display, PercentDisplay::Remaining);
+        assert_eq!(settings.auto_close_secs, 15);
+        assert!(settings.is_enabled(Provider::Claude));
+        assert!(!settings.is_enabled(Provider::OpenCode));
+        assert!(!settings
+            .providers
+            .iter()
+            .any(|(p, _)| *p == Provider::ClaudeOrg));
+
+        let mut applied = config.clone();
+        settings.apply(&mut applied).unwrap();
+        assert_eq!(Settings::from_config(&applied), settings);
+    }
+
+    #[test]
+    fn test_apply_writes_changes() {
+        let mut config = Config::default();
+        let mut settings = Settings::from_config(&config);
+        settings.set_enabled(Provider::OpenCode, true);
+        settings.set_enabled(Provider::Codex, false);
+        settings.selected = Some(Provider::OpenCode.into());
+        settings.display = PercentDisplay::Remaining;
+        settings.auto_close_secs = 30;
+        settings.apply(&mut config).unwrap();
+
+        assert!(config.is_provider_enabled(Provider::OpenCode));
+        assert!(!config.is_provider_enabled(Provider::Codex));
+        assert_eq!(
+            config.general.selected_provider,
+            Some(Provider::OpenCode.into())
+        );
+        assert_eq!(config.general.display, PercentDisplay::Remaining);
+        assert_eq!(config.popup.auto_close_secs, 30);
+    }
+
+    #[test]
+    fn test_apply_rejects_invalid() {
+        let original = Config::default();
+        let mut config = original.clone();
+        let mut settings = Settings::from_config(&config);
+        settings.warning = 90.0;
+        settings.critical = 80.0;
+        let error = settings.apply(&mut config).unwrap_err().to_string();
+        assert_eq!(error, "Warning (90%) must be below critical (80%)");
+
+        let mut settings = Settings::from_config(&config);
+        settings.selected = Some(Provider::Codex.into());
+        settings.set_enabled(Provider::Codex, false);
+        let error = settings.apply(&mut config).unwrap_err().to_string();
+        assert_eq!(error, "Codex is shown in the bar but disabled");
+        // Nothing is written when validation fails
+        assert_eq!(
+            Settings::from_config(&config),
+            Settings::from_config(&original)
+        );
+    }
+}
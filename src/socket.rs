@@ -0,0 +1,348 @@
+//! The daemon's snapshot socket: one JSON request per line, answered from
+//! memory, so prompt and status-line commands needn't read state.json
+
+use crate::cache::{self, Backend, CacheState};
+use crate::files;
+use crate::models::{Provider, UsageSnapshot};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::Permissions;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+/// How long a command waits on the daemon before reading the file itself
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// A request line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Request {
+    /// `{"get":"all"}` for the whole cache, `{"get":"claude"}` for one
+    /// provider's snapshot
+    Get(String),
+    /// `{"refresh":true}` refreshes now, as SIGUSR1 does
+    Refresh(bool),
+}
+
+/// A response line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Response {
+    /// The cache, or null before there is one
+    State(Option<CacheState>),
+    /// The provider's snapshot, or null without one
+    Snapshot(Option<Box<UsageSnapshot>>),
+    /// Whether a refresh was started
+    Refreshing(bool),
+    Error(String),
+}
+
+/// Modification time and size of each of the backend's files
+type Stamp = Vec<Option<(SystemTime, u64)>>;
+
+/// The cache as the daemon last read it; read again when its files change,
+/// whoever wrote them
+struct Memory {
+    backend: Backend,
+    /// How the files looked when `state` was read
+    stamp: Stamp,
+    state: Option<CacheState>,
+}
+
+impl Memory {
+    fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            stamp: Stamp::new(),
+            state: None,
+        }
+    }
+
+    fn current(&mut self) -> Option<&CacheState> {
+        let stamp: Stamp = self
+            .backend
+            .files()
+            .iter()
+            .map(|file| {
+                std::fs::metadata(file)
+                    .and_then(|m| Ok((m.modified()?, m.len())))
+                    .ok()
+            })
+            .collect();
+        if stamp.first().is_none_or(Option::is_none) {
+            self.state = None;
+        } else if stamp != self.stamp {
+            match self.backend.load() {
+                Ok(state) => self.state = state,
+                Err(e) => tracing::warn!("socket: failed to read the cache: {:#}", e),
+            }
+        }
+        self.stamp = stamp;
+        self.state.as_ref()
+    }
+}
+
+/// Answers requests on the socket until dropped, which removes it
+pub struct Server {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on `socket`, answering from the cache in `cache`; refresh
+/// requests are passed on to `refresh`. A socket left behind by a daemon that died is
+/// replaced, but one another daemon still answers on is an error.
+pub fn serve(socket: &Path, cache: Backend, refresh: UnboundedSender<()>) -> Result<Server> {
+    if socket.symlink_metadata().is_ok() {
+        if UnixStream::connect(socket).is_ok() {
+            bail!("another daemon is listening on {}", socket.display());
+        }
+        std::fs::remove_file(socket)
+            .with_context(|| format!("Failed to remove stale {}", socket.display()))?;
+        tracing::info!("removed stale socket {}", socket.display());
+    }
+    if let Some(parent) = socket.parent() {
+        files::create_private_dir(parent)?;
+    }
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    std::fs::set_permissions(socket, Permissions::from_mode(0o600))?;
+    tracing::info!("serving snapshots on {}", socket.display());
+
+    let memory = Arc::new(Mutex::new(Memory::new(cache)));
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle(stream, memory.clone(), refresh.clone()));
+                }
+                Err(e) => tracing::warn!("socket: accept failed: {}", e),
+            }
+        }
+    });
+    Ok(Server {
+        path: socket.to_path_buf(),
+        task,
+    })
+}
+
+/// Answer each line on one connection until the client hangs up
+async fn handle(
+    stream: tokio::net::UnixStream,
+    memory: Arc<Mutex<Memory>>,
+    refresh: UnboundedSender<()>,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = answer(&line, &memory, &refresh);
+        let Ok(mut out) = serde_json::to_string(&response) else {
+            break;
+        };
+        out.push('\n');
+        if write.write_all(out.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn answer(line: &str, memory: &Mutex<Memory>, refresh: &UnboundedSender<()>) -> Response {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(e) => return Response::Error(format!("bad request: {}", e)),
+    };
+    tracing::trace!(?request, "socket request");
+    let mut memory = memory.lock().unwrap_or_else(|e| e.into_inner());
+    match request {
+        Request::Get(what) if what == "all" => Response::State(memory.current().cloned()),
+        Request::Get(what) => match serde_json::from_value::<Provider>(Value::String(what)) {
+            Ok(provider) => {
+                let snapshot = memory.current().and_then(|c| c.get(provider)).cloned();
+                Response::Snapshot(snapshot.map(Box::new))
+            }
+            Err(e) => Response::Error(e.to_string()),
+        },
+        Request::Refresh(wanted) => Response::Refreshing(wanted && refresh.send(()).is_ok()),
+    }
+}
+
+/// Ask the daemon listening on `socket`
+pub fn request(socket: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// The cache from the daemon when one is listening, otherwise from the
+/// configured storage
+pub fn load_cache() -> Option<CacheState> {
+    if let Some(socket) = crate::paths::socket_path() {
+        match request(&socket, &Request::Get("all".to_string())) {
+            Ok(Response::State(state)) => {
+                tracing::debug!(socket = %socket.display(), "read cache from the daemon");
+                return state;
+            }
+            Ok(other) => tracing::debug!(?other, "unexpected answer from the daemon"),
+            Err(e) => tracing::trace!(socket = %socket.display(), "no daemon: {:#}", e),
+        }
+    }
+    CacheState::load().ok().flatten()
+}
+
+/// One provider's snapshot from the daemon when one is listening, otherwise
+/// by the quickest read of the configured storage; for `get`, which reads
+/// nothing else
+pub fn load_snapshot(provider: Provider) -> Option<UsageSnapshot> {
+    if let Some(socket) = crate::paths::socket_path() {
+        match request(&socket, &Request::Get(provider.id().to_string())) {
+            Ok(Response::Snapshot(snapshot)) => {
+                tracing::debug!(socket = %socket.display(), "read cache from the daemon");
+                return snapshot.map(|s| *s);
+            }
+            Ok(other) => tracing::debug!(?other, "unexpected answer from the daemon"),
+            Err(e) => tracing::trace!(socket = %socket.display(), "no daemon: {:#}", e),
+        }
+    }
+    let snapshot = match Backend::current() {
+        Backend::Json(path) => cache::read_minimal(path, provider),
+        #[cfg(feature = "sqlite")]
+        backend => backend
+            .load()
+            .map(|state| state.and_then(|mut s| s.snapshots.remove(&provider))),
+    };
+    snapshot
+        .inspect_err(|e| tracing::warn!("failed to read the cache: {:#}", e))
+        .ok()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::test_backends;
+    use crate::mock::mock_snapshots;
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+
+    fn write_cache(backend: &Backend, providers: &[Provider]) {
+        let mut snapshots = mock_snapshots();
+        snapshots.retain(|p, _| providers.contains(p));
+        let state = CacheState {
+            snapshots,
+            ..CacheState::empty(Utc::now())
+        };
+        backend.save(&state).unwrap();
+    }
+
+    #[test]
+    fn test_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Backend::Json(dir.path().join("state.json"));
+        let memory = Mutex::new(Memory::new(cache.clone()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let ask = |line: &str| answer(line, &memory, &tx);
+
+        assert!(matches!(ask(r#"{"get":"all"}"#), Response::State(None)));
+
+        write_cache(&cache, &[Provider::Claude]);
+        let Response::State(Some(state)) = ask(r#"{"get":"all"}"#) else {
+            panic!("no state");
+        };
+        assert_eq!(state.snapshots.len(), 1);
+        let Response::Snapshot(Some(snapshot)) = ask(r#"{"get":"claude"}"#) else {
+            panic!("no snapshot");
+        };
+        assert_eq!(snapshot.provider, Provider::Claude);
+        assert!(matches!(
+            ask(r#"{"get":"codex"}"#),
+            Response::Snapshot(None)
+        ));
+        assert!(matches!(ask(r#"{"get":"gemini"}"#), Response::Error(_)));
+        assert!(matches!(ask("get all"), Response::Error(_)));
+
+        assert!(matches!(
+            ask(r#"{"refresh":true}"#),
+            Response::Refreshing(true)
+        ));
+        assert!(rx.try_recv().is_ok());
+        assert!(matches!(
+            ask(r#"{"refresh":false}"#),
+            Response::Refreshing(false)
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_memory_follows_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for cache in test_backends(dir.path()) {
+            let mut memory = Memory::new(cache.clone());
+
+            write_cache(&cache, &[Provider::Claude]);
+            assert_eq!(memory.current().unwrap().snapshots.len(), 1);
+            // Another process writes; the size changes even if the time
+            // doesn't
+            write_cache(&cache, &[Provider::Claude, Provider::Codex]);
+            assert_eq!(memory.current().unwrap().snapshots.len(), 2, "{:?}", cache);
+            // `cache clear`
+            cache.clear().unwrap();
+            assert!(memory.current().is_none(), "{:?}", cache);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Backend::Json(dir.path().join("state.json"));
+        let socket = dir.path().join("run").join("quotabar.sock");
+        write_cache(&cache, &[Provider::Codex]);
+
+        // Left behind by a daemon that was killed
+        drop(std::os::unix::net::UnixListener::bind(dir.path().join("stale.sock")).unwrap());
+        std::fs::create_dir(dir.path().join("run")).unwrap();
+        std::fs::rename(dir.path().join("stale.sock"), &socket).unwrap();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let server = serve(&socket, cache.clone(), tx.clone()).unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        assert!(serve(&socket, cache, tx).is_err());
+
+        let socket_path = socket.clone();
+        let response = tokio::task::spawn_blocking(move || {
+            request(&socket_path, &Request::Get("codex".to_string()))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(matches!(response, Response::Snapshot(Some(_))));
+
+        drop(server);
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,371 @@
+//! `storage = "sqlite"`: the cache, events and history in one database, so
+//! a long history is filtered by an index rather than read whole, and
+//! concurrent writers are SQLite's problem rather than ours
+
+use crate::cache::CacheState;
+use crate::files;
+use crate::history::{self, Sample};
+use crate::models::Provider;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a statement waits for another process's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tables for `PRAGMA user_version` 1. Values are the JSON state.json would
+/// hold, so loading goes through the same upgrade and parsing as the file;
+/// `at` columns are fixed-width UTC timestamps, so they sort as text.
+const SCHEMA: &str = "
+    CREATE TABLE state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE snapshots (provider TEXT PRIMARY KEY, snapshot TEXT NOT NULL);
+    CREATE TABLE providers (
+        provider TEXT PRIMARY KEY,
+        backoff_until TEXT,
+        last_error TEXT
+    );
+    CREATE TABLE events (id INTEGER PRIMARY KEY, at TEXT NOT NULL, event TEXT NOT NULL);
+    CREATE TABLE samples (
+        id INTEGER PRIMARY KEY,
+        at TEXT NOT NULL,
+        provider TEXT NOT NULL,
+        sample TEXT NOT NULL
+    );
+    CREATE INDEX samples_at ON samples (at);
+";
+
+/// An open quotabar.db
+pub struct Db {
+    conn: Connection,
+}
+
+impl Db {
+    /// Open the database at `path`, creating it readable only by the user.
+    /// A new database first imports state.json and history.jsonl from beside
+    /// it, once, however many processes open it at the same time; the JSON
+    /// files are left where they are.
+    pub fn open(path: &Path) -> Result<Self> {
+        // An empty file is a valid empty database; creating it ourselves
+        // makes it 0600, and SQLite gives the WAL files the same mode
+        files::open_append(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let conn =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        let mut db = Self { conn };
+        db.init(path)?;
+        Ok(db)
+    }
+
+    fn init(&mut self, path: &Path) -> Result<()> {
+        let tx = self.write()?;
+        let version: i64 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > 0 {
+            return Ok(());
+        }
+        tx.execute_batch(SCHEMA)?;
+        import(&tx, path)?;
+        tx.execute_batch("PRAGMA user_version = 1")?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// A transaction that holds the write lock from the start, so reads in
+    /// it can't go stale before its writes
+    fn write(&mut self) -> Result<Transaction<'_>> {
+        Ok(self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?)
+    }
+
+    /// The cache, or None before one has been saved (or after clearing)
+    pub fn load_state(&self) -> Result<Option<CacheState>> {
+        let mut object = Map::new();
+        let mut rows = self.conn.prepare("SELECT key, value FROM state")?;
+        for row in rows.query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))? {
+            let (key, value) = row?;
+            object.insert(key, serde_json::from_str(&value)?);
+        }
+        if !object.contains_key("updated_at") {
+            return Ok(None);
+        }
+
+        let mut snapshots = Map::new();
+        let mut rows = self
+            .conn
+            .prepare("SELECT provider, snapshot FROM snapshots")?;
+        for row in rows.query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))? {
+            let (provider, snapshot) = row?;
+            snapshots.insert(provider, serde_json::from_str(&snapshot)?);
+        }
+        object.insert("snapshots".to_string(), Value::Object(snapshots));
+
+        let (mut backoff_until, mut last_errors) = (Map::new(), Map::new());
+        let mut rows = self
+            .conn
+            .prepare("SELECT provider, backoff_until, last_error FROM providers")?;
+        let query = rows.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+        for row in query {
+            let (provider, backoff, error) = row?;
+            if let Some(until) = backoff {
+                backoff_until.insert(provider.clone(), serde_json::from_str(&until)?);
+            }
+            if let Some(error) = error {
+                last_errors.insert(provider, serde_json::from_str(&error)?);
+            }
+        }
+        object.insert("backoff_until".to_string(), Value::Object(backoff_until));
+        object.insert("last_errors".to_string(), Value::Object(last_errors));
+
+        let mut rows = self.conn.prepare("SELECT event FROM events ORDER BY id")?;
+        let events = rows
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|event| Ok(serde_json::from_str(&event?)?))
+            .collect::<Result<Vec<Value>>>()?;
+        object.insert("events".to_string(), Value::Array(events));
+
+        CacheState::from_value(Value::Object(object)).map(Some)
+    }
+
+    /// Replace the cache with `state`
+    pub fn save_state(&mut self, state: &CacheState) -> Result<()> {
+        let tx = self.write()?;
+        save(&tx, state)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Remove the cache, keeping history; false when there was none
+    pub fn clear_state(&mut self) -> Result<bool> {
+        let tx = self.write()?;
+        let existed = tx
+            .query_row("SELECT 1 FROM state WHERE key = 'updated_at'", [], |_| {
+                Ok(())
+            })
+            .optional()?
+            .is_some();
+        clear(&tx)?;
+        tx.commit()?;
+        Ok(existed)
+    }
+
+    /// Add history samples and drop those taken before `cutoff`
+    pub fn append_samples(&mut self, samples: &[Sample], cutoff: DateTime<Utc>) -> Result<()> {
+        let tx = self.write()?;
+        insert_samples(&tx, samples)?;
+        tx.execute("DELETE FROM samples WHERE at < ?1", params![stamp(cutoff)])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// History samples taken at or after `since`, oldest first, and how many
+    /// rows couldn't be read as samples
+    pub fn samples_since(&self, since: DateTime<Utc>) -> Result<(Vec<Sample>, usize)> {
+        let mut rows = self
+            .conn
+            .prepare("SELECT sample FROM samples WHERE at >= ?1 ORDER BY at, id")?;
+        let (mut samples, mut skipped) = (Vec::new(), 0);
+        for row in rows.query_map(params![stamp(since)], |row| row.get::<_, String>(0))? {
+            match serde_json::from_str(&row?) {
+                Ok(sample) => samples.push(sample),
+                Err(_) => skipped += 1,
+            }
+        }
+        Ok((samples, skipped))
+    }
+}
+
+fn clear(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "DELETE FROM state; DELETE FROM snapshots; DELETE FROM providers; DELETE FROM events;",
+    )?;
+    Ok(())
+}
+
+fn save(tx: &Transaction, state: &CacheState) -> Result<()> {
+    clear(tx)?;
+    tx.execute(
+        "INSERT INTO state (key, value) VALUES ('schema_version', ?1), ('updated_at', ?2)",
+        params![json(&state.schema_version)?, json(&state.updated_at)?],
+    )?;
+    for (provider, snapshot) in &state.snapshots {
+        tx.execute(
+            "INSERT INTO snapshots (provider, snapshot) VALUES (?1, ?2)",
+            params![provider.id(), json(snapshot)?],
+        )?;
+    }
+    let providers: HashSet<Provider> = state
+        .backoff_until
+        .keys()
+        .chain(state.last_errors.keys())
+        .copied()
+        .collect();
+    for provider in providers {
+        tx.execute(
+            "INSERT INTO providers (provider, backoff_until, last_error) VALUES (?1, ?2, ?3)",
+            params![
+                provider.id(),
+                state.backoff_until.get(&provider).map(json).transpose()?,
+                state.last_errors.get(&provider).map(json).transpose()?,
+            ],
+        )?;
+    }
+    for event in &state.events {
+        tx.execute(
+            "INSERT INTO events (at, event) VALUES (?1, ?2)",
+            params![stamp(event.at), json(event)?],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_samples(tx: &Transaction, samples: &[Sample]) -> Result<()> {
+    let mut insert =
+        tx.prepare_cached("INSERT INTO samples (at, provider, sample) VALUES (?1, ?2, ?3)")?;
+    for sample in samples {
+        insert.execute(params![
+            stamp(sample.at),
+            sample.provider.id(),
+            json(sample)?
+        ])?;
+    }
+    Ok(())
+}
+
+/// Bring in state.json and history.jsonl from beside a new database
+fn import(tx: &Transaction, path: &Path) -> Result<()> {
+    let state_path = path.with_file_name("state.json");
+    if let Some(state) = CacheState::load_from(&state_path)? {
+        save(tx, &state)?;
+        tracing::info!("imported {} into {}", state_path.display(), path.display());
+    }
+    let history_path = history::history_path(path);
+    if history_path.exists() {
+        let samples = history::read(&history_path)?;
+        insert_samples(tx, &samples)?;
+        tracing::info!(
+            "imported {} samples from {} into {}",
+            samples.len(),
+            history_path.display(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn json(value: &impl Serialize) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+/// `at` as text that sorts in time order
+fn stamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use chrono::Duration as ChronoDuration;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_imports_json_files_once() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy(
+            "tests/fixtures/cache/v1-baseline.json",
+            dir.path().join("state.json"),
+        )
+        .unwrap();
+        let now = Utc::now();
+        let samples: Vec<Sample> = mock_snapshots()
+            .values()
+            .map(|snapshot| Sample {
+                at: now - ChronoDuration::hours(1),
+                ..Sample::from_snapshot(snapshot)
+            })
+            .collect();
+        history::record_to(
+            &crate::cache::Backend::Json(dir.path().join("state.json")),
+            &samples,
+            std::time::Duration::from_secs(86400),
+            now,
+        )
+        .unwrap();
+
+        let path = dir.path().join("quotabar.db");
+        let db = Db::open(&path).unwrap();
+        let state = db.load_state().unwrap().unwrap();
+        let file = CacheState::load_from(&dir.path().join("state.json"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&state).unwrap(),
+            serde_json::to_value(&file).unwrap()
+        );
+        let (imported, skipped) = db.samples_since(DateTime::<Utc>::MIN_UTC).unwrap();
+        assert_eq!((imported.len(), skipped), (samples.len(), 0));
+        drop(db);
+
+        // Opening again doesn't import twice, and the files are untouched
+        let db = Db::open(&path).unwrap();
+        let (imported, _) = db.samples_since(DateTime::<Utc>::MIN_UTC).unwrap();
+        assert_eq!(imported.len(), samples.len());
+        assert!(dir.path().join("state.json").exists());
+        assert!(dir.path().join("history.jsonl").exists());
+    }
+
+    #[test]
+    fn test_samples_since_and_pruning() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::open(&dir.path().join("quotabar.db")).unwrap();
+        let now = Utc::now();
+        let sample = |hours| Sample {
+            at: now - ChronoDuration::hours(hours),
+            ..Sample::from_snapshot(&mock_snapshots()[&Provider::Claude])
+        };
+
+        db.append_samples(
+            &[sample(50), sample(30), sample(10)],
+            now - ChronoDuration::hours(100),
+        )
+        .unwrap();
+        let (since, _) = db.samples_since(now - ChronoDuration::hours(40)).unwrap();
+        assert_eq!(since, [sample(30), sample(10)]);
+
+        db.append_samples(&[sample(0)], now - ChronoDuration::hours(20))
+            .unwrap();
+        let (all, _) = db.samples_since(DateTime::<Utc>::MIN_UTC).unwrap();
+        assert_eq!(all, [sample(10), sample(0)]);
+
+        db.conn
+            .execute(
+                "INSERT INTO samples (at, provider, sample) VALUES (?1, 'claude', '{oops')",
+                params![stamp(now)],
+            )
+            .unwrap();
+        let (all, skipped) = db.samples_since(DateTime::<Utc>::MIN_UTC).unwrap();
+        assert_eq!((all.len(), skipped), (2, 1));
+    }
+
+    #[test]
+    fn test_stamps_sort_in_time_order() {
+        let at = DateTime::parse_from_rfc3339("2026-10-17T18:45:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(stamp(at), "2026-10-17T18:45:00.000000Z");
+        assert!(stamp(at) < stamp(at + ChronoDuration::microseconds(1)));
+        assert!(stamp(at + ChronoDuration::hours(1)) > stamp(at));
+    }
+}
@@ -0,0 +1,655 @@
+use crate::config::{Config, DisplayInfo, ProviderConfig, Thresholds};
+use crate::models::{PercentDisplay, Provider, RateWindow, UsageSnapshot};
+use crate::providers::ProviderError;
+use crate::textbar::{self, IconSet};
+use crate::timefmt::TimeStyle;
+use chrono::{DateTime, Local, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+const BAR_WIDTH: usize = 20;
+
+/// Presentation options for the human-readable status output
+#[derive(Debug, Clone)]
+pub struct StatusStyle {
+    pub precision: u8,
+    pub display: PercentDisplay,
+    pub bars: bool,
+    pub icon_set: IconSet,
+    pub color: bool,
+    /// Per-provider thresholds from the config; missing ones use the defaults
+    pub thresholds: HashMap<Provider, Thresholds>,
+    /// Provider settings, for icon and label overrides
+    pub providers: HashMap<Provider, ProviderConfig>,
+    /// Print the account email with the organization
+    pub show_email: bool,
+    pub time: TimeStyle,
+}
+
+impl StatusStyle {
+    pub fn from_config(config: &Config, bars_flag: bool) -> Self {
+        Self {
+            precision: config.general.precision,
+            display: config.general.display,
+            bars: bars_flag || config.status.bars,
+            icon_set: config.general.icon_set,
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            thresholds: Provider::ALL
+                .into_iter()
+                .map(|p| (p, config.thresholds_for(p)))
+                .collect(),
+            providers: config.providers.clone(),
+            show_email: config.popup.show_email,
+            time: config.time_style(),
+        }
+    }
+
+    fn class(&self, provider: Provider, percent: f64) -> &'static str {
+        self.thresholds
+            .get(&provider)
+            .copied()
+            .unwrap_or_default()
+            .classify(percent)
+    }
+
+    /// Mark a value that changed since the last draw: reverse video, or a
+    /// trailing `*` without color
+    fn highlight(&self, text: &str, changed: bool) -> String {
+        match (changed, self.color) {
+            (false, _) => text.to_string(),
+            (true, true) => format!("\x1b[7m{}\x1b[0m", text),
+            (true, false) => format!("{}*", text),
+        }
+    }
+
+    fn bar(&self, percent: f64, class: &str) -> String {
+        if !self.bars {
+            return String::new();
+        }
+        let bar = textbar::render_bar(percent, BAR_WIDTH, self.icon_set);
+        let bar = if self.color {
+            textbar::colorize(&bar, class)
+        } else {
+            bar
+        };
+        format!("[{}] ", bar)
+    }
+}
+
+/// Output of `quotabar status --json`
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StatusReport {
+    pub generated_at: DateTime<Utc>,
+    pub providers: Vec<ProviderStatus>,
+}
+
+/// One provider's entry: exactly one of `snapshot` and `error` is set
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProviderStatus {
+    pub provider: Provider,
+    pub snapshot: Option<UsageSnapshot>,
+    pub error: Option<StatusError>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StatusError {
+    pub kind: StatusErrorKind,
+    pub message: String,
+    /// When a rate-limited provider will be fetched again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusErrorKind {
+    /// Turned off in the config
+    Disabled,
+    /// Backing off after an HTTP 429
+    RateLimited,
+    /// No fetcher for this provider in this build
+    Unsupported,
+    /// Any other failure (credentials, network, parsing)
+    FetchFailed,
+}
+
+impl ProviderStatus {
+    pub fn ok(snapshot: UsageSnapshot) -> Self {
+        Self {
+            provider: snapshot.provider,
+            snapshot: Some(snapshot),
+            error: None,
+        }
+    }
+
+    pub fn disabled(provider: Provider) -> Self {
+        Self::error(
+            provider,
+            StatusError {
+                kind: StatusErrorKind::Disabled,
+                message: format!("{} is disabled in the config", provider.display_name()),
+                retry_after: None,
+            },
+        )
+    }
+
+    pub fn failed(provider: Provider, error: &anyhow::Error) -> Self {
+        let (kind, retry_after) = match error.downcast_ref::<ProviderError>() {
+            Some(ProviderError::RateLimited { until }) => {
+                (StatusErrorKind::RateLimited, Some(*until))
+            }
+            Some(ProviderError::Unsupported(_)) => (StatusErrorKind::Unsupported, None),
+            Some(ProviderError::Unauthorized(_)) | None => (StatusErrorKind::FetchFailed, None),
+        };
+        Self::error(
+            provider,
+            StatusError {
+                kind,
+                message: format!("{:#}", error),
+                retry_after,
+            },
+        )
+    }
+
+    fn error(provider: Provider, error: StatusError) -> Self {
+        Self {
+            provider,
+            snapshot: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Extra state for the live `quotabar watch` view
+pub struct LiveView<'a> {
+    /// Resets count down from `resets_at` relative to this instant
+    pub now: DateTime<Utc>,
+    /// The last drawn snapshot; values that differ are highlighted
+    pub previous: Option<&'a UsageSnapshot>,
+}
+
+pub fn print_status(snapshot: &UsageSnapshot, style: &StatusStyle) {
+    print!("{}", status_text([snapshot], style));
+}
+
+/// The `status` output for each snapshot in turn, exactly as printed; the
+/// popup copies this to the clipboard
+pub fn status_text<'a>(
+    snapshots: impl IntoIterator<Item = &'a UsageSnapshot>,
+    style: &StatusStyle,
+) -> String {
+    snapshots
+        .into_iter()
+        .flat_map(|snapshot| status_lines(snapshot, style, None))
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// The `status` layout as lines, optionally with live countdowns and
+/// change highlighting
+pub fn status_lines(
+    snapshot: &UsageSnapshot,
+    style: &StatusStyle,
+    live: Option<&LiveView>,
+) -> Vec<String> {
+    let info = DisplayInfo::resolve(
+        snapshot,
+        style.providers.get(&snapshot.provider),
+        snapshot.provider.icon(),
+    );
+    let display = style
+        .providers
+        .get(&snapshot.provider)
+        .map(|c| c.display)
+        .unwrap_or_default();
+    let rows = display.rows(snapshot);
+    let heading = match style.icon_set {
+        IconSet::Nerd => info.prefix(&info.label),
+        IconSet::Ascii => info.label,
+    };
+    let mut lines = vec![format!(
+        "{} {}",
+        heading,
+        rows.identity
+            .and_then(|i| i.plan.as_ref())
+            .map(|p| format!("({})", p))
+            .unwrap_or_default()
+    )];
+    if let Some(account) = rows.identity.and_then(|i| i.account_line(style.show_email)) {
+        lines.push(format!("  {}", account));
+    }
+
+    let previous = live.and_then(|l| l.previous);
+    // Names are padded to the longest so the bars line up
+    let width = rows
+        .windows
+        .iter()
+        .map(|w| w.name().chars().count() + 1)
+        .max()
+        .unwrap_or(0);
+    for window in rows.windows {
+        let changed = previous.is_some_and(|p| {
+            p.window(&window.kind).map(|w| w.used_percent) != Some(window.used_percent)
+        });
+        let class = style.class(snapshot.provider, window.used_percent);
+        let label = format!("{:<width$}", format!("{}:", window.name()));
+        lines.push(window_line(&label, window, class, style, live, changed));
+        // Counts go under the bar, or the percentage without bars
+        if let Some(counts) = window.counts_text() {
+            lines.push(format!("  {:width$} {}", "", counts));
+        }
+    }
+    if let Some(cost) = rows.cost {
+        let percent = cost.used_percent();
+        let class = style.class(snapshot.provider, percent);
+        let changed = previous.is_some_and(|p| p.cost.as_ref().map(|c| c.used) != Some(cost.used));
+        let label = if cost.extra_usage { "Extra:" } else { "Cost:" };
+        lines.push(format!(
+            "  {:<9}{}{}",
+            label,
+            style.bar(percent, class),
+            style.highlight(&cost.describe(), changed)
+        ));
+    }
+    if let Some(summary) = rows.busiest_member {
+        lines.push(format!("  {}", summary));
+    }
+    lines
+}
+
+/// The line for a provider that's turned off, with its configured name
+pub fn disabled_line(provider: Provider, style: &StatusStyle) -> String {
+    let info = DisplayInfo::for_provider(provider, style.providers.get(&provider));
+    let heading = match style.icon_set {
+        IconSet::Nerd => info.prefix(&info.label),
+        IconSet::Ascii => info.label,
+    };
+    format!("{} (disabled)", heading)
+}
+
+fn window_line(
+    label: &str,
+    window: &RateWindow,
+    class: &str,
+    style: &StatusStyle,
+    live: Option<&LiveView>,
+    changed: bool,
+) -> String {
+    let reset = match (live, window.resets_at) {
+        (Some(live), Some(resets_at)) => style.time.reset_live(resets_at, live.now, &Local),
+        _ => window
+            .reset_text(Utc::now(), &style.time)
+            .unwrap_or_default(),
+    };
+    let percent = format!("{}%", style.display.format(window, style.precision));
+    format!(
+        "  {} {}{} {} {}",
+        label,
+        style.bar(style.display.percent(window), class),
+        style.highlight(&percent, changed),
+        style.display.suffix(),
+        reset
+    )
+}
+
+pub fn print_timings(snapshot: &UsageSnapshot, elapsed: std::time::Duration) {
+    println!("  Fetched in {}ms", elapsed.as_millis());
+    if let Some(ref rate_limit) = snapshot.rate_limit {
+        println!("  Usage endpoint: {}", rate_limit.describe(Utc::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CostSnapshot, Granularity, IdentitySnapshot, WindowKind};
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn plain_style() -> StatusStyle {
+        StatusStyle {
+            precision: 0,
+            display: PercentDisplay::Used,
+            bars: false,
+            icon_set: IconSet::Ascii,
+            color: false,
+            thresholds: HashMap::new(),
+            providers: HashMap::new(),
+            show_email: false,
+            time: TimeStyle::default(),
+        }
+    }
+
+    #[test]
+    fn test_live_lines_count_down_and_highlight_changes() {
+        let snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        let now = snapshot.windows[0].resets_at.unwrap() - chrono::Duration::seconds(90);
+        let mut previous = snapshot.clone();
+        previous.windows[0].used_percent -= 1.0;
+
+        let live = LiveView {
+            now,
+            previous: Some(&previous),
+        };
+        let lines = status_lines(&snapshot, &plain_style(), Some(&live));
+        assert!(lines[1].ends_with("* used in 1m 30s"), "{}", lines[1]);
+        assert!(!lines[2].contains('*'), "{}", lines[2]);
+
+        // Without a live view the reset is recomputed from the clock, not
+        // the description stored at fetch time
+        let lines = status_lines(&snapshot, &plain_style(), None);
+        assert!(lines[1].contains("% used in 5h, "), "{}", lines[1]);
+
+        let style = StatusStyle {
+            display: PercentDisplay::Remaining,
+            ..plain_style()
+        };
+        let lines = status_lines(&snapshot, &style, None);
+        assert!(lines[1].contains(" 28% left in 5h, "), "{}", lines[1]);
+    }
+
+    #[test]
+    fn test_status_text() {
+        let snapshots = crate::mock::mock_snapshots();
+        let claude = &snapshots[&Provider::Claude];
+        let codex = &snapshots[&Provider::Codex];
+        let style = plain_style();
+
+        let text = status_text([claude, codex], &style);
+        let mut expected = status_lines(claude, &style, None);
+        expected.extend(status_lines(codex, &style, None));
+        assert_eq!(text, expected.join("\n") + "\n");
+        assert!(
+            text.starts_with("Claude (Max)\n  5-hour session: 72% used"),
+            "{}",
+            text
+        );
+        assert_eq!(status_text([], &style), "");
+    }
+
+    #[test]
+    fn test_account_line_follows_show_email() {
+        let snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        let style = StatusStyle {
+            show_email: true,
+            ..plain_style()
+        };
+        let lines = status_lines(&snapshot, &style, None);
+        assert_eq!(lines[1], "  user@example.com");
+        assert!(lines[2].starts_with("  5-hour session:"), "{}", lines[2]);
+
+        let lines = status_lines(&snapshot, &plain_style(), None);
+        assert!(lines[1].starts_with("  5-hour session:"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn test_hidden_rows_are_left_out() {
+        let snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        let mut style = StatusStyle {
+            show_email: true,
+            ..plain_style()
+        };
+        let mut config = ProviderConfig::default();
+        config.display.show_secondary = false;
+        config.display.show_identity = false;
+        style.providers.insert(Provider::Claude, config);
+
+        let lines = status_lines(&snapshot, &style, None);
+        assert_eq!(lines[0], "Claude ");
+        assert!(lines[1].starts_with("  5-hour session:"), "{}", lines[1]);
+        assert!(lines[2].starts_with("  Extra:"), "{}", lines[2]);
+        assert_eq!(lines.len(), 3, "{:?}", lines);
+    }
+
+    #[test]
+    fn test_window_names_line_up() {
+        let mut snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        let sonnet = RateWindow {
+            kind: WindowKind::Model("sonnet".to_string()),
+            ..snapshot.windows[1].clone().with_label("Weekly — Sonnet")
+        };
+        snapshot.windows.push(sonnet);
+        let lines = status_lines(&snapshot, &plain_style(), None);
+        assert!(
+            lines[1].starts_with("  5-hour session:  72%"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].starts_with("  Weekly limit:    45%"),
+            "{}",
+            lines[2]
+        );
+        assert!(
+            lines[3].starts_with("  Weekly — Sonnet: 45%"),
+            "{}",
+            lines[3]
+        );
+    }
+
+    #[test]
+    fn test_counts_line_under_window() {
+        let mut snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Claude)
+            .unwrap();
+        let without = status_lines(&snapshot, &plain_style(), None);
+
+        snapshot.windows[0] = snapshot.windows[0]
+            .clone()
+            .with_counts(412.0, 500.0, "requests");
+        let lines = status_lines(&snapshot, &plain_style(), None);
+        assert_eq!(lines.len(), without.len() + 1);
+        assert!(
+            lines[1].starts_with("  5-hour session: 72%"),
+            "{}",
+            lines[1]
+        );
+        assert_eq!(lines[2], "                  412 / 500 requests");
+        assert!(
+            lines[3].starts_with("  Weekly limit:   45%"),
+            "{}",
+            lines[3]
+        );
+    }
+
+    #[test]
+    fn test_heading_uses_configured_icon_and_label() {
+        let snapshot = crate::mock::mock_snapshots()
+            .remove(&Provider::Codex)
+            .unwrap();
+        let mut style = StatusStyle {
+            icon_set: IconSet::Nerd,
+            ..plain_style()
+        };
+        style.providers.insert(
+            Provider::Codex,
+            ProviderConfig {
+                icon: Some("C".to_string()),
+                label: Some("Work".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(status_lines(&snapshot, &style, None)[0], "C Work (Pro)");
+
+        style.providers.get_mut(&Provider::Codex).unwrap().icon = Some(String::new());
+        assert_eq!(status_lines(&snapshot, &style, None)[0], "Work (Pro)");
+    }
+
+    #[test]
+    fn test_disabled_line_uses_configured_icon_and_label() {
+        let mut style = StatusStyle {
+            icon_set: IconSet::Nerd,
+            ..plain_style()
+        };
+        assert_eq!(
+            disabled_line(Provider::Claude, &style),
+            format!("{} Claude (disabled)", Provider::Claude.icon())
+        );
+
+        style.providers.insert(
+            Provider::Codex,
+            ProviderConfig {
+                icon: Some("C".to_string()),
+                label: Some("Work".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(disabled_line(Provider::Codex, &style), "C Work (disabled)");
+        style.icon_set = IconSet::Ascii;
+        assert_eq!(disabled_line(Provider::Codex, &style), "Work (disabled)");
+    }
+
+    /// Locks the field names of `status --json`; scripts depend on them, so a
+    /// failure here means bumping SCHEMA_VERSION
+    #[test]
+    fn test_status_json_shape() {
+        let at = Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let snapshot = UsageSnapshot {
+            provider: Provider::Claude,
+            windows: vec![
+                RateWindow {
+                    kind: WindowKind::Session,
+                    used_percent: 42.0,
+                    window_minutes: Some(300),
+                    resets_at: Some(at),
+                    reset_description: Some("in 5h".to_string()),
+                    granularity: Granularity::Percent,
+                    label: None,
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+                RateWindow {
+                    kind: WindowKind::Model("sonnet".to_string()),
+                    used_percent: 10.0,
+                    window_minutes: Some(10080),
+                    resets_at: None,
+                    reset_description: None,
+                    granularity: Granularity::Unknown,
+                    label: Some("Weekly — Sonnet".to_string()),
+                    used: None,
+                    limit: None,
+                    unit: None,
+                },
+            ],
+            cost: Some(CostSnapshot {
+                used: 1.5,
+                limit: 10.0,
+                currency_code: "USD".to_string(),
+                period: Some("Monthly".to_string()),
+                resets_at: None,
+                extra_usage: false,
+            }),
+            identity: Some(IdentitySnapshot {
+                email: Some("me@example.com".to_string()),
+                plan: Some("Max".to_string()),
+                organization: None,
+                account_id: None,
+            }),
+            rate_limit: None,
+            busiest_member: None,
+            origin: None,
+            updated_at: at,
+        };
+        let report = StatusReport {
+            generated_at: at,
+            providers: vec![
+                ProviderStatus::ok(snapshot),
+                ProviderStatus::failed(
+                    Provider::Codex,
+                    &ProviderError::RateLimited { until: at }.into(),
+                ),
+                ProviderStatus::failed(Provider::Windsurf, &anyhow::anyhow!("boom")),
+                ProviderStatus::disabled(Provider::OpenCode),
+            ],
+        };
+
+        let mut value = serde_json::to_value(&report).unwrap();
+        // The rate-limit message embeds local time; the kind is what's stable
+        value["providers"][1]["error"]["message"] = json!("<local time>");
+
+        assert_eq!(
+            value,
+            json!({
+                "generated_at": "2026-01-02T03:04:05Z",
+                "providers": [
+                    {
+                        "provider": "claude",
+                        "snapshot": {
+                            "provider": "claude",
+                            "windows": [
+                                {
+                                    "kind": "session",
+                                    "used_percent": 42.0,
+                                    "window_minutes": 300,
+                                    "resets_at": "2026-01-02T03:04:05Z",
+                                    "reset_description": "in 5h",
+                                    "granularity": "percent"
+                                },
+                                {
+                                    "kind": {"model": "sonnet"},
+                                    "used_percent": 10.0,
+                                    "window_minutes": 10080,
+                                    "resets_at": null,
+                                    "reset_description": null,
+                                    "granularity": "unknown",
+                                    "label": "Weekly — Sonnet"
+                                }
+                            ],
+                            "cost": {
+                                "used": 1.5,
+                                "limit": 10.0,
+                                "currency_code": "USD",
+                                "period": "Monthly",
+                                "resets_at": null
+                            },
+                            "identity": {
+                                "email": "me@example.com",
+                                "plan": "Max",
+                                "organization": null,
+                                "account_id": null
+                            },
+                            "rate_limit": null,
+                            "busiest_member": null,
+                            "updated_at": "2026-01-02T03:04:05Z"
+                        },
+                        "error": null
+                    },
+                    {
+                        "provider": "codex",
+                        "snapshot": null,
+                        "error": {
+                            "kind": "rate_limited",
+                            "message": "<local time>",
+                            "retry_after": "2026-01-02T03:04:05Z"
+                        }
+                    },
+                    {
+                        "provider": "windsurf",
+                        "snapshot": null,
+                        "error": {"kind": "fetch_failed", "message": "boom"}
+                    },
+                    {
+                        "provider": "opencode",
+                        "snapshot": null,
+                        "error": {
+                            "kind": "disabled",
+                            "message": "OpenCode is disabled in the config"
+                        }
+                    }
+                ]
+            })
+        );
+    }
+}
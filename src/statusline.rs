@@ -0,0 +1,391 @@
+use crate::config::{Config, DisplayInfo};
+use crate::format;
+use crate::models::{PercentDisplay, RateWindow, UsageSnapshot};
+use crate::timefmt::TimeStyle;
+use chrono::{Local, Utc};
+use std::collections::HashMap;
+
+/// Module icon shared by all bar outputs
+pub const ICON: &str = "󰧑";
+
+/// Text shown when no provider has data
+pub fn no_data_text() -> String {
+    format!("{} --", ICON)
+}
+
+/// Escape text for Pango markup (Waybar tooltips)
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\'' => escaped.push_str("&apos;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Default bar text: "󰧑 31% / 51%" (session / week), dropping missing windows
+pub fn bar_text(
+    snapshot: &UsageSnapshot,
+    info: &DisplayInfo,
+    precision: u8,
+    display: PercentDisplay,
+) -> String {
+    let session = snapshot.primary().map(|r| display.format(r, precision));
+    let week = snapshot.secondary().map(|r| display.format(r, precision));
+
+    let text = match (session, week) {
+        (Some(s), Some(w)) => format!("{}% / {}%", s, w),
+        (Some(s), None) | (None, Some(s)) => format!("{}%", s),
+        // Cost-only providers: "󰧑 $42 / $100"
+        (None, None) => match snapshot.cost {
+            Some(ref cost) => cost.compact(),
+            None => return no_data_text(),
+        },
+    };
+    info.prefix(&text)
+}
+
+/// Provider icon and most constrained window, e.g. " 85%"
+pub fn compact_text(
+    snapshot: &UsageSnapshot,
+    info: &DisplayInfo,
+    precision: u8,
+    display: PercentDisplay,
+) -> String {
+    let text = match (snapshot.most_constrained(), snapshot.cost.as_ref()) {
+        (Some(w), _) => format!("{}%", display.format(w, precision)),
+        (None, Some(cost)) => cost.compact(),
+        (None, None) => "--".to_string(),
+    };
+    info.prefix(&text)
+}
+
+/// Placeholder values for user format templates
+pub fn template_values(
+    snapshot: &UsageSnapshot,
+    info: &DisplayInfo,
+    precision: u8,
+    time: &TimeStyle,
+) -> format::Values {
+    let used = |w: Option<&RateWindow>| w.map(|r| r.format_percent(r.used_percent, precision));
+    let remaining =
+        |w: Option<&RateWindow>| w.map(|r| r.format_percent(r.remaining_percent(), precision));
+    let now = Utc::now();
+    let reset = |w: Option<&RateWindow>| {
+        w.and_then(|r| match r.resets_at {
+            Some(resets_at) => Some(time.reset_brief(resets_at, now, &Local)),
+            None => r.reset_description.clone(),
+        })
+    };
+    HashMap::from([
+        ("icon", Some(info.icon.clone())),
+        ("provider", Some(info.label.clone())),
+        ("session", used(snapshot.primary())),
+        ("session_remaining", remaining(snapshot.primary())),
+        ("week", used(snapshot.secondary())),
+        ("week_remaining", remaining(snapshot.secondary())),
+        ("cost", snapshot.cost.as_ref().map(|c| c.compact())),
+        (
+            "cost_used",
+            snapshot.cost.as_ref().map(|c| c.format_amount(c.used)),
+        ),
+        (
+            "cost_limit",
+            snapshot
+                .cost
+                .as_ref()
+                .filter(|c| c.limit > 0.0)
+                .map(|c| c.format_amount(c.limit)),
+        ),
+        ("reset_session", reset(snapshot.primary())),
+        ("reset_week", reset(snapshot.secondary())),
+    ])
+}
+
+/// One polybar line, e.g. `%{F#e06c75}󰧑 82% / 45%%{F-}`
+pub fn polybar_line(snapshot: Option<&UsageSnapshot>, config: &Config, color: bool) -> String {
+    let Some(snapshot) = snapshot else {
+        return no_data_text();
+    };
+    let precision = config.general.precision;
+    let info = config.display_info(snapshot, ICON);
+    let text = match config.polybar.format {
+        Some(ref template) => format::render(
+            template,
+            &template_values(snapshot, &info, precision, &config.time_style()),
+        ),
+        None => bar_text(snapshot, &info, precision, config.general.display),
+    };
+
+    let fg = config.colors.for_class(snapshot.status_class(config));
+    match fg.filter(|_| color) {
+        Some(fg) => format!("%{{F{}}}{}%{{F-}}", fg, text),
+        None => text,
+    }
+}
+
+/// tmux color for a status class
+pub fn tmux_color(class: &str) -> &'static str {
+    match class {
+        "critical" => "red",
+        "warning" => "yellow",
+        _ => "green",
+    }
+}
+
+/// A tmux status-line segment, e.g. `#[fg=yellow]󰧑 82%#[default]`. Only the
+/// session is shown unless `detail` adds the week.
+pub fn tmux_segment(snapshot: Option<&UsageSnapshot>, config: &Config, detail: bool) -> String {
+    let Some(snapshot) = snapshot else {
+        return no_data_text();
+    };
+    let precision = config.general.precision;
+    let display = config.general.display;
+    let info = config.display_info(snapshot, ICON);
+    let text = match (detail, snapshot.primary()) {
+        (false, Some(r)) => info.prefix(&format!("{}%", display.format(r, precision))),
+        _ => bar_text(snapshot, &info, precision, display),
+    };
+    format!(
+        "#[fg={}]{}#[default]",
+        tmux_color(snapshot.status_class(config)),
+        text
+    )
+}
+
+/// Shell prompt text such as "󰧑82%", with the provider's configured icon,
+/// or None below `threshold` so the prompt module hides itself
+pub fn prompt_text(
+    snapshot: Option<&UsageSnapshot>,
+    config: &Config,
+    threshold: f64,
+) -> Option<String> {
+    let snapshot = snapshot?;
+    let used = snapshot.max_used()?;
+    (used >= threshold).then(|| format!("{}{:.0}%", config.display_info(snapshot, ICON).icon, used))
+}
+
+/// i3blocks protocol: full text, short text, and color (omitted when the
+/// class has no color). With no data, a single `--` line.
+pub fn i3blocks_lines(snapshot: Option<&UsageSnapshot>, config: &Config) -> Vec<String> {
+    let Some(snapshot) = snapshot else {
+        return vec!["--".to_string()];
+    };
+    let precision = config.general.precision;
+    let display = config.general.display;
+    let info = config.display_info(snapshot, ICON);
+    let short = match snapshot.primary() {
+        Some(r) => info.prefix(&format!("{}%", display.format(r, precision))),
+        None => no_data_text(),
+    };
+
+    let mut lines = vec![bar_text(snapshot, &info, precision, display), short];
+    if let Some(color) = config.colors.for_class(snapshot.status_class(config)) {
+        lines.push(color.to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::mock_snapshots;
+    use crate::models::Provider;
+
+    #[test]
+    fn test_escape_markup() {
+        assert_eq!(
+            escape_markup("R&D <team> 'a' \"b\""),
+            "R&amp;D &lt;team&gt; &apos;a&apos; &quot;b&quot;"
+        );
+        assert_eq!(escape_markup("in 3h 12m"), "in 3h 12m");
+    }
+
+    #[test]
+    fn test_color_selection() {
+        let mut colors = Config::default().colors;
+        assert_eq!(colors.for_class("critical"), Some("#e06c75"));
+        assert_eq!(colors.for_class("warning"), Some("#e5c07b"));
+        assert_eq!(colors.for_class("normal"), None);
+        colors.normal = Some("#98c379".into());
+        assert_eq!(colors.for_class("normal"), Some("#98c379"));
+    }
+
+    #[test]
+    fn test_polybar_line_wraps_by_class() {
+        let snapshots = mock_snapshots();
+        let config = Config::default();
+
+        // Codex mock is at 85% session: warning
+        let codex = polybar_line(snapshots.get(&Provider::Codex), &config, true);
+        assert!(codex.starts_with("%{F#e5c07b}"), "{}", codex);
+        assert!(codex.ends_with("%{F-}"));
+
+        let plain = polybar_line(snapshots.get(&Provider::Codex), &config, false);
+        assert!(!plain.contains("%{"));
+
+        // OpenCode mock is healthy: no color tags
+        let healthy = polybar_line(snapshots.get(&Provider::OpenCode), &config, true);
+        assert!(!healthy.contains("%{"), "{}", healthy);
+
+        assert_eq!(polybar_line(None, &config, true), no_data_text());
+    }
+
+    #[test]
+    fn test_polybar_template() {
+        let snapshots = mock_snapshots();
+        let mut config = Config::default();
+        config.polybar.format = Some("{provider}: {session}% ({week_remaining}% left)".into());
+
+        let line = polybar_line(snapshots.get(&Provider::Codex), &config, false);
+        assert_eq!(line, "Codex: 85% (--% left)");
+    }
+
+    #[test]
+    fn test_template_values() {
+        let snapshots = mock_snapshots();
+        let config = Config::default();
+        let values = |p: Provider| {
+            let snapshot = &snapshots[&p];
+            template_values(
+                snapshot,
+                &config.display_info(snapshot, ICON),
+                0,
+                &config.time_style(),
+            )
+        };
+        let claude = values(Provider::Claude);
+        assert_eq!(claude["cost_used"].as_deref(), Some("$42.50"));
+        assert_eq!(claude["cost_limit"].as_deref(), Some("$100.00"));
+        assert_eq!(claude["reset_session"].as_deref(), Some("in 5h"));
+
+        let codex = values(Provider::Codex);
+        assert_eq!(codex["cost_used"], None);
+        assert_eq!(codex["reset_week"], None);
+        let text = format::render("{session}%[ / {week}%][ · {cost_used}]", &codex);
+        assert_eq!(text, "85%");
+    }
+
+    fn snapshot_at(session: f64, week: Option<f64>) -> UsageSnapshot {
+        let mut snapshot = mock_snapshots().remove(&Provider::Claude).unwrap();
+        snapshot.windows[0].used_percent = session;
+        match week {
+            Some(w) => snapshot.windows[1].used_percent = w,
+            None => snapshot.windows.truncate(1),
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_i3blocks_normal() {
+        let mut config = Config::default();
+        let snapshot = snapshot_at(20.0, Some(40.0));
+        assert_eq!(
+            i3blocks_lines(Some(&snapshot), &config),
+            vec!["󰧑 20% / 40%", "󰧑 20%"]
+        );
+
+        config.colors.normal = Some("#98c379".into());
+        assert_eq!(
+            i3blocks_lines(Some(&snapshot), &config),
+            vec!["󰧑 20% / 40%", "󰧑 20%", "#98c379"]
+        );
+    }
+
+    #[test]
+    fn test_i3blocks_warning_and_critical() {
+        let config = Config::default();
+        let warning = snapshot_at(80.0, None);
+        assert_eq!(
+            i3blocks_lines(Some(&warning), &config),
+            vec!["󰧑 80%", "󰧑 80%", "#e5c07b"]
+        );
+
+        // The weekly window drives the color even when the session is fine
+        let critical = snapshot_at(10.0, Some(95.0));
+        assert_eq!(
+            i3blocks_lines(Some(&critical), &config),
+            vec!["󰧑 10% / 95%", "󰧑 10%", "#e06c75"]
+        );
+    }
+
+    #[test]
+    fn test_i3blocks_no_data() {
+        assert_eq!(i3blocks_lines(None, &Config::default()), vec!["--"]);
+    }
+
+    #[test]
+    fn test_tmux_color_escapes() {
+        assert_eq!(tmux_color("critical"), "red");
+        assert_eq!(tmux_color("warning"), "yellow");
+        assert_eq!(tmux_color("normal"), "green");
+
+        let config = Config::default();
+        let warning = snapshot_at(82.0, Some(45.0));
+        assert_eq!(
+            tmux_segment(Some(&warning), &config, false),
+            "#[fg=yellow]󰧑 82%#[default]"
+        );
+        assert_eq!(
+            tmux_segment(Some(&warning), &config, true),
+            "#[fg=yellow]󰧑 82% / 45%#[default]"
+        );
+
+        let critical = snapshot_at(95.0, None);
+        assert_eq!(
+            tmux_segment(Some(&critical), &config, true),
+            "#[fg=red]󰧑 95%#[default]"
+        );
+
+        assert_eq!(tmux_segment(None, &config, false), no_data_text());
+
+        // Colors follow the configured thresholds
+        let mut config = Config::default();
+        config.thresholds.warning = 85.0;
+        assert_eq!(
+            tmux_segment(Some(&warning), &config, false),
+            "#[fg=green]󰧑 82%#[default]"
+        );
+    }
+
+    #[test]
+    fn test_prompt_silent_under_threshold() {
+        let config = Config::default();
+        let snapshot = snapshot_at(60.0, Some(74.0));
+        assert_eq!(prompt_text(Some(&snapshot), &config, 75.0), None);
+        assert_eq!(prompt_text(None, &config, 75.0), None);
+        assert_eq!(prompt_text(None, &config, 0.0), None);
+    }
+
+    #[test]
+    fn test_prompt_shows_most_constrained_window() {
+        let config = Config::default();
+        let snapshot = snapshot_at(60.0, Some(82.0));
+        let prompt = |threshold| prompt_text(Some(&snapshot), &config, threshold);
+        assert_eq!(prompt(75.0).as_deref(), Some("󰧑82%"));
+        assert_eq!(prompt(82.0).as_deref(), Some("󰧑82%"));
+        assert_eq!(prompt(90.0), None);
+    }
+
+    #[test]
+    fn test_prompt_uses_configured_icon() {
+        let mut config = Config::default();
+        let snapshot = snapshot_at(60.0, Some(82.0));
+        config.providers.entry(Provider::Claude).or_default().icon = Some("C".to_string());
+        assert_eq!(
+            prompt_text(Some(&snapshot), &config, 75.0).as_deref(),
+            Some("C82%")
+        );
+        config.providers.get_mut(&Provider::Claude).unwrap().icon = Some(String::new());
+        assert_eq!(
+            prompt_text(Some(&snapshot), &config, 75.0).as_deref(),
+            Some("82%")
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+/// Glyph set used for terminal output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconSet {
+    /// Nerd Font glyphs and Unicode block elements
+    #[default]
+    Nerd,
+    /// Plain ASCII only
+    Ascii,
+}
+
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+const FULL: char = '█';
+const EMPTY: char = '░';
+
+/// Render a fixed-width bar for a 0-100 value. The result is always exactly
+/// `width` characters; Unicode bars use eighth-blocks for partial cells.
+pub fn render_bar(value: f64, width: usize, charset: IconSet) -> String {
+    let fraction = if value.is_finite() {
+        (value / 100.0).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    match charset {
+        IconSet::Nerd => {
+            let eighths = (fraction * width as f64 * 8.0).round() as usize;
+            let full = eighths / 8;
+            let partial = eighths % 8;
+            let mut bar = String::with_capacity(width * 3);
+            bar.extend(std::iter::repeat_n(FULL, full));
+            if partial > 0 {
+                bar.push(EIGHTHS[partial - 1]);
+            }
+            let used = full + usize::from(partial > 0);
+            bar.extend(std::iter::repeat_n(EMPTY, width - used));
+            bar
+        }
+        IconSet::Ascii => {
+            let filled = (fraction * width as f64).round() as usize;
+            let mut bar = "#".repeat(filled);
+            bar.push_str(&"-".repeat(width - filled));
+            bar
+        }
+    }
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const ASCII_SPARKS: [char; 8] = ['_', '.', ',', '-', '~', '=', '*', '#'];
+
+/// A sparkline of 0-100 values on a fixed scale, at most `width` cells wide.
+/// Longer series are squeezed by taking each bucket's peak.
+pub fn sparkline(values: &[f64], width: usize, charset: IconSet) -> String {
+    let glyphs = match charset {
+        IconSet::Nerd => &SPARKS,
+        IconSet::Ascii => &ASCII_SPARKS,
+    };
+    let cells = values.len().min(width);
+    (0..cells)
+        .map(|i| {
+            let bucket = &values[i * values.len() / cells..(i + 1) * values.len() / cells];
+            let peak = bucket.iter().copied().fold(0.0, f64::max);
+            let level = (peak.clamp(0.0, 100.0) / 100.0 * 7.0).round() as usize;
+            glyphs[level]
+        })
+        .collect()
+}
+
+/// Wrap text in the ANSI color for a status class
+pub fn colorize(text: &str, class: &str) -> String {
+    let code = match class {
+        "critical" => "31",
+        "warning" => "33",
+        _ => "32",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width_invariant() {
+        for width in [0, 1, 7, 20, 33] {
+            for step in 0..=200 {
+                let value = step as f64 * 0.5;
+                for charset in [IconSet::Nerd, IconSet::Ascii] {
+                    let bar = render_bar(value, width, charset);
+                    assert_eq!(bar.chars().count(), width, "{} at {}", value, width);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_and_empty() {
+        assert_eq!(render_bar(0.0, 4, IconSet::Nerd), "░░░░");
+        assert_eq!(render_bar(100.0, 4, IconSet::Nerd), "████");
+        assert_eq!(render_bar(150.0, 4, IconSet::Ascii), "####");
+        assert_eq!(render_bar(-5.0, 4, IconSet::Ascii), "----");
+        assert_eq!(render_bar(f64::NAN, 2, IconSet::Ascii), "--");
+    }
+
+    #[test]
+    fn test_partial_blocks() {
+        assert_eq!(render_bar(45.0, 20, IconSet::Nerd), "█████████░░░░░░░░░░░");
+        // 10% of 4 cells = 3.2 eighths -> rounds to 3 eighths
+        assert_eq!(render_bar(10.0, 4, IconSet::Nerd), "▍░░░");
+        // Under half an eighth rounds down to nothing, an eighth shows a sliver
+        assert_eq!(render_bar(0.5, 10, IconSet::Nerd), "░░░░░░░░░░");
+        assert_eq!(render_bar(1.25, 10, IconSet::Nerd), "▏░░░░░░░░░");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0.0, 50.0, 100.0], 10, IconSet::Nerd), "▁▅█");
+        assert_eq!(sparkline(&[0.0, 50.0, 100.0], 10, IconSet::Ascii), "_~#");
+        // Six values into three cells keep each pair's peak
+        assert_eq!(
+            sparkline(&[0.0, 100.0, 10.0, 0.0, 0.0, 0.0], 3, IconSet::Nerd),
+            "█▂▁"
+        );
+        assert_eq!(sparkline(&[], 10, IconSet::Nerd), "");
+    }
+
+    #[test]
+    fn test_ascii_rounding() {
+        assert_eq!(render_bar(44.0, 10, IconSet::Ascii), "####------");
+        assert_eq!(render_bar(46.0, 10, IconSet::Ascii), "#####-----");
+    }
+}
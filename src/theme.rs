@@ -0,0 +1,149 @@
+//! The popup's built-in stylesheets and the `[colors]` they're given
+
+use crate::config::{ColorsConfig, PopupTheme};
+
+/// A built-in stylesheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shade {
+    Dark,
+    Light,
+}
+
+impl Shade {
+    pub fn stylesheet(self) -> &'static str {
+        match self {
+            Shade::Dark => include_str!("popup.css"),
+            Shade::Light => include_str!("popup-light.css"),
+        }
+    }
+
+    /// The stylesheet's file name in the source tree, for `--mock` editing
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Shade::Dark => "popup.css",
+            Shade::Light => "popup-light.css",
+        }
+    }
+
+    /// Accent, background, warning and critical for when `[colors]` leaves
+    /// them unset or isn't usable
+    fn defaults(self) -> [&'static str; 4] {
+        match self {
+            Shade::Dark => ["#f92672", "#1c1c1c", "#e6db74", "#f92672"],
+            Shade::Light => ["#d6336c", "#fafafa", "#986801", "#e45649"],
+        }
+    }
+}
+
+/// The stylesheet `theme` picks. Auto follows the desktop portal's
+/// color-scheme (1 prefers dark, 2 light), then GTK's dark preference.
+pub fn resolve(theme: PopupTheme, portal: Option<u32>, prefers_dark: bool) -> Shade {
+    match theme {
+        PopupTheme::Dark => Shade::Dark,
+        PopupTheme::Light => Shade::Light,
+        PopupTheme::Auto => match portal {
+            Some(1) => Shade::Dark,
+            Some(2) => Shade::Light,
+            _ if prefers_dark => Shade::Dark,
+            _ => Shade::Light,
+        },
+    }
+}
+
+/// `@define-color` rules for the colors the stylesheets refer to, to go
+/// ahead of them. Values that aren't hex colors are left out with a warning,
+/// so nothing from the config reaches the CSS unchecked.
+pub fn color_definitions(shade: Shade, colors: &ColorsConfig) -> String {
+    let [accent, background, warning, critical] = shade.defaults();
+    [
+        ("accent", colors.accent.as_deref(), accent),
+        ("background", colors.background.as_deref(), background),
+        ("warning", Some(colors.warning.as_str()), warning),
+        ("critical", Some(colors.critical.as_str()), critical),
+    ]
+    .into_iter()
+    .map(|(name, value, fallback)| {
+        let value = value.and_then(|v| {
+            let color = css_hex(v);
+            if color.is_none() {
+                tracing::warn!("[colors] {} {:?} is not a hex color; ignored", name, v);
+            }
+            color
+        });
+        format!(
+            "@define-color quotabar_{} {};\n",
+            name,
+            value.unwrap_or(fallback)
+        )
+    })
+    .collect()
+}
+
+/// `value` trimmed, when it's `#RGB`, `#RRGGBB` or `#RRGGBBAA`
+fn css_hex(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let hex = value.strip_prefix('#')?;
+    (matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(PopupTheme::Dark, Some(2), false), Shade::Dark);
+        assert_eq!(resolve(PopupTheme::Light, Some(1), true), Shade::Light);
+        assert_eq!(resolve(PopupTheme::Auto, Some(1), false), Shade::Dark);
+        assert_eq!(resolve(PopupTheme::Auto, Some(2), true), Shade::Light);
+        // No portal preference: GTK's setting decides
+        assert_eq!(resolve(PopupTheme::Auto, Some(0), true), Shade::Dark);
+        assert_eq!(resolve(PopupTheme::Auto, None, false), Shade::Light);
+    }
+
+    #[test]
+    fn test_color_definitions() {
+        let colors = ColorsConfig {
+            accent: Some(" #ABCDEF ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            color_definitions(Shade::Light, &colors),
+            "@define-color quotabar_accent #ABCDEF;\n\
+             @define-color quotabar_background #fafafa;\n\
+             @define-color quotabar_warning #e5c07b;\n\
+             @define-color quotabar_critical #e06c75;\n"
+        );
+    }
+
+    #[test]
+    fn test_only_hex_colors_reach_the_css() {
+        let colors = ColorsConfig {
+            background: Some("#000; } window { background: url('x')".to_string()),
+            critical: "red".to_string(),
+            warning: "#abcd".to_string(),
+            ..Default::default()
+        };
+        let css = color_definitions(Shade::Dark, &colors);
+        assert_eq!(css.lines().count(), 4);
+        assert!(css.contains("quotabar_background #1c1c1c;"), "{}", css);
+        assert!(css.contains("quotabar_critical #f92672;"), "{}", css);
+        assert!(css.contains("quotabar_warning #e6db74;"), "{}", css);
+
+        assert_eq!(css_hex("#fff"), Some("#fff"));
+        assert_eq!(css_hex("#11223344"), Some("#11223344"));
+        assert_eq!(css_hex("fff"), None);
+        assert_eq!(css_hex("#ggg"), None);
+    }
+
+    #[test]
+    fn test_stylesheets_define_nothing_themselves() {
+        // The names must come from color_definitions, which goes first
+        for shade in [Shade::Dark, Shade::Light] {
+            let css = shade.stylesheet();
+            assert!(!css.contains("@define-color"));
+            assert!(css.contains("@quotabar_accent"));
+        }
+    }
+}
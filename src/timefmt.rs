@@ -0,0 +1,334 @@
+//! How times are written: resets, clock times, countdowns and ages, following
+//! `[general] time_format`, `relative_times` and `relative_style`
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// `[general] time_format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockFormat {
+    /// "18:45"
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    /// "6:45 PM"
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+/// `[general] relative_style`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelativeStyle {
+    /// "in 3h 12m", "in 3 days"
+    #[default]
+    Long,
+    /// "3h 12m", "3d"
+    Short,
+}
+
+/// The time settings, as every display takes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeStyle {
+    pub clock: ClockFormat,
+    /// Lead with how far off a reset is; off shows only when it happens
+    pub relative: bool,
+    pub relative_style: RelativeStyle,
+}
+
+impl Default for TimeStyle {
+    fn default() -> Self {
+        Self {
+            clock: ClockFormat::default(),
+            relative: true,
+            relative_style: RelativeStyle::default(),
+        }
+    }
+}
+
+impl TimeStyle {
+    /// A reset with its clock time in `tz`: "in 3h 12m, 18:45", "3d, Fri
+    /// 09:00", or "Mon 18:45" without relative times
+    pub fn reset<Tz: TimeZone>(&self, reset: DateTime<Utc>, now: DateTime<Utc>, tz: &Tz) -> String
+    where
+        Tz::Offset: Display,
+    {
+        if reset <= now {
+            return "now".to_string();
+        }
+        let clock = clock_on(
+            &reset.with_timezone(tz),
+            now.with_timezone(tz).date_naive(),
+            self.clock,
+        );
+        match self.relative {
+            true => format!("{}, {}", relative(reset, now, self.relative_style), clock),
+            false => clock,
+        }
+    }
+
+    /// A reset on its own, for bar templates: "in 3h 12m", or "18:45"
+    /// without relative times
+    pub fn reset_brief<Tz: TimeZone>(
+        &self,
+        reset: DateTime<Utc>,
+        now: DateTime<Utc>,
+        tz: &Tz,
+    ) -> String
+    where
+        Tz::Offset: Display,
+    {
+        match self.relative {
+            true => relative(reset, now, self.relative_style),
+            false => self.reset(reset, now, tz),
+        }
+    }
+
+    /// A reset for displays redrawn every second: a ticking [`countdown`],
+    /// or the clock time without relative times
+    pub fn reset_live<Tz: TimeZone>(
+        &self,
+        reset: DateTime<Utc>,
+        now: DateTime<Utc>,
+        tz: &Tz,
+    ) -> String
+    where
+        Tz::Offset: Display,
+    {
+        match self.relative {
+            true => countdown(reset - now),
+            false => self.reset(reset, now, tz),
+        }
+    }
+}
+
+/// How far off `reset` is: "in 45 min", "in 3h 12m", "in 1 day", "in 2d 4h"
+/// (or "45m", "3h 12m", "1d", "2d 4h" short), and "now" once it has passed.
+/// Partial minutes round up.
+pub fn relative(reset: DateTime<Utc>, now: DateTime<Utc>, style: RelativeStyle) -> String {
+    let seconds = (reset - now).num_seconds();
+    if seconds <= 0 {
+        return "now".to_string();
+    }
+    let total_minutes = (seconds + 59) / 60;
+    let (days, hours, minutes) = (
+        total_minutes / (24 * 60),
+        total_minutes / 60 % 24,
+        total_minutes % 60,
+    );
+    match style {
+        RelativeStyle::Long => match (days, hours, minutes) {
+            (0, 0, m) => format!("in {} min", m),
+            (0, h, 0) => format!("in {}h", h),
+            (0, h, m) => format!("in {}h {}m", h, m),
+            (1, 0, _) => "in 1 day".to_string(),
+            (d, 0, _) => format!("in {} days", d),
+            (d, h, _) => format!("in {}d {}h", d, h),
+        },
+        RelativeStyle::Short => match (days, hours, minutes) {
+            (0, 0, m) => format!("{}m", m),
+            (0, h, 0) => format!("{}h", h),
+            (0, h, m) => format!("{}h {}m", h, m),
+            (d, 0, _) => format!("{}d", d),
+            (d, h, _) => format!("{}d {}h", d, h),
+        },
+    }
+}
+
+/// `at`'s time of day: "18:45" or "6:45 PM"
+pub fn clock<Tz: TimeZone>(at: &DateTime<Tz>, format: ClockFormat) -> String
+where
+    Tz::Offset: Display,
+{
+    match format {
+        ClockFormat::TwentyFourHour => at.format("%H:%M").to_string(),
+        ClockFormat::TwelveHour => at.format("%-I:%M %p").to_string(),
+    }
+}
+
+/// [`clock`], after the weekday unless `at` falls on `today`: "18:45",
+/// "Fri 09:00"
+pub fn clock_on<Tz: TimeZone>(at: &DateTime<Tz>, today: NaiveDate, format: ClockFormat) -> String
+where
+    Tz::Offset: Display,
+{
+    let time = clock(at, format);
+    match at.date_naive() == today {
+        true => time,
+        false => format!("{} {}", at.format("%a"), time),
+    }
+}
+
+/// A countdown that ticks every second: "in 2h 05m 09s", "in 3d 04h 10m"
+pub fn countdown(remaining: Duration) -> String {
+    let secs = remaining.num_seconds();
+    if secs <= 0 {
+        return "resetting now".to_string();
+    }
+    let (days, hours, minutes, seconds) =
+        (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("in {}d {:02}h {:02}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("in {}h {:02}m {:02}s", hours, minutes, seconds)
+    } else {
+        format!("in {}m {:02}s", minutes, seconds)
+    }
+}
+
+/// How long ago something happened, e.g. "3 min ago" or "2 h ago".
+/// Future times (clock skew) read as "just now".
+pub fn age(age: Duration) -> String {
+    let seconds = age.num_seconds();
+    if seconds < 10 {
+        "just now".to_string()
+    } else if seconds < 60 {
+        format!("{} s ago", seconds)
+    } else if seconds < 3600 {
+        format!("{} min ago", seconds / 60)
+    } else if seconds < 86400 {
+        format!("{} h ago", seconds / 3600)
+    } else {
+        format!("{} d ago", seconds / 86400)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    #[test]
+    fn test_relative() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 10, 0, 0).unwrap();
+        let at = |d: Duration| relative(now + d, now, RelativeStyle::Long);
+        assert_eq!(at(Duration::hours(5)), "in 5h");
+        // A reset a moment short of the hour still reads as the hour
+        assert_eq!(at(Duration::hours(5) - Duration::seconds(1)), "in 5h");
+        assert_eq!(at(Duration::minutes(192)), "in 3h 12m");
+        assert_eq!(at(Duration::minutes(30)), "in 30 min");
+        assert_eq!(at(Duration::seconds(10)), "in 1 min");
+        assert_eq!(at(Duration::hours(24)), "in 1 day");
+        assert_eq!(at(Duration::days(3)), "in 3 days");
+        assert_eq!(at(Duration::hours(28) + Duration::minutes(5)), "in 1d 4h");
+        assert_eq!(at(Duration::seconds(-5)), "now");
+
+        let short = |d: Duration| relative(now + d, now, RelativeStyle::Short);
+        assert_eq!(short(Duration::hours(5)), "5h");
+        assert_eq!(short(Duration::minutes(192)), "3h 12m");
+        assert_eq!(short(Duration::minutes(30)), "30m");
+        assert_eq!(short(Duration::hours(24)), "1d");
+        assert_eq!(short(Duration::days(3)), "3d");
+        assert_eq!(short(Duration::hours(28) + Duration::minutes(5)), "1d 4h");
+        assert_eq!(short(Duration::seconds(-5)), "now");
+    }
+
+    #[test]
+    fn test_reset_across_days() {
+        // 22:30 local (UTC+2), a Monday
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 20, 30, 0).unwrap();
+        let style = TimeStyle::default();
+
+        let tonight = now + Duration::minutes(75);
+        assert_eq!(style.reset(tonight, now, &tz), "in 1h 15m, 23:45");
+
+        // Past local midnight, though still the same day in UTC
+        let tomorrow = now + Duration::minutes(105);
+        assert_eq!(style.reset(tomorrow, now, &tz), "in 1h 45m, Tue 00:15");
+
+        let friday = now + Duration::days(3) + Duration::hours(12);
+        assert_eq!(style.reset(friday, now, &tz), "in 3d 12h, Fri 10:30");
+
+        assert_eq!(style.reset(now, now, &tz), "now");
+    }
+
+    #[test]
+    fn test_reset_behind_utc() {
+        // 19:00 Sunday in UTC-5, already Monday in UTC
+        let tz = FixedOffset::west_opt(5 * 3600).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 0, 0, 0).unwrap();
+        let style = TimeStyle {
+            clock: ClockFormat::TwelveHour,
+            relative: false,
+            relative_style: RelativeStyle::Long,
+        };
+
+        // Same UTC day as now, but still Sunday locally
+        let tonight = now + Duration::hours(4) + Duration::minutes(45);
+        assert_eq!(style.reset(tonight, now, &tz), "11:45 PM");
+        // Monday locally, still the same UTC date
+        let midnight = now + Duration::hours(5);
+        assert_eq!(style.reset(midnight, now, &tz), "Mon 12:00 AM");
+        let morning = now + Duration::hours(14) + Duration::minutes(5);
+        assert_eq!(style.reset(morning, now, &tz), "Mon 9:05 AM");
+        assert_eq!(style.reset_brief(morning, now, &tz), "Mon 9:05 AM");
+        assert_eq!(
+            style.reset_live(morning, now + Duration::seconds(1), &tz),
+            "Mon 9:05 AM"
+        );
+        assert_eq!(style.reset(now, now, &tz), "now");
+
+        let style = TimeStyle {
+            relative: true,
+            relative_style: RelativeStyle::Short,
+            ..style
+        };
+        assert_eq!(style.reset(morning, now, &tz), "14h 5m, Mon 9:05 AM");
+        assert_eq!(style.reset_brief(morning, now, &tz), "14h 5m");
+        assert_eq!(
+            style.reset_live(morning, now + Duration::seconds(1), &tz),
+            "in 14h 04m 59s"
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let tz = FixedOffset::east_opt(9 * 3600 + 1800).unwrap();
+        let at = Utc
+            .with_ymd_and_hms(2026, 3, 2, 8, 15, 0)
+            .unwrap()
+            .with_timezone(&tz);
+        assert_eq!(clock(&at, ClockFormat::TwentyFourHour), "17:45");
+        assert_eq!(clock(&at, ClockFormat::TwelveHour), "5:45 PM");
+        let today = at.date_naive();
+        assert_eq!(clock_on(&at, today, ClockFormat::TwentyFourHour), "17:45");
+        let yesterday = today.pred_opt().unwrap();
+        assert_eq!(
+            clock_on(&at, yesterday, ClockFormat::TwelveHour),
+            "Mon 5:45 PM"
+        );
+    }
+
+    #[test]
+    fn test_countdown() {
+        assert_eq!(countdown(Duration::seconds(59)), "in 0m 59s");
+        assert_eq!(
+            countdown(Duration::seconds(2 * 3600 + 5 * 60 + 9)),
+            "in 2h 05m 09s"
+        );
+        assert_eq!(
+            countdown(Duration::seconds(3 * 86400 + 4 * 3600 + 600)),
+            "in 3d 04h 10m"
+        );
+        assert_eq!(countdown(Duration::seconds(-5)), "resetting now");
+    }
+
+    #[test]
+    fn test_age() {
+        let secs = Duration::seconds;
+        assert_eq!(age(secs(-30)), "just now");
+        assert_eq!(age(secs(0)), "just now");
+        assert_eq!(age(secs(9)), "just now");
+        assert_eq!(age(secs(42)), "42 s ago");
+        assert_eq!(age(secs(60)), "1 min ago");
+        assert_eq!(age(secs(3 * 60 + 59)), "3 min ago");
+        assert_eq!(age(secs(3599)), "59 min ago");
+        assert_eq!(age(secs(3600)), "1 h ago");
+        assert_eq!(age(secs(2 * 3600 + 1800)), "2 h ago");
+        assert_eq!(age(secs(86399)), "23 h ago");
+        assert_eq!(age(secs(86400)), "1 d ago");
+        assert_eq!(age(secs(10 * 86400)), "10 d ago");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::cache::CacheState;
+use crate::config::Config;
+use crate::fetch;
+use crate::models::{Provider, UsageSnapshot};
+use crate::status::{self, LiveView, StatusStyle};
+use crate::timefmt;
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Alternate screen + hidden cursor while the view runs
+const ENTER: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE: &str = "\x1b[?25h\x1b[?1049l";
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// Restores the terminal however the loop exits
+struct Screen;
+
+impl Screen {
+    fn enter() -> Self {
+        print!("{}", ENTER);
+        let _ = std::io::stdout().flush();
+        Screen
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("{}", LEAVE);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Redraw the `status` layout every second until Ctrl-C. Data comes from
+/// the cache; the network is only used every `interval`.
+pub async fn run(
+    config: &Config,
+    shown: &[Provider],
+    interval: Duration,
+    style: &StatusStyle,
+) -> Result<()> {
+    let enabled: Vec<Provider> = shown
+        .iter()
+        .copied()
+        .filter(|p| config.is_provider_enabled(*p))
+        .collect();
+    let mut current: HashMap<Provider, UsageSnapshot> = HashMap::new();
+    let mut previous: HashMap<Provider, UsageSnapshot> = HashMap::new();
+    let mut errors: HashMap<Provider, String> = HashMap::new();
+    let mut next_fetch = Instant::now();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    let _screen = Screen::enter();
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+
+        if Instant::now() >= next_fetch {
+            let refresh = tokio::select! {
+                r = fetch::refresh(config, &enabled, false) => r,
+                _ = tokio::signal::ctrl_c() => break,
+            };
+            errors = refresh
+                .errors
+                .iter()
+                .map(|(p, e)| (*p, format!("{:#}", e)))
+                .collect();
+            next_fetch = Instant::now() + interval;
+        }
+
+        // Re-read every tick so a daemon or bar refreshing in the
+        // background shows up immediately
+        let cached = CacheState::load()
+            .ok()
+            .flatten()
+            .map(|c| c.snapshots)
+            .unwrap_or_default();
+        for provider in &enabled {
+            let Some(snapshot) = cached.get(provider) else {
+                continue;
+            };
+            match current.insert(*provider, snapshot.clone()) {
+                Some(old) if old.updated_at != snapshot.updated_at => {
+                    previous.insert(*provider, old);
+                }
+                _ => {}
+            }
+        }
+
+        let screen = draw(
+            shown,
+            config,
+            &current,
+            &previous,
+            &errors,
+            next_fetch.saturating_duration_since(Instant::now()),
+            style,
+        );
+        print!("{}{}", CLEAR, screen);
+        std::io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+fn draw(
+    shown: &[Provider],
+    config: &Config,
+    current: &HashMap<Provider, UsageSnapshot>,
+    previous: &HashMap<Provider, UsageSnapshot>,
+    errors: &HashMap<Provider, String>,
+    until_fetch: Duration,
+    style: &StatusStyle,
+) -> String {
+    let now = Utc::now();
+    let mut lines = vec![
+        format!(
+            "quotabar watch · next refresh {} · Ctrl-C to quit",
+            timefmt::countdown(chrono::Duration::from_std(until_fetch).unwrap_or_default())
+        ),
+        String::new(),
+    ];
+    for provider in shown {
+        if !config.is_provider_enabled(*provider) {
+            lines.push(format!(
+                "{} {} (disabled)",
+                provider.icon(),
+                provider.display_name()
+            ));
+        } else if let Some(snapshot) = current.get(provider) {
+            let live = LiveView {
+                now,
+                previous: previous.get(provider),
+            };
+            lines.extend(status::status_lines(snapshot, style, Some(&live)));
+        } else {
+            let reason = errors.get(provider).map_or("no data yet", |e| e.as_str());
+            lines.push(format!("{}: {}", provider.display_name(), reason));
+        }
+        if let Some(error) = errors
+            .get(provider)
+            .filter(|_| current.contains_key(provider))
+        {
+            lines.push(format!("  Last refresh failed: {}", error));
+        }
+    }
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+/// Run the binary with config and cache isolated under `home`
+fn quotabar(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_quotabar"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env_remove("QUOTABAR_CONFIG")
+        .env_remove("QUOTABAR_CONFIG_DIR")
+        .env_remove("QUOTABAR_CACHE_DIR")
+        .output()
+        .unwrap()
+}
+
+/// A bar tick must not wait for a refresh another process is running
+#[test]
+fn test_waybar_does_not_wait_for_held_lock() {
+    let home = tempfile::tempdir().unwrap();
+    let cache = home.path().join(".cache/quotabar");
+    std::fs::create_dir_all(&cache).unwrap();
+    let lock = File::create(cache.join("state.lock")).unwrap();
+    lock.try_lock().unwrap();
+
+    let started = Instant::now();
+    // OpenCode has no fetcher, so nothing here touches the network
+    let output = quotabar(
+        home.path(),
+        &["waybar", "--provider", "opencode", "--fetch", "always"],
+    );
+    let elapsed = started.elapsed();
+    assert!(output.status.success());
+    assert!(elapsed < Duration::from_secs(3), "took {:?}", elapsed);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(json["text"].is_string());
+}
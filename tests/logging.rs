@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Run the binary with config and cache isolated under `home`
+fn quotabar(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_quotabar"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .output()
+        .unwrap()
+}
+
+/// Waybar parses stdout as JSON, so logs must only ever reach stderr
+#[test]
+fn test_waybar_stdout_is_json_under_verbose_logging() {
+    let home = tempfile::tempdir().unwrap();
+    // OpenCode has no fetcher, so this fails (and logs) without the network
+    let output = quotabar(home.path(), &["-vv", "waybar", "--provider", "opencode"]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(json["text"].is_string());
+    assert!(json["class"].is_array());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("OpenCode"), "{}", stderr);
+}
+
+#[test]
+fn test_log_file_without_verbose() {
+    let home = tempfile::tempdir().unwrap();
+    let config_dir = home.path().join(".config").join("quotabar");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[general]\nlog_file = \"quotabar.log\"\n",
+    )
+    .unwrap();
+
+    let output = quotabar(home.path(), &["waybar", "--provider", "opencode"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+
+    let log = std::fs::read_to_string(home.path().join(".cache/quotabar/quotabar.log")).unwrap();
+    assert!(log.contains("WARN"), "{}", log);
+    assert!(log.contains("OpenCode"), "{}", log);
+}
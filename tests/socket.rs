@@ -0,0 +1,132 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// The binary with config, cache and runtime dir isolated under `home`
+fn quotabar(home: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_quotabar"));
+    command
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_RUNTIME_DIR", home.join("run"))
+        .env_remove("QUOTABAR_CONFIG")
+        .env_remove("QUOTABAR_CONFIG_DIR")
+        .env_remove("QUOTABAR_CACHE_DIR");
+    command
+}
+
+fn run(home: &Path, args: &[&str]) -> Output {
+    quotabar(home).args(args).output().unwrap()
+}
+
+/// A cache with Claude and Codex, and a config whose only provider has no
+/// fetcher, so the daemon never touches the network
+fn setup(home: &Path) {
+    let cache = home.join(".cache/quotabar");
+    std::fs::create_dir_all(&cache).unwrap();
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cache/v1-baseline.json"),
+        cache.join("state.json"),
+    )
+    .unwrap();
+
+    let config = home.join(".config/quotabar");
+    std::fs::create_dir_all(&config).unwrap();
+    let disabled: String = ["claude", "claude-org", "codex", "kiro", "windsurf"]
+        .iter()
+        .map(|id| format!("[providers.{}]\nenabled = false\n", id))
+        .collect();
+    std::fs::write(config.join("config.toml"), disabled).unwrap();
+
+    std::fs::create_dir(home.join("run")).unwrap();
+    std::fs::set_permissions(home.join("run"), std::fs::Permissions::from_mode(0o700)).unwrap();
+}
+
+/// Start the daemon and wait until it answers on its socket
+fn start_daemon(home: &Path) -> Child {
+    let mut daemon = quotabar(home)
+        .arg("daemon")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let socket = home.join("run/quotabar.sock");
+    let started = Instant::now();
+    while UnixStream::connect(&socket).is_err() {
+        if started.elapsed() > Duration::from_secs(10) {
+            daemon.kill().unwrap();
+            panic!("the daemon never listened on {}", socket.display());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    daemon
+}
+
+fn stop_daemon(mut daemon: Child) {
+    unsafe { libc::kill(daemon.id() as libc::pid_t, libc::SIGTERM) };
+    assert!(daemon.wait().unwrap().success());
+}
+
+#[test]
+fn test_daemon_serves_snapshots() {
+    let home = tempfile::tempdir().unwrap();
+    setup(home.path());
+    let socket = home.path().join("run/quotabar.sock");
+    // Left behind by a daemon that was killed
+    drop(UnixListener::bind(&socket).unwrap());
+
+    let daemon = start_daemon(home.path());
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o600);
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream
+        .write_all(b"{\"get\":\"all\"}\n{\"get\":\"codex\"}\n{\"get\":\"gemini\"}\n")
+        .unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let mut next =
+        || -> serde_json::Value { serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() };
+    let all = next();
+    assert_eq!(
+        all["state"]["snapshots"]["claude"]["windows"][0]["used_percent"],
+        31.0
+    );
+    assert_eq!(next()["snapshot"]["provider"], "codex");
+    assert!(next()["error"].is_string());
+
+    // Read commands ask the daemon first
+    let output = run(home.path(), &["-v", "get", "claude.primary.used_percent"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "31");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("read cache from the daemon"), "{}", stderr);
+
+    stop_daemon(daemon);
+    assert!(!socket.exists());
+
+    // ...and read the file without one
+    let output = run(home.path(), &["-v", "get", "claude.primary.used_percent"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "31");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("read cache from the daemon"), "{}", stderr);
+}
+
+#[test]
+fn test_refresh_request() {
+    let home = tempfile::tempdir().unwrap();
+    setup(home.path());
+    let daemon = start_daemon(home.path());
+
+    let mut stream = UnixStream::connect(home.path().join("run/quotabar.sock")).unwrap();
+    stream.write_all(b"{\"refresh\":true}\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    assert_eq!(line, "{\"refreshing\":true}\n");
+
+    stop_daemon(daemon);
+}